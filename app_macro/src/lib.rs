@@ -45,7 +45,7 @@ fn impl_dao(ast: &syn::DeriveInput) -> TokenStream {
             let created = POOL.save(&self, &[]).await?;
             Ok(created.last_insert_id.unwrap())
         }
-        async fn create_all(all: &Vec<Self>) -> Result<i64, DBError> {
+        async fn create_all(all: &[Self]) -> Result<i64, DBError> {
             let created = POOL.save_batch(all, &[]).await?;
             Ok(created.last_insert_id.unwrap())
         }
@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS `refresh_tokens`(
+  `id` VARCHAR(50) NOT NULL,
+  `user_id` VARCHAR(50) NOT NULL REFERENCES `users`(`id`),
+  `family_id` VARCHAR(50) NOT NULL,
+  `token_hash` VARCHAR(100) NOT NULL,
+  `replaced_by` VARCHAR(50) DEFAULT NULL,
+  `is_revoked` INT(1) NOT NULL DEFAULT '0',
+  `expire` TIMESTAMP NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `uk_token_hash` (`token_hash`)
+);
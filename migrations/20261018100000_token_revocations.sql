@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS `token_revocations`(
+  `id` VARCHAR(50) NOT NULL,
+  `user_id` VARCHAR(50) NOT NULL REFERENCES `users`(`id`),
+  `jti` VARCHAR(50) DEFAULT NULL,
+  `expire` TIMESTAMP NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (`id`)
+);
@@ -0,0 +1,33 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS `oauth_clients`(
+  `id` VARCHAR(50) NOT NULL,
+  `name` VARCHAR(100) NOT NULL,
+  `secret_hash` VARCHAR(100) DEFAULT NULL,
+  `redirect_uris` TEXT NOT NULL,
+  `grant_types` VARCHAR(200) NOT NULL,
+  `scopes` VARCHAR(200) NOT NULL,
+  `domain_id` VARCHAR(50) NOT NULL REFERENCES `domains`(`id`),
+  `is_deleted` INT(1) NOT NULL DEFAULT '0',
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  `updated_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  `created_by` VARCHAR(50),
+  `updated_by` VARCHAR(50),
+  PRIMARY KEY (`id`)
+);
+
+CREATE TABLE IF NOT EXISTS `oauth_codes`(
+  `id` VARCHAR(50) NOT NULL,
+  `code_hash` VARCHAR(100) NOT NULL,
+  `client_id` VARCHAR(50) NOT NULL REFERENCES `oauth_clients`(`id`),
+  `user_id` VARCHAR(50) NOT NULL REFERENCES `users`(`id`),
+  `redirect_uri` TEXT NOT NULL,
+  `scope` VARCHAR(200) NOT NULL,
+  `nonce` VARCHAR(200) DEFAULT NULL,
+  `code_challenge` VARCHAR(200) DEFAULT NULL,
+  `code_challenge_method` VARCHAR(10) DEFAULT NULL,
+  `is_used` INT(1) NOT NULL DEFAULT '0',
+  `expire` TIMESTAMP NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `uk_code_hash` (`code_hash`)
+);
@@ -0,0 +1,30 @@
+-- Add migration script here
+ALTER TABLE `domains` ADD COLUMN `allow_jit` INT(1) NOT NULL DEFAULT '0';
+
+CREATE TABLE IF NOT EXISTS `identity_providers`(
+  `id` VARCHAR(50) NOT NULL,
+  `name` VARCHAR(100) NOT NULL,
+  `domain_id` VARCHAR(50) NOT NULL REFERENCES `domains`(`id`),
+  `issuer` VARCHAR(200) NOT NULL,
+  `audience` VARCHAR(200) NOT NULL,
+  `jwks_uri` TEXT,
+  `public_key` TEXT,
+  `is_deleted` INT(1) NOT NULL DEFAULT '0',
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  `updated_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  `created_by` VARCHAR(50),
+  `updated_by` VARCHAR(50),
+  PRIMARY KEY (`id`)
+);
+
+CREATE TABLE IF NOT EXISTS `user_identities`(
+  `id` VARCHAR(50) NOT NULL,
+  `user_id` VARCHAR(50) NOT NULL REFERENCES `users`(`id`),
+  `provider_id` VARCHAR(50) NOT NULL REFERENCES `identity_providers`(`id`),
+  `subject` VARCHAR(200) NOT NULL,
+  `email` VARCHAR(200) DEFAULT NULL,
+  `last_logined_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `uk_provider_subject` (`provider_id`, `subject`)
+);
@@ -0,0 +1,21 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS `user_totps`(
+  `id` VARCHAR(50) NOT NULL,
+  `user_id` VARCHAR(50) NOT NULL REFERENCES `users`(`id`),
+  `secret` VARCHAR(100) NOT NULL,
+  `last_used_step` BIGINT NOT NULL DEFAULT '0',
+  `is_enabled` INT(1) NOT NULL DEFAULT '0',
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `uk_user_id` (`user_id`)
+);
+
+CREATE TABLE IF NOT EXISTS `recovery_codes`(
+  `id` VARCHAR(50) NOT NULL,
+  `user_id` VARCHAR(50) NOT NULL REFERENCES `users`(`id`),
+  `code_hash` VARCHAR(100) NOT NULL,
+  `is_used` INT(1) NOT NULL DEFAULT '0',
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `uk_code_hash` (`code_hash`)
+);
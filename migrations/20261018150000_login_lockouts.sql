@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS `login_lockouts`(
+  `id` VARCHAR(50) NOT NULL,
+  `lock_key` VARCHAR(250) NOT NULL,
+  `user_id` VARCHAR(50) DEFAULT NULL,
+  `username` VARCHAR(200) DEFAULT NULL,
+  `ip` VARCHAR(50) DEFAULT NULL,
+  `failures` INT NOT NULL,
+  `locked_until` TIMESTAMP NOT NULL,
+  `is_unlocked` INT(1) NOT NULL DEFAULT '0',
+  `unlocked_by` VARCHAR(50) DEFAULT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (`id`),
+  KEY `idx_lock_key` (`lock_key`)
+);
@@ -0,0 +1,11 @@
+-- Add migration script here
+ALTER TABLE `users` MODIFY `password` VARCHAR(255) NOT NULL;
+
+CREATE TABLE IF NOT EXISTS `password_histories`(
+  `id` VARCHAR(50) NOT NULL,
+  `user_id` VARCHAR(50) NOT NULL REFERENCES `users`(`id`),
+  `password` VARCHAR(255) NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (`id`),
+  KEY `idx_user_id` (`user_id`)
+);
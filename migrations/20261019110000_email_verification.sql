@@ -0,0 +1,18 @@
+-- Add migration script here
+ALTER TABLE `users` ADD COLUMN `email_verified_at` TIMESTAMP NULL DEFAULT NULL;
+-- accounts created before verification existed are trusted
+UPDATE `users` SET `email_verified_at` = `created_at` WHERE `email` IS NOT NULL;
+
+ALTER TABLE `domains` ADD COLUMN `email_verification` INT(1) NOT NULL DEFAULT '0';
+
+CREATE TABLE IF NOT EXISTS `email_verification_tokens`(
+  `id` VARCHAR(50) NOT NULL,
+  `user_id` VARCHAR(50) NOT NULL REFERENCES `users`(`id`),
+  `email` VARCHAR(200) NOT NULL,
+  `token_hash` VARCHAR(100) NOT NULL,
+  `is_used` INT(1) NOT NULL DEFAULT '0',
+  `expire` TIMESTAMP NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `uk_token_hash` (`token_hash`)
+);
@@ -0,0 +1,14 @@
+-- Add migration script here
+ALTER TABLE `users` ADD COLUMN `is_deleted` INT(1) NOT NULL DEFAULT '0';
+
+CREATE TABLE IF NOT EXISTS `impersonations`(
+  `id` VARCHAR(50) NOT NULL,
+  `actor_id` VARCHAR(50) NOT NULL REFERENCES `users`(`id`),
+  `user_id` VARCHAR(50) NOT NULL REFERENCES `users`(`id`),
+  `reason` TEXT NOT NULL,
+  `jti` VARCHAR(50) NOT NULL,
+  `expire` TIMESTAMP NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (`id`),
+  KEY `idx_user_id` (`user_id`)
+);
@@ -0,0 +1,19 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS `audit_logs`(
+  `id` VARCHAR(50) NOT NULL,
+  `actor_id` VARCHAR(50) NOT NULL,
+  `impersonator_id` VARCHAR(50) DEFAULT NULL,
+  `action` VARCHAR(100) NOT NULL,
+  `target_type` VARCHAR(50) NOT NULL,
+  `target_id` VARCHAR(50) NOT NULL,
+  `domain_id` VARCHAR(50) DEFAULT NULL,
+  `before_json` JSON DEFAULT NULL,
+  `after_json` JSON DEFAULT NULL,
+  `request_id` VARCHAR(100) NOT NULL,
+  `ip` VARCHAR(50) NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (`id`),
+  KEY `idx_domain_created` (`domain_id`, `created_at`),
+  KEY `idx_actor_id` (`actor_id`),
+  KEY `idx_target` (`target_type`, `target_id`)
+);
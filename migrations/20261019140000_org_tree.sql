@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE `orgs` ADD COLUMN `parent_id` VARCHAR(50) DEFAULT NULL REFERENCES `orgs`(`id`);
+CREATE INDEX `idx_orgs_parent_id` ON `orgs`(`parent_id`);
@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS `role_has_parents`(
+  `role_id` VARCHAR(50) NOT NULL REFERENCES `roles`(`id`),
+  `parent_id` VARCHAR(50) NOT NULL REFERENCES `roles`(`id`),
+  `domain_id` VARCHAR(50) NOT NULL REFERENCES `domains`(`id`),
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY(`role_id`, `parent_id`),
+  KEY `idx_domain_id` (`domain_id`)
+);
@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS `org_has_roles`(
+  `org_id` VARCHAR(50) NOT NULL REFERENCES `orgs`(`id`),
+  `role_id` VARCHAR(50) NOT NULL REFERENCES `roles`(`id`),
+  `expire` TIMESTAMP NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY(`org_id`, `role_id`),
+  KEY `idx_role_id` (`role_id`)
+);
@@ -0,0 +1,31 @@
+-- Add migration script here
+-- `groups` is reserved in MySQL 8
+CREATE TABLE IF NOT EXISTS `user_groups` (
+  `id` VARCHAR(50) NOT NULL,
+  `name` VARCHAR(100) NOT NULL,
+  `description` TEXT,
+  `is_deleted` INT(1) NOT NULL DEFAULT '0',
+  `domain_id` VARCHAR(50) NOT NULL REFERENCES `domains`(`id`),
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  `updated_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  `created_by` VARCHAR(100) NOT NULL REFERENCES `users`(`id`),
+  `updated_by` VARCHAR(100) NOT NULL REFERENCES `users`(`id`),
+  PRIMARY KEY (`id`)
+);
+
+CREATE TABLE IF NOT EXISTS `user_has_groups`(
+  `user_id` VARCHAR(50) REFERENCES `users`(`id`),
+  `group_id` VARCHAR(50) REFERENCES `user_groups`(`id`),
+  `expire` TIMESTAMP NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY(`user_id`, `group_id`)
+);
+
+CREATE TABLE IF NOT EXISTS `group_has_roles`(
+  `group_id` VARCHAR(50) NOT NULL REFERENCES `user_groups`(`id`),
+  `role_id` VARCHAR(50) NOT NULL REFERENCES `roles`(`id`),
+  `expire` TIMESTAMP NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY(`group_id`, `role_id`),
+  KEY `idx_role_id` (`role_id`)
+);
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- `condition` is reserved in MySQL
+ALTER TABLE `role_has_perms` ADD COLUMN `condition_expr` TEXT DEFAULT NULL;
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS `resource_grants`(
+  `id` VARCHAR(50) NOT NULL,
+  `perm_id` VARCHAR(50) NOT NULL REFERENCES `perms`(`id`),
+  `resource` VARCHAR(255) NOT NULL,
+  `subject_type` VARCHAR(10) NOT NULL,
+  `subject_id` VARCHAR(50) NOT NULL,
+  `domain_id` VARCHAR(50) NOT NULL REFERENCES `domains`(`id`),
+  `expire` TIMESTAMP NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  `created_by` VARCHAR(100) REFERENCES `users`(`id`),
+  PRIMARY KEY(`id`),
+  KEY `idx_subject` (`subject_type`, `subject_id`),
+  KEY `idx_perm_id` (`perm_id`)
+);
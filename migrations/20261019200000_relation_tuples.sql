@@ -0,0 +1,26 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS `relation_schemas`(
+  `domain_id` VARCHAR(50) NOT NULL REFERENCES `domains`(`id`),
+  `definition` TEXT NOT NULL,
+  -- bumped by every schema change and tuple write, consistency tokens carry it
+  `revision` BIGINT NOT NULL DEFAULT '0',
+  `updated_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  `updated_by` VARCHAR(100) REFERENCES `users`(`id`),
+  PRIMARY KEY(`domain_id`)
+);
+
+CREATE TABLE IF NOT EXISTS `relation_tuples`(
+  `id` VARCHAR(50) NOT NULL,
+  `domain_id` VARCHAR(50) NOT NULL REFERENCES `domains`(`id`),
+  `object_type` VARCHAR(64) NOT NULL,
+  `object_id` VARCHAR(100) NOT NULL,
+  `relation` VARCHAR(64) NOT NULL,
+  `subject_type` VARCHAR(64) NOT NULL,
+  `subject_id` VARCHAR(100) NOT NULL,
+  -- empty unless the subject is a userset like `group:eng#member`
+  `subject_relation` VARCHAR(64) NOT NULL DEFAULT '',
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  `created_by` VARCHAR(100) REFERENCES `users`(`id`),
+  PRIMARY KEY(`id`),
+  KEY `idx_domain_object` (`domain_id`, `object_type`, `object_id`, `relation`)
+);
@@ -0,0 +1,5 @@
+-- Add migration script here
+ALTER TABLE `role_has_perms` ADD COLUMN `effect` VARCHAR(10) NOT NULL DEFAULT 'allow';
+
+-- how a domain settles a perm that is both allowed and denied
+ALTER TABLE `domains` ADD COLUMN `conflict_strategy` VARCHAR(20) NOT NULL DEFAULT 'deny_overrides';
@@ -0,0 +1,30 @@
+-- Add migration script here
+-- no user may hold (`static`) or activate in one session (`dynamic`) two roles of a set
+CREATE TABLE IF NOT EXISTS `role_exclusions` (
+  `id` VARCHAR(50) NOT NULL,
+  `name` VARCHAR(100) NOT NULL,
+  `kind` VARCHAR(10) NOT NULL DEFAULT 'static',
+  `domain_id` VARCHAR(50) NOT NULL REFERENCES `domains`(`id`),
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  `created_by` VARCHAR(100) REFERENCES `users`(`id`),
+  PRIMARY KEY (`id`),
+  KEY `idx_domain_id` (`domain_id`)
+);
+
+CREATE TABLE IF NOT EXISTS `role_exclusion_members`(
+  `exclusion_id` VARCHAR(50) NOT NULL REFERENCES `role_exclusions`(`id`),
+  `role_id` VARCHAR(50) NOT NULL REFERENCES `roles`(`id`),
+  PRIMARY KEY(`exclusion_id`, `role_id`),
+  KEY `idx_role_id` (`role_id`)
+);
+
+-- roles activated for the access token `jti`
+CREATE TABLE IF NOT EXISTS `session_roles`(
+  `jti` VARCHAR(50) NOT NULL,
+  `user_id` VARCHAR(50) NOT NULL REFERENCES `users`(`id`),
+  `role_id` VARCHAR(50) NOT NULL REFERENCES `roles`(`id`),
+  `expire` TIMESTAMP NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY(`jti`, `role_id`),
+  KEY `idx_role_id` (`role_id`)
+);
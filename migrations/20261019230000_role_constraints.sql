@@ -0,0 +1,13 @@
+-- Add migration script here
+-- at most this many users hold the role directly or through orgs and groups, no limit when NULL
+ALTER TABLE `roles` ADD COLUMN `max_holders` INT DEFAULT NULL;
+
+-- `role_id` is only granted to users already holding `prerequisite_id`
+CREATE TABLE IF NOT EXISTS `role_has_prerequisites`(
+  `role_id` VARCHAR(50) NOT NULL REFERENCES `roles`(`id`),
+  `prerequisite_id` VARCHAR(50) NOT NULL REFERENCES `roles`(`id`),
+  `domain_id` VARCHAR(50) NOT NULL REFERENCES `domains`(`id`),
+  `created_at` TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY(`role_id`, `prerequisite_id`),
+  KEY `idx_prerequisite_id` (`prerequisite_id`)
+);
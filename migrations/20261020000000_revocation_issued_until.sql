@@ -0,0 +1,6 @@
+-- Add migration script here
+-- revocations of every token of a user: tokens issued up to this instant are revoked,
+-- in milliseconds since the epoch so that a login right after it is not
+ALTER TABLE `token_revocations` ADD COLUMN `issued_until` BIGINT DEFAULT NULL;
+UPDATE `token_revocations` SET `issued_until` = UNIX_TIMESTAMP(`created_at`) * 1000 + 999
+  WHERE `jti` IS NULL;
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- JWK set file read by the server, set by sys admins only
+ALTER TABLE `identity_providers` ADD COLUMN `jwks_path` TEXT DEFAULT NULL;
@@ -13,6 +13,7 @@ macro_rules! reply {
 }
 
 mod v1;
+mod well_known;
 
 async fn index() -> &'static str {
   "hello world"
@@ -21,5 +22,8 @@ async fn index() -> &'static str {
 pub fn apply_routes() -> Router<BoxRoute> {
   let prefix = "/api/v1";
   let router = Router::new().route("/", get(index));
-  router.nest(prefix, v1::apply_routes()).boxed()
+  router
+    .nest(prefix, v1::apply_routes())
+    .or(well_known::apply_routes())
+    .boxed()
 }
\ No newline at end of file
@@ -0,0 +1,49 @@
+use axum::{
+    extract::{Extension, Query},
+    handler::get,
+    routing::BoxRoute,
+    Router,
+};
+use tower_http::auth::RequireAuthorizationLayer;
+
+use crate::{
+    repository::{
+        dao::{Domain, UserRole},
+        dto::QueryAudit,
+        Dao,
+    },
+    util::{jwt::Auth, restrict::Restrict, APIResult},
+};
+use validator::Validate;
+
+/// admins see every entry, domain admins only the entries of their domain
+async fn all(Query(q): Query<QueryAudit>, Extension(auth): Extension<Auth>) -> APIResult {
+    q.validate()?;
+    if !auth.is_admin {
+        let domain_id = match &q.domain_id {
+            Some(val) => val,
+            None => return Err(reject!("仅管理员可访问")),
+        };
+        let domain = Domain::find_by_id(domain_id)
+            .await
+            .map_err(|_| reject!(format!("来源域 {} 不存在", domain_id)))?;
+        let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+        if !user_roles
+            .into_iter()
+            .any(|v| v.role_id == domain.admin_role_id)
+        {
+            return Err(reject!(format!("仅域管理员可操作")));
+        }
+    }
+    let all = q.find_all().await?;
+    Ok(reply!(all))
+}
+
+pub fn apply_routes() -> Router<BoxRoute> {
+    let router = Router::new();
+    let restrict_layer = RequireAuthorizationLayer::custom(Restrict::new());
+    router
+        .route("/audit", get(all))
+        .layer(restrict_layer)
+        .boxed()
+}
@@ -1,75 +1,378 @@
-use axum::{handler::post, routing::BoxRoute, Json, Router};
+use axum::{
+    extract::ConnectInfo, handler::post, http::HeaderMap, routing::BoxRoute, Json, Router,
+};
+use std::net::SocketAddr;
 use validator::Validate;
 
 use crate::{
     repository::{
-        dao::User,
-        dto::{ConnectUser, LoginUser, NewUser},
+        dao::{
+            Domain, EmailVerificationToken, IdentityProvider, PasswordHistory, PasswordResetToken,
+            RefreshToken, User, UserIdentity, UserTotp,
+        },
+        dto::{
+            is_verification_enabled, verify_email_ttl, FederatedLogin, ForgotPassword, LoginMfa,
+            LoginUser, NewEmailVerification, NewLockout, NewRefreshToken, NewUser,
+            ResendVerification, ResetPassword, RevokeSessions, RotateRefreshToken, VerifyEmail,
+            VerifyTotp,
+        },
+        Dao,
     },
     util::{
+        audit::Actor,
+        client_ip,
         jwt::{self, Auth},
-        APIResult,
+        lockout::{self, POLICY},
+        mail, now, password, uuid_v4, APIError, APIResult,
     },
 };
 
-async fn register(Json(body): Json<NewUser>) -> APIResult {
-    body.validate()?;
-    if User::find_by_username(&body.username).await.is_ok() {
-        return Err(reject!("用户已存在"));
-    }
-    let user = body.create().await?;
+use super::federation::verify_identity;
+
+fn reply_token(user: User, is_admin: bool, refresh_token: Option<String>) -> APIResult {
     let token = jwt::generate_token(Auth {
         id: user.id.clone(),
         username: user.username.clone(),
-        is_admin: false,
+        is_admin,
     });
     Ok(reply!({
-      "token": token, "user": user
+      "token": token, "refresh_token": refresh_token,
+      "expires_in": jwt::access_token_ttl().num_seconds(), "user": user
     }))
 }
 
-async fn login(Json(body): Json<LoginUser>) -> APIResult {
+const LOGIN_FAILED: &str = "用户名或密码不正确";
+
+fn check_blocked(keys: &[&str]) -> Result<(), APIError> {
+    let until = keys.iter().filter_map(|v| lockout::blocked_until(v)).max();
+    match until {
+        Some(until) => {
+            let secs = (until - now()).num_seconds().max(1);
+            Err(reject!(format!("登录尝试过于频繁，请 {} 秒后重试", secs)))
+        }
+        None => Ok(()),
+    }
+}
+
+/// counts a failed attempt against the account and the client address, locks either on threshold
+async fn record_failure(
+    account_key: &str,
+    user_id: Option<String>,
+    username: &str,
+    ip: &str,
+) -> Result<(), APIError> {
+    if let Some((failures, until)) = lockout::record_failure(account_key, POLICY.account_threshold) {
+        NewLockout {
+            lock_key: account_key.to_string(),
+            user_id,
+            username: Some(username.to_string()),
+            ip: Some(ip.to_string()),
+            failures,
+            locked_until: until,
+        }
+        .create()
+        .await?;
+    }
+    let ip_key = lockout::ip_key(ip);
+    if let Some((failures, until)) = lockout::record_failure(&ip_key, POLICY.ip_threshold) {
+        NewLockout {
+            lock_key: ip_key,
+            user_id: None,
+            username: None,
+            ip: Some(ip.to_string()),
+            failures,
+            locked_until: until,
+        }
+        .create()
+        .await?;
+    }
+    Ok(())
+}
+
+/// rejects unverified emails when a domain of the user requires verification to sign in
+async fn check_email_verified(user: &User) -> Result<(), APIError> {
+    if user.is_email_verified() {
+        return Ok(());
+    }
+    let domains = Domain::find_by_member(&user.id).await?;
+    if domains.iter().any(|v| v.email_verification == 2) {
+        return Err(reject!("邮箱未验证"));
+    }
+    Ok(())
+}
+
+async fn register(Json(body): Json<NewUser>) -> APIResult {
+    body.validate()?;
+    if User::find_by_username(&body.username).await.is_ok() {
+        return Err(reject!("用户已存在"));
+    }
+    if is_verification_enabled() && body.email.is_none() {
+        return Err(reject!("请输入邮箱"));
+    }
+    let user = body.create().await?;
+    if let (Some(email), true) = (user.email.clone(), is_verification_enabled()) {
+        let verification = NewEmailVerification { user_id: user.id.clone(), email };
+        let (_, mail) = verification.create(&user.username).await?;
+        mail::deliver(mail);
+    }
+    let (_, refresh_token) = NewRefreshToken { user_id: user.id.clone() }.create().await?;
+    reply_token(user, false, Some(refresh_token))
+}
+
+async fn login(
+    Json(body): Json<LoginUser>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> APIResult {
+    body.validate()?;
+    let ip = client_ip(&headers, addr);
+    let found = User::find_by_username_or_email(&body.username_or_email).await.ok();
+    // unknown identifiers are throttled like accounts, so lockouts don't reveal which users exist
+    let account_key = match &found {
+        Some(user) => lockout::account_key(&user.id),
+        None => lockout::account_key(&body.username_or_email),
+    };
+    check_blocked(&[&account_key, &lockout::ip_key(&ip)])?;
+    let matched = match &found {
+        Some(user) => body.is_password_matched(&user.password).await,
+        None => {
+            body.spend_verify().await;
+            false
+        }
+    };
+    let user_dao = match found {
+        Some(user) if matched => user,
+        found => {
+            let user_id = found.map(|v| v.id);
+            record_failure(&account_key, user_id, &body.username_or_email, &ip).await?;
+            return Err(reject!(LOGIN_FAILED));
+        }
+    };
+    let user_dao = if password::needs_rehash(&user_dao.password) {
+        body.rehash(&user_dao).await?
+    } else {
+        user_dao
+    };
+    if user_dao.is_actived == 0 {
+        return Err(reject!("用户被禁用"));
+    }
+    check_email_verified(&user_dao).await?;
+    // failures are only reset once the second factor is passed too
+    if UserTotp::is_enabled_for(&user_dao.id).await? {
+        return Ok(reply!({
+          "mfa_required": true, "challenge_token": jwt::generate_challenge(&user_dao.id),
+          "expires_in": jwt::challenge_ttl().num_seconds()
+        }));
+    }
+    lockout::clear(&account_key);
+
+    let user = body.login(&user_dao).await?;
+    let is_admin = user.sys_role.clone().unwrap() == "admin";
+    let (_, refresh_token) = NewRefreshToken { user_id: user.id.clone() }.create().await?;
+    reply_token(user, is_admin, Some(refresh_token))
+}
+
+async fn login_mfa(
+    Json(body): Json<LoginMfa>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> APIResult {
     body.validate()?;
-    let user_dao = match User::find_by_username_or_email(&body.username_or_email).await {
+    let ip = client_ip(&headers, addr);
+    let challenge = match jwt::decode_challenge(&body.challenge_token) {
+        Ok(val) => val.claims,
+        Err(_) => return Err(reject!("登录验证已过期")),
+    };
+    let user_dao = match User::find_by_id(&challenge.mfa_user_id).await {
         Ok(val) => val,
         Err(_) => return Err(reject!("用户不存在")),
     };
-    if !body.is_password_matched(&user_dao.password) {
-        return Err(reject!("密码不正确"));
-    }
+    let account_key = lockout::account_key(&user_dao.id);
+    check_blocked(&[&account_key, &lockout::ip_key(&ip)])?;
     if user_dao.is_actived == 0 {
         return Err(reject!("用户被禁用"));
     }
+    check_email_verified(&user_dao).await?;
+    let totp = match UserTotp::find_by_user(&user_dao.id).await {
+        Ok(val) if val.is_enabled == 1 => val,
+        _ => return Err(reject!("未启用两步验证")),
+    };
+    let passed = match (&body.code, &body.recovery_code) {
+        (Some(code), _) => VerifyTotp { code: code.clone() }.verify(&totp).await?,
+        (None, Some(recovery_code)) => body.use_recovery_code(&user_dao.id, recovery_code).await?,
+        (None, None) => return Err(reject!("请输入验证码或恢复码")),
+    };
+    if !passed {
+        let user_id = Some(user_dao.id.clone());
+        record_failure(&account_key, user_id, &user_dao.username, &ip).await?;
+        return Err(reject!("验证码不正确"));
+    }
+    lockout::clear(&account_key);
 
     let user = body.login(&user_dao).await?;
-    let is_admin = user.sys_role.clone().unwrap() == "admin";
+    let is_admin = user.sys_role.as_deref() == Some("admin");
+    let (_, refresh_token) = NewRefreshToken { user_id: user.id.clone() }.create().await?;
+    reply_token(user, is_admin, Some(refresh_token))
+}
 
-    let token = jwt::generate_token(Auth {
-        id: user.id.clone(),
-        username: user.username.clone(),
-        is_admin,
-    });
-    Ok(reply!({
-      "token": token, "user": user
-    }))
+async fn connect(
+    Json(body): Json<FederatedLogin>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> APIResult {
+    body.validate()?;
+    let provider = match IdentityProvider::find_by_id(&body.provider_id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("身份提供方 {} 不存在", &body.provider_id))),
+    };
+    let claims = verify_identity(&provider, &body.token).await?;
+    let user = match UserIdentity::find_by_subject(&provider.id, &claims.sub).await {
+        Ok(identity) => {
+            let identity = body.login(&identity, &claims).await?;
+            match User::find_by_id(&identity.user_id).await {
+                Ok(val) => val,
+                Err(_) => return Err(reject!("用户不存在")),
+            }
+        }
+        Err(_) => {
+            let domain = Domain::find_by_id(&provider.domain_id).await?;
+            if domain.allow_jit == 0 {
+                return Err(reject!("外部账号未关联用户"));
+            }
+            // the new user stands as the actor of its own provisioning
+            let actor = Actor::new(&uuid_v4(), &headers, addr);
+            body.provision(&domain, &claims, &actor).await?
+        }
+    };
+    if user.is_actived == 0 {
+        return Err(reject!("用户被禁用"));
+    }
+    check_email_verified(&user).await?;
+    // the provider only stands in for the password, the second factor is still asked for
+    if UserTotp::is_enabled_for(&user.id).await? {
+        return Ok(reply!({
+          "mfa_required": true, "challenge_token": jwt::generate_challenge(&user.id),
+          "expires_in": jwt::challenge_ttl().num_seconds()
+        }));
+    }
+    let is_admin = user.sys_role.as_deref() == Some("admin");
+    let (_, refresh_token) = NewRefreshToken { user_id: user.id.clone() }.create().await?;
+    reply_token(user, is_admin, Some(refresh_token))
 }
 
-async fn connect(Json(body): Json<ConnectUser>) -> APIResult {
+async fn refresh(Json(body): Json<RotateRefreshToken>) -> APIResult {
     body.validate()?;
-    let user: User;
-    if let Ok(val) = User::find_by_username(&body.username).await {
-        user = val.into();
-    } else {
-        user = body.create().await?;
+    let prev = match RefreshToken::find_by_token(&body.refresh_token).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!("刷新令牌无效")),
     };
-    let token = jwt::generate_token(Auth {
-        id: user.id.clone(),
-        username: user.username.clone(),
-        is_admin: false,
-    });
-    Ok(reply!({
-      "token": token, "user": user
-    }))
+    if prev.is_revoked == 1 || prev.replaced_by.is_some() {
+        RefreshToken::revoke_family(&prev.family_id).await?;
+        return Err(reject!("刷新令牌已失效"));
+    }
+    if prev.expire <= now() {
+        return Err(reject!("刷新令牌已过期"));
+    }
+    let user = match User::find_by_id(&prev.user_id).await {
+        Ok(val) => val,
+        Err(_) => {
+            RefreshToken::revoke_family(&prev.family_id).await?;
+            return Err(reject!("用户不存在"));
+        }
+    };
+    if user.is_actived == 0 {
+        RefreshToken::revoke_family(&prev.family_id).await?;
+        return Err(reject!("用户被禁用"));
+    }
+    check_email_verified(&user).await?;
+    let family_id = prev.family_id.clone();
+    let refresh_token = match body.save(prev).await? {
+        Some((_, token)) => token,
+        None => {
+            RefreshToken::revoke_family(&family_id).await?;
+            return Err(reject!("刷新令牌已失效"));
+        }
+    };
+    let is_admin = user.sys_role.as_deref() == Some("admin");
+    reply_token(user, is_admin, Some(refresh_token))
+}
+
+async fn forgot_password(Json(body): Json<ForgotPassword>) -> APIResult {
+    body.validate()?;
+    // replies the same whether or not the account exists
+    if let Ok(user) = User::find_by_username_or_email(&body.username_or_email).await {
+        // an unverified address may belong to someone else, it never gets a reset link
+        if let (Some(email), true, 1) =
+            (user.email.clone(), user.is_email_verified(), user.is_actived)
+        {
+            let (_, mail) = body.create(&user, &email).await?;
+            mail::deliver(mail);
+        }
+    }
+    Ok(reply!({"expires_in": password::reset_token_ttl().num_seconds()}))
+}
+
+async fn reset_password(Json(body): Json<ResetPassword>) -> APIResult {
+    body.validate()?;
+    let token = match &body.token {
+        Some(val) => val,
+        None => return Err(reject!("请输入重置令牌")),
+    };
+    let reset_token = match PasswordResetToken::find_by_token(token).await {
+        Ok(val) if val.is_used == 0 && val.expire > now() => val,
+        _ => return Err(reject!("重置令牌无效或已过期")),
+    };
+    let user = match User::find_by_id(&reset_token.user_id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!("用户不存在")),
+    };
+    if user.is_actived == 0 {
+        return Err(reject!("用户被禁用"));
+    }
+    if PasswordHistory::is_reused(&user, &body.new_password).await? {
+        return Err(reject!("不能使用最近用过的密码"));
+    }
+    if !reset_token.consume().await? {
+        return Err(reject!("重置令牌无效或已过期"));
+    }
+    let user = body.reset_password(&user).await?;
+    PasswordResetToken::invalidate_by_user(&user.id).await?;
+    RevokeSessions { user_id: user.id.clone() }.save().await?;
+    Ok(reply!(user))
+}
+
+async fn verify_email(Json(body): Json<VerifyEmail>) -> APIResult {
+    body.validate()?;
+    let token = match EmailVerificationToken::find_by_token(&body.token).await {
+        Ok(val) if val.is_used == 0 && val.expire > now() => val,
+        _ => return Err(reject!("验证链接无效或已过期")),
+    };
+    let user = match User::find_by_id(&token.user_id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!("用户不存在")),
+    };
+    // the email changed after the link was sent
+    if user.email.as_deref() != Some(token.email.as_str()) {
+        return Err(reject!("验证链接无效或已过期"));
+    }
+    if !token.consume().await? {
+        return Err(reject!("验证链接无效或已过期"));
+    }
+    let user = body.save(&user).await?;
+    Ok(reply!(user))
+}
+
+async fn resend_verification(Json(body): Json<ResendVerification>) -> APIResult {
+    body.validate()?;
+    // replies the same whether or not the account exists
+    if let Ok(user) = User::find_by_username_or_email(&body.username_or_email).await {
+        if let (Some(email), false) = (user.email.clone(), user.is_email_verified()) {
+            let verification = NewEmailVerification { user_id: user.id.clone(), email };
+            let (_, mail) = verification.create(&user.username).await?;
+            mail::deliver(mail);
+        }
+    }
+    Ok(reply!({"expires_in": verify_email_ttl().num_seconds()}))
 }
 
 pub fn apply_routes() -> Router<BoxRoute> {
@@ -77,6 +380,12 @@ pub fn apply_routes() -> Router<BoxRoute> {
     router
         .route("/register", post(register))
         .route("/login", post(login))
+        .route("/login/mfa", post(login_mfa))
         .route("/connect", post(connect))
+        .route("/token/refresh", post(refresh))
+        .route("/password/forgot", post(forgot_password))
+        .route("/password/reset", post(reset_password))
+        .route("/email/verify", post(verify_email))
+        .route("/email/verify/resend", post(resend_verification))
         .boxed()
 }
@@ -12,7 +12,7 @@ use crate::{
         dto::{NewDomain, UpdateDomain},
         Dao,
     },
-    util::{jwt::Auth, restrict::Restrict, APIResult},
+    util::{audit::Actor, jwt::Auth, restrict::Restrict, APIResult},
 };
 use validator::Validate;
 
@@ -27,12 +27,16 @@ async fn one(Path(id): Path<String>) -> APIResult {
     Ok(reply!(one))
 }
 
-async fn create(Json(body): Json<NewDomain>, Extension(auth): Extension<Auth>) -> APIResult {
+async fn create(
+    Json(body): Json<NewDomain>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
     if !auth.is_admin {
         return Err(reject!("仅管理员可访问"));
     }
     body.validate()?;
-    let created = body.create(&auth.id).await?;
+    let created = body.create(&actor).await?;
     Ok(reply!(created))
 }
 
@@ -40,12 +44,13 @@ async fn update(
     Path(id): Path<String>,
     Json(body): Json<UpdateDomain>,
     Extension(auth): Extension<Auth>,
+    actor: Actor,
 ) -> APIResult {
     if !auth.is_admin {
         return Err(reject!("仅管理员可访问"));
     }
     body.validate()?;
-    let updated = body.save(&id).await?;
+    let updated = body.save(&id, &actor).await?;
     Ok(reply!(updated))
 }
 
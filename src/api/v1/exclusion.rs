@@ -0,0 +1,156 @@
+use axum::{
+    extract::{Extension, Path, Query},
+    handler::{delete, get, post},
+    routing::BoxRoute,
+    Json, Router,
+};
+use tower_http::auth::RequireAuthorizationLayer;
+
+use crate::{
+    repository::{
+        dao::{Domain, Role, RoleExclusion, RoleExclusionMember, SessionRole, UserRole},
+        dto::{
+            find_exclusion_violations, find_session_violation, ActivateRoles,
+            DeleteRoleExclusion, NewRoleExclusion, QueryRoleExclusion,
+        },
+        vo, Dao,
+    },
+    util::{
+        audit::Actor,
+        jwt::{Auth, Payload},
+        restrict::Restrict,
+        APIError, APIResult,
+    },
+};
+use validator::Validate;
+
+/// only admins and admins of the domain manage its exclusions
+async fn check_domain_admin(domain_id: &str, auth: &Auth) -> Result<(), APIError> {
+    let domain = match Domain::find_by_id(domain_id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("来源域 {} 不存在", domain_id))),
+    };
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+    if !auth.is_admin
+        && !user_roles
+            .into_iter()
+            .any(|v| v.role_id == domain.admin_role_id)
+    {
+        return Err(reject!(format!("仅域管理员可操作")));
+    }
+    Ok(())
+}
+
+async fn all(Query(q): Query<QueryRoleExclusion>, Extension(auth): Extension<Auth>) -> APIResult {
+    check_domain_admin(&q.domain_id, &auth).await?;
+    let all = q.find_all().await?;
+    Ok(reply!(all))
+}
+
+async fn create(
+    Json(mut body): Json<NewRoleExclusion>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
+    check_domain_admin(&body.domain_id, &auth).await?;
+    body.role_ids.sort();
+    body.role_ids.dedup();
+    if body.role_ids.len() < 2 {
+        return Err(reject!("互斥集合至少需要两个角色"));
+    }
+    let roles = Role::find_by_ids(body.role_ids.clone()).await?;
+    if let Some(role_id) = body.role_ids.iter().find(|v| !roles.iter().any(|r| &r.id == *v)) {
+        return Err(reject!(format!("角色 {} 不存在", role_id)));
+    }
+    if let Some(role) = roles.iter().find(|v| v.domain_id != body.domain_id) {
+        return Err(reject!(format!("角色 {} 不属于域 {}", role.id, &body.domain_id)));
+    }
+    let created = body.create(&actor).await?;
+    Ok(reply!(created))
+}
+
+async fn remove(
+    Path(id): Path<String>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    let found = match RoleExclusion::find_by_id(&id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("角色互斥 {} 不存在", &id))),
+    };
+    check_domain_admin(&found.domain_id, &auth).await?;
+    let role_ids: Vec<String> = RoleExclusionMember::find_by_exclusions(&[id.clone()])
+        .await?
+        .into_iter()
+        .map(|v| v.role_id)
+        .collect();
+    let found: vo::RoleExclusion = (found, role_ids).into();
+    DeleteRoleExclusion { id }.save(&found, &actor).await?;
+    Ok(reply!(found))
+}
+
+async fn violations(Path(id): Path<String>, Extension(auth): Extension<Auth>) -> APIResult {
+    check_domain_admin(&id, &auth).await?;
+    let violations = find_exclusion_violations(&id).await?;
+    Ok(reply!(violations))
+}
+
+async fn session_roles(Extension(payload): Extension<Payload>) -> APIResult {
+    let role_ids: Vec<String> = SessionRole::find_by_jti(&payload.jti)
+        .await?
+        .into_iter()
+        .map(|v| v.role_id)
+        .collect();
+    Ok(reply!({ "jti": payload.jti, "role_ids": role_ids }))
+}
+
+async fn activate_roles(
+    Json(mut body): Json<ActivateRoles>,
+    Extension(payload): Extension<Payload>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
+    body.role_ids.sort();
+    body.role_ids.dedup();
+    let held = Role::find_held_ids(&payload.auth.id).await?;
+    if let Some(role_id) = body.role_ids.iter().find(|v| !held.contains(v)) {
+        return Err(reject!(format!("未持有角色 {}", role_id)));
+    }
+    let roles = if body.role_ids.is_empty() {
+        vec![]
+    } else {
+        Role::find_by_ids(body.role_ids.clone()).await?
+    };
+    let mut domain_ids: Vec<&String> = roles.iter().map(|v| &v.domain_id).collect();
+    domain_ids.sort();
+    domain_ids.dedup();
+    for domain_id in domain_ids {
+        let role_ids: Vec<String> = roles
+            .iter()
+            .filter(|v| &v.domain_id == domain_id)
+            .map(|v| v.id.clone())
+            .collect();
+        if let Some((name, conflict)) = find_session_violation(domain_id, &role_ids).await? {
+            return Err(reject!(format!(
+                "角色 {} 受互斥约束 {} 限制, 不能在同一会话中同时启用",
+                conflict.join(", "),
+                name
+            )));
+        }
+    }
+    let activated = body.save(&payload, &actor).await?;
+    Ok(reply!(activated))
+}
+
+pub fn apply_routes() -> Router<BoxRoute> {
+    let router = Router::new();
+    let restrict_layer = RequireAuthorizationLayer::custom(Restrict::new());
+    router
+        .route("/exclusion", post(create).get(all))
+        .route("/exclusion/:id", delete(remove))
+        .route("/domain/:id/exclusion/violation", get(violations))
+        .route("/session/role", get(session_roles).put(activate_roles))
+        .layer(restrict_layer)
+        .boxed()
+}
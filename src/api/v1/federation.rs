@@ -0,0 +1,159 @@
+use axum::{
+    extract::{Extension, Path, Query},
+    handler::{delete, get, post},
+    routing::BoxRoute,
+    Json, Router,
+};
+use serde::Deserialize;
+use tower_http::auth::RequireAuthorizationLayer;
+use validator::Validate;
+
+use crate::{
+    repository::{
+        dao::{Domain, IdentityProvider, User, UserIdentity, UserRole},
+        dto::{FederatedLogin, NewIdentityProvider},
+        Dao,
+    },
+    util::{
+        federation::{self, ExternalClaims, KeySource},
+        jwt::Auth,
+        restrict::Restrict,
+        APIError, APIResult,
+    },
+};
+
+#[derive(Debug, Deserialize)]
+struct QueryProvider {
+    domain_id: String,
+}
+
+async fn is_domain_admin(auth: &Auth, domain: &Domain) -> Result<bool, APIError> {
+    if auth.is_admin {
+        return Ok(true);
+    }
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+    Ok(user_roles
+        .into_iter()
+        .any(|v| v.role_id == domain.admin_role_id))
+}
+
+/// verifies an upstream identity token against the provider's keys
+pub async fn verify_identity(
+    provider: &IdentityProvider,
+    token: &str,
+) -> Result<ExternalClaims, APIError> {
+    let keys = match (&provider.jwks_uri, &provider.jwks_path, &provider.public_key) {
+        (Some(jwks_uri), _, _) => KeySource::Jwks(jwks_uri),
+        (None, Some(jwks_path), _) => KeySource::JwksFile(jwks_path),
+        (None, None, Some(public_key)) => KeySource::PublicKey(public_key),
+        _ => return Err(reject!("身份提供方未配置公钥")),
+    };
+    federation::verify_token(token, &provider.issuer, &provider.audience, keys)
+        .await
+        .map_err(|e| reject!(format!("身份令牌校验失败: {}", e)))
+}
+
+async fn providers(Query(q): Query<QueryProvider>, Extension(auth): Extension<Auth>) -> APIResult {
+    let domain = match Domain::find_by_id(&q.domain_id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("来源域 {} 不存在", &q.domain_id))),
+    };
+    if !is_domain_admin(&auth, &domain).await? {
+        return Err(reject!("仅域管理员可操作"));
+    }
+    let providers = IdentityProvider::find_by_domain(&domain.id).await?;
+    Ok(reply!(providers))
+}
+
+async fn create_provider(
+    Json(mut body): Json<NewIdentityProvider>,
+    Extension(auth): Extension<Auth>,
+) -> APIResult {
+    body.validate()?;
+    let domain = match Domain::find_by_id(&body.domain_id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("来源域 {} 不存在", &body.domain_id))),
+    };
+    if !is_domain_admin(&auth, &domain).await? {
+        return Err(reject!("仅域管理员可操作"));
+    }
+    let configured = [
+        body.jwks_uri.is_some(),
+        body.jwks_path.is_some(),
+        body.public_key.is_some(),
+    ];
+    if configured.iter().filter(|v| **v).count() != 1 {
+        return Err(reject!("jwks_uri, jwks_path 与 public_key 需且仅需配置一项"));
+    }
+    // the server reads the file, so a domain admin must not pick it
+    if body.jwks_path.is_some() && !auth.is_admin {
+        return Err(reject!("仅系统管理员可配置 jwks_path"));
+    }
+    body.created_by = Some(auth.id);
+    let created = body.create().await?;
+    Ok(reply!(created))
+}
+
+async fn remove_provider(Path(id): Path<String>, Extension(auth): Extension<Auth>) -> APIResult {
+    let found = IdentityProvider::find_by_id(&id)
+        .await
+        .map_err(|_| reject!(format!("身份提供方 {} 不存在", &id)))?;
+    let domain = Domain::find_by_id(&found.domain_id).await?;
+    if !is_domain_admin(&auth, &domain).await? {
+        return Err(reject!("仅域管理员可操作"));
+    }
+    IdentityProvider::delete_by_id(&id).await?;
+    Ok(reply!(found))
+}
+
+async fn link(Json(body): Json<FederatedLogin>, Extension(auth): Extension<Auth>) -> APIResult {
+    body.validate()?;
+    let provider = IdentityProvider::find_by_id(&body.provider_id)
+        .await
+        .map_err(|_| reject!(format!("身份提供方 {} 不存在", &body.provider_id)))?;
+    let claims = verify_identity(&provider, &body.token).await?;
+    if UserIdentity::find_by_subject(&provider.id, &claims.sub)
+        .await
+        .is_ok()
+    {
+        return Err(reject!("外部账号已关联其他用户"));
+    }
+    let linked = body.link(&auth.id, &claims).await?;
+    Ok(reply!(linked))
+}
+
+async fn unlink(Path(id): Path<String>, Extension(auth): Extension<Auth>) -> APIResult {
+    let found = UserIdentity::find_by_id(&id)
+        .await
+        .map_err(|_| reject!(format!("外部账号 {} 不存在", &id)))?;
+    if found.user_id != auth.id && !auth.is_admin {
+        return Err(reject!("仅管理员可访问"));
+    }
+    UserIdentity::delete_by_id(&id).await?;
+    Ok(reply!(found))
+}
+
+async fn identities_of_user(Path(id): Path<String>, Extension(auth): Extension<Auth>) -> APIResult {
+    if id != auth.id && !auth.is_admin {
+        return Err(reject!("仅管理员可访问"));
+    }
+    match User::find_by_id(&id).await {
+        Ok(_) => (),
+        Err(_) => return Err(reject!(format!("用户 {} 不存在", &id))),
+    };
+    let identities = UserIdentity::find_by_user(&id).await?;
+    Ok(reply!(identities))
+}
+
+pub fn apply_routes() -> Router<BoxRoute> {
+    let router = Router::new();
+    let restrict_layer = RequireAuthorizationLayer::custom(Restrict::new());
+    router
+        .route("/idp", post(create_provider).get(providers))
+        .route("/idp/:id", delete(remove_provider))
+        .route("/identity", post(link))
+        .route("/identity/:id", delete(unlink))
+        .route("/user/:id/identity", get(identities_of_user))
+        .layer(restrict_layer)
+        .boxed()
+}
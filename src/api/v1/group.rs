@@ -0,0 +1,202 @@
+use axum::{
+    extract::{Extension, Path, Query},
+    handler::{post, put},
+    routing::BoxRoute,
+    Json, Router,
+};
+
+use crate::{
+    repository::{
+        dao::{Domain, Group, GroupRole, Role, User, UserGroup, UserRole},
+        dto::{
+            DeleteGroup, GroupGrantRole, GroupRevokeRole, NewGroup,
+            QueryGroup, UpdateGroup, UserJoinGroup, UserLeaveGroup,
+        },
+        Dao,
+    },
+    util::{audit::Actor, jwt::Auth, restrict::Restrict, APIError, APIResult},
+};
+use tower_http::auth::RequireAuthorizationLayer;
+use validator::Validate;
+
+/// only admins and admins of the domain manage its groups
+async fn check_domain_admin(domain_id: &str, auth: &Auth) -> Result<(), APIError> {
+    let domain = match Domain::find_by_id(domain_id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("来源域 {} 不存在", domain_id))),
+    };
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+    if !auth.is_admin
+        && !user_roles
+            .into_iter()
+            .any(|v| v.role_id == domain.admin_role_id)
+    {
+        return Err(reject!(format!("仅域管理员可操作")));
+    }
+    Ok(())
+}
+
+async fn find_group(id: &str) -> Result<Group, APIError> {
+    Group::find_by_id(id)
+        .await
+        .map_err(|_| reject!(format!("用户组 {} 不存在", id)))
+}
+
+/// the users must exist
+async fn check_users(user_ids: &[String]) -> Result<(), APIError> {
+    let users = User::find_by_ids(user_ids.to_vec()).await?;
+    let found_ids: Vec<String> = users.iter().map(|v| v.id.clone()).collect();
+    if let Some(user_id) = user_ids.iter().find(|v| !found_ids.contains(v)) {
+        return Err(reject!(format!("用户 {:?} 不存在", user_id)));
+    }
+    Ok(())
+}
+
+async fn all(Query(q): Query<QueryGroup>, Extension(_): Extension<Auth>) -> APIResult {
+    q.validate()?;
+    let all = q.find_all().await?;
+    Ok(reply!(all))
+}
+
+async fn one(Path(id): Path<String>) -> APIResult {
+    let one = find_group(&id).await?;
+    Ok(reply!(one))
+}
+
+async fn create(
+    Json(body): Json<NewGroup>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    check_domain_admin(&body.domain_id, &auth).await?;
+    body.validate()?;
+    let created = body.create(&actor).await?;
+    Ok(reply!(created))
+}
+
+async fn update(
+    Path(id): Path<String>,
+    Json(body): Json<UpdateGroup>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    let found = find_group(&id).await?;
+    check_domain_admin(&found.domain_id, &auth).await?;
+    body.validate()?;
+    let updated = body.save(&found, &actor).await?;
+    Ok(reply!(updated))
+}
+
+async fn remove(
+    Path(id): Path<String>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    let found = find_group(&id).await?;
+    check_domain_admin(&found.domain_id, &auth).await?;
+    DeleteGroup { id }.save(&found, &actor).await?;
+    Ok(reply!(found))
+}
+
+async fn join(
+    Json(body): Json<UserJoinGroup>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
+    let group = find_group(&body.group_id).await?;
+    check_users(&body.user_ids).await?;
+    check_domain_admin(&group.domain_id, &auth).await?;
+    let joined = body.save(&group, &actor).await?;
+    Ok(reply!(joined))
+}
+
+async fn leave(
+    Json(body): Json<UserLeaveGroup>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
+    let group = find_group(&body.group_id).await?;
+    check_domain_admin(&group.domain_id, &auth).await?;
+    let user_groups = UserGroup::find_by_group(&body.group_id, true).await?;
+    let user_ids: Vec<String> = user_groups.into_iter().map(|v| v.user_id).collect();
+    let found = body.user_ids.iter().find(|v| !user_ids.contains(v));
+    if let Some(found) = found {
+        return Err(reject!(format!(
+            "用户 {} 未加入用户组 {}",
+            found, &body.group_id
+        )));
+    }
+    let left = body.save(&group, &actor).await?;
+    Ok(reply!(left))
+}
+
+/// the groups must exist in the role's domain and the actor must outrank the role
+async fn check_role_grant(group_ids: &[String], role: &Role, auth: &Auth) -> Result<(), APIError> {
+    let groups = Group::find_by_ids(group_ids.to_vec(), None).await?;
+    let found_ids: Vec<String> = groups.iter().map(|v| v.id.clone()).collect();
+    if let Some(group_id) = group_ids.iter().find(|v| !found_ids.contains(v)) {
+        return Err(reject!(format!("用户组 {:?} 不存在", group_id)));
+    }
+    if let Some(group) = groups.iter().find(|v| v.domain_id != role.domain_id) {
+        return Err(reject!(format!("用户组 {:?} 和角色不属于同一个域", group.id)));
+    }
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+    if !auth.is_admin && !user_roles.into_iter().any(|v| v.role_level < role.level) {
+        return Err(reject!(format!("不能操作高等级角色 {:?}", role.id)));
+    }
+    Ok(())
+}
+
+async fn grant_role(
+    Json(body): Json<GroupGrantRole>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
+    let role: Role = Role::find_by_id(&body.role_id)
+        .await
+        .map_err(|_| reject!(format!("角色 {} 不存在", &body.role_id)))?;
+    check_role_grant(&body.group_ids, &role, &auth).await?;
+    let granted = body.save(&role, &actor).await?;
+    Ok(reply!(granted))
+}
+
+async fn revoke_role(
+    Json(body): Json<GroupRevokeRole>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
+    let role: Role = Role::find_by_id(&body.role_id)
+        .await
+        .map_err(|_| reject!(format!("角色 {} 不存在", &body.role_id)))?;
+    check_role_grant(&body.group_ids, &role, &auth).await?;
+    let group_roles = GroupRole::find_by_groups(&body.group_ids, true).await?;
+    let found = body
+        .group_ids
+        .iter()
+        .find(|v| !group_roles.iter().any(|r| &r.group_id == *v && r.role_id == body.role_id));
+    if let Some(found) = found {
+        return Err(reject!(format!(
+            "用户组 {} 未赋予角色 {}",
+            found, &body.role_id
+        )));
+    }
+    let revoked = body.save(&role, &actor).await?;
+    Ok(reply!(revoked))
+}
+
+pub fn apply_routes() -> Router<BoxRoute> {
+    let router = Router::new();
+    let restrict_layer = RequireAuthorizationLayer::custom(Restrict::new());
+    router.route("/group", post(create).get(all))
+        .route("/group/:id", put(update).get(one).delete(remove))
+        .route("/join/group", post(join))
+        .route("/leave/group", post(leave))
+        .route("/grant/role/group", post(grant_role))
+        .route("/revoke/role/group", post(revoke_role))
+        .layer(restrict_layer)
+        .boxed()
+}
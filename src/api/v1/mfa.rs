@@ -0,0 +1,106 @@
+use axum::{
+    extract::{Extension, Path},
+    handler::{delete, get, post},
+    routing::BoxRoute,
+    Json, Router,
+};
+use tower_http::auth::RequireAuthorizationLayer;
+use validator::Validate;
+
+use crate::{
+    repository::{
+        dao::{RecoveryCode, User, UserTotp},
+        dto::{EnrollTotp, ResetMfa, VerifyTotp},
+        Dao,
+    },
+    util::{jwt::Auth, restrict::Restrict, APIResult},
+};
+
+async fn status(Extension(auth): Extension<Auth>) -> APIResult {
+    let enabled = UserTotp::is_enabled_for(&auth.id).await?;
+    let recovery_codes = RecoveryCode::count_unused(&auth.id).await?;
+    Ok(reply!({"totp_enabled": enabled, "recovery_codes_left": recovery_codes}))
+}
+
+async fn enroll(Extension(auth): Extension<Auth>) -> APIResult {
+    let user = match User::find_by_id(&auth.id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!("用户不存在")),
+    };
+    if UserTotp::is_enabled_for(&user.id).await? {
+        return Err(reject!("两步验证已启用"));
+    }
+    let (totp, uri) = EnrollTotp { user_id: user.id.clone() }.create(&user).await?;
+    Ok(reply!({"secret": totp.secret, "provisioning_uri": uri}))
+}
+
+async fn activate(Json(body): Json<VerifyTotp>, Extension(auth): Extension<Auth>) -> APIResult {
+    body.validate()?;
+    let totp = match UserTotp::find_by_user(&auth.id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!("请先绑定验证器")),
+    };
+    if totp.is_enabled == 1 {
+        return Err(reject!("两步验证已启用"));
+    }
+    if !body.verify(&totp).await? {
+        return Err(reject!("验证码不正确"));
+    }
+    let recovery_codes = body.activate(&totp).await?;
+    Ok(reply!({"recovery_codes": recovery_codes}))
+}
+
+async fn disable(Json(body): Json<VerifyTotp>, Extension(auth): Extension<Auth>) -> APIResult {
+    body.validate()?;
+    let totp = match UserTotp::find_by_user(&auth.id).await {
+        Ok(val) if val.is_enabled == 1 => val,
+        _ => return Err(reject!("未启用两步验证")),
+    };
+    if !body.verify(&totp).await? {
+        return Err(reject!("验证码不正确"));
+    }
+    let removed = ResetMfa { user_id: auth.id }.save().await?;
+    Ok(reply!(removed))
+}
+
+async fn regenerate_recovery_codes(
+    Json(body): Json<VerifyTotp>,
+    Extension(auth): Extension<Auth>,
+) -> APIResult {
+    body.validate()?;
+    let totp = match UserTotp::find_by_user(&auth.id).await {
+        Ok(val) if val.is_enabled == 1 => val,
+        _ => return Err(reject!("未启用两步验证")),
+    };
+    if !body.verify(&totp).await? {
+        return Err(reject!("验证码不正确"));
+    }
+    let recovery_codes = body.regenerate_recovery_codes(&auth.id).await?;
+    Ok(reply!({"recovery_codes": recovery_codes}))
+}
+
+async fn reset(Path(id): Path<String>, Extension(auth): Extension<Auth>) -> APIResult {
+    if !auth.is_admin {
+        return Err(reject!("仅管理员可访问"));
+    }
+    let user = match User::find_by_id(&id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("用户 {} 不存在", &id))),
+    };
+    let removed = ResetMfa { user_id: user.id }.save().await?;
+    Ok(reply!(removed))
+}
+
+pub fn apply_routes() -> Router<BoxRoute> {
+    let router = Router::new();
+    let restrict_layer = RequireAuthorizationLayer::custom(Restrict::new());
+    router
+        .route("/mfa", get(status))
+        .route("/mfa/totp", post(enroll))
+        .route("/mfa/totp/activate", post(activate))
+        .route("/mfa/totp/disable", post(disable))
+        .route("/mfa/recovery-codes", post(regenerate_recovery_codes))
+        .route("/user/:id/mfa", delete(reset))
+        .layer(restrict_layer)
+        .boxed()
+}
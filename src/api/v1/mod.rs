@@ -3,21 +3,37 @@ use axum::{routing::BoxRoute, Router};
 use tower::layer::layer_fn;
 
 mod auth;
+mod oauth;
+mod federation;
+mod mfa;
 mod domain;
 mod org;
+mod group;
 mod perm;
 mod rbac;
+mod resource;
+mod relation;
 mod role;
+mod exclusion;
 mod user;
+mod audit;
 
 pub fn apply_routes() -> Router<BoxRoute> {
     auth::apply_routes()
         .or(user::apply_routes())
         .or(domain::apply_routes())
         .or(org::apply_routes())
+        .or(group::apply_routes())
         .or(role::apply_routes())
+        .or(exclusion::apply_routes())
         .or(perm::apply_routes())
         .or(rbac::apply_routes())
+        .or(resource::apply_routes())
+        .or(relation::apply_routes())
+        .or(oauth::apply_routes())
+        .or(federation::apply_routes())
+        .or(mfa::apply_routes())
+        .or(audit::apply_routes())
         .layer(layer_fn(|inner| Cors { inner }))
         .boxed()
 }
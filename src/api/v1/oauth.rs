@@ -0,0 +1,330 @@
+use axum::{
+    extract::{Extension, Form, Path, Query},
+    handler::{get, post, put},
+    http::{header::AUTHORIZATION, HeaderMap},
+    routing::BoxRoute,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use tower_http::auth::RequireAuthorizationLayer;
+use url::Url;
+use validator::Validate;
+
+use crate::{
+    repository::{
+        dao::{Domain, OAuthClient, OAuthCode, Role, User, UserRole},
+        dto::{Authorize, NewClient, RemoveClient, TokenRequest, UpdateClient},
+        vo, Dao,
+    },
+    util::{
+        constant_time_eq, hash_token,
+        jwt::{self, Auth, TokenType},
+        now,
+        restrict::Restrict,
+        revocation,
+        serde_format::utc_datetime_millis,
+        uuid_v4, APIError, APIResult,
+    },
+};
+
+#[derive(Debug, Deserialize)]
+struct QueryClient {
+    domain_id: String,
+}
+
+async fn is_domain_admin(auth: &Auth, domain: &Domain) -> Result<bool, APIError> {
+    if auth.is_admin {
+        return Ok(true);
+    }
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+    Ok(user_roles
+        .into_iter()
+        .any(|v| v.role_id == domain.admin_role_id))
+}
+
+fn oauth_error(error: &str, description: &str) -> APIError {
+    APIError::OAuth(error.to_string(), description.to_string())
+}
+
+async fn clients(Query(q): Query<QueryClient>, Extension(auth): Extension<Auth>) -> APIResult {
+    let domain = match Domain::find_by_id(&q.domain_id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("来源域 {} 不存在", &q.domain_id))),
+    };
+    if !is_domain_admin(&auth, &domain).await? {
+        return Err(reject!("仅域管理员可操作"));
+    }
+    let clients: Vec<vo::OAuthClient> = OAuthClient::find_by_domain(&domain.id)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    Ok(reply!(clients))
+}
+
+async fn create_client(Json(mut body): Json<NewClient>, Extension(auth): Extension<Auth>) -> APIResult {
+    body.validate()?;
+    let domain = match Domain::find_by_id(&body.domain_id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("来源域 {} 不存在", &body.domain_id))),
+    };
+    if !is_domain_admin(&auth, &domain).await? {
+        return Err(reject!("仅域管理员可操作"));
+    }
+    if body.is_public.unwrap_or(false) && body.grant_types.iter().any(|v| v == "client_credentials") {
+        return Err(reject!("公开客户端不支持 client_credentials"));
+    }
+    body.created_by = Some(auth.id);
+    let created = body.create().await?;
+    Ok(reply!(created))
+}
+
+async fn update_client(
+    Path(id): Path<String>,
+    Json(mut body): Json<UpdateClient>,
+    Extension(auth): Extension<Auth>,
+) -> APIResult {
+    let found = OAuthClient::find_by_id(&id)
+        .await
+        .map_err(|_| reject!(format!("客户端 {} 不存在", &id)))?;
+    let domain = Domain::find_by_id(&found.domain_id).await?;
+    if !is_domain_admin(&auth, &domain).await? {
+        return Err(reject!("仅域管理员可操作"));
+    }
+    body.validate()?;
+    if let Some(grant_types) = &body.grant_types {
+        if found.is_public() && grant_types.iter().any(|v| v == "client_credentials") {
+            return Err(reject!("公开客户端不支持 client_credentials"));
+        }
+    }
+    body.updated_by = Some(auth.id);
+    let updated: vo::OAuthClient = body.save(&id).await?.into();
+    Ok(reply!(updated))
+}
+
+async fn remove_client(Path(id): Path<String>, Extension(auth): Extension<Auth>) -> APIResult {
+    let found = OAuthClient::find_by_id(&id)
+        .await
+        .map_err(|_| reject!(format!("客户端 {} 不存在", &id)))?;
+    let domain = Domain::find_by_id(&found.domain_id).await?;
+    if !is_domain_admin(&auth, &domain).await? {
+        return Err(reject!("仅域管理员可操作"));
+    }
+    let removed: vo::OAuthClient = RemoveClient { removed_by: auth.id }.save(found).await?.into();
+    Ok(reply!(removed))
+}
+
+async fn authorize(Json(body): Json<Authorize>, Extension(auth): Extension<Auth>) -> APIResult {
+    body.validate()?;
+    if body.response_type != "code" {
+        return Err(reject!("仅支持 response_type=code"));
+    }
+    let client = OAuthClient::find_by_id(&body.client_id)
+        .await
+        .map_err(|_| reject!(format!("客户端 {} 不存在", &body.client_id)))?;
+    if !client.has_grant_type("authorization_code") {
+        return Err(reject!("客户端不支持授权码模式"));
+    }
+    if !client.has_redirect_uri(&body.redirect_uri) {
+        return Err(reject!("回调地址不匹配"));
+    }
+    match body.code_challenge_method.as_deref() {
+        // plain PKCE protects nothing once the challenge leaks
+        None | Some("S256") => (),
+        _ => return Err(reject!("code_challenge_method 不合法")),
+    };
+    if client.is_public() && body.code_challenge.is_none() {
+        return Err(reject!("公开客户端必须使用 PKCE"));
+    }
+    let scope = client
+        .allowed_scope(body.scope.as_deref().unwrap_or("openid"))
+        .ok_or_else(|| reject!("授权范围不合法"))?;
+    let mut redirect_uri = Url::parse(&body.redirect_uri).map_err(|_| reject!("回调地址不匹配"))?;
+    let code = body.create(&auth.id, scope).await?;
+    redirect_uri.query_pairs_mut().append_pair("code", &code);
+    if let Some(state) = &body.state {
+        redirect_uri.query_pairs_mut().append_pair("state", state);
+    }
+    Ok(reply!({
+      "redirect_uri": redirect_uri.to_string(), "code": code, "state": body.state
+    }))
+}
+
+/// client credentials from `Authorization: Basic`, falling back to the form fields
+fn client_auth(body: &TokenRequest, headers: &HeaderMap) -> (Option<String>, Option<String>) {
+    let basic = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Basic "))
+        .and_then(|v| base64::decode(v).ok())
+        .and_then(|v| String::from_utf8(v).ok());
+    if let Some((id, secret)) = basic.as_deref().and_then(|v| v.split_once(':')) {
+        return (Some(id.to_string()), Some(secret.to_string()));
+    }
+    (body.client_id.clone(), body.client_secret.clone())
+}
+
+async fn exchange_code(client: OAuthClient, body: TokenRequest) -> APIResult {
+    let code = match &body.code {
+        Some(code) => OAuthCode::find_by_code(code).await.ok(),
+        None => None,
+    }
+    .filter(|v| v.client_id == client.id)
+    .ok_or_else(|| oauth_error("invalid_grant", "授权码无效"))?;
+    if code.is_used == 1 || code.expire <= now() {
+        return Err(oauth_error("invalid_grant", "授权码已失效"));
+    }
+    if body.redirect_uri.as_deref() != Some(code.redirect_uri.as_str()) {
+        return Err(oauth_error("invalid_grant", "回调地址不匹配"));
+    }
+    if !body.is_verifier_matched(&code) {
+        return Err(oauth_error("invalid_grant", "code_verifier 不正确"));
+    }
+    if !body.redeem(&code).await? {
+        return Err(oauth_error("invalid_grant", "授权码已失效"));
+    }
+    let user = User::find_by_id(&code.user_id)
+        .await
+        .map_err(|_| oauth_error("invalid_grant", "用户不存在"))?;
+    if user.is_actived == 0 {
+        return Err(oauth_error("invalid_grant", "用户被禁用"));
+    }
+    let expires_in = jwt::access_token_ttl().num_seconds();
+    let issued_at = Utc::now();
+    let iat = issued_at.timestamp();
+    // bound to the client and the granted scope, only good for `/userinfo`
+    let claims = json!({
+      "sub": user.id, "client_id": client.id, "scope": code.scope,
+      "iat": issued_at.timestamp_millis() as f64 / 1000.0, "exp": iat + expires_in, "jti": uuid_v4()
+    });
+    let access_token = jwt::sign(TokenType::OAuth, &client.id, claims);
+    let mut reply = json!({
+      "access_token": access_token, "token_type": "Bearer", "expires_in": expires_in, "scope": code.scope
+    });
+    let scopes: Vec<&str> = code.scope.split(' ').collect();
+    if scopes.contains(&"openid") {
+        let roles: Vec<String> = Role::find_by_user(&user.id, &client.domain_id)
+            .await?
+            .into_iter()
+            .map(|v| v.value)
+            .collect();
+        let mut claims = json!({
+          "sub": user.id, "iat": iat, "exp": iat + expires_in, "roles": roles
+        });
+        if let Some(nonce) = &code.nonce {
+            claims["nonce"] = json!(nonce);
+        }
+        if scopes.contains(&"profile") {
+            claims["preferred_username"] = json!(user.username);
+            claims["picture"] = json!(user.avatar);
+        }
+        if scopes.contains(&"email") {
+            claims["email"] = json!(user.email);
+        }
+        reply["id_token"] = json!(jwt::sign(TokenType::Id, &client.id, claims));
+    }
+    Ok(Json(reply))
+}
+
+fn issue_client_token(client: OAuthClient, body: TokenRequest) -> APIResult {
+    let scope = client
+        .allowed_scope(body.scope.as_deref().unwrap_or(""))
+        .ok_or_else(|| oauth_error("invalid_scope", "授权范围不合法"))?;
+    let expires_in = jwt::access_token_ttl().num_seconds();
+    let iat = Utc::now().timestamp();
+    let claims = json!({
+      "sub": client.id, "client_id": client.id, "domain_id": client.domain_id,
+      "scope": scope, "iat": iat, "exp": iat + expires_in, "jti": uuid_v4()
+    });
+    Ok(Json(json!({
+      "access_token": jwt::sign(TokenType::Client, &client.id, claims), "token_type": "Bearer", "expires_in": expires_in, "scope": scope
+    })))
+}
+
+async fn token(Form(body): Form<TokenRequest>, headers: HeaderMap) -> APIResult {
+    let (client_id, client_secret) = client_auth(&body, &headers);
+    let client_id = client_id.ok_or_else(|| oauth_error("invalid_client", "缺少 client_id"))?;
+    let client = OAuthClient::find_by_id(&client_id)
+        .await
+        .map_err(|_| oauth_error("invalid_client", "客户端不存在"))?;
+    if let Some(secret_hash) = &client.secret_hash {
+        match &client_secret {
+            Some(secret) if constant_time_eq(&hash_token(secret), secret_hash) => (),
+            _ => return Err(oauth_error("invalid_client", "客户端密钥不正确")),
+        };
+    }
+    if !client.has_grant_type(&body.grant_type) {
+        return Err(oauth_error("unauthorized_client", "客户端不支持该授权类型"));
+    }
+    match body.grant_type.as_str() {
+        "authorization_code" => exchange_code(client, body).await,
+        "client_credentials" if !client.is_public() => issue_client_token(client, body),
+        "client_credentials" => Err(oauth_error("invalid_client", "公开客户端不支持 client_credentials")),
+        _ => Err(oauth_error("unsupported_grant_type", "不支持的授权类型")),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct UserinfoClaims {
+    sub: String,
+    aud: String,
+    scope: String,
+    #[serde(with = "utc_datetime_millis")]
+    iat: DateTime<Utc>,
+    jti: String,
+}
+
+/// takes the access token of `exchange_code`, the claims returned follow its scope
+async fn userinfo(headers: HeaderMap) -> APIResult {
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| oauth_error("invalid_token", "缺少访问令牌"))?;
+    let claims = jwt::verify::<UserinfoClaims>(token, TokenType::OAuth, None)
+        .map_err(|_| oauth_error("invalid_token", "访问令牌无效"))?
+        .claims;
+    let issued_at = claims.iat.timestamp_millis();
+    if revocation::is_revoked(&claims.jti, &claims.sub, issued_at) {
+        return Err(oauth_error("invalid_token", "访问令牌已失效"));
+    }
+    // the client it was issued for may have been removed since
+    OAuthClient::find_by_id(&claims.aud)
+        .await
+        .map_err(|_| oauth_error("invalid_token", "客户端不存在"))?;
+    let scopes: Vec<&str> = claims.scope.split(' ').collect();
+    if !scopes.contains(&"openid") {
+        return Err(oauth_error("insufficient_scope", "需要 openid 授权范围"));
+    }
+    let user = User::find_by_id(&claims.sub)
+        .await
+        .map_err(|_| oauth_error("invalid_token", "用户不存在"))?;
+    if user.is_actived == 0 {
+        return Err(oauth_error("invalid_token", "用户被禁用"));
+    }
+    let mut reply = json!({ "sub": user.id });
+    if scopes.contains(&"profile") {
+        reply["preferred_username"] = json!(user.username);
+        reply["picture"] = json!(user.avatar);
+    }
+    if scopes.contains(&"email") {
+        reply["email"] = json!(user.email);
+    }
+    Ok(Json(reply))
+}
+
+pub fn apply_routes() -> Router<BoxRoute> {
+    let router = Router::new();
+    let restrict_layer = RequireAuthorizationLayer::custom(Restrict::new());
+    router
+        .route("/oauth/client", post(create_client).get(clients))
+        .route("/oauth/client/:id", put(update_client).delete(remove_client))
+        .route("/oauth/authorize", post(authorize))
+        .layer(restrict_layer)
+        .or(Router::new()
+            .route("/oauth/token", post(token))
+            .route("/userinfo", get(userinfo)))
+        .boxed()
+}
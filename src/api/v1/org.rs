@@ -1,6 +1,6 @@
 use axum::{
     extract::{Extension, Path, Query},
-    handler::{post, put},
+    handler::{get, post, put},
     routing::BoxRoute,
     Json, Router,
 };
@@ -8,10 +8,10 @@ use axum::{
 use crate::{
     repository::{
         dao::{Domain, Org, User, UserOrg, UserRole},
-        dto::{NewOrg, QueryOrg, UpdateOrg, UserJoinOrg, UserLeaveOrg},
-        Dao,
+        dto::{MoveOrg, NewOrg, QueryOrg, UpdateOrg, UserJoinOrg, UserLeaveOrg},
+        vo, Dao,
     },
-    util::{jwt::Auth, restrict::Restrict, APIResult},
+    util::{audit::Actor, jwt::Auth, restrict::Restrict, APIError, APIResult},
 };
 use tower_http::auth::RequireAuthorizationLayer;
 use validator::Validate;
@@ -27,12 +27,16 @@ async fn one(Path(id): Path<String>) -> APIResult {
     Ok(reply!(one))
 }
 
-async fn create(Json(mut body): Json<NewOrg>, Extension(auth): Extension<Auth>) -> APIResult {
+async fn create(
+    Json(mut body): Json<NewOrg>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
     let domain = match Domain::find_by_id(&body.domain_id).await {
         Ok(val) => val,
         Err(_) => return Err(reject!(format!("来源域 {} 不存在", &body.domain_id))),
     };
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     if !auth.is_admin
         && !user_roles
             .into_iter()
@@ -41,8 +45,11 @@ async fn create(Json(mut body): Json<NewOrg>, Extension(auth): Extension<Auth>)
         return Err(reject!(format!("仅域管理员可操作")));
     }
     body.validate()?;
+    if let Some(parent_id) = &body.parent_id {
+        find_parent(parent_id, &body.domain_id).await?;
+    }
     body.created_by = Some(auth.id);
-    let created = body.create().await?;
+    let created = body.create(&actor).await?;
     Ok(reply!(created))
 }
 
@@ -50,11 +57,12 @@ async fn update(
     Path(id): Path<String>,
     Json(body): Json<UpdateOrg>,
     Extension(auth): Extension<Auth>,
+    actor: Actor,
 ) -> APIResult {
     let found = Org::find_by_id(&id)
         .await
         .map_err(|_| reject!(format!("组织 {} 不存在", &id)))?;
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     let domain = Domain::find_by_id(&found.domain_id).await?;
     if !auth.is_admin
         && !user_roles
@@ -64,21 +72,78 @@ async fn update(
         return Err(reject!(format!("仅域管理员可操作")));
     }
     body.validate()?;
-    let updated = body.save(&id).await?;
+    let updated = body.save(&id, &actor).await?;
     Ok(reply!(updated))
 }
 
-async fn join(Json(body): Json<UserJoinOrg>, Extension(auth): Extension<Auth>) -> APIResult {
+async fn subtree(Path(id): Path<String>, Extension(_): Extension<Auth>) -> APIResult {
+    let found = Org::find_by_id(&id)
+        .await
+        .map_err(|_| reject!(format!("组织 {} 不存在", &id)))?;
+    let all = found.find_subtree().await?;
+    let tree = vo::OrgNode::build(found, &all);
+    Ok(reply!(tree))
+}
+
+async fn ancestors(Path(id): Path<String>, Extension(_): Extension<Auth>) -> APIResult {
+    let found = Org::find_by_id(&id)
+        .await
+        .map_err(|_| reject!(format!("组织 {} 不存在", &id)))?;
+    let all = found.find_ancestors().await?;
+    Ok(reply!(all))
+}
+
+async fn move_to(
+    Path(id): Path<String>,
+    Json(body): Json<MoveOrg>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    let found = Org::find_by_id(&id)
+        .await
+        .map_err(|_| reject!(format!("组织 {} 不存在", &id)))?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+    let domain = Domain::find_by_id(&found.domain_id).await?;
+    if !auth.is_admin
+        && !user_roles
+            .into_iter()
+            .any(|v| v.role_id == domain.admin_role_id)
+    {
+        return Err(reject!(format!("仅域管理员可操作")));
+    }
+    if let Some(parent_id) = &body.parent_id {
+        find_parent(parent_id, &found.domain_id).await?;
+    }
+    let moved = body.save(&found, &actor).await?;
+    Ok(reply!(moved))
+}
+
+/// the parent of a new or moved org must exist in the same domain
+async fn find_parent(parent_id: &str, domain_id: &str) -> Result<Org, APIError> {
+    let parent = Org::find_by_id(parent_id)
+        .await
+        .map_err(|_| reject!(format!("上级组织 {} 不存在", parent_id)))?;
+    if parent.domain_id != domain_id {
+        return Err(reject!(format!("上级组织 {} 和组织不属于同一个域", parent_id)));
+    }
+    Ok(parent)
+}
+
+async fn join(
+    Json(body): Json<UserJoinOrg>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
     let org: Org = Org::find_by_id(&body.org_id)
         .await
         .map_err(|_| reject!(format!("组织 {} 不存在", &body.org_id)))?;
     let users = User::find_by_ids(body.user_ids.clone()).await?;
     let user_ids: Vec<String> = users.iter().map(|v| v.id.clone()).collect();
-    let found = body.user_ids.iter().find(|v| !user_ids.contains(&v));
+    let found = body.user_ids.iter().find(|v| !user_ids.contains(v));
     if let Some(user_id) = found {
         return Err(reject!(format!("用户 {:?} 不存在", user_id)));
     }
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     let domain = Domain::find_by_id(&org.domain_id).await?;
     if !auth.is_admin
         && !user_roles
@@ -87,30 +152,34 @@ async fn join(Json(body): Json<UserJoinOrg>, Extension(auth): Extension<Auth>) -
     {
         return Err(reject!(format!("仅域管理员可操作")));
     }
-    let user_orgs = UserOrg::find_by_org(&body.org_id).await?;
+    let user_orgs = UserOrg::find_by_org(&body.org_id, false).await?;
     let user_ids: Vec<String> = user_orgs.into_iter().map(|v| v.user_id.clone()).collect();
-    let found = body.user_ids.iter().find(|v| user_ids.contains(&v));
+    let found = body.user_ids.iter().find(|v| user_ids.contains(v));
     if let Some(found) = found {
         return Err(reject!(format!(
             "用户 {} 已参与组织 {}",
             found, &body.org_id
         )));
     }
-    let joined = body.save().await?;
+    let joined = body.save(&org, &actor).await?;
     Ok(reply!(joined))
 }
 
-async fn leave(Json(body): Json<UserLeaveOrg>, Extension(auth): Extension<Auth>) -> APIResult {
+async fn leave(
+    Json(body): Json<UserLeaveOrg>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
     let org: Org = Org::find_by_id(&body.org_id)
         .await
         .map_err(|_| reject!(format!("组织 {} 不存在", &body.org_id)))?;
     let users = User::find_by_ids(body.user_ids.clone()).await?;
     let user_ids: Vec<String> = users.iter().map(|v| v.id.clone()).collect();
-    let found = body.user_ids.iter().find(|v| !user_ids.contains(&v));
+    let found = body.user_ids.iter().find(|v| !user_ids.contains(v));
     if let Some(user_id) = found {
         return Err(reject!(format!("用户 {:?} 不存在", user_id)));
     }
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     let domain = Domain::find_by_id(&org.domain_id).await?;
     if !auth.is_admin
         && !user_roles
@@ -119,16 +188,16 @@ async fn leave(Json(body): Json<UserLeaveOrg>, Extension(auth): Extension<Auth>)
     {
         return Err(reject!(format!("仅域管理员可操作")));
     }
-    let user_orgs = UserOrg::find_by_org(&body.org_id).await?;
+    let user_orgs = UserOrg::find_by_org(&body.org_id, true).await?;
     let user_ids: Vec<String> = user_orgs.into_iter().map(|v| v.user_id.clone()).collect();
-    let found = body.user_ids.iter().find(|v| !user_ids.contains(&v));
+    let found = body.user_ids.iter().find(|v| !user_ids.contains(v));
     if let Some(found) = found {
         return Err(reject!(format!(
             "用户 {} 未参与组织 {}",
             found, &body.org_id
         )));
     }
-    let left = body.save().await?;
+    let left = body.save(&org, &actor).await?;
     Ok(reply!(left))
 }
 
@@ -137,6 +206,9 @@ pub fn apply_routes() -> Router<BoxRoute> {
     let restrict_layer = RequireAuthorizationLayer::custom(Restrict::new());
     router.route("/org", post(create).get(all))
         .route("/org/:id", put(update).get(one))
+        .route("/org/:id/subtree", get(subtree))
+        .route("/org/:id/ancestors", get(ancestors))
+        .route("/org/:id/move", post(move_to))
         .route("/join/org", post(join))
         .route("/leave/org", post(leave))
         .layer(restrict_layer)
@@ -10,12 +10,12 @@ use crate::{
     repository::{
         dao::{Domain, Perm, Role, RolePerm, UserRole},
         dto::{
-            BatchInsertPerm, NewPerm, QueryPerm, RoleChangePerm, RoleGrantPerm, RoleRevokePerm,
-            UpdatePerm,
+            BatchInsertPerm, DeletePerm, NewPerm, QueryPerm, RoleChangePerm, RoleGrantPerm,
+            RoleRevokePerm, UpdatePerm, UpdateRolePerm,
         },
         Dao,
     },
-    util::{jwt::Auth, now, restrict::Restrict, uuid_v4, APIResult},
+    util::{audit::Actor, jwt::Auth, restrict::Restrict, APIResult},
 };
 use validator::Validate;
 
@@ -30,7 +30,11 @@ async fn one(Path(id): Path<String>) -> APIResult {
     Ok(reply!(one))
 }
 
-async fn create(Json(mut body): Json<NewPerm>, Extension(auth): Extension<Auth>) -> APIResult {
+async fn create(
+    Json(mut body): Json<NewPerm>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
     let domain = match Domain::find_by_id(&body.domain_id).await {
         Ok(val) => val,
         Err(_) => {
@@ -40,7 +44,7 @@ async fn create(Json(mut body): Json<NewPerm>, Extension(auth): Extension<Auth>)
             )))
         }
     };
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     if !auth.is_admin
         && !user_roles
             .into_iter()
@@ -50,7 +54,7 @@ async fn create(Json(mut body): Json<NewPerm>, Extension(auth): Extension<Auth>)
     }
     body.validate()?;
     body.created_by = Some(auth.id);
-    let created = body.create().await?;
+    let created = body.create(&actor).await?;
     Ok(reply!(created))
 }
 
@@ -58,11 +62,12 @@ async fn update(
     Path(id): Path<String>,
     Json(mut body): Json<UpdatePerm>,
     Extension(auth): Extension<Auth>,
+    actor: Actor,
 ) -> APIResult {
     let found = Perm::find_by_id(&id)
         .await
         .map_err(|_| reject!(format!("权限 {} 不存在", &id)))?;
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     let domain = Domain::find_by_id(&found.domain_id).await?;
     if !auth.is_admin
         && !user_roles
@@ -73,15 +78,19 @@ async fn update(
     }
     body.validate()?;
     body.updated_by = Some(auth.id);
-    let updated = body.save(&id).await?;
+    let updated = body.save(&id, &actor).await?;
     Ok(reply!(updated))
 }
 
-async fn remove(Path(id): Path<String>, Extension(auth): Extension<Auth>) -> APIResult {
+async fn remove(
+    Path(id): Path<String>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
     let found = Perm::find_by_id(&id)
         .await
         .map_err(|_| reject!(format!("权限 {} 不存在", &id)))?;
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     let domain = Domain::find_by_id(&found.domain_id).await?;
     if !auth.is_admin
         && !user_roles
@@ -90,17 +99,22 @@ async fn remove(Path(id): Path<String>, Extension(auth): Extension<Auth>) -> API
     {
         return Err(reject!(format!("仅域管理员可操作")));
     }
-    Perm::delete_by_id(&id).await?;
+    DeletePerm { id }.save(&found, &actor).await?;
     Ok(reply!(found))
 }
 
-async fn grant(Json(body): Json<RoleGrantPerm>, Extension(auth): Extension<Auth>) -> APIResult {
+async fn grant(
+    Json(body): Json<RoleGrantPerm>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
     let role: Role = Role::find_by_id(&body.role_id)
         .await
         .map_err(|_| reject!(format!("角色 {} 不存在", &body.role_id)))?;
     let perms = Perm::find_by_ids(body.perm_ids.clone(), None).await?;
     let perm_ids: Vec<String> = perms.iter().map(|v| v.id.clone()).collect();
-    let found = body.perm_ids.iter().find(|v| !perm_ids.contains(&v));
+    let found = body.perm_ids.iter().find(|v| !perm_ids.contains(v));
     if let Some(perm_id) = found {
         return Err(reject!(format!("权限 {:?} 不存在", perm_id)));
     }
@@ -108,7 +122,7 @@ async fn grant(Json(body): Json<RoleGrantPerm>, Extension(auth): Extension<Auth>
     if let Some(perm) = found {
         return Err(reject!(format!("权限 {:?} 和角色不属于同一个域", perm.id)));
     }
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     let domain = Domain::find_by_id(&role.domain_id).await?;
     if !auth.is_admin
         && !user_roles
@@ -119,24 +133,28 @@ async fn grant(Json(body): Json<RoleGrantPerm>, Extension(auth): Extension<Auth>
     }
     let role_perms = RolePerm::find_by_role(&body.role_id).await?;
     let perm_ids: Vec<String> = role_perms.into_iter().map(|v| v.perm_id.clone()).collect();
-    let found = body.perm_ids.iter().find(|v| perm_ids.contains(&v));
+    let found = body.perm_ids.iter().find(|v| perm_ids.contains(v));
     if let Some(found) = found {
         return Err(reject!(format!(
             "角色 {} 已赋予权限 {}",
             &body.role_id, found
         )));
     }
-    let granted = body.save().await?;
+    let granted = body.save(&role, &actor).await?;
     Ok(reply!(granted))
 }
 
-async fn revoke(Json(body): Json<RoleRevokePerm>, Extension(auth): Extension<Auth>) -> APIResult {
+async fn revoke(
+    Json(body): Json<RoleRevokePerm>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
     let role: Role = Role::find_by_id(&body.role_id)
         .await
         .map_err(|_| reject!(format!("角色 {} 不存在", &body.role_id)))?;
     let perms = Perm::find_by_ids(body.perm_ids.clone(), None).await?;
     let perm_ids: Vec<String> = perms.iter().map(|v| v.id.clone()).collect();
-    let found = body.perm_ids.iter().find(|v| !perm_ids.contains(&v));
+    let found = body.perm_ids.iter().find(|v| !perm_ids.contains(v));
     if let Some(perm_id) = found {
         return Err(reject!(format!("权限 {:?} 不存在", perm_id)));
     }
@@ -144,7 +162,7 @@ async fn revoke(Json(body): Json<RoleRevokePerm>, Extension(auth): Extension<Aut
     if let Some(perm) = found {
         return Err(reject!(format!("权限 {:?} 和角色不属于同一个域", perm.id)));
     }
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     let domain = Domain::find_by_id(&role.domain_id).await?;
     if !auth.is_admin
         && !user_roles
@@ -155,24 +173,29 @@ async fn revoke(Json(body): Json<RoleRevokePerm>, Extension(auth): Extension<Aut
     }
     let role_perms = RolePerm::find_by_role(&body.role_id).await?;
     let perm_ids: Vec<String> = role_perms.into_iter().map(|v| v.perm_id.clone()).collect();
-    let found = body.perm_ids.iter().find(|v| !perm_ids.contains(&v));
+    let found = body.perm_ids.iter().find(|v| !perm_ids.contains(v));
     if let Some(found) = found {
         return Err(reject!(format!(
             "角色 {} 未赋予权限 {}",
             &body.role_id, found
         )));
     }
-    let revoked = body.save().await?;
+    let revoked = body.save(&role, &actor).await?;
     Ok(reply!(revoked))
 }
 
-async fn change(Json(body): Json<RoleChangePerm>, Extension(auth): Extension<Auth>) -> APIResult {
+async fn change(
+    Json(body): Json<RoleChangePerm>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
     let role: Role = Role::find_by_id(&body.role_id)
         .await
         .map_err(|_| reject!(format!("角色 {} 不存在", &body.role_id)))?;
     let perms = Perm::find_by_ids(body.perm_ids.clone(), None).await?;
     let perm_ids: Vec<String> = perms.iter().map(|v| v.id.clone()).collect();
-    let found = body.perm_ids.iter().find(|v| !perm_ids.contains(&v));
+    let found = body.perm_ids.iter().find(|v| !perm_ids.contains(v));
     if let Some(perm_id) = found {
         return Err(reject!(format!("权限 {:?} 不存在", perm_id)));
     }
@@ -180,7 +203,7 @@ async fn change(Json(body): Json<RoleChangePerm>, Extension(auth): Extension<Aut
     if let Some(perm) = found {
         return Err(reject!(format!("权限 {:?} 和角色不属于同一个域", perm.id)));
     }
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     let domain = Domain::find_by_id(&role.domain_id).await?;
     if !auth.is_admin
         && !user_roles
@@ -189,15 +212,42 @@ async fn change(Json(body): Json<RoleChangePerm>, Extension(auth): Extension<Aut
     {
         return Err(reject!(format!("仅域管理员可操作")));
     }
-    let role_perms = body.save().await?;
+    let role_perms = body.save(&role, &actor).await?;
     Ok(reply!(role_perms))
 }
 
+async fn update_grant(
+    Path((id, perm_id)): Path<(String, String)>,
+    Json(body): Json<UpdateRolePerm>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
+    let role: Role = Role::find_by_id(&id)
+        .await
+        .map_err(|_| reject!(format!("角色 {} 不存在", &id)))?;
+    let found = RolePerm::find_by_id(&id, &perm_id)
+        .await
+        .map_err(|_| reject!(format!("角色 {} 未赋予权限 {}", &id, &perm_id)))?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+    let domain = Domain::find_by_id(&role.domain_id).await?;
+    if !auth.is_admin
+        && !user_roles
+            .into_iter()
+            .any(|v| v.role_id == domain.admin_role_id)
+    {
+        return Err(reject!(format!("仅域管理员可操作")));
+    }
+    let updated = body.save(&found, &role, &actor).await?;
+    Ok(reply!(updated))
+}
+
 async fn create_all(
     Json(body): Json<BatchInsertPerm>,
     Extension(auth): Extension<Auth>,
+    actor: Actor,
 ) -> APIResult {
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     let domain = Domain::find_by_id(&body.domain_id).await?;
     if !auth.is_admin
         && !user_roles
@@ -206,26 +256,8 @@ async fn create_all(
     {
         return Err(reject!(format!("仅域管理员可操作")));
     }
-    let perms: Vec<Perm> = body
-        .perms
-        .iter()
-        .map(|v| {
-            let id = uuid_v4();
-            return Perm {
-                id,
-                name: v.name.clone(),
-                description: Some(v.name.clone()),
-                value: v.value.clone(),
-                domain_id: body.domain_id.clone(),
-                is_deleted: 0,
-                created_at: now(),
-                updated_at: now(),
-                created_by: Some(auth.id.clone()),
-                updated_by: Some(auth.id.clone()),
-            };
-        })
-        .collect();
-    Perm::create_all(&perms).await?;
+    body.validate()?;
+    let perms = body.create(&actor).await?;
     Ok(reply!(perms))
 }
 
@@ -237,6 +269,7 @@ pub fn apply_routes() -> Router<BoxRoute> {
         .route("/grant/perm", post(grant))
         .route("/revoke/perm", post(revoke))
         .route("/change/perm", post(change))
+        .route("/role/:id/perm/:perm_id/condition", put(update_grant))
         .route("/batch/perm", post(create_all))
         .layer(restrict_layer)
         .boxed()
@@ -1,48 +1,223 @@
 use crate::{
     repository::{
-        dao::{org, perm, role, Org, Perm, Role, Domain, RolePerm, User, UserOrg, UserRole},
-        dto::Access,
+        dao::{
+            group, org, perm, role, Domain, Group, GroupRole, Org, OrgRole, Perm, Role, RolePerm,
+            User, UserGroup, UserOrg, UserRole,
+        },
+        dto::{decide_rule, find_inactive_role_ids, Access, Decide, QueryMember},
         vo, Dao,
     },
-    util::{jwt::Auth, restrict::Restrict, APIResult},
+    util::{
+        audit::Actor,
+        condition,
+        jwt::{Auth, Payload},
+        perm_value,
+        restrict::Restrict,
+        APIError, APIResult,
+    },
 };
 use axum::{
-    extract::{Extension, Path},
+    extract::{Extension, Path, Query},
     handler::{get, post},
     routing::BoxRoute,
     Json, Router,
 };
+use serde_json::json;
 use std::collections::HashMap;
 use tower_http::auth::RequireAuthorizationLayer;
+use validator::Validate;
 
-async fn access(Json(body): Json<Access>, Extension(auth): Extension<Auth>) -> APIResult {
-    let role_perms = RolePerm::find_by_role(&body.role_id).await?;
-    let mut perm_map: HashMap<String, bool> = HashMap::new();
-    let perms = Perm::find_by_ids(body.perm_id, None).await?;
-    for perm in perms.into_iter() {
-        perm_map.insert(
-            perm.name.clone(),
-            role_perms.iter().any(|v| v.perm_id == perm.id),
-        );
-    }
-    let user_role = UserRole::find_by_id(&auth.id, &body.role_id).await;
-    if user_role.is_err() {
-        for (_, item) in perm_map.iter_mut() {
-            *item = false
+/// false when the role's domain withholds permissions from users with unverified email
+async fn has_verified_access(user_id: &str, role_id: &str) -> Result<bool, APIError> {
+    let role = match Role::find_by_id(role_id).await {
+        Ok(val) => val,
+        Err(_) => return Ok(false),
+    };
+    let domain = Domain::find_by_id(&role.domain_id).await?;
+    if domain.email_verification == 0 {
+        return Ok(true);
+    }
+    let user = User::find_by_id(user_id).await?;
+    Ok(user.is_email_verified())
+}
+
+/// attributes of the user conditions see as `subject`
+fn subject_of(user: &User) -> serde_json::Value {
+    json!({ "id": user.id, "username": user.username, "email": user.email })
+}
+
+async fn access(
+    Json(body): Json<Access>,
+    Extension(auth): Extension<Auth>,
+    Extension(payload): Extension<Payload>,
+    actor: Actor,
+) -> APIResult {
+    let role = match Role::find_by_id(&body.role_id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("角色 {} 不存在", &body.role_id))),
+    };
+    let user = User::find_by_id(&auth.id).await?;
+    let ctx = condition::context(body.context.clone(), subject_of(&user), &actor.ip);
+    let domain = Domain::find_by_id(&role.domain_id).await?;
+    let role_ids = role.find_effective_ids().await?;
+    let role_values: HashMap<String, String> = Role::find_by_ids(role_ids.clone())
+        .await?
+        .into_iter()
+        .map(|v| (v.id, v.value))
+        .collect();
+    let role_perms: Vec<RolePerm> = RolePerm::find_by_roles(&role_ids)
+        .await?
+        .into_iter()
+        .filter(|v| v.applies(&ctx))
+        .collect();
+    // values of the granted perms, a pattern grant covers every perm it matches
+    let granted_ids: Vec<String> = role_perms.iter().map(|v| v.perm_id.clone()).collect();
+    let mut granted_values: HashMap<String, String> = HashMap::new();
+    if !granted_ids.is_empty() {
+        for v in Perm::find_by_ids(granted_ids, Some(role.domain_id.clone())).await? {
+            granted_values.insert(v.id, v.value);
         }
     }
+    let held = Role::find_held_ids(&auth.id).await?.contains(&body.role_id);
+    let inactive = find_inactive_role_ids(&auth.id, &role.domain_id, Some(&payload.jti)).await?;
+    let granted = held
+        && !inactive.contains(&role.id)
+        && has_verified_access(&auth.id, &body.role_id).await?;
+    let explain = body.explain.unwrap_or(false);
+    let mut perm_map: HashMap<String, serde_json::Value> = HashMap::new();
+    let perms = Perm::find_by_ids(body.perm_id, Some(role.domain_id.clone())).await?;
+    for perm in perms.into_iter() {
+        let rules: Vec<vo::Rule> = role_perms
+            .iter()
+            .filter_map(|v| granted_values.get(&v.perm_id).map(|value| (v, value)))
+            .filter(|(_, value)| perm_value::covers(value, &perm.value))
+            .map(|(v, value)| vo::Rule {
+                effect: v.effect.clone(),
+                perm: value.clone(),
+                role: role_values.get(&v.role_id).cloned(),
+                resource: None,
+                condition: v.condition_expr.clone(),
+            })
+            .collect();
+        let rule = decide_rule(&domain.conflict_strategy, rules.iter());
+        let allowed = granted && rule.map_or(false, |v| v.effect != "deny");
+        let item = if explain {
+            json!({ "allowed": allowed, "rule": rule })
+        } else {
+            json!(allowed)
+        };
+        perm_map.insert(perm.name.clone(), item);
+    }
     Ok(reply!(perm_map))
 }
 
-async fn roles_of_user(Path(id): Path<String>, Extension(_): Extension<Auth>) -> APIResult {
+async fn decide(
+    Json(mut body): Json<Decide>,
+    Extension(auth): Extension<Auth>,
+    Extension(payload): Extension<Payload>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
+    let domain = match Domain::find_by_id(&body.domain_id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("来源域 {} 不存在", &body.domain_id))),
+    };
+    let user_id = body.user_id.clone().unwrap_or_else(|| auth.id.clone());
+    if user_id != auth.id && !auth.is_admin {
+        let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+        if !user_roles
+            .into_iter()
+            .any(|v| v.role_id == domain.admin_role_id)
+        {
+            return Err(reject!("仅域管理员可查询其他用户"));
+        }
+    }
+    let user = match User::find_by_id(&user_id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("用户 {} 不存在", &user_id))),
+    };
+    let unverified = domain.email_verification > 0 && !user.is_email_verified();
+    let decisions = if user.is_actived == 0 || unverified {
+        body.perms.iter().map(|v| vo::Decision::new(v)).collect()
+    } else {
+        // the caller's own session unless another one is named
+        let session = match &body.session {
+            Some(jti) => Some(jti.clone()),
+            None if user.id == auth.id => Some(payload.jti.clone()),
+            None => None,
+        };
+        body.inactive_role_ids =
+            find_inactive_role_ids(&user.id, &domain.id, session.as_deref()).await?;
+        let ctx = condition::context(body.context.clone(), subject_of(&user), &actor.ip);
+        body.decide(&user.id, &ctx, &domain.conflict_strategy).await?
+    };
+    let groups: Vec<String> = Group::find_by_user(&user.id, Some(domain.id.clone()))
+        .await?
+        .into_iter()
+        .map(|v| v.id)
+        .collect();
+    Ok(reply!({
+      "user_id": user.id, "domain_id": domain.id, "decisions": decisions, "groups": groups,
+      "strategy": domain.conflict_strategy
+    }))
+}
+
+async fn roles_of_user(
+    Path(id): Path<String>,
+    Query(q): Query<QueryMember>,
+    Extension(auth): Extension<Auth>,
+) -> APIResult {
     use role::IntoVecOfVo;
+    let include_expired = q.include_expired.unwrap_or(false);
+    if include_expired && !auth.is_admin {
+        return Err(reject!("仅管理员可查看已过期授权"));
+    }
     match User::find_by_id(&id).await {
         Ok(_) => (),
         Err(_) => return Err(reject!(format!("用户 {} 不存在", &id))),
     };
-    let user_roles = UserRole::find_by_user(&id).await?;
-    let role_ids: Vec<String> = user_roles.into_iter().map(|v| v.role_id).collect();
-    let roles: Vec<vo::Role> = if role_ids.len() > 0 {
+    let mut sources: HashMap<String, Vec<vo::RoleSource>> = HashMap::new();
+    for v in UserRole::find_by_user(&id, include_expired).await? {
+        let source = vo::RoleSource { org_id: None, group_id: None, expire: v.expire };
+        sources.entry(v.role_id).or_default().push(source);
+    }
+    for v in OrgRole::find_by_user(&id, include_expired).await? {
+        let source = vo::RoleSource { org_id: Some(v.org_id), group_id: None, expire: v.expire };
+        sources.entry(v.role_id).or_default().push(source);
+    }
+    for v in GroupRole::find_by_user(&id, include_expired).await? {
+        let source = vo::RoleSource { org_id: None, group_id: Some(v.group_id), expire: v.expire };
+        sources.entry(v.role_id).or_default().push(source);
+    }
+    let role_ids: Vec<String> = sources.keys().cloned().collect();
+    let mut roles: Vec<vo::Role> = if !role_ids.is_empty() {
+        Role::find_by_ids(role_ids).await?.into_vo().await?
+    } else {
+        vec![]
+    };
+    for role in roles.iter_mut() {
+        role.sources = sources.remove(&role.id).unwrap_or_default();
+    }
+    Ok(reply!(roles))
+}
+
+async fn roles_of_org(
+    Path(id): Path<String>,
+    Query(q): Query<QueryMember>,
+    Extension(auth): Extension<Auth>,
+) -> APIResult {
+    use role::IntoVecOfVo;
+    let include_expired = q.include_expired.unwrap_or(false);
+    if include_expired && !auth.is_admin {
+        return Err(reject!("仅管理员可查看已过期授权"));
+    }
+    match Org::find_by_id(&id).await {
+        Ok(_) => (),
+        Err(_) => return Err(reject!(format!("组织 {} 不存在", &id))),
+    };
+    let org_roles = OrgRole::find_by_org(&id, include_expired).await?;
+    let role_ids: Vec<String> = org_roles.into_iter().map(|v| v.role_id).collect();
+    let roles: Vec<vo::Role> = if !role_ids.is_empty() {
         Role::find_by_ids(role_ids).await?.into_vo().await?
     } else {
         vec![]
@@ -50,14 +225,22 @@ async fn roles_of_user(Path(id): Path<String>, Extension(_): Extension<Auth>) ->
     Ok(reply!(roles))
 }
 
-async fn users_of_role(Path(id): Path<String>) -> APIResult {
+async fn users_of_role(
+    Path(id): Path<String>,
+    Query(q): Query<QueryMember>,
+    Extension(auth): Extension<Auth>,
+) -> APIResult {
+    let include_expired = q.include_expired.unwrap_or(false);
+    if include_expired && !auth.is_admin {
+        return Err(reject!("仅管理员可查看已过期授权"));
+    }
     match Role::find_by_id(&id).await {
         Ok(_) => (),
         Err(_) => return Err(reject!(format!("角色 {} 不存在", &id))),
     };
-    let user_roles = UserRole::find_by_role(&id).await?;
+    let user_roles = UserRole::find_by_role(&id, include_expired).await?;
     let user_ids: Vec<String> = user_roles.into_iter().map(|v| v.user_id).collect();
-    let users = if user_ids.len() > 0 {
+    let users = if !user_ids.is_empty() {
         User::find_by_ids(user_ids).await?
     } else {
         vec![]
@@ -72,24 +255,84 @@ async fn perms_of_role(Path(id): Path<String>, Extension(_): Extension<Auth>) ->
         Err(_) => return Err(reject!(format!("角色 {} 不存在", &id))),
     };
     let role_perms = RolePerm::find_by_role(&id).await?;
-    let perm_ids: Vec<String> = role_perms.into_iter().map(|v| v.perm_id).collect();
-    let perms: Vec<vo::Perm> = if perm_ids.len() > 0 {
+    let perm_ids: Vec<String> = role_perms.iter().map(|v| v.perm_id.clone()).collect();
+    let mut perms: Vec<vo::Perm> = if !perm_ids.is_empty() {
         Perm::find_by_ids(perm_ids, None).await?.into_vo().await?
     } else {
         vec![]
     };
+    for perm in perms.iter_mut() {
+        if let Some(role_perm) = role_perms.iter().find(|v| v.perm_id == perm.id) {
+            perm.condition = role_perm.condition_expr.clone();
+            perm.effect = Some(role_perm.effect.clone());
+        }
+    }
     Ok(reply!(perms))
 }
 
-async fn orgs_of_user(Path(id): Path<String>, Extension(_): Extension<Auth>) -> APIResult {
+/// direct perms of the role and, per inherited role, the perms it passes down
+async fn effective_perms_of_role(Path(id): Path<String>, Extension(_): Extension<Auth>) -> APIResult {
+    use perm::IntoVecOfVo;
+    let found = match Role::find_by_id(&id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("角色 {} 不存在", &id))),
+    };
+    let role_ids = found.find_effective_ids().await?;
+    let role_perms = RolePerm::find_by_roles(&role_ids).await?;
+    let perm_ids: Vec<String> = role_perms.iter().map(|v| v.perm_id.clone()).collect();
+    let perms: HashMap<String, vo::Perm> = if !perm_ids.is_empty() {
+        Perm::find_by_ids(perm_ids, None).await?.into_vo().await?
+    } else {
+        vec![]
+    }
+    .into_iter()
+    .map(|v| (v.id.clone(), v))
+    .collect();
+    let perms_of = |role_id: &str| -> Vec<vo::Perm> {
+        role_perms
+            .iter()
+            .filter(|v| v.role_id == role_id)
+            .filter_map(|v| {
+                let mut perm = perms.get(&v.perm_id).cloned()?;
+                perm.condition = v.condition_expr.clone();
+                perm.effect = Some(v.effect.clone());
+                Some(perm)
+            })
+            .collect()
+    };
+    let roles: HashMap<String, Role> = Role::find_by_ids(role_ids.clone())
+        .await?
+        .into_iter()
+        .map(|v| (v.id.clone(), v))
+        .collect();
+    let inherited: Vec<serde_json::Value> = role_ids
+        .iter()
+        .filter(|v| **v != found.id)
+        .filter_map(|v| roles.get(v))
+        .map(|role| json!({ "role": role, "perms": perms_of(&role.id) }))
+        .collect();
+    Ok(reply!({
+      "direct": perms_of(&found.id), "inherited": inherited
+    }))
+}
+
+async fn orgs_of_user(
+    Path(id): Path<String>,
+    Query(q): Query<QueryMember>,
+    Extension(auth): Extension<Auth>,
+) -> APIResult {
     use org::IntoVecOfVo;
+    let include_expired = q.include_expired.unwrap_or(false);
+    if include_expired && !auth.is_admin {
+        return Err(reject!("仅管理员可查看已过期授权"));
+    }
     match User::find_by_id(&id).await {
         Ok(_) => (),
         Err(_) => return Err(reject!(format!("用户 {} 不存在", &id))),
     };
-    let user_orgs = UserOrg::find_by_user(&id).await?;
+    let user_orgs = UserOrg::find_by_user(&id, include_expired).await?;
     let org_ids: Vec<String> = user_orgs.iter().map(|v| v.org_id.clone()).collect();
-    let orgs: Vec<vo::Org> = if org_ids.len() > 0 {
+    let orgs: Vec<vo::Org> = if !org_ids.is_empty() {
         Org::find_by_ids(org_ids, None).await?.into_vo().await?
     } else {
         vec![]
@@ -97,14 +340,76 @@ async fn orgs_of_user(Path(id): Path<String>, Extension(_): Extension<Auth>) ->
     Ok(reply!(orgs))
 }
 
-async fn users_of_org(Path(id): Path<String>) -> APIResult {
-    match Org::find_by_id(&id).await {
-        Ok(_) => (),
+async fn users_of_org(
+    Path(id): Path<String>,
+    Query(q): Query<QueryMember>,
+    Extension(auth): Extension<Auth>,
+) -> APIResult {
+    let include_expired = q.include_expired.unwrap_or(false);
+    if include_expired && !auth.is_admin {
+        return Err(reject!("仅管理员可查看已过期授权"));
+    }
+    let found = match Org::find_by_id(&id).await {
+        Ok(val) => val,
         Err(_) => return Err(reject!(format!("组织 {} 不存在", &id))),
     };
-    let user_orgs = UserOrg::find_by_org(&id).await?;
-    let user_ids: Vec<String> = user_orgs.iter().map(|v| v.user_id.clone()).collect();
-    let users: Vec<User> = if user_ids.len() > 0 {
+    let user_orgs = if q.descendants.unwrap_or(false) {
+        let org_ids: Vec<String> = found.find_subtree().await?.into_iter().map(|v| v.id).collect();
+        UserOrg::find_by_orgs(&org_ids, include_expired).await?
+    } else {
+        UserOrg::find_by_org(&id, include_expired).await?
+    };
+    let mut user_ids: Vec<String> = user_orgs.iter().map(|v| v.user_id.clone()).collect();
+    user_ids.sort();
+    user_ids.dedup();
+    let users: Vec<User> = if !user_ids.is_empty() {
+        User::find_by_ids(user_ids).await?
+    } else {
+        vec![]
+    };
+    Ok(reply!(users))
+}
+
+async fn groups_of_user(
+    Path(id): Path<String>,
+    Query(q): Query<QueryMember>,
+    Extension(auth): Extension<Auth>,
+) -> APIResult {
+    use group::IntoVecOfVo;
+    let include_expired = q.include_expired.unwrap_or(false);
+    if include_expired && !auth.is_admin {
+        return Err(reject!("仅管理员可查看已过期授权"));
+    }
+    match User::find_by_id(&id).await {
+        Ok(_) => (),
+        Err(_) => return Err(reject!(format!("用户 {} 不存在", &id))),
+    };
+    let user_groups = UserGroup::find_by_user(&id, include_expired).await?;
+    let group_ids: Vec<String> = user_groups.iter().map(|v| v.group_id.clone()).collect();
+    let groups: Vec<vo::Group> = if !group_ids.is_empty() {
+        Group::find_by_ids(group_ids, None).await?.into_vo().await?
+    } else {
+        vec![]
+    };
+    Ok(reply!(groups))
+}
+
+async fn users_of_group(
+    Path(id): Path<String>,
+    Query(q): Query<QueryMember>,
+    Extension(auth): Extension<Auth>,
+) -> APIResult {
+    let include_expired = q.include_expired.unwrap_or(false);
+    if include_expired && !auth.is_admin {
+        return Err(reject!("仅管理员可查看已过期授权"));
+    }
+    match Group::find_by_id(&id).await {
+        Ok(_) => (),
+        Err(_) => return Err(reject!(format!("用户组 {} 不存在", &id))),
+    };
+    let user_groups = UserGroup::find_by_group(&id, include_expired).await?;
+    let user_ids: Vec<String> = user_groups.iter().map(|v| v.user_id.clone()).collect();
+    let users: Vec<User> = if !user_ids.is_empty() {
         User::find_by_ids(user_ids).await?
     } else {
         vec![]
@@ -112,12 +417,36 @@ async fn users_of_org(Path(id): Path<String>) -> APIResult {
     Ok(reply!(users))
 }
 
+async fn roles_of_group(
+    Path(id): Path<String>,
+    Query(q): Query<QueryMember>,
+    Extension(auth): Extension<Auth>,
+) -> APIResult {
+    use role::IntoVecOfVo;
+    let include_expired = q.include_expired.unwrap_or(false);
+    if include_expired && !auth.is_admin {
+        return Err(reject!("仅管理员可查看已过期授权"));
+    }
+    match Group::find_by_id(&id).await {
+        Ok(_) => (),
+        Err(_) => return Err(reject!(format!("用户组 {} 不存在", &id))),
+    };
+    let group_roles = GroupRole::find_by_group(&id, include_expired).await?;
+    let role_ids: Vec<String> = group_roles.into_iter().map(|v| v.role_id).collect();
+    let roles: Vec<vo::Role> = if !role_ids.is_empty() {
+        Role::find_by_ids(role_ids).await?.into_vo().await?
+    } else {
+        vec![]
+    };
+    Ok(reply!(roles))
+}
+
 async fn domains_of_user(Path(id): Path<String>, Extension(_): Extension<Auth>) -> APIResult {
     match User::find_by_id(&id).await {
         Ok(_) => (),
         Err(_) => return Err(reject!(format!("用户 {} 不存在", &id))),
     };
-    let user_roles = UserRole::find_by_user(&id).await?;
+    let user_roles = UserRole::find_by_user(&id, false).await?;
     let role_ids: Vec<String> = user_roles.into_iter().map(|v| v.role_id).collect();
     let domains = Domain::find_by_admin_role(role_ids).await?;
     Ok(reply!(domains))
@@ -126,12 +455,18 @@ async fn domains_of_user(Path(id): Path<String>, Extension(_): Extension<Auth>)
 pub fn apply_routes() -> Router<BoxRoute> {
     let router = Router::new();
     let restrict_layer = RequireAuthorizationLayer::custom(Restrict::new());
-    router.route("/access", post(access))
+    router.route("/decision", post(decide))
+        .route("/access", post(access))
         .route("/user/:id/role", get(roles_of_user))
         .route("/user/:id/org", get(orgs_of_user))
         .route("/role/:id/user", get(users_of_role))
         .route("/role/:id/perm", get(perms_of_role))
+        .route("/role/:id/perm/effective", get(effective_perms_of_role))
         .route("/org/:id/user", get(users_of_org))
+        .route("/org/:id/role", get(roles_of_org))
+        .route("/user/:id/group", get(groups_of_user))
+        .route("/group/:id/user", get(users_of_group))
+        .route("/group/:id/role", get(roles_of_group))
         .route("/user/:id/domain", get(domains_of_user))
         .layer(restrict_layer)
         .boxed()
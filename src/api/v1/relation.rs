@@ -0,0 +1,244 @@
+use axum::{
+    extract::{Extension, Path, Query},
+    handler::{get, post},
+    routing::BoxRoute,
+    Json, Router,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::{
+    repository::{
+        dao::{Domain, RelationSchema, RelationTuple, UserRole},
+        dto::{
+            relation_snapshot, CheckRelation, ExpandRelation, ListObjects, ListSubjects,
+            QueryRelation, RelationBody, UpdateRelationSchema, WriteRelations,
+        },
+        Dao,
+    },
+    util::{
+        audit::Actor,
+        jwt::Auth,
+        relation::{self, Object, Schema, Snapshot, Subject, Tuple},
+        restrict::Restrict,
+        APIError, APIResult,
+    },
+};
+use tower_http::auth::RequireAuthorizationLayer;
+use validator::Validate;
+
+/// whether the caller is an admin or an admin of the domain
+async fn is_domain_admin(domain_id: &str, auth: &Auth) -> Result<bool, APIError> {
+    let domain = match Domain::find_by_id(domain_id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("来源域 {} 不存在", domain_id))),
+    };
+    if auth.is_admin {
+        return Ok(true);
+    }
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+    Ok(user_roles
+        .into_iter()
+        .any(|v| v.role_id == domain.admin_role_id))
+}
+
+async fn check_domain_admin(domain_id: &str, auth: &Auth) -> Result<(), APIError> {
+    if !is_domain_admin(domain_id, auth).await? {
+        return Err(reject!(format!("仅域管理员可操作")));
+    }
+    Ok(())
+}
+
+/// users may ask about themselves, domain admins about anyone
+async fn check_subject_access(
+    domain_id: &str,
+    subject: &Subject,
+    auth: &Auth,
+) -> Result<(), APIError> {
+    let is_self = subject.relation.is_none()
+        && subject.object.object_type == "user"
+        && subject.object.object_id == auth.id;
+    if !is_self && !is_domain_admin(domain_id, auth).await? {
+        return Err(reject!("仅域管理员可查询其他主体"));
+    }
+    Ok(())
+}
+
+/// a snapshot at least as fresh as the consistency token
+async fn snapshot_at(domain_id: &str, token: &Option<String>) -> Result<Arc<Snapshot>, APIError> {
+    let min_revision = match token {
+        Some(token) => match relation::decode_token(domain_id, token) {
+            Some(val) => Some(val),
+            None => return Err(reject!("一致性令牌无效")),
+        },
+        None => None,
+    };
+    let snapshot = relation_snapshot(domain_id, min_revision).await?;
+    if min_revision.map_or(false, |v| v > snapshot.revision) {
+        return Err(reject!("一致性令牌无效"));
+    }
+    Ok(snapshot)
+}
+
+fn token_of(snapshot: &Snapshot) -> String {
+    relation::encode_token(&snapshot.domain_id, snapshot.revision)
+}
+
+fn parse_object(v: &str) -> Result<Object, APIError> {
+    Object::parse(v).map_err(|e| reject!(e))
+}
+
+fn parse_subject(v: &str) -> Result<Subject, APIError> {
+    Subject::parse(v).map_err(|e| reject!(e))
+}
+
+/// tuples that are valid in the schema
+fn parse_tuples(bodies: &[RelationBody], schema: &Schema) -> Result<Vec<Tuple>, APIError> {
+    let mut tuples = vec![];
+    for v in bodies {
+        let tuple = v.tuple().map_err(|e| reject!(e))?;
+        schema.check_tuple(&tuple).map_err(|e| reject!(e))?;
+        tuples.push(tuple);
+    }
+    Ok(tuples)
+}
+
+async fn schema(Path(id): Path<String>, Extension(auth): Extension<Auth>) -> APIResult {
+    check_domain_admin(&id, &auth).await?;
+    let found = RelationSchema::find_by_domain(&id).await?;
+    let (definition, revision) = match found {
+        Some(found) => (
+            serde_json::from_str(&found.definition).unwrap_or_default(),
+            found.revision,
+        ),
+        None => (json!({}), 0),
+    };
+    Ok(reply!({
+      "definition": definition, "revision": revision, "token": relation::encode_token(&id, revision)
+    }))
+}
+
+async fn update_schema(
+    Path(id): Path<String>,
+    Json(body): Json<UpdateRelationSchema>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    check_domain_admin(&id, &auth).await?;
+    let schema = Schema::parse(&body.definition).map_err(|e| reject!(e))?;
+    // every stored tuple must stay valid
+    for tuple in RelationTuple::find_by_domain(&id).await? {
+        let tuple: Tuple = tuple.into();
+        if let Err(e) = schema.check_tuple(&tuple) {
+            return Err(reject!(format!("{}, 仍被 {} 使用", e, tuple)));
+        }
+    }
+    let found = RelationSchema::find_by_domain(&id).await?;
+    let updated = match body.save(&id, found.as_ref(), &actor).await? {
+        Some(val) => val,
+        None => return Err(reject!("并发写入冲突, 请重试")),
+    };
+    Ok(reply!({
+      "revision": updated.revision, "token": relation::encode_token(&id, updated.revision)
+    }))
+}
+
+async fn all(Query(q): Query<QueryRelation>, Extension(auth): Extension<Auth>) -> APIResult {
+    check_domain_admin(&q.domain_id, &auth).await?;
+    let object = q.object.as_deref().map(parse_object).transpose()?;
+    let subject = q.subject.as_deref().map(parse_subject).transpose()?;
+    let snapshot = snapshot_at(&q.domain_id, &q.token).await?;
+    let mut tuples: Vec<String> = snapshot
+        .tuples()
+        .filter(|v| object.as_ref().map_or(true, |o| v.object == *o))
+        .filter(|v| q.relation.as_ref().map_or(true, |r| v.relation == *r))
+        .filter(|v| subject.as_ref().map_or(true, |s| v.subject == *s))
+        .map(|v| v.to_string())
+        .collect();
+    tuples.sort();
+    Ok(reply!({ "tuples": tuples, "token": token_of(&snapshot) }))
+}
+
+async fn write(
+    Json(body): Json<WriteRelations>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
+    if body.writes.is_empty() && body.deletes.is_empty() {
+        return Err(reject!("没有要写入的关系"));
+    }
+    check_domain_admin(&body.domain_id, &auth).await?;
+    let found = match RelationSchema::find_by_domain(&body.domain_id).await? {
+        Some(val) => val,
+        None => return Err(reject!(format!("域 {} 未定义关系模式", &body.domain_id))),
+    };
+    let definition = serde_json::from_str(&found.definition).unwrap_or_default();
+    let schema = Schema::parse(&definition).map_err(|e| reject!(e))?;
+    let writes = parse_tuples(&body.writes, &schema)?;
+    let deletes = parse_tuples(&body.deletes, &schema)?;
+    let revision = match body.save(&found, &writes, &deletes, &actor).await? {
+        Some(val) => val,
+        None => return Err(reject!("并发写入冲突, 请重试")),
+    };
+    Ok(reply!({
+      "revision": revision, "token": relation::encode_token(&body.domain_id, revision)
+    }))
+}
+
+async fn check(Json(body): Json<CheckRelation>, Extension(auth): Extension<Auth>) -> APIResult {
+    let object = parse_object(&body.object)?;
+    let subject = parse_subject(&body.subject)?;
+    check_subject_access(&body.domain_id, &subject, &auth).await?;
+    let snapshot = snapshot_at(&body.domain_id, &body.token).await?;
+    let allowed = snapshot.check(&object, &body.relation, &subject);
+    Ok(reply!({ "allowed": allowed, "token": token_of(&snapshot) }))
+}
+
+async fn expand(Json(body): Json<ExpandRelation>, Extension(auth): Extension<Auth>) -> APIResult {
+    check_domain_admin(&body.domain_id, &auth).await?;
+    let object = parse_object(&body.object)?;
+    let snapshot = snapshot_at(&body.domain_id, &body.token).await?;
+    let tree = snapshot.expand(&object, &body.relation);
+    Ok(reply!({ "tree": tree, "token": token_of(&snapshot) }))
+}
+
+async fn objects(Json(body): Json<ListObjects>, Extension(auth): Extension<Auth>) -> APIResult {
+    body.validate()?;
+    let subject = parse_subject(&body.subject)?;
+    check_subject_access(&body.domain_id, &subject, &auth).await?;
+    let snapshot = snapshot_at(&body.domain_id, &body.token).await?;
+    let objects = snapshot.objects(&body.object_type, &body.relation, &subject);
+    Ok(reply!({ "objects": objects, "token": token_of(&snapshot) }))
+}
+
+async fn subjects(Json(body): Json<ListSubjects>, Extension(auth): Extension<Auth>) -> APIResult {
+    check_domain_admin(&body.domain_id, &auth).await?;
+    let object = parse_object(&body.object)?;
+    let snapshot = snapshot_at(&body.domain_id, &body.token).await?;
+    let subjects: Vec<String> = snapshot
+        .subjects(&object, &body.relation)
+        .into_iter()
+        .filter(|v| match &body.subject_type {
+            Some(t) => v.relation.is_none() && v.object.object_type == *t,
+            None => true,
+        })
+        .map(|v| v.to_string())
+        .collect();
+    Ok(reply!({ "subjects": subjects, "token": token_of(&snapshot) }))
+}
+
+pub fn apply_routes() -> Router<BoxRoute> {
+    let router = Router::new();
+    let restrict_layer = RequireAuthorizationLayer::custom(Restrict::new());
+    router
+        .route("/domain/:id/relation/schema", get(schema).put(update_schema))
+        .route("/relation", get(all))
+        .route("/relation/write", post(write))
+        .route("/relation/check", post(check))
+        .route("/relation/expand", post(expand))
+        .route("/relation/objects", post(objects))
+        .route("/relation/subjects", post(subjects))
+        .layer(restrict_layer)
+        .boxed()
+}
@@ -0,0 +1,121 @@
+use axum::{
+    extract::{Extension, Query},
+    handler::{get, post},
+    routing::BoxRoute,
+    Json, Router,
+};
+
+use crate::{
+    repository::{
+        dao::{Domain, Group, Org, Perm, ResourceGrant, Role, User, UserRole},
+        dto::{QueryResourceGrant, ResourceGrantPerm, ResourceRevokePerm},
+        Dao,
+    },
+    util::{audit::Actor, jwt::Auth, restrict::Restrict, APIError, APIResult},
+};
+use tower_http::auth::RequireAuthorizationLayer;
+use validator::Validate;
+
+/// only admins and admins of the domain manage its resource grants
+async fn check_domain_admin(domain_id: &str, auth: &Auth) -> Result<(), APIError> {
+    let domain = match Domain::find_by_id(domain_id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!(format!("来源域 {} 不存在", domain_id))),
+    };
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+    if !auth.is_admin
+        && !user_roles
+            .into_iter()
+            .any(|v| v.role_id == domain.admin_role_id)
+    {
+        return Err(reject!(format!("仅域管理员可操作")));
+    }
+    Ok(())
+}
+
+/// the subject must exist, roles, orgs and groups in the perm's domain
+async fn check_subject(
+    subject_type: &str,
+    subject_id: &str,
+    domain_id: &str,
+) -> Result<(), APIError> {
+    let found_domain = match subject_type {
+        "user" => {
+            return User::find_by_id(subject_id)
+                .await
+                .map(|_| ())
+                .map_err(|_| reject!(format!("用户 {} 不存在", subject_id)))
+        }
+        "role" => Role::find_by_id(subject_id)
+            .await
+            .map(|v| v.domain_id)
+            .map_err(|_| reject!(format!("角色 {} 不存在", subject_id)))?,
+        "org" => Org::find_by_id(subject_id)
+            .await
+            .map(|v| v.domain_id)
+            .map_err(|_| reject!(format!("组织 {} 不存在", subject_id)))?,
+        _ => Group::find_by_id(subject_id)
+            .await
+            .map(|v| v.domain_id)
+            .map_err(|_| reject!(format!("用户组 {} 不存在", subject_id)))?,
+    };
+    if found_domain != domain_id {
+        return Err(reject!(format!("{} 和权限不属于同一个域", subject_id)));
+    }
+    Ok(())
+}
+
+async fn all(Query(q): Query<QueryResourceGrant>, Extension(auth): Extension<Auth>) -> APIResult {
+    q.validate()?;
+    if q.include_expired.unwrap_or(false) && !auth.is_admin {
+        return Err(reject!("仅管理员可查看已过期授权"));
+    }
+    check_domain_admin(&q.domain_id, &auth).await?;
+    let all = q.find_all().await?;
+    Ok(reply!(all))
+}
+
+async fn grant(
+    Json(body): Json<ResourceGrantPerm>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
+    let perm = Perm::find_by_id(&body.perm_id)
+        .await
+        .map_err(|_| reject!(format!("权限 {} 不存在", &body.perm_id)))?;
+    check_domain_admin(&perm.domain_id, &auth).await?;
+    check_subject(&body.subject_type, &body.subject_id, &perm.domain_id).await?;
+    let granted = body.save(&perm, &actor).await?;
+    Ok(reply!(granted))
+}
+
+async fn revoke(
+    Json(body): Json<ResourceRevokePerm>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
+    let found = ResourceGrant::find_by_ids(body.ids.clone()).await?;
+    if let Some(id) = body.ids.iter().find(|v| !found.iter().any(|g| &g.id == *v)) {
+        return Err(reject!(format!("资源授权 {} 不存在", id)));
+    }
+    let mut domain_ids: Vec<&String> = found.iter().map(|v| &v.domain_id).collect();
+    domain_ids.sort();
+    domain_ids.dedup();
+    for domain_id in domain_ids {
+        check_domain_admin(domain_id, &auth).await?;
+    }
+    let revoked = body.save(&found, &actor).await?;
+    Ok(reply!(revoked))
+}
+
+pub fn apply_routes() -> Router<BoxRoute> {
+    let router = Router::new();
+    let restrict_layer = RequireAuthorizationLayer::custom(Restrict::new());
+    router.route("/resource/grant", get(all))
+        .route("/grant/resource", post(grant))
+        .route("/revoke/resource", post(revoke))
+        .layer(restrict_layer)
+        .boxed()
+}
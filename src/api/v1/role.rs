@@ -1,6 +1,6 @@
 use axum::{
     extract::{Extension, Path, Query},
-    handler::{post, put},
+    handler::{get, post, put},
     routing::BoxRoute,
     Json, Router,
 };
@@ -8,14 +8,18 @@ use tower_http::auth::RequireAuthorizationLayer;
 
 use crate::{
     repository::{
-        dao::{Domain, Role, User, UserRole},
+        dao::{
+            role, role_prerequisite, Domain, Org, OrgRole, Role, RoleParent,
+            RolePrerequisite, User, UserRole,
+        },
         dto::{
-            NewRole, QueryRole, UpdateRole, UpdateUserRole, UserChangeRole, UserGrantRole,
-            UserRevokeRole,
+            DeleteRole, NewRole, OrgGrantRole, OrgRevokeRole, QueryRole,
+            RoleChangeParent, RoleChangePrerequisite, UpdateRole, UpdateUserRole, UserChangeRole,
+            UserGrantRole, UserRevokeRole,
         },
         Dao,
     },
-    util::{jwt::Auth, restrict::Restrict, APIResult},
+    util::{audit::Actor, jwt::Auth, restrict::Restrict, APIError, APIResult},
 };
 use validator::Validate;
 
@@ -26,16 +30,20 @@ async fn all(Query(q): Query<QueryRole>) -> APIResult {
 }
 
 async fn one(Path(id): Path<String>) -> APIResult {
-    let one: Role = Role::find_by_id(&id).await?.into();
+    let one: Role = Role::find_by_id(&id).await?;
     Ok(reply!(one))
 }
 
-async fn create(Json(mut body): Json<NewRole>, Extension(auth): Extension<Auth>) -> APIResult {
+async fn create(
+    Json(mut body): Json<NewRole>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
     let domain = match Domain::find_by_id(&body.domain_id).await {
         Ok(val) => val,
         Err(_) => return Err(reject!(format!("来源域 {} 不存在", &body.domain_id))),
     };
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     if !auth.is_admin
         && !user_roles
             .into_iter()
@@ -45,7 +53,7 @@ async fn create(Json(mut body): Json<NewRole>, Extension(auth): Extension<Auth>)
     }
     body.validate()?;
     body.created_by = Some(auth.id.clone());
-    let created = body.create().await?;
+    let created = body.create(&actor).await?;
     Ok(reply!(created))
 }
 
@@ -53,11 +61,12 @@ async fn update(
     Path(id): Path<String>,
     Json(mut body): Json<UpdateRole>,
     Extension(auth): Extension<Auth>,
+    actor: Actor,
 ) -> APIResult {
     let found: Role = Role::find_by_id(&id)
         .await
         .map_err(|_| reject!(format!("角色 {} 不存在", &id)))?;
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     let domain = Domain::find_by_id(&found.domain_id).await?;
     if !auth.is_admin
         && !user_roles
@@ -68,15 +77,19 @@ async fn update(
     }
     body.validate()?;
     body.updated_by = Some(auth.id.clone());
-    let updated = body.save(&id).await?;
+    let updated = body.save(&id, &actor).await?;
     Ok(reply!(updated))
 }
 
-async fn remove(Path(id): Path<String>, Extension(auth): Extension<Auth>) -> APIResult {
+async fn remove(
+    Path(id): Path<String>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
     let found: Role = Role::find_by_id(&id)
         .await
         .map_err(|_| reject!(format!("角色 {} 不存在", &id)))?;
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     let domain = Domain::find_by_id(&found.domain_id).await?;
     if !auth.is_admin
         && !user_roles
@@ -85,27 +98,145 @@ async fn remove(Path(id): Path<String>, Extension(auth): Extension<Auth>) -> API
     {
         return Err(reject!(format!("仅域管理员可操作")));
     }
-    Role::delete_by_id(&id).await?;
+    DeleteRole { id }.save(&found, &actor).await?;
     Ok(reply!(found))
 }
 
-async fn grant(Json(mut body): Json<UserGrantRole>, Extension(auth): Extension<Auth>) -> APIResult {
+async fn parents(Path(id): Path<String>) -> APIResult {
+    use role::IntoVecOfVo;
+    match Role::find_by_id(&id).await {
+        Ok(_) => (),
+        Err(_) => return Err(reject!(format!("角色 {} 不存在", &id))),
+    };
+    let role_parents = RoleParent::find_by_role(&id).await?;
+    let parent_ids: Vec<String> = role_parents.into_iter().map(|v| v.parent_id).collect();
+    let parents = if !parent_ids.is_empty() {
+        Role::find_by_ids(parent_ids).await?.into_vo().await?
+    } else {
+        vec![]
+    };
+    Ok(reply!(parents))
+}
+
+async fn change_parents(
+    Path(id): Path<String>,
+    Json(mut body): Json<RoleChangeParent>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    let found: Role = Role::find_by_id(&id)
+        .await
+        .map_err(|_| reject!(format!("角色 {} 不存在", &id)))?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+    let domain = Domain::find_by_id(&found.domain_id).await?;
+    if !auth.is_admin
+        && !user_roles
+            .into_iter()
+            .any(|v| v.role_id == domain.admin_role_id)
+    {
+        return Err(reject!(format!("仅域管理员可操作")));
+    }
+    body.validate()?;
+    body.parent_ids.sort();
+    body.parent_ids.dedup();
+    let parents = Role::find_by_ids(body.parent_ids.clone()).await?;
+    let parent_ids: Vec<String> = parents.iter().map(|v| v.id.clone()).collect();
+    if let Some(parent_id) = body.parent_ids.iter().find(|v| !parent_ids.contains(v)) {
+        return Err(reject!(format!("角色 {} 不存在", parent_id)));
+    }
+    if let Some(parent) = parents.iter().find(|v| v.domain_id != found.domain_id) {
+        return Err(reject!(format!("角色 {} 和角色不属于同一个域", parent.id)));
+    }
+    let role_parents = body.save(&found, &actor).await?;
+    Ok(reply!(role_parents))
+}
+
+async fn prerequisites(Path(id): Path<String>) -> APIResult {
+    use role::IntoVecOfVo;
+    match Role::find_by_id(&id).await {
+        Ok(_) => (),
+        Err(_) => return Err(reject!(format!("角色 {} 不存在", &id))),
+    };
+    let prerequisite_ids: Vec<String> = RolePrerequisite::find_by_role(&id)
+        .await?
+        .into_iter()
+        .map(|v| v.prerequisite_id)
+        .collect();
+    let prerequisites = if !prerequisite_ids.is_empty() {
+        Role::find_by_ids(prerequisite_ids).await?.into_vo().await?
+    } else {
+        vec![]
+    };
+    Ok(reply!(prerequisites))
+}
+
+async fn change_prerequisites(
+    Path(id): Path<String>,
+    Json(mut body): Json<RoleChangePrerequisite>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    let found: Role = Role::find_by_id(&id)
+        .await
+        .map_err(|_| reject!(format!("角色 {} 不存在", &id)))?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+    let domain = Domain::find_by_id(&found.domain_id).await?;
+    if !auth.is_admin
+        && !user_roles
+            .into_iter()
+            .any(|v| v.role_id == domain.admin_role_id)
+    {
+        return Err(reject!(format!("仅域管理员可操作")));
+    }
+    body.validate()?;
+    body.prerequisite_ids.sort();
+    body.prerequisite_ids.dedup();
+    let prerequisites = Role::find_by_ids(body.prerequisite_ids.clone()).await?;
+    let ids: Vec<String> = prerequisites.iter().map(|v| v.id.clone()).collect();
+    if let Some(prerequisite_id) = body.prerequisite_ids.iter().find(|v| !ids.contains(v)) {
+        return Err(reject!(format!("角色 {} 不存在", prerequisite_id)));
+    }
+    if let Some(prerequisite) = prerequisites.iter().find(|v| v.domain_id != found.domain_id) {
+        return Err(reject!(format!("角色 {} 和角色不属于同一个域", prerequisite.id)));
+    }
+    // no prerequisite may itself require the role
+    let edges: Vec<RolePrerequisite> = RolePrerequisite::find_by_domain(&found.domain_id)
+        .await?
+        .into_iter()
+        .filter(|v| v.role_id != found.id)
+        .collect();
+    for prerequisite_id in body.prerequisite_ids.iter() {
+        if *prerequisite_id == found.id
+            || role_prerequisite::required(&edges, prerequisite_id).contains(&found.id)
+        {
+            return Err(reject!(format!("角色 {} 会形成循环前置", prerequisite_id)));
+        }
+    }
+    let role_prerequisites = body.save(&found, &actor).await?;
+    Ok(reply!(role_prerequisites))
+}
+
+async fn grant(
+    Json(mut body): Json<UserGrantRole>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
     let role: Role = Role::find_by_id(&body.role_id)
         .await
         .map_err(|_| reject!(format!("角色 {} 不存在", &body.role_id)))?;
     let users = User::find_by_ids(body.user_ids.clone()).await?;
     let user_ids: Vec<String> = users.iter().map(|v| v.id.clone()).collect();
-    let found = body.user_ids.iter().find(|v| !user_ids.contains(&v));
+    let found = body.user_ids.iter().find(|v| !user_ids.contains(v));
     if let Some(user_id) = found {
         return Err(reject!(format!("用户 {:?} 不存在", user_id)));
     }
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     if !auth.is_admin && !user_roles.into_iter().any(|v| v.role_level < role.level) {
         return Err(reject!(format!("不能操作高等级角色 {:?}", role.id)));
     }
-    let user_roles = UserRole::find_by_role(&body.role_id).await?;
+    let user_roles = UserRole::find_by_role(&body.role_id, false).await?;
     let user_ids: Vec<String> = user_roles.into_iter().map(|v| v.user_id.clone()).collect();
-    let found = body.user_ids.iter().find(|v| user_ids.contains(&v));
+    let found = body.user_ids.iter().find(|v| user_ids.contains(v));
     if let Some(found) = found {
         return Err(reject!(format!(
             "用户 {} 已赋予角色 {}",
@@ -113,64 +244,136 @@ async fn grant(Json(mut body): Json<UserGrantRole>, Extension(auth): Extension<A
         )));
     }
     body.role_level = role.level;
-    let granted = body.save().await?;
+    let granted = body.save(&role, &actor).await?;
     Ok(reply!(granted))
 }
 
-async fn revoke(Json(body): Json<UserRevokeRole>, Extension(auth): Extension<Auth>) -> APIResult {
+async fn revoke(
+    Json(body): Json<UserRevokeRole>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
     let role: Role = Role::find_by_id(&body.role_id)
         .await
         .map_err(|_| reject!(format!("角色 {} 不存在", &body.role_id)))?;
     let users = User::find_by_ids(body.user_ids.clone()).await?;
     let user_ids: Vec<String> = users.iter().map(|v| v.id.clone()).collect();
-    let found = body.user_ids.iter().find(|v| !user_ids.contains(&v));
+    let found = body.user_ids.iter().find(|v| !user_ids.contains(v));
     if let Some(user_id) = found {
         return Err(reject!(format!("用户 {:?} 不存在", user_id)));
     }
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     if !auth.is_admin && !user_roles.into_iter().any(|v| v.role_level < role.level) {
         return Err(reject!(format!("不能操作高等级角色 {:?}", role.id)));
     }
-    let user_roles = UserRole::find_by_role(&body.role_id).await?;
+    let user_roles = UserRole::find_by_role(&body.role_id, true).await?;
     let user_ids: Vec<String> = user_roles.into_iter().map(|v| v.user_id.clone()).collect();
-    let found = body.user_ids.iter().find(|v| !user_ids.contains(&v));
+    let found = body.user_ids.iter().find(|v| !user_ids.contains(v));
     if let Some(found) = found {
         return Err(reject!(format!(
             "用户 {} 未赋予角色 {}",
             found, &body.role_id
         )));
     }
-    let revoked = body.save().await?;
+    let revoked = body.save(&role, &actor).await?;
     Ok(reply!(revoked))
 }
 
-async fn change(Json(body): Json<UserChangeRole>, Extension(auth): Extension<Auth>) -> APIResult {
+async fn change(
+    Json(body): Json<UserChangeRole>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
     let role: Role = Role::find_by_id(&body.role_id)
         .await
         .map_err(|_| reject!(format!("角色 {} 不存在", &body.role_id)))?;
     let users = User::find_by_ids(body.user_ids.clone()).await?;
     let user_ids: Vec<String> = users.iter().map(|v| v.id.clone()).collect();
-    let found = body.user_ids.iter().find(|v| !user_ids.contains(&v));
+    let found = body.user_ids.iter().find(|v| !user_ids.contains(v));
     if let Some(user_id) = found {
         return Err(reject!(format!("用户 {:?} 不存在", user_id)));
     }
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     if !auth.is_admin && !user_roles.into_iter().any(|v| v.role_level < role.level) {
         return Err(reject!(format!("不能操作高等级角色 {:?}", role.id)));
     }
-    let user_roles = body.save(role, users).await?;
+    let user_roles = body.save(role, users, &actor).await?;
     Ok(reply!(user_roles))
 }
 
-async fn expire(Json(body): Json<UpdateUserRole>, Extension(auth): Extension<Auth>) -> APIResult {
+/// the orgs must exist in the role's domain
+async fn find_orgs(org_ids: &[String], role: &Role) -> Result<Vec<Org>, APIError> {
+    let orgs = Org::find_by_ids(org_ids.to_vec(), None).await?;
+    let found_ids: Vec<String> = orgs.iter().map(|v| v.id.clone()).collect();
+    if let Some(org_id) = org_ids.iter().find(|v| !found_ids.contains(v)) {
+        return Err(reject!(format!("组织 {:?} 不存在", org_id)));
+    }
+    if let Some(org) = orgs.iter().find(|v| v.domain_id != role.domain_id) {
+        return Err(reject!(format!("组织 {:?} 和角色不属于同一个域", org.id)));
+    }
+    Ok(orgs)
+}
+
+async fn grant_org(
+    Json(body): Json<OrgGrantRole>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
+    let role: Role = Role::find_by_id(&body.role_id)
+        .await
+        .map_err(|_| reject!(format!("角色 {} 不存在", &body.role_id)))?;
+    find_orgs(&body.org_ids, &role).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+    if !auth.is_admin && !user_roles.into_iter().any(|v| v.role_level < role.level) {
+        return Err(reject!(format!("不能操作高等级角色 {:?}", role.id)));
+    }
+    let granted = body.save(&role, &actor).await?;
+    Ok(reply!(granted))
+}
+
+async fn revoke_org(
+    Json(body): Json<OrgRevokeRole>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    body.validate()?;
+    let role: Role = Role::find_by_id(&body.role_id)
+        .await
+        .map_err(|_| reject!(format!("角色 {} 不存在", &body.role_id)))?;
+    find_orgs(&body.org_ids, &role).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
+    if !auth.is_admin && !user_roles.into_iter().any(|v| v.role_level < role.level) {
+        return Err(reject!(format!("不能操作高等级角色 {:?}", role.id)));
+    }
+    let org_roles = OrgRole::find_by_orgs(&body.org_ids, true).await?;
+    let found = body
+        .org_ids
+        .iter()
+        .find(|v| !org_roles.iter().any(|r| &r.org_id == *v && r.role_id == body.role_id));
+    if let Some(found) = found {
+        return Err(reject!(format!(
+            "组织 {} 未赋予角色 {}",
+            found, &body.role_id
+        )));
+    }
+    let revoked = body.save(&role, &actor).await?;
+    Ok(reply!(revoked))
+}
+
+async fn expire(
+    Json(body): Json<UpdateUserRole>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
     let role: Role = Role::find_by_id(&body.role_id)
         .await
         .map_err(|_| reject!(format!("角色 {} 不存在", &body.role_id)))?;
-    let user_roles = UserRole::find_by_user(&auth.id).await?;
+    let user_roles = UserRole::find_by_user(&auth.id, false).await?;
     if !auth.is_admin && !user_roles.into_iter().any(|v| v.role_level < role.level) {
         return Err(reject!(format!("不能操作高等级角色 {:?}", role.id)));
     }
-    let user_role = body.save().await?;
+    let user_role = body.save(&role, &actor).await?;
     Ok(reply!(user_role))
 }
 
@@ -179,10 +382,14 @@ pub fn apply_routes() -> Router<BoxRoute> {
     let restrict_layer = RequireAuthorizationLayer::custom(Restrict::new());
     router.route("/role", post(create).get(all))
         .route("/role/:id", put(update).get(one).delete(remove))
+        .route("/role/:id/parent", get(parents).put(change_parents))
+        .route("/role/:id/prerequisite", get(prerequisites).put(change_prerequisites))
         .route("/grant/role", post(grant))
         .route("/revoke/role", post(revoke))
         .route("/change/role", post(change))
         .route("/expire/role", post(expire))
+        .route("/grant/role/org", post(grant_org))
+        .route("/revoke/role/org", post(revoke_org))
         .layer(restrict_layer)
         .boxed()
 }
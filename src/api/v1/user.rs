@@ -1,10 +1,20 @@
 use crate::{
     repository::{
-        dao::User,
-        dto::{ChangePassword, QueryUser, ResetPassword, UpdateUser},
+        dao::{Impersonation, PasswordHistory, User},
+        dto::{
+            is_verification_enabled, ChangePassword, ChangeSysRole, CreateUser, DeleteUser,
+            Impersonate, Logout, NewEmailVerification, QueryLockout, QueryUser, ResetPassword,
+            RevokeSessions, UnlockUser, UpdateUser, UpdateUserStatus,
+        },
         Dao,
     },
-    util::{jwt::Auth, restrict::Restrict, APIResult},
+    util::{
+        audit::Actor,
+        jwt::{self, Auth, Payload},
+        mail,
+        restrict::Restrict,
+        APIError, APIResult,
+    },
 };
 use axum::{
     extract::{Extension, Path, Query},
@@ -22,34 +32,57 @@ async fn all(Query(q): Query<QueryUser>) -> APIResult {
 }
 
 async fn one(Path(id): Path<String>) -> APIResult {
-    let one: User = User::find_by_id(&id).await?.into();
+    let one: User = User::find_by_id(&id).await?;
     Ok(reply!(one))
 }
 
-async fn update(Path(id): Path<String>, Json(body): Json<UpdateUser>) -> APIResult {
+async fn update(
+    Path(id): Path<String>,
+    Json(body): Json<UpdateUser>,
+    Extension(auth): Extension<Auth>,
+) -> APIResult {
+    if id != auth.id && !auth.is_admin {
+        return Err(reject!("仅管理员可访问"));
+    }
     body.validate()?;
+    let prev: User = User::find_by_id(&id).await?;
     let updated = body.save(&id).await?;
+    if let (Some(email), true) = (updated.email.clone(), prev.email != updated.email) {
+        if is_verification_enabled() {
+            let verification = NewEmailVerification { user_id: updated.id.clone(), email };
+            let (_, mail) = verification.create(&updated.username).await?;
+            mail::deliver(mail);
+        }
+    }
     Ok(reply!(updated))
 }
 
 async fn change_password(
     Json(body): Json<ChangePassword>,
     Extension(auth): Extension<Auth>,
+    Extension(payload): Extension<Payload>,
 ) -> APIResult {
+    if payload.act.is_some() {
+        return Err(reject!("模拟登录不能修改密码"));
+    }
     body.validate()?;
     let user = match User::find_by_id(&auth.id).await {
         Ok(val) => val,
         Err(_) => return Err(reject!("用户不存在")),
     };
-    if !body.is_password_matched(&user.password) {
+    if !body.is_password_matched(&user.password).await {
         return Err(reject!("旧密码不正确"));
     }
+    if PasswordHistory::is_reused(&user, &body.new_password).await? {
+        return Err(reject!("不能使用最近用过的密码"));
+    }
     let user = body.change_password(&user).await?;
+    RevokeSessions { user_id: user.id.clone() }.save().await?;
     Ok(reply!(user))
 }
 
 async fn reset_password(
-    id: String,
+    Path(id): Path<String>,
     Json(body): Json<ResetPassword>,
     Extension(auth): Extension<Auth>,
 ) -> APIResult {
@@ -61,10 +94,177 @@ async fn reset_password(
         Ok(val) => val,
         Err(_) => return Err(reject!("用户不存在")),
     };
+    if PasswordHistory::is_reused(&user, &body.new_password).await? {
+        return Err(reject!("不能使用最近用过的密码"));
+    }
     let user = body.reset_password(&user).await?;
+    RevokeSessions { user_id: user.id.clone() }.save().await?;
+    Ok(reply!(user))
+}
+
+async fn logout(Json(body): Json<Logout>, Extension(payload): Extension<Payload>) -> APIResult {
+    body.validate()?;
+    let revoked = body.save(&payload).await?;
+    Ok(reply!(revoked))
+}
+
+async fn revoke_sessions(Path(id): Path<String>, Extension(auth): Extension<Auth>) -> APIResult {
+    if id != auth.id && !auth.is_admin {
+        return Err(reject!("仅管理员可访问"));
+    }
+    let user = match User::find_by_id(&id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!("用户不存在")),
+    };
+    let revoked = RevokeSessions { user_id: user.id }.save().await?;
+    Ok(reply!(revoked))
+}
+
+async fn unlock(Path(id): Path<String>, Extension(auth): Extension<Auth>) -> APIResult {
+    if !auth.is_admin {
+        return Err(reject!("仅管理员可访问"));
+    }
+    let user = match User::find_by_id(&id).await {
+        Ok(val) => val,
+        Err(_) => return Err(reject!("用户不存在")),
+    };
+    let unlocked = UnlockUser {
+        user_id: user.id,
+        unlocked_by: auth.id,
+    }
+    .save()
+    .await?;
+    Ok(reply!(unlocked))
+}
+
+async fn lockouts(Query(q): Query<QueryLockout>, Extension(auth): Extension<Auth>) -> APIResult {
+    if !auth.is_admin {
+        return Err(reject!("仅管理员可访问"));
+    }
+    q.validate()?;
+    let all = q.find_all().await?;
+    Ok(reply!(all))
+}
+
+/// the target of an admin action, admins can't act on their own account
+async fn find_other_user(id: &str, auth: &Auth) -> Result<User, APIError> {
+    if !auth.is_admin {
+        return Err(reject!("仅管理员可访问"));
+    }
+    if id == auth.id {
+        return Err(reject!("不能操作自己的账号"));
+    }
+    match User::find_by_id(id).await {
+        Ok(val) => Ok(val),
+        Err(_) => Err(reject!("用户不存在")),
+    }
+}
+
+async fn create(Json(body): Json<CreateUser>, Extension(auth): Extension<Auth>) -> APIResult {
+    if !auth.is_admin {
+        return Err(reject!("仅管理员可访问"));
+    }
+    body.validate()?;
+    if User::find_by_username(&body.username).await.is_ok() {
+        return Err(reject!("用户已存在"));
+    }
+    let user = body.create().await?;
+    if let (Some(email), false) = (user.email.clone(), user.is_email_verified()) {
+        if is_verification_enabled() {
+            let verification = NewEmailVerification { user_id: user.id.clone(), email };
+            let (_, mail) = verification.create(&user.username).await?;
+            mail::deliver(mail);
+        }
+    }
+    Ok(reply!(user))
+}
+
+async fn disable(
+    Path(id): Path<String>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    let user = find_other_user(&id, &auth).await?;
+    let user = UpdateUserStatus { is_actived: false }.save(&user, &actor).await?;
+    RevokeSessions { user_id: user.id.clone() }.save().await?;
+    Ok(reply!(user))
+}
+
+async fn enable(
+    Path(id): Path<String>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    let user = find_other_user(&id, &auth).await?;
+    let user = UpdateUserStatus { is_actived: true }.save(&user, &actor).await?;
+    Ok(reply!(user))
+}
+
+async fn change_sys_role(
+    Path(id): Path<String>,
+    Json(body): Json<ChangeSysRole>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    let user = find_other_user(&id, &auth).await?;
+    body.validate()?;
+    let user = body.save(&user, &actor).await?;
+    // tokens carry `is_admin`, so the change applies from the next sign in
+    RevokeSessions { user_id: user.id.clone() }.save().await?;
     Ok(reply!(user))
 }
 
+async fn remove(
+    Path(id): Path<String>,
+    Extension(auth): Extension<Auth>,
+    actor: Actor,
+) -> APIResult {
+    let user = find_other_user(&id, &auth).await?;
+    let removed = DeleteUser { user_id: user.id.clone() }.save(&user, &actor).await?;
+    RevokeSessions { user_id: user.id }.save().await?;
+    Ok(reply!(removed))
+}
+
+async fn impersonate(
+    Path(id): Path<String>,
+    Json(body): Json<Impersonate>,
+    Extension(auth): Extension<Auth>,
+    Extension(payload): Extension<Payload>,
+) -> APIResult {
+    if payload.act.is_some() {
+        return Err(reject!("模拟登录不能再次模拟"));
+    }
+    let user = find_other_user(&id, &auth).await?;
+    body.validate()?;
+    if user.is_actived == 0 {
+        return Err(reject!("用户被禁用"));
+    }
+    if user.sys_role.as_deref() == Some("admin") {
+        return Err(reject!("不能模拟管理员"));
+    }
+    let (token, payload) = jwt::generate_impersonation(
+        Auth {
+            id: user.id.clone(),
+            username: user.username.clone(),
+            is_admin: false,
+        },
+        &auth.id,
+    );
+    let impersonation = body.create(&auth.id, &payload).await?;
+    Ok(reply!({
+      "token": token, "expires_in": jwt::impersonation_ttl().num_seconds(),
+      "user": user, "impersonation": impersonation
+    }))
+}
+
+async fn impersonations(Path(id): Path<String>, Extension(auth): Extension<Auth>) -> APIResult {
+    if !auth.is_admin {
+        return Err(reject!("仅管理员可访问"));
+    }
+    let all = Impersonation::find_by_user(&id).await?;
+    Ok(reply!(all))
+}
+
 async fn me(Extension(auth): Extension<Auth>) -> APIResult {
     let user = User::find_by_id(auth.id).await?;
     Ok(reply!(user))
@@ -74,11 +274,20 @@ pub fn apply_routes() -> Router<BoxRoute> {
     let router = Router::new();
     let restrict_layer = RequireAuthorizationLayer::custom(Restrict::new());
     router
-        .route("/user", get(all))
-        .route("/user/:id", put(update).get(one))
+        .route("/user", get(all).post(create))
+        .route("/user/:id", put(update).get(one).delete(remove))
+        .route("/user/:id/disable", post(disable))
+        .route("/user/:id/enable", post(enable))
+        .route("/user/:id/sys-role", put(change_sys_role))
+        .route("/user/:id/impersonate", post(impersonate))
+        .route("/user/:id/impersonations", get(impersonations))
         .route("/change/password", post(change_password))
         .route("/reset/:id/password", post(reset_password))
         .route("/me", get(me))
+        .route("/logout", post(logout))
+        .route("/user/:id/sessions/revoke", post(revoke_sessions))
+        .route("/user/:id/unlock", post(unlock))
+        .route("/lockout", get(lockouts))
         .layer(restrict_layer)
         .boxed()
 }
@@ -0,0 +1,34 @@
+use crate::util::jwt;
+use axum::{handler::get, routing::BoxRoute, Json, Router};
+use serde_json::{json, Value};
+
+async fn jwks() -> Json<Value> {
+    Json(jwt::jwks())
+}
+
+async fn openid_configuration() -> Json<Value> {
+    let issuer = jwt::issuer();
+    Json(json!({
+      "issuer": issuer,
+      "authorization_endpoint": format!("{}/api/v1/oauth/authorize", issuer),
+      "token_endpoint": format!("{}/api/v1/oauth/token", issuer),
+      "userinfo_endpoint": format!("{}/api/v1/userinfo", issuer),
+      "jwks_uri": format!("{}/.well-known/jwks.json", issuer),
+      "response_types_supported": ["code"],
+      "grant_types_supported": ["authorization_code", "client_credentials"],
+      "subject_types_supported": ["public"],
+      "id_token_signing_alg_values_supported": [jwt::algorithm()],
+      "scopes_supported": ["openid", "profile", "email"],
+      "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+      "code_challenge_methods_supported": ["S256"],
+      "claims_supported": ["iss", "sub", "aud", "exp", "iat", "nonce", "preferred_username", "picture", "email", "roles"]
+    }))
+}
+
+pub fn apply_routes() -> Router<BoxRoute> {
+    let router = Router::new();
+    router
+        .route("/.well-known/jwks.json", get(jwks))
+        .route("/.well-known/openid-configuration", get(openid_configuration))
+        .boxed()
+}
@@ -3,4 +3,5 @@
 
 pub mod repository;
 pub mod api;
-pub mod util;
\ No newline at end of file
+pub mod util;
+pub mod task;
\ No newline at end of file
@@ -1,5 +1,5 @@
 use axum::Server;
-use azman::{api::apply_routes, util::handle_error};
+use azman::{api::apply_routes, task::{load_lockouts, load_revocations, spawn_expire_sweeper, spawn_lockout_sync, spawn_revocation_sync}, util::{handle_error, jwt::load_keys}};
 use dotenv::dotenv;
 use std::{env, net::SocketAddr, time::Duration};
 use tower::{timeout::TimeoutLayer, ServiceBuilder};
@@ -23,8 +23,16 @@ async fn main() {
         .parse::<u16>()
         .expect("environment variable APP_PORT must be u16");
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    load_keys();
+    load_revocations()
+        .await
+        .expect("load token revocations failed");
+    load_lockouts().await.expect("load login lockouts failed");
+    spawn_expire_sweeper();
+    spawn_revocation_sync();
+    spawn_lockout_sync();
     Server::bind(&addr)
-        .serve(routes.into_make_service())
+        .serve(routes.into_make_service_with_connect_info::<SocketAddr, _>())
         .await
         .expect("app started failed")
 }
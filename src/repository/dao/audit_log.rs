@@ -0,0 +1,30 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::serde_format::naive_datetime,
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+/// append-only record of a mutation, never updated or deleted
+#[crud_table(table_name: "audit_logs")]
+#[derive(Debug, Clone, Dao)]
+pub struct AuditLog {
+    pub id: String,
+    pub actor_id: String,
+    pub impersonator_id: Option<String>,
+    // `<target_type>.<verb>`, e.g. `user_role.grant`
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub domain_id: Option<String>,
+    // JSON snapshots of the target, `before` is reserved in MySQL and column lists aren't quoted
+    pub before_json: Option<String>,
+    pub after_json: Option<String>,
+    pub request_id: String,
+    pub ip: String,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
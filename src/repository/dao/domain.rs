@@ -5,9 +5,13 @@ use crate::{
 use app_macro::Dao;
 use async_trait::async_trait;
 use chrono::NaiveDateTime;
-use rbatis::{crud::CRUD, wrapper::Wrapper};
+use rbatis::{
+    crud::{CRUDMut, CRUD},
+    executor::RBatisTxExecutor,
+    wrapper::Wrapper,
+};
 use serde::Serialize;
-use super::{UserRole, User};
+use super::{Role, UserRole, User};
 
 #[crud_table(table_name: "domains")]
 #[derive(Debug, Clone, Dao)]
@@ -17,6 +21,13 @@ pub struct Domain {
     pub description: Option<String>,
     pub default_role_id: String,
     pub admin_role_id: String,
+    // provision unknown federated users on first login
+    #[serde(serialize_with = "i32_bool::serialize")]
+    pub allow_jit: i32,
+    // members with unverified email: 0 unaffected, 1 no permissions, 2 can't sign in
+    pub email_verification: i32,
+    // `deny_overrides` or `allow_overrides`, see `dto::decide_rule`
+    pub conflict_strategy: String,
     #[serde(serialize_with = "i32_bool::serialize")]
     pub is_deleted: i32,
     #[serde(serialize_with = "naive_datetime::serialize")]
@@ -30,9 +41,31 @@ impl Domain{
     let w = POOL.new_wrapper().r#in("admin_role_id", &role_ids);
     Self::find_list(w).await
   }
+  /// locks the domain row until `tx` ends, writes checked against the domain's role
+  /// constraints take it first so that they run one after another
+  pub async fn lock(tx: &mut RBatisTxExecutor<'_>, id: &str) -> Result<Self, DBError> {
+    let w = POOL.new_wrapper().eq("id", id).push_sql(" FOR UPDATE");
+    tx.fetch_by_wrapper(w).await
+  }
+  /// domains the user currently holds a role in
+  pub async fn find_by_member(user_id: &str) -> Result<Vec<Self>, DBError>{
+    let role_ids = Role::find_held_ids(user_id).await?;
+    if role_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut domain_ids: Vec<String> = Role::find_by_ids(role_ids)
+        .await?
+        .into_iter()
+        .map(|v| v.domain_id)
+        .collect();
+    domain_ids.sort();
+    domain_ids.dedup();
+    Self::find_by_ids(domain_ids).await
+  }
 }
 
 #[async_trait]
+#[allow(clippy::wrong_self_convention)]
 pub trait IntoVo{
   async fn into_vo(&self) -> Result<vo::Domain, DBError>;
 }
@@ -41,9 +74,9 @@ pub trait IntoVo{
 impl IntoVo for Domain{
   async fn into_vo(&self) -> Result<vo::Domain, DBError>{
     let mut domain = vo::Domain::from(self.clone());
-    let user_roles = UserRole::find_by_role(&self.admin_role_id).await?;
+    let user_roles = UserRole::find_by_role(&self.admin_role_id, false).await?;
     let user_ids: Vec<String> = user_roles.into_iter().map(|v| v.user_id).collect();
-    let users = if user_ids.len() > 0 {
+    let users = if !user_ids.is_empty() {
         User::find_by_ids(user_ids).await?
     } else {
         vec![]
@@ -0,0 +1,56 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::{
+        hash_token,
+        serde_format::{i32_bool, naive_datetime},
+    },
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+#[crud_table(table_name: "email_verification_tokens")]
+#[derive(Debug, Clone, Dao)]
+pub struct EmailVerificationToken {
+    pub id: String,
+    pub user_id: String,
+    pub email: String,
+    pub token_hash: String,
+    #[serde(serialize_with = "i32_bool::serialize")]
+    pub is_used: i32,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub expire: NaiveDateTime,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl EmailVerificationToken {
+    pub async fn find_by_token(token: &str) -> Result<Self, DBError> {
+        let w = POOL.new_wrapper().eq("token_hash", hash_token(token));
+        Self::find_one(w).await
+    }
+    /// marks the token used, returns false when it was already used
+    pub async fn consume(&self) -> Result<bool, DBError> {
+        let mut dao = self.clone();
+        dao.is_used = 1;
+        let w = POOL.new_wrapper().eq("id", &self.id).and().eq("is_used", 0);
+        Ok(dao.update_one(w).await? > 0)
+    }
+    pub async fn invalidate_by_user(user_id: &str) -> Result<u64, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .eq("user_id", user_id)
+            .and()
+            .eq("is_used", 0);
+        let tokens = Self::find_list(w).await?;
+        let mut invalidated = 0;
+        for mut token in tokens.into_iter() {
+            token.is_used = 1;
+            let w = POOL.new_wrapper().eq("id", &token.id);
+            invalidated += token.update_one(w).await?;
+        }
+        Ok(invalidated)
+    }
+}
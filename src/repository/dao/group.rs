@@ -0,0 +1,79 @@
+use super::{Domain, UserGroup};
+use crate::{
+    repository::{vo, DBError, Dao, POOL},
+    util::serde_format::{i32_bool, naive_datetime},
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// an ad-hoc set of users in a domain, beside the org tree
+#[crud_table(table_name: "user_groups")]
+#[derive(Debug, Clone, Dao)]
+pub struct Group {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub domain_id: String,
+    #[serde(serialize_with = "i32_bool::serialize")]
+    pub is_deleted: i32,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub updated_at: NaiveDateTime,
+    pub created_by: Option<String>,
+    pub updated_by: Option<String>,
+}
+
+impl Group {
+    pub async fn find_by_ids(
+        id: Vec<String>,
+        domain_id: Option<String>,
+    ) -> Result<Vec<Self>, DBError> {
+        let mut w = POOL.new_wrapper().r#in("id", &id);
+        if let Some(domain_id) = domain_id {
+            w = w.and().eq("domain_id", domain_id);
+        }
+        Self::find_list(w).await
+    }
+    /// groups the user currently belongs to, in one domain if given
+    pub async fn find_by_user(user_id: &str, domain_id: Option<String>) -> Result<Vec<Self>, DBError> {
+        let group_ids: Vec<String> = UserGroup::find_by_user(user_id, false)
+            .await?
+            .into_iter()
+            .map(|v| v.group_id)
+            .collect();
+        if group_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        Self::find_by_ids(group_ids, domain_id).await
+    }
+}
+
+#[async_trait]
+#[allow(clippy::wrong_self_convention)]
+pub trait IntoVecOfVo {
+    async fn into_vo(&self) -> Result<Vec<vo::Group>, DBError>;
+}
+
+#[async_trait]
+impl IntoVecOfVo for Vec<Group> {
+    async fn into_vo(&self) -> Result<Vec<vo::Group>, DBError> {
+        let domain_ids: Vec<String> = self.iter().map(|v| v.domain_id.clone()).collect();
+        let w = POOL.new_wrapper().r#in("id", &domain_ids);
+        let domains = POOL.fetch_list_by_wrapper::<Domain>(w).await?;
+        let mut domain_map = HashMap::new();
+        for domain in domains {
+            domain_map.insert(domain.id.clone(), domain.clone());
+        }
+        let mut records: Vec<vo::Group> = self.iter().map(|v| vo::Group::from(v.clone())).collect();
+        for r in &mut records {
+            let domain = domain_map.get(&r.domain_id).cloned();
+            r.domain = domain.map(Into::into);
+        }
+        Ok(records)
+    }
+}
@@ -0,0 +1,59 @@
+use super::UserGroup;
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::{now, serde_format::naive_datetime},
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+/// a role granted to every member of the group
+#[crud_table(table_name: "group_has_roles")]
+#[derive(Debug, Clone, Dao)]
+pub struct GroupRole {
+    pub group_id: String,
+    pub role_id: String,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub expire: NaiveDateTime,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl GroupRole {
+    pub async fn find_by_group(group_id: &str, include_expired: bool) -> Result<Vec<Self>, DBError> {
+        let mut w = POOL.new_wrapper().eq("group_id", group_id);
+        if !include_expired {
+            w = w.and().gt("expire", now());
+        }
+        Self::find_list(w).await
+    }
+    pub async fn find_by_groups(group_ids: &[String], include_expired: bool) -> Result<Vec<Self>, DBError> {
+        let mut w = POOL.new_wrapper().r#in("group_id", group_ids);
+        if !include_expired {
+            w = w.and().gt("expire", now());
+        }
+        Self::find_list(w).await
+    }
+    /// grants reaching the user through the groups they belong to
+    pub async fn find_by_user(user_id: &str, include_expired: bool) -> Result<Vec<Self>, DBError> {
+        let group_ids: Vec<String> = UserGroup::find_by_user(user_id, include_expired)
+            .await?
+            .into_iter()
+            .map(|v| v.group_id)
+            .collect();
+        if group_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        Self::find_by_groups(&group_ids, include_expired).await
+    }
+    pub async fn find_expired(at: NaiveDateTime) -> Result<Vec<Self>, DBError> {
+        let w = POOL.new_wrapper().le("expire", at);
+        Self::find_list(w).await
+    }
+    pub async fn delete_expired(at: NaiveDateTime) -> Result<u64, DBError> {
+        let w = POOL.new_wrapper().le("expire", at);
+        Self::delete_one(w).await
+    }
+}
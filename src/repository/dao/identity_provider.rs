@@ -0,0 +1,54 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::serde_format::{i32_bool, naive_datetime},
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+#[crud_table(table_name: "identity_providers")]
+#[derive(Debug, Clone, Dao)]
+pub struct IdentityProvider {
+    pub id: String,
+    pub name: String,
+    pub domain_id: String,
+    pub issuer: String,
+    // expected `aud` of the upstream tokens
+    pub audience: String,
+    // https url, for OIDC issuers
+    pub jwks_uri: Option<String>,
+    // JWK set file on the server, set by sys admins only
+    pub jwks_path: Option<String>,
+    // PEM public key, for signed assertions of trusted services
+    pub public_key: Option<String>,
+    #[serde(serialize_with = "i32_bool::serialize")]
+    pub is_deleted: i32,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub updated_at: NaiveDateTime,
+    pub created_by: Option<String>,
+    pub updated_by: Option<String>,
+}
+
+impl IdentityProvider {
+    /// removed providers keep their row, so they are skipped here unlike
+    /// `Dao::find_by_id`, which this shadows
+    pub async fn find_by_id<T>(id: T) -> Result<Self, DBError>
+    where
+        T: Serialize + Send + Sync,
+    {
+        let w = POOL.new_wrapper().eq("id", id).and().eq("is_deleted", 0);
+        Self::find_one(w).await
+    }
+    pub async fn find_by_domain(domain_id: &str) -> Result<Vec<Self>, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .eq("domain_id", domain_id)
+            .and()
+            .eq("is_deleted", 0);
+        Self::find_list(w).await
+    }
+}
@@ -0,0 +1,34 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::serde_format::naive_datetime,
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+/// an access token an admin obtained to act as `user_id`
+#[crud_table(table_name: "impersonations")]
+#[derive(Debug, Clone, Dao)]
+pub struct Impersonation {
+    pub id: String,
+    pub actor_id: String,
+    pub user_id: String,
+    pub reason: String,
+    pub jti: String,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub expire: NaiveDateTime,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl Impersonation {
+    pub async fn find_by_user(user_id: &str) -> Result<Vec<Self>, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .eq("user_id", user_id)
+            .order_by(false, &["created_at"]);
+        Self::find_list(w).await
+    }
+}
@@ -0,0 +1,60 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::serde_format::{i32_bool, naive_datetime},
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+/// a lock of an account (`user_id`/`username` set) or a client address (`ip` set)
+/// after too many failed logins, kept for security review
+#[crud_table(table_name: "login_lockouts")]
+#[derive(Debug, Clone, Dao)]
+pub struct LoginLockout {
+    pub id: String,
+    pub lock_key: String,
+    pub user_id: Option<String>,
+    // identifier the failed logins were made with
+    pub username: Option<String>,
+    pub ip: Option<String>,
+    pub failures: i32,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub locked_until: NaiveDateTime,
+    #[serde(serialize_with = "i32_bool::serialize")]
+    pub is_unlocked: i32,
+    pub unlocked_by: Option<String>,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl LoginLockout {
+    pub async fn find_active(at: NaiveDateTime) -> Result<Vec<Self>, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .gt("locked_until", at)
+            .and()
+            .eq("is_unlocked", 0);
+        Self::find_list(w).await
+    }
+    /// locks lifted by an admin before they ran out
+    pub async fn find_lifted(at: NaiveDateTime) -> Result<Vec<Self>, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .gt("locked_until", at)
+            .and()
+            .eq("is_unlocked", 1);
+        Self::find_list(w).await
+    }
+    pub async fn find_active_by_key(lock_key: &str, at: NaiveDateTime) -> Result<Vec<Self>, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .eq("lock_key", lock_key)
+            .and()
+            .gt("locked_until", at)
+            .and()
+            .eq("is_unlocked", 0);
+        Self::find_list(w).await
+    }
+}
@@ -3,9 +3,34 @@ pub mod role;
 pub mod perm;
 pub mod org;
 pub mod domain;
-mod role_perm;
+pub mod role_perm;
+pub mod role_parent;
+pub mod role_exclusion;
+pub mod role_prerequisite;
 mod user_role;
 mod user_org;
+mod org_role;
+pub mod group;
+mod user_group;
+mod group_role;
+pub mod resource_grant;
+mod relation_schema;
+mod relation_tuple;
+mod refresh_token;
+mod token_revocation;
+mod session_role;
+mod oauth_client;
+mod oauth_code;
+mod identity_provider;
+mod user_identity;
+mod user_totp;
+mod recovery_code;
+mod login_lockout;
+mod password_history;
+mod password_reset_token;
+mod email_verification_token;
+mod impersonation;
+mod audit_log;
 
 pub use user::User;
 pub use role::Role;
@@ -13,5 +38,30 @@ pub use perm::Perm;
 pub use org::Org;
 pub use domain::Domain;
 pub use role_perm::RolePerm;
+pub use role_parent::RoleParent;
+pub use role_exclusion::{RoleExclusion, RoleExclusionMember};
+pub use role_prerequisite::RolePrerequisite;
 pub use user_role::UserRole;
-pub use user_org::UserOrg;
\ No newline at end of file
+pub use user_org::UserOrg;
+pub use org_role::OrgRole;
+pub use group::Group;
+pub use user_group::UserGroup;
+pub use group_role::GroupRole;
+pub use resource_grant::ResourceGrant;
+pub use relation_schema::RelationSchema;
+pub use relation_tuple::RelationTuple;
+pub use refresh_token::RefreshToken;
+pub use token_revocation::TokenRevocation;
+pub use session_role::SessionRole;
+pub use oauth_client::OAuthClient;
+pub use oauth_code::OAuthCode;
+pub use identity_provider::IdentityProvider;
+pub use user_identity::UserIdentity;
+pub use user_totp::UserTotp;
+pub use recovery_code::RecoveryCode;
+pub use login_lockout::LoginLockout;
+pub use password_history::PasswordHistory;
+pub use password_reset_token::PasswordResetToken;
+pub use email_verification_token::EmailVerificationToken;
+pub use impersonation::Impersonation;
+pub use audit_log::AuditLog;
\ No newline at end of file
@@ -0,0 +1,71 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::serde_format::{i32_bool, naive_datetime},
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+#[crud_table(table_name: "oauth_clients")]
+#[derive(Debug, Clone, Dao)]
+pub struct OAuthClient {
+    pub id: String,
+    pub name: String,
+    pub secret_hash: Option<String>,
+    // space separated, as `scope` is in OAuth 2.0
+    pub redirect_uris: String,
+    pub grant_types: String,
+    pub scopes: String,
+    pub domain_id: String,
+    #[serde(serialize_with = "i32_bool::serialize")]
+    pub is_deleted: i32,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub updated_at: NaiveDateTime,
+    pub created_by: Option<String>,
+    pub updated_by: Option<String>,
+}
+
+impl OAuthClient {
+    /// removed clients keep their row, so they are skipped here unlike
+    /// `Dao::find_by_id`, which this shadows
+    pub async fn find_by_id<T>(id: T) -> Result<Self, DBError>
+    where
+        T: Serialize + Send + Sync,
+    {
+        let w = POOL.new_wrapper().eq("id", id).and().eq("is_deleted", 0);
+        Self::find_one(w).await
+    }
+    pub async fn find_by_domain(domain_id: &str) -> Result<Vec<Self>, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .eq("domain_id", domain_id)
+            .and()
+            .eq("is_deleted", 0)
+            .order_by(false, &["created_at"]);
+        Self::find_list(w).await
+    }
+    pub fn is_public(&self) -> bool {
+        self.secret_hash.is_none()
+    }
+    pub fn has_redirect_uri(&self, redirect_uri: &str) -> bool {
+        self.redirect_uris.split(' ').any(|v| v == redirect_uri)
+    }
+    pub fn has_grant_type(&self, grant_type: &str) -> bool {
+        self.grant_types.split(' ').any(|v| v == grant_type)
+    }
+    /// requested scopes the client is allowed to use, `None` if any of them is not
+    pub fn allowed_scope(&self, scope: &str) -> Option<String> {
+        let requested: Vec<&str> = scope.split(' ').filter(|v| !v.is_empty()).collect();
+        if requested
+            .iter()
+            .any(|v| !self.scopes.split(' ').any(|s| s == *v))
+        {
+            return None;
+        }
+        Some(requested.join(" "))
+    }
+}
@@ -0,0 +1,39 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::{
+        hash_token,
+        serde_format::{i32_bool, naive_datetime},
+    },
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+#[crud_table(table_name: "oauth_codes")]
+#[derive(Debug, Clone, Dao)]
+pub struct OAuthCode {
+    pub id: String,
+    pub code_hash: String,
+    pub client_id: String,
+    pub user_id: String,
+    pub redirect_uri: String,
+    pub scope: String,
+    pub nonce: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    #[serde(serialize_with = "i32_bool::serialize")]
+    pub is_used: i32,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub expire: NaiveDateTime,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl OAuthCode {
+    pub async fn find_by_code(code: &str) -> Result<Self, DBError> {
+        let w = POOL.new_wrapper().eq("code_hash", hash_token(code));
+        Self::find_one(w).await
+    }
+}
@@ -1,4 +1,4 @@
-use super::Domain;
+use super::{Domain, UserOrg};
 use crate::{
     repository::{vo, DBError, Dao, POOL},
     util::serde_format::{i32_bool, naive_datetime},
@@ -17,6 +17,7 @@ pub struct Org {
     pub name: String,
     pub description: Option<String>,
     pub domain_id: String,
+    pub parent_id: Option<String>,
     #[serde(serialize_with = "i32_bool::serialize")]
     pub is_deleted: i32,
     #[serde(serialize_with = "naive_datetime::serialize")]
@@ -42,9 +43,70 @@ impl Org {
         let w = POOL.new_wrapper().r#in("domain_id", &domain_ids);
         Self::find_list(w).await
     }
+    /// the org and all orgs below it, the org first
+    pub async fn find_subtree(&self) -> Result<Vec<Self>, DBError> {
+        let all = Self::find_all(vec![self.domain_id.clone()]).await?;
+        Ok(self.subtree_of(&all))
+    }
+    /// the orgs above this one, the parent first and the root last
+    pub async fn find_ancestors(&self) -> Result<Vec<Self>, DBError> {
+        let all: HashMap<String, Self> = Self::find_all(vec![self.domain_id.clone()])
+            .await?
+            .into_iter()
+            .map(|v| (v.id.clone(), v))
+            .collect();
+        let mut ancestors: Vec<Self> = vec![];
+        let mut parent_id = self.parent_id.clone();
+        while let Some(id) = parent_id {
+            // stop on broken links and on cycles
+            let parent = match all.get(&id) {
+                Some(val) if val.id != self.id && !ancestors.iter().any(|v| v.id == id) => val,
+                _ => break,
+            };
+            parent_id = parent.parent_id.clone();
+            ancestors.push(parent.clone());
+        }
+        Ok(ancestors)
+    }
+    /// ids of the orgs the user belongs to and of the orgs above those
+    pub async fn find_ids_by_member(user_id: &str, include_expired: bool) -> Result<Vec<String>, DBError> {
+        let member_ids: Vec<String> = UserOrg::find_by_user(user_id, include_expired)
+            .await?
+            .into_iter()
+            .map(|v| v.org_id)
+            .collect();
+        if member_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut org_ids: Vec<String> = vec![];
+        for org in Self::find_by_ids(member_ids, None).await? {
+            for ancestor in org.find_ancestors().await? {
+                org_ids.push(ancestor.id);
+            }
+            org_ids.push(org.id);
+        }
+        org_ids.sort();
+        org_ids.dedup();
+        Ok(org_ids)
+    }
+    pub fn subtree_of(&self, all: &[Self]) -> Vec<Self> {
+        let mut subtree = vec![self.clone()];
+        let mut i = 0;
+        while i < subtree.len() {
+            let id = subtree[i].id.clone();
+            for org in all.iter().filter(|v| v.parent_id.as_deref() == Some(id.as_str())) {
+                if !subtree.iter().any(|v| v.id == org.id) {
+                    subtree.push(org.clone());
+                }
+            }
+            i += 1;
+        }
+        subtree
+    }
 }
 
 #[async_trait]
+#[allow(clippy::wrong_self_convention)]
 pub trait IntoVecOfVo {
     async fn into_vo(&self) -> Result<Vec<vo::Org>, DBError>;
 }
@@ -60,7 +122,7 @@ impl IntoVecOfVo for Vec<Org> {
             domain_map.insert(domain.id.clone(), domain.clone());
         }
         let mut records: Vec<vo::Org> = self.iter().map(|v| vo::Org::from(v.clone())).collect();
-        for mut r in &mut records {
+        for r in &mut records {
             let domain = domain_map.get(&r.domain_id).cloned();
             r.domain = domain.map(Into::into);
         }
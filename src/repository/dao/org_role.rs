@@ -0,0 +1,55 @@
+use super::Org;
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::{now, serde_format::naive_datetime},
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+/// a role granted to every member of the org and of the orgs below it
+#[crud_table(table_name: "org_has_roles")]
+#[derive(Debug, Clone, Dao)]
+pub struct OrgRole {
+    pub org_id: String,
+    pub role_id: String,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub expire: NaiveDateTime,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl OrgRole {
+    pub async fn find_by_org(org_id: &str, include_expired: bool) -> Result<Vec<Self>, DBError> {
+        let mut w = POOL.new_wrapper().eq("org_id", org_id);
+        if !include_expired {
+            w = w.and().gt("expire", now());
+        }
+        Self::find_list(w).await
+    }
+    pub async fn find_by_orgs(org_ids: &[String], include_expired: bool) -> Result<Vec<Self>, DBError> {
+        let mut w = POOL.new_wrapper().r#in("org_id", org_ids);
+        if !include_expired {
+            w = w.and().gt("expire", now());
+        }
+        Self::find_list(w).await
+    }
+    /// grants reaching the user through the orgs they belong to or the orgs above those
+    pub async fn find_by_user(user_id: &str, include_expired: bool) -> Result<Vec<Self>, DBError> {
+        let org_ids = Org::find_ids_by_member(user_id, include_expired).await?;
+        if org_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        Self::find_by_orgs(&org_ids, include_expired).await
+    }
+    pub async fn find_expired(at: NaiveDateTime) -> Result<Vec<Self>, DBError> {
+        let w = POOL.new_wrapper().le("expire", at);
+        Self::find_list(w).await
+    }
+    pub async fn delete_expired(at: NaiveDateTime) -> Result<u64, DBError> {
+        let w = POOL.new_wrapper().le("expire", at);
+        Self::delete_one(w).await
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::{now, password, serde_format::naive_datetime, uuid_v4},
+};
+use super::User;
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+/// hashes of the passwords a user had, newest first
+#[crud_table(table_name: "password_histories")]
+#[derive(Debug, Clone, Dao)]
+pub struct PasswordHistory {
+    pub id: String,
+    pub user_id: String,
+    #[serde(skip_serializing)]
+    pub password: String,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl PasswordHistory {
+    pub async fn find_recent(user_id: &str, limit: usize) -> Result<Vec<Self>, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .eq("user_id", user_id)
+            .order_by(false, &["created_at"])
+            .limit(limit as u64);
+        Self::find_list(w).await
+    }
+    /// whether `plain` is the current password or one of the last `password::POLICY.history`
+    pub async fn is_reused(user: &User, plain: &str) -> Result<bool, DBError> {
+        if password::POLICY.history == 0 {
+            return Ok(false);
+        }
+        let recent = Self::find_recent(&user.id, password::POLICY.history).await?;
+        let mut hashes = vec![user.password.clone()];
+        hashes.extend(recent.into_iter().map(|v| v.password));
+        Ok(password::verify_any(plain, hashes).await)
+    }
+    /// records `hashed` and drops entries beyond the configured history
+    pub async fn push(user_id: &str, hashed: &str) -> Result<(), DBError> {
+        let dao = Self {
+            id: uuid_v4(),
+            user_id: user_id.to_string(),
+            password: hashed.to_string(),
+            created_at: now(),
+        };
+        Self::create_one(&dao).await?;
+        let w = POOL
+            .new_wrapper()
+            .eq("user_id", user_id)
+            .order_by(false, &["created_at"]);
+        let all = Self::find_list(w).await?;
+        for v in all.iter().skip(password::POLICY.history.max(1)) {
+            Self::delete_one(POOL.new_wrapper().eq("id", &v.id)).await?;
+        }
+        Ok(())
+    }
+}
@@ -46,6 +46,7 @@ impl Perm {
 }
 
 #[async_trait]
+#[allow(clippy::wrong_self_convention)]
 pub trait IntoVecOfVo {
     async fn into_vo(&self) -> Result<Vec<vo::Perm>, DBError>;
 }
@@ -61,7 +62,7 @@ impl IntoVecOfVo for Vec<Perm> {
             domain_map.insert(domain.id.clone(), domain.clone());
         }
         let mut records: Vec<vo::Perm> = self.iter().map(|v| vo::Perm::from(v.clone())).collect();
-        for mut r in &mut records {
+        for r in &mut records {
             let domain = domain_map.get(&r.domain_id).cloned();
             r.domain = domain.map(Into::into);
         }
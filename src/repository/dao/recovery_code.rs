@@ -0,0 +1,49 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::{
+        hash_token,
+        serde_format::{i32_bool, naive_datetime},
+    },
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+#[crud_table(table_name: "recovery_codes")]
+#[derive(Debug, Clone, Dao)]
+pub struct RecoveryCode {
+    pub id: String,
+    pub user_id: String,
+    pub code_hash: String,
+    #[serde(serialize_with = "i32_bool::serialize")]
+    pub is_used: i32,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl RecoveryCode {
+    pub async fn find_unused(user_id: &str, code: &str) -> Result<Self, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .eq("user_id", user_id)
+            .and()
+            .eq("code_hash", hash_token(code.trim()))
+            .and()
+            .eq("is_used", 0);
+        Self::find_one(w).await
+    }
+    pub async fn count_unused(user_id: &str) -> Result<usize, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .eq("user_id", user_id)
+            .and()
+            .eq("is_used", 0);
+        Ok(Self::find_list(w).await?.len())
+    }
+    pub async fn delete_by_user(user_id: &str) -> Result<u64, DBError> {
+        let w = POOL.new_wrapper().eq("user_id", user_id);
+        Self::delete_one(w).await
+    }
+}
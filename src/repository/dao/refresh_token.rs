@@ -0,0 +1,61 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::{
+        hash_token,
+        serde_format::{i32_bool, naive_datetime},
+    },
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+#[crud_table(table_name: "refresh_tokens")]
+#[derive(Debug, Clone, Dao)]
+pub struct RefreshToken {
+    pub id: String,
+    pub user_id: String,
+    pub family_id: String,
+    pub token_hash: String,
+    pub replaced_by: Option<String>,
+    #[serde(serialize_with = "i32_bool::serialize")]
+    pub is_revoked: i32,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub expire: NaiveDateTime,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl RefreshToken {
+    pub async fn find_by_token(token: &str) -> Result<Self, DBError> {
+        let w = POOL.new_wrapper().eq("token_hash", hash_token(token));
+        Self::find_one(w).await
+    }
+    pub async fn revoke_family(family_id: &str) -> Result<u64, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .eq("family_id", family_id)
+            .and()
+            .eq("is_revoked", 0);
+        Self::revoke_all(w).await
+    }
+    pub async fn revoke_by_user(user_id: &str) -> Result<u64, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .eq("user_id", user_id)
+            .and()
+            .eq("is_revoked", 0);
+        Self::revoke_all(w).await
+    }
+    async fn revoke_all(w: Wrapper) -> Result<u64, DBError> {
+        let tokens = Self::find_list(w).await?;
+        let mut revoked = 0;
+        for mut token in tokens.into_iter() {
+            token.is_revoked = 1;
+            let w = POOL.new_wrapper().eq("id", &token.id);
+            revoked += token.update_one(w).await?;
+        }
+        Ok(revoked)
+    }
+}
@@ -0,0 +1,29 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::serde_format::naive_datetime,
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+/// object types and relation rewrites of a domain, see `util::relation`
+#[crud_table(table_name: "relation_schemas")]
+#[derive(Debug, Clone, Dao)]
+pub struct RelationSchema {
+    pub domain_id: String,
+    // JSON
+    pub definition: String,
+    pub revision: i64,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub updated_at: NaiveDateTime,
+    pub updated_by: Option<String>,
+}
+
+impl RelationSchema {
+    pub async fn find_by_domain(domain_id: &str) -> Result<Option<Self>, DBError> {
+        let w = POOL.new_wrapper().eq("domain_id", domain_id);
+        Ok(Self::find_list(w).await?.into_iter().next())
+    }
+}
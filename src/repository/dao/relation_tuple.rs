@@ -0,0 +1,83 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::{
+        now,
+        relation::{Object, Subject, Tuple},
+        serde_format::naive_datetime,
+        uuid_v4,
+    },
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+/// `object_type:object_id#relation@subject_type:subject_id[#subject_relation]`
+#[crud_table(table_name: "relation_tuples")]
+#[derive(Debug, Clone, Dao)]
+pub struct RelationTuple {
+    pub id: String,
+    pub domain_id: String,
+    pub object_type: String,
+    pub object_id: String,
+    pub relation: String,
+    pub subject_type: String,
+    pub subject_id: String,
+    pub subject_relation: String,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+    pub created_by: Option<String>,
+}
+
+impl RelationTuple {
+    pub fn new(domain_id: &str, tuple: &Tuple, created_by: &str) -> Self {
+        Self {
+            id: uuid_v4(),
+            domain_id: domain_id.to_string(),
+            object_type: tuple.object.object_type.clone(),
+            object_id: tuple.object.object_id.clone(),
+            relation: tuple.relation.clone(),
+            subject_type: tuple.subject.object.object_type.clone(),
+            subject_id: tuple.subject.object.object_id.clone(),
+            subject_relation: tuple.subject.relation.clone().unwrap_or_default(),
+            created_at: now(),
+            created_by: Some(created_by.to_string()),
+        }
+    }
+    pub async fn find_by_domain(domain_id: &str) -> Result<Vec<Self>, DBError> {
+        let w = POOL.new_wrapper().eq("domain_id", domain_id);
+        Self::find_list(w).await
+    }
+    /// matches exactly this tuple of the domain
+    pub fn wrapper_of(domain_id: &str, tuple: &Tuple) -> Wrapper {
+        POOL.new_wrapper()
+            .eq("domain_id", domain_id)
+            .and()
+            .eq("object_type", &tuple.object.object_type)
+            .and()
+            .eq("object_id", &tuple.object.object_id)
+            .and()
+            .eq("relation", &tuple.relation)
+            .and()
+            .eq("subject_type", &tuple.subject.object.object_type)
+            .and()
+            .eq("subject_id", &tuple.subject.object.object_id)
+            .and()
+            .eq("subject_relation", tuple.subject.relation.clone().unwrap_or_default())
+    }
+}
+
+impl From<RelationTuple> for Tuple {
+    fn from(d: RelationTuple) -> Self {
+        let relation = Some(d.subject_relation).filter(|v| !v.is_empty());
+        Self {
+            object: Object::new(&d.object_type, &d.object_id),
+            relation: d.relation,
+            subject: Subject {
+                object: Object::new(&d.subject_type, &d.subject_id),
+                relation,
+            },
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use super::{Org, Role, RoleParent, UserGroup};
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::{now, pattern, serde_format::naive_datetime},
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+pub const SUBJECT_TYPES: [&str; 4] = ["user", "role", "org", "group"];
+
+/// a perm granted on the resources matching `resource` only, to a user, role, org or group
+#[crud_table(table_name: "resource_grants")]
+#[derive(Debug, Clone, Dao)]
+pub struct ResourceGrant {
+    pub id: String,
+    pub perm_id: String,
+    /// a resource id like `project:42` or a pattern like `bucket:logs/*`
+    pub resource: String,
+    pub subject_type: String,
+    pub subject_id: String,
+    pub domain_id: String,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub expire: NaiveDateTime,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+    pub created_by: Option<String>,
+}
+
+impl ResourceGrant {
+    pub async fn find_by_ids(id: Vec<String>) -> Result<Vec<Self>, DBError> {
+        let w = POOL.new_wrapper().r#in("id", &id);
+        Self::find_list(w).await
+    }
+    /// unexpired grants in a domain reaching the user directly, through a held or inherited role,
+    /// an org they belong to or one above it, or a group
+    pub async fn find_by_user(user_id: &str, domain_id: &str) -> Result<Vec<Self>, DBError> {
+        let mut subjects: Vec<(&str, String)> = vec![("user", user_id.to_string())];
+        let held: Vec<String> = Role::find_by_user(user_id, domain_id)
+            .await?
+            .into_iter()
+            .map(|v| v.id)
+            .collect();
+        let inherited = RoleParent::find_inherited(domain_id, &held).await?;
+        subjects.extend(held.into_iter().map(|v| ("role", v)));
+        subjects.extend(inherited.keys().map(|v| ("role", v.clone())));
+        for org_id in Org::find_ids_by_member(user_id, false).await? {
+            subjects.push(("org", org_id));
+        }
+        for v in UserGroup::find_by_user(user_id, false).await? {
+            subjects.push(("group", v.group_id));
+        }
+        let subject_ids: Vec<&String> = subjects.iter().map(|(_, id)| id).collect();
+        let w = POOL
+            .new_wrapper()
+            .eq("domain_id", domain_id)
+            .and()
+            .gt("expire", now())
+            .and()
+            .r#in("subject_id", &subject_ids);
+        let grants = Self::find_list(w).await?;
+        Ok(grants
+            .into_iter()
+            .filter(|v| {
+                subjects
+                    .iter()
+                    .any(|(t, id)| *t == v.subject_type && *id == v.subject_id)
+            })
+            .collect())
+    }
+    /// whether the grant covers the resource
+    pub fn covers(&self, resource: &str) -> bool {
+        pattern::matches(&self.resource, resource)
+    }
+    pub async fn find_expired(at: NaiveDateTime) -> Result<Vec<Self>, DBError> {
+        let w = POOL.new_wrapper().le("expire", at);
+        Self::find_list(w).await
+    }
+    pub async fn delete_expired(at: NaiveDateTime) -> Result<u64, DBError> {
+        let w = POOL.new_wrapper().le("expire", at);
+        Self::delete_one(w).await
+    }
+}
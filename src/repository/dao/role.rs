@@ -1,12 +1,21 @@
-use super::Domain;
+use super::{
+    role_parent, Domain, Group, GroupRole, Org, OrgRole, RoleParent, UserGroup, UserOrg, UserRole,
+};
 use crate::{
     repository::{vo, DBError, Dao, POOL},
-    util::serde_format::{i32_bool, naive_datetime},
+    util::{
+        now,
+        serde_format::{i32_bool, naive_datetime},
+    },
 };
 use app_macro::Dao;
 use async_trait::async_trait;
 use chrono::NaiveDateTime;
-use rbatis::{crud::CRUD, wrapper::Wrapper};
+use rbatis::{
+    crud::{CRUDMut, CRUD},
+    executor::RBatisTxExecutor,
+    wrapper::Wrapper,
+};
 use serde::Serialize;
 use std::collections::HashMap;
 
@@ -18,6 +27,8 @@ pub struct Role {
     pub description: Option<String>,
     pub value: String,
     pub level: i32,
+    // most users holding the role directly or through orgs and groups, no limit when `None`
+    pub max_holders: Option<i32>,
     pub domain_id: String,
     #[serde(serialize_with = "i32_bool::serialize")]
     pub is_deleted: i32,
@@ -34,13 +45,331 @@ impl Role {
         let w = POOL.new_wrapper().r#in("id", &id);
         Self::find_list(w).await
     }
+    /// ids of the unexpired roles the user holds, granted directly or through an org or group
+    pub async fn find_held_ids(user_id: &str) -> Result<Vec<String>, DBError> {
+        let mut role_ids: Vec<String> = UserRole::find_by_user(user_id, false)
+            .await?
+            .into_iter()
+            .map(|v| v.role_id)
+            .collect();
+        let org_roles = OrgRole::find_by_user(user_id, false).await?;
+        role_ids.extend(org_roles.into_iter().map(|v| v.role_id));
+        let group_roles = GroupRole::find_by_user(user_id, false).await?;
+        role_ids.extend(group_roles.into_iter().map(|v| v.role_id));
+        role_ids.sort();
+        role_ids.dedup();
+        Ok(role_ids)
+    }
+    /// unexpired roles the user holds in a domain, removed roles left out
+    pub async fn find_by_user(user_id: &str, domain_id: &str) -> Result<Vec<Self>, DBError> {
+        let role_ids = Self::find_held_ids(user_id).await?;
+        if role_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let w = POOL
+            .new_wrapper()
+            .r#in("id", &role_ids)
+            .and()
+            .eq("domain_id", domain_id)
+            .and()
+            .eq("is_deleted", 0);
+        Self::find_list(w).await
+    }
     pub async fn find_all(domain_ids: Vec<String>) -> Result<Vec<Self>, DBError> {
         let w = POOL.new_wrapper().r#in("domain_id", &domain_ids);
         Self::find_list(w).await
     }
+    /// the role followed by every role it inherits from, nearest first
+    pub async fn find_effective_ids(&self) -> Result<Vec<String>, DBError> {
+        let edges = RoleParent::find_by_domain(&self.domain_id).await?;
+        let mut role_ids = vec![self.id.clone()];
+        role_ids.extend(role_parent::ancestors(&edges, &self.id));
+        Ok(role_ids)
+    }
+    /// reads the role again and locks its row until `tx` ends, so that grants of the role
+    /// are checked one after another
+    pub async fn lock(tx: &mut RBatisTxExecutor<'_>, id: &str) -> Result<Self, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .eq("id", id)
+            .and()
+            .eq("is_deleted", 0)
+            .push_sql(" FOR UPDATE");
+        tx.fetch_by_wrapper(w).await
+    }
+}
+
+/// what a change touches: the users it changes, and the roles, orgs and groups whose
+/// holders and members it reaches
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    pub user_ids: Vec<String>,
+    pub role_ids: Vec<String>,
+    pub org_ids: Vec<String>,
+    pub group_ids: Vec<String>,
+}
+
+/// who holds the roles of a domain, directly or through orgs and groups, as read in one
+/// transaction for the users a change reaches; a change is checked by applying it to a copy
+/// and comparing the two
+#[derive(Debug, Clone, Default)]
+pub struct Holdings {
+    pub user_roles: Vec<UserRole>,
+    pub org_roles: Vec<OrgRole>,
+    pub group_roles: Vec<GroupRole>,
+    pub orgs: Vec<Org>,
+    pub user_orgs: Vec<UserOrg>,
+    pub user_groups: Vec<UserGroup>,
+    pub edges: Vec<RoleParent>,
+    role_ids: Vec<String>,
+    group_ids: Vec<String>,
+}
+
+impl Holdings {
+    /// locks the domain until `tx` ends, so that checked writes of a domain run one after
+    /// another, then reads its orgs, role edges and unexpired grants to orgs and groups and the
+    /// unexpired grants and memberships of the users `scope` reaches
+    pub async fn load(
+        tx: &mut RBatisTxExecutor<'_>,
+        domain_id: &str,
+        scope: &Scope,
+    ) -> Result<Self, DBError> {
+        let mut holdings = Self::lock(tx, domain_id).await?;
+        holdings.load_members(tx, scope).await?;
+        Ok(holdings)
+    }
+    /// `load` without the members, for a change that needs the grants to find its scope;
+    /// `load_members` reads them then
+    pub async fn lock(tx: &mut RBatisTxExecutor<'_>, domain_id: &str) -> Result<Self, DBError> {
+        Domain::lock(tx, domain_id).await?;
+        let in_domain = || {
+            POOL.new_wrapper()
+                .eq("domain_id", domain_id)
+                .and()
+                .eq("is_deleted", 0)
+        };
+        let mut holdings = Self {
+            edges: tx
+                .fetch_list_by_wrapper(POOL.new_wrapper().eq("domain_id", domain_id))
+                .await?,
+            orgs: tx.fetch_list_by_wrapper(in_domain()).await?,
+            ..Default::default()
+        };
+        holdings.role_ids = tx
+            .fetch_list_by_wrapper::<Role>(in_domain())
+            .await?
+            .into_iter()
+            .map(|v| v.id)
+            .collect();
+        holdings.group_ids = tx
+            .fetch_list_by_wrapper::<Group>(in_domain())
+            .await?
+            .into_iter()
+            .map(|v| v.id)
+            .collect();
+        if !holdings.role_ids.is_empty() {
+            let w = POOL
+                .new_wrapper()
+                .r#in("role_id", &holdings.role_ids)
+                .and()
+                .gt("expire", now());
+            holdings.org_roles = tx.fetch_list_by_wrapper(w.clone()).await?;
+            holdings.group_roles = tx.fetch_list_by_wrapper(w).await?;
+        }
+        Ok(holdings)
+    }
+    /// reads the unexpired grants and memberships of the users in `scope`, of the holders of
+    /// its roles and of the members of its orgs, the orgs below them and its groups
+    pub async fn load_members(
+        &mut self,
+        tx: &mut RBatisTxExecutor<'_>,
+        scope: &Scope,
+    ) -> Result<(), DBError> {
+        let mut org_ids = scope.org_ids.clone();
+        org_ids.extend(
+            self.org_roles
+                .iter()
+                .filter(|v| scope.role_ids.contains(&v.role_id))
+                .map(|v| v.org_id.clone()),
+        );
+        let org_ids = self.subtree_ids(&org_ids);
+        let mut group_ids = scope.group_ids.clone();
+        group_ids.extend(
+            self.group_roles
+                .iter()
+                .filter(|v| scope.role_ids.contains(&v.role_id))
+                .map(|v| v.group_id.clone()),
+        );
+        let mut user_ids = scope.user_ids.clone();
+        if !scope.role_ids.is_empty() {
+            let w = POOL
+                .new_wrapper()
+                .r#in("role_id", &scope.role_ids)
+                .and()
+                .gt("expire", now());
+            let found: Vec<UserRole> = tx.fetch_list_by_wrapper(w).await?;
+            user_ids.extend(found.into_iter().map(|v| v.user_id));
+        }
+        if !org_ids.is_empty() {
+            let w = POOL
+                .new_wrapper()
+                .r#in("org_id", &org_ids)
+                .and()
+                .gt("expire", now());
+            let found: Vec<UserOrg> = tx.fetch_list_by_wrapper(w).await?;
+            user_ids.extend(found.into_iter().map(|v| v.user_id));
+        }
+        if !group_ids.is_empty() {
+            let w = POOL
+                .new_wrapper()
+                .r#in("group_id", &group_ids)
+                .and()
+                .gt("expire", now());
+            let found: Vec<UserGroup> = tx.fetch_list_by_wrapper(w).await?;
+            user_ids.extend(found.into_iter().map(|v| v.user_id));
+        }
+        user_ids.sort();
+        user_ids.dedup();
+        if user_ids.is_empty() {
+            return Ok(());
+        }
+        let of_users = |column: &str, ids: &[String]| {
+            POOL.new_wrapper()
+                .r#in("user_id", &user_ids)
+                .and()
+                .r#in(column, ids)
+                .and()
+                .gt("expire", now())
+        };
+        if !self.role_ids.is_empty() {
+            self.user_roles = tx
+                .fetch_list_by_wrapper(of_users("role_id", &self.role_ids))
+                .await?;
+        }
+        let domain_org_ids: Vec<String> = self.orgs.iter().map(|v| v.id.clone()).collect();
+        if !domain_org_ids.is_empty() {
+            self.user_orgs = tx
+                .fetch_list_by_wrapper(of_users("org_id", &domain_org_ids))
+                .await?;
+        }
+        if !self.group_ids.is_empty() {
+            self.user_groups = tx
+                .fetch_list_by_wrapper(of_users("group_id", &self.group_ids))
+                .await?;
+        }
+        Ok(())
+    }
+    /// every org with the org and the orgs above it, whose grants reach the org's members
+    fn org_chains(&self) -> HashMap<&str, Vec<&str>> {
+        let parents: HashMap<&str, &str> = self
+            .orgs
+            .iter()
+            .filter_map(|v| v.parent_id.as_deref().map(|parent_id| (v.id.as_str(), parent_id)))
+            .collect();
+        self.orgs
+            .iter()
+            .map(|org| {
+                let mut chain = vec![org.id.as_str()];
+                let mut current = org.id.as_str();
+                while let Some(&parent_id) = parents.get(current) {
+                    // stop on cycles
+                    if chain.contains(&parent_id) {
+                        break;
+                    }
+                    chain.push(parent_id);
+                    current = parent_id;
+                }
+                (org.id.as_str(), chain)
+            })
+            .collect()
+    }
+    /// `org_ids` and the orgs below them
+    pub fn subtree_ids(&self, org_ids: &[String]) -> Vec<String> {
+        self.org_chains()
+            .into_iter()
+            .filter(|(_, chain)| chain.iter().any(|v| org_ids.iter().any(|id| id == v)))
+            .map(|(id, _)| id.to_string())
+            .collect()
+    }
+    /// roles granted to `org_id` or the orgs above it
+    pub fn org_role_ids(&self, org_id: &str) -> Vec<String> {
+        let chains = self.org_chains();
+        let chain = chains.get(org_id).cloned().unwrap_or_else(|| vec![org_id]);
+        let mut role_ids: Vec<String> = self
+            .org_roles
+            .iter()
+            .filter(|v| chain.contains(&v.org_id.as_str()))
+            .map(|v| v.role_id.clone())
+            .collect();
+        role_ids.sort();
+        role_ids.dedup();
+        role_ids
+    }
+    /// roles granted to `group_id`
+    pub fn group_role_ids(&self, group_id: &str) -> Vec<String> {
+        let mut role_ids: Vec<String> = self
+            .group_roles
+            .iter()
+            .filter(|v| v.group_id == group_id)
+            .map(|v| v.role_id.clone())
+            .collect();
+        role_ids.sort();
+        role_ids.dedup();
+        role_ids
+    }
+    /// the roles each loaded user holds directly or through orgs and groups, inherited ones
+    /// left out
+    pub fn roles_by_user(&self) -> HashMap<String, Vec<String>> {
+        let chains = self.org_chains();
+        let mut by_org: HashMap<&str, Vec<&String>> = HashMap::new();
+        for v in self.org_roles.iter() {
+            by_org.entry(v.org_id.as_str()).or_default().push(&v.role_id);
+        }
+        let mut by_group: HashMap<&str, Vec<&String>> = HashMap::new();
+        for v in self.group_roles.iter() {
+            by_group.entry(v.group_id.as_str()).or_default().push(&v.role_id);
+        }
+        let mut held: HashMap<String, Vec<String>> = HashMap::new();
+        for v in self.user_roles.iter() {
+            held.entry(v.user_id.clone()).or_default().push(v.role_id.clone());
+        }
+        for member in self.user_orgs.iter() {
+            let role_ids = held.entry(member.user_id.clone()).or_default();
+            for org_id in chains.get(member.org_id.as_str()).into_iter().flatten() {
+                role_ids.extend(by_org.get(org_id).into_iter().flatten().map(|v| (*v).clone()));
+            }
+        }
+        for member in self.user_groups.iter() {
+            let role_ids = held.entry(member.user_id.clone()).or_default();
+            role_ids.extend(
+                by_group
+                    .get(member.group_id.as_str())
+                    .into_iter()
+                    .flatten()
+                    .map(|v| (*v).clone()),
+            );
+        }
+        for role_ids in held.values_mut() {
+            role_ids.sort();
+            role_ids.dedup();
+        }
+        held
+    }
+    /// loaded users holding the role directly or through orgs and groups
+    pub fn holders_of(&self, role_id: &str) -> Vec<String> {
+        let mut user_ids: Vec<String> = self
+            .roles_by_user()
+            .into_iter()
+            .filter(|(_, role_ids)| role_ids.iter().any(|v| v == role_id))
+            .map(|(user_id, _)| user_id)
+            .collect();
+        user_ids.sort();
+        user_ids
+    }
 }
 
 #[async_trait]
+#[allow(clippy::wrong_self_convention)]
 pub trait IntoVecOfVo {
     async fn into_vo(&self) -> Result<Vec<vo::Role>, DBError>;
 }
@@ -56,10 +385,140 @@ impl IntoVecOfVo for Vec<Role> {
             domain_map.insert(domain.id.clone(), domain.clone());
         }
         let mut records: Vec<vo::Role> = self.iter().map(|v| vo::Role::from(v.clone())).collect();
-        for mut r in &mut records {
+        for r in &mut records {
             let domain = domain_map.get(&r.domain_id).cloned();
             r.domain = domain.map(Into::into);
         }
         Ok(records)
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// holdings written out grant by grant, for tests here and of the checks built on them
+    impl Holdings {
+        pub(crate) fn grant(mut self, user_id: &str, role_id: &str) -> Self {
+            self.user_roles.push(UserRole {
+                user_id: user_id.to_string(),
+                role_id: role_id.to_string(),
+                role_level: 0,
+                expire: now(),
+                created_at: now(),
+            });
+            self
+        }
+        pub(crate) fn grant_org(mut self, org_id: &str, role_id: &str) -> Self {
+            self.org_roles.push(OrgRole {
+                org_id: org_id.to_string(),
+                role_id: role_id.to_string(),
+                expire: now(),
+                created_at: now(),
+            });
+            self
+        }
+        pub(crate) fn grant_group(mut self, group_id: &str, role_id: &str) -> Self {
+            self.group_roles.push(GroupRole {
+                group_id: group_id.to_string(),
+                role_id: role_id.to_string(),
+                expire: now(),
+                created_at: now(),
+            });
+            self
+        }
+        pub(crate) fn org(mut self, org_id: &str, parent_id: Option<&str>) -> Self {
+            self.orgs.push(Org {
+                id: org_id.to_string(),
+                name: org_id.to_string(),
+                description: None,
+                domain_id: "d1".to_string(),
+                parent_id: parent_id.map(String::from),
+                is_deleted: 0,
+                created_at: now(),
+                updated_at: now(),
+                created_by: None,
+                updated_by: None,
+            });
+            self
+        }
+        pub(crate) fn join_org(mut self, user_id: &str, org_id: &str) -> Self {
+            self.user_orgs.push(UserOrg {
+                user_id: user_id.to_string(),
+                org_id: org_id.to_string(),
+                expire: now(),
+                created_at: now(),
+            });
+            self
+        }
+        pub(crate) fn join_group(mut self, user_id: &str, group_id: &str) -> Self {
+            self.user_groups.push(UserGroup {
+                user_id: user_id.to_string(),
+                group_id: group_id.to_string(),
+                expire: now(),
+                created_at: now(),
+            });
+            self
+        }
+        pub(crate) fn inherit(mut self, role_id: &str, parent_id: &str) -> Self {
+            self.edges.push(RoleParent {
+                role_id: role_id.to_string(),
+                parent_id: parent_id.to_string(),
+                domain_id: "d1".to_string(),
+                created_at: now(),
+            });
+            self
+        }
+    }
+
+    fn ids(v: &[&str]) -> Vec<String> {
+        v.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn collects_roles_through_orgs_and_groups() {
+        let holdings = Holdings::default()
+            .org("root", None)
+            .org("team", Some("root"))
+            .grant_org("root", "r1")
+            .grant_group("g1", "r2")
+            .grant("u1", "r3")
+            .join_org("u1", "team")
+            .join_group("u2", "g1")
+            .grant("u2", "r2");
+        let held = holdings.roles_by_user();
+        assert_eq!(held.get("u1"), Some(&ids(&["r1", "r3"])));
+        assert_eq!(held.get("u2"), Some(&ids(&["r2"])));
+        assert_eq!(holdings.holders_of("r1"), ids(&["u1"]));
+        assert_eq!(holdings.holders_of("r2"), ids(&["u2"]));
+        assert!(holdings.holders_of("r4").is_empty());
+    }
+
+    #[test]
+    fn walks_org_chains() {
+        let holdings = Holdings::default()
+            .org("root", None)
+            .org("team", Some("root"))
+            .org("squad", Some("team"))
+            .org("other", None)
+            .grant_org("root", "r1")
+            .grant_org("team", "r2")
+            .grant_org("other", "r3");
+        assert_eq!(holdings.org_role_ids("squad"), ids(&["r1", "r2"]));
+        assert_eq!(holdings.org_role_ids("root"), ids(&["r1"]));
+        let mut subtree = holdings.subtree_ids(&ids(&["team"]));
+        subtree.sort();
+        assert_eq!(subtree, ids(&["squad", "team"]));
+    }
+
+    #[test]
+    fn stops_on_org_cycles() {
+        let holdings = Holdings::default()
+            .org("a", Some("b"))
+            .org("b", Some("a"))
+            .grant_org("a", "r1")
+            .join_org("u1", "b");
+        assert_eq!(holdings.org_role_ids("b"), ids(&["r1"]));
+        assert_eq!(holdings.holders_of("r1"), ids(&["u1"]));
+    }
+}
@@ -0,0 +1,88 @@
+use super::RoleParent;
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::serde_format::naive_datetime,
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+use std::collections::HashMap;
+
+pub const KINDS: [&str; 2] = ["static", "dynamic"];
+
+/// a set of roles no user may hold together when `static`, or activate in one session when `dynamic`
+#[crud_table(table_name: "role_exclusions")]
+#[derive(Debug, Clone, Dao)]
+pub struct RoleExclusion {
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    pub domain_id: String,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+    pub created_by: Option<String>,
+}
+
+#[crud_table(table_name: "role_exclusion_members")]
+#[derive(Debug, Clone, Dao)]
+pub struct RoleExclusionMember {
+    pub exclusion_id: String,
+    pub role_id: String,
+}
+
+impl RoleExclusion {
+    /// the exclusions of a domain, of one kind if given, mapped to their roles
+    pub async fn find_by_domain(
+        domain_id: &str,
+        kind: Option<&str>,
+    ) -> Result<Vec<(Self, Vec<String>)>, DBError> {
+        let mut w = POOL.new_wrapper().eq("domain_id", domain_id);
+        if let Some(kind) = kind {
+            w = w.and().eq("kind", kind);
+        }
+        w = w.order_by(true, &["created_at"]);
+        let exclusions = Self::find_list(w).await?;
+        if exclusions.is_empty() {
+            return Ok(vec![]);
+        }
+        let ids: Vec<String> = exclusions.iter().map(|v| v.id.clone()).collect();
+        let mut members: HashMap<String, Vec<String>> = HashMap::new();
+        for v in RoleExclusionMember::find_by_exclusions(&ids).await? {
+            members.entry(v.exclusion_id).or_default().push(v.role_id);
+        }
+        Ok(exclusions
+            .into_iter()
+            .map(|v| {
+                let role_ids = members.remove(&v.id).unwrap_or_default();
+                (v, role_ids)
+            })
+            .collect())
+    }
+}
+
+impl RoleExclusionMember {
+    pub async fn find_by_exclusions(exclusion_ids: &[String]) -> Result<Vec<Self>, DBError> {
+        let w = POOL.new_wrapper().r#in("exclusion_id", exclusion_ids);
+        Self::find_list(w).await
+    }
+}
+
+/// the roles of an exclusion set among `role_ids`, a conflict when more than one
+pub fn conflicting(set: &[String], role_ids: &[String]) -> Vec<String> {
+    set.iter().filter(|v| role_ids.contains(v)).cloned().collect()
+}
+
+/// `role_ids` together with every role they inherit
+pub fn with_inherited(edges: &[RoleParent], role_ids: &[String]) -> Vec<String> {
+    let mut found: Vec<String> = role_ids.to_vec();
+    for role_id in role_ids {
+        for ancestor in super::role_parent::ancestors(edges, role_id) {
+            if !found.contains(&ancestor) {
+                found.push(ancestor);
+            }
+        }
+    }
+    found
+}
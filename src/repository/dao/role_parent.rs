@@ -0,0 +1,61 @@
+use crate::{repository::{DBError, POOL, Dao}, util::serde_format::naive_datetime};
+use app_macro::Dao;
+use serde::Serialize;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use std::collections::HashMap;
+
+/// `role_id` inherits every permission of `parent_id`
+#[crud_table(table_name: "role_has_parents")]
+#[derive(Debug, Clone, Dao)]
+pub struct RoleParent {
+    pub role_id: String,
+    pub parent_id: String,
+    pub domain_id: String,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl RoleParent{
+  pub async fn find_by_role(role_id: &str) -> Result<Vec<Self>, DBError> {
+    let w = POOL.new_wrapper().eq("role_id", role_id);
+    Self::find_list(w).await
+  }
+  pub async fn find_by_domain(domain_id: &str) -> Result<Vec<Self>, DBError> {
+    let w = POOL.new_wrapper().eq("domain_id", domain_id);
+    Self::find_list(w).await
+  }
+  /// roles inherited by `role_ids` in a domain, mapped to the given roles they are inherited through
+  pub async fn find_inherited(domain_id: &str, role_ids: &[String]) -> Result<HashMap<String, Vec<String>>, DBError> {
+    let edges = Self::find_by_domain(domain_id).await?;
+    let mut inherited: HashMap<String, Vec<String>> = HashMap::new();
+    for role_id in role_ids {
+      for ancestor in ancestors(&edges, role_id) {
+        let via = inherited.entry(ancestor).or_default();
+        if !via.contains(role_id) {
+          via.push(role_id.clone());
+        }
+      }
+    }
+    Ok(inherited)
+  }
+}
+
+/// every role reachable through parents of `role_id`, nearest first, without `role_id` itself
+pub fn ancestors(edges: &[RoleParent], role_id: &str) -> Vec<String> {
+  let mut found: Vec<String> = vec![];
+  let mut queue = vec![role_id.to_string()];
+  let mut i = 0;
+  while i < queue.len() {
+    let current = queue[i].clone();
+    for edge in edges.iter().filter(|v| v.role_id == current) {
+      if edge.parent_id != role_id && !found.contains(&edge.parent_id) {
+        found.push(edge.parent_id.clone());
+        queue.push(edge.parent_id.clone());
+      }
+    }
+    i += 1;
+  }
+  found
+}
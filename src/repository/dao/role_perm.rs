@@ -1,15 +1,22 @@
-use crate::{repository::{DBError, POOL, Dao}, util::serde_format::naive_datetime};
+use crate::{repository::{DBError, POOL, Dao}, util::{condition, serde_format::naive_datetime}};
 use app_macro::Dao;
 use serde::Serialize;
 use async_trait::async_trait;
 use chrono::NaiveDateTime;
 use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde_json::Value;
+
+pub const EFFECTS: [&str; 2] = ["allow", "deny"];
 
 #[crud_table(table_name: "role_has_perms")]
 #[derive(Debug, Clone, Dao)]
 pub struct RolePerm {
     pub role_id: String,
     pub perm_id: String,
+    // see `util::condition`, the grant applies only while it holds
+    pub condition_expr: Option<String>,
+    // `allow` or `deny`
+    pub effect: String,
     #[serde(serialize_with = "naive_datetime::serialize")]
     pub created_at: NaiveDateTime,
 }
@@ -23,4 +30,54 @@ impl RolePerm{
     let w = POOL.new_wrapper().eq("role_id", role_id);
     Self::find_list(w).await
   }
-}
\ No newline at end of file
+  pub async fn find_by_roles(role_ids: &[String]) -> Result<Vec<Self>, DBError> {
+    let w = POOL.new_wrapper().r#in("role_id", role_ids);
+    Self::find_list(w).await
+  }
+  /// whether the grant applies in the context of an access check; a condition that fails to
+  /// evaluate makes a deny apply and an allow not, so a broken context can't escape a deny
+  pub fn applies(&self, ctx: &Value) -> bool {
+    match &self.condition_expr {
+      Some(v) => match condition::evaluate(v, ctx) {
+        Ok(holds) => holds,
+        Err(e) => {
+          tracing::warn!("condition {:?} failed: {}", v, e);
+          self.effect == "deny"
+        }
+      },
+      None => true,
+    }
+  }
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::util::now;
+  use serde_json::json;
+
+  fn grant(effect: &str, condition: Option<&str>) -> RolePerm {
+    RolePerm {
+      role_id: "r1".to_string(),
+      perm_id: "p1".to_string(),
+      condition_expr: condition.map(String::from),
+      effect: effect.to_string(),
+      created_at: now(),
+    }
+  }
+
+  #[test]
+  fn applies_while_the_condition_holds() {
+    let ctx = json!({"subject": {"level": 3}});
+    assert!(grant("allow", None).applies(&ctx));
+    assert!(grant("allow", Some("subject.level >= 3")).applies(&ctx));
+    assert!(!grant("allow", Some("subject.level > 3")).applies(&ctx));
+    assert!(!grant("deny", Some("subject.level > 3")).applies(&ctx));
+  }
+
+  #[test]
+  fn failing_conditions_apply_denies_only() {
+    let ctx = json!({"subject": {"level": "high"}});
+    assert!(grant("deny", Some("subject.level > 3")).applies(&ctx));
+    assert!(!grant("allow", Some("subject.level > 3")).applies(&ctx));
+  }
+}
@@ -0,0 +1,46 @@
+use crate::{repository::{DBError, POOL, Dao}, util::serde_format::naive_datetime};
+use app_macro::Dao;
+use serde::Serialize;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+
+/// `role_id` is only granted to users who already hold `prerequisite_id`
+#[crud_table(table_name: "role_has_prerequisites")]
+#[derive(Debug, Clone, Dao)]
+pub struct RolePrerequisite {
+    pub role_id: String,
+    pub prerequisite_id: String,
+    pub domain_id: String,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl RolePrerequisite{
+  pub async fn find_by_role(role_id: &str) -> Result<Vec<Self>, DBError> {
+    let w = POOL.new_wrapper().eq("role_id", role_id);
+    Self::find_list(w).await
+  }
+  pub async fn find_by_domain(domain_id: &str) -> Result<Vec<Self>, DBError> {
+    let w = POOL.new_wrapper().eq("domain_id", domain_id);
+    Self::find_list(w).await
+  }
+}
+
+/// every role `role_id` requires, directly or through its prerequisites, without itself
+pub fn required(edges: &[RolePrerequisite], role_id: &str) -> Vec<String> {
+  let mut found: Vec<String> = vec![];
+  let mut queue = vec![role_id.to_string()];
+  let mut i = 0;
+  while i < queue.len() {
+    let current = queue[i].clone();
+    for edge in edges.iter().filter(|v| v.role_id == current) {
+      if edge.prerequisite_id != role_id && !found.contains(&edge.prerequisite_id) {
+        found.push(edge.prerequisite_id.clone());
+        queue.push(edge.prerequisite_id.clone());
+      }
+    }
+    i += 1;
+  }
+  found
+}
@@ -0,0 +1,33 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::{now, serde_format::naive_datetime},
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+/// a role activated for the access token `jti`, lives as long as the token
+#[crud_table(table_name: "session_roles")]
+#[derive(Debug, Clone, Dao)]
+pub struct SessionRole {
+    pub jti: String,
+    pub user_id: String,
+    pub role_id: String,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub expire: NaiveDateTime,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl SessionRole {
+    pub async fn find_by_jti(jti: &str) -> Result<Vec<Self>, DBError> {
+        let w = POOL.new_wrapper().eq("jti", jti).and().gt("expire", now());
+        Self::find_list(w).await
+    }
+    pub async fn delete_expired(at: NaiveDateTime) -> Result<u64, DBError> {
+        let w = POOL.new_wrapper().le("expire", at);
+        Self::delete_one(w).await
+    }
+}
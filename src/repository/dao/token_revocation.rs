@@ -0,0 +1,36 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::serde_format::naive_datetime,
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+/// revokes a single token when `jti` is set,
+/// otherwise every token of the user issued up to `issued_until`
+#[crud_table(table_name: "token_revocations")]
+#[derive(Debug, Clone, Dao)]
+pub struct TokenRevocation {
+    pub id: String,
+    pub user_id: String,
+    pub jti: Option<String>,
+    // milliseconds since the epoch, set when `jti` is not
+    pub issued_until: Option<i64>,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub expire: NaiveDateTime,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl TokenRevocation {
+    pub async fn find_active(at: NaiveDateTime) -> Result<Vec<Self>, DBError> {
+        let w = POOL.new_wrapper().gt("expire", at);
+        Self::find_list(w).await
+    }
+    pub async fn delete_expired(at: NaiveDateTime) -> Result<u64, DBError> {
+        let w = POOL.new_wrapper().le("expire", at);
+        Self::delete_one(w).await
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{repository::{DBError, POOL, Dao}, util::serde_format::{naive_datetime, i32_bool}};
+use crate::{repository::{DBError, POOL, Dao}, util::serde_format::{naive_datetime, option_naive_datetime, i32_bool}};
 use app_macro::Dao;
 use async_trait::async_trait;
 use chrono::NaiveDateTime;
@@ -12,11 +12,15 @@ pub struct User{
     pub username: String,
     pub password: String,
     pub email: Option<String>,
+    #[serde(serialize_with = "option_naive_datetime::serialize")]
+    pub email_verified_at: Option<NaiveDateTime>,
     pub avatar: Option<String>,
     pub memo: Option<String>,
     pub sys_role: Option<String>,
     #[serde(serialize_with = "i32_bool::serialize")]
     pub is_actived: i32,
+    #[serde(serialize_with = "i32_bool::serialize")]
+    pub is_deleted: i32,
     #[serde(serialize_with = "naive_datetime::serialize")]
     pub last_logined_at: NaiveDateTime,
     #[serde(serialize_with = "naive_datetime::serialize")]
@@ -24,16 +28,45 @@ pub struct User{
 }
 
 impl User {
+    pub fn is_email_verified(&self) -> bool {
+        self.email.is_some() && self.email_verified_at.is_some()
+    }
     pub async fn find_by_username(username: &str) -> Result<Self, DBError> {
         let w = POOL.new_wrapper().eq("username", username);
         Self::find_one(w).await
     }
+    /// only users who can sign in, deleted ones keep their row but not their login
     pub async fn find_by_username_or_email(username_or_email: &str) -> Result<Self, DBError> {
-        let w = POOL
+        let either = POOL
             .new_wrapper()
+            .push_sql("(")
             .eq("username", username_or_email)
             .or()
-            .eq("email", username_or_email);
+            .eq("email", username_or_email)
+            .push_sql(")");
+        let w = POOL.new_wrapper().eq("is_deleted", 0).and().push_wrapper(either);
+        Self::find_one(w).await
+    }
+    /// deletes only set `is_deleted`, so lookups by id skip deleted users unlike
+    /// `Dao::find_by_id`, which this shadows
+    pub async fn find_by_id<T>(id: T) -> Result<Self, DBError>
+    where
+        T: Serialize + Send + Sync,
+    {
+        let w = POOL.new_wrapper().eq("id", id).and().eq("is_deleted", 0);
         Self::find_one(w).await
     }
+    /// like `find_by_id`, deleted users are left out
+    pub async fn find_by_ids<T>(ids: Vec<T>) -> Result<Vec<Self>, DBError>
+    where
+        T: Serialize + Send + Sync,
+    {
+        let w = POOL
+            .new_wrapper()
+            .r#in("id", &ids)
+            .and()
+            .eq("is_deleted", 0)
+            .order_by(true, &["created_at"]);
+        Self::find_list(w).await
+    }
 }
@@ -0,0 +1,42 @@
+use crate::{repository::{DBError, POOL, Dao}, util::{now, serde_format::naive_datetime}};
+use app_macro::Dao;
+use async_trait::async_trait;
+use serde::Serialize;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+
+#[crud_table(table_name: "user_has_groups")]
+#[derive(Debug, Clone, Dao)]
+pub struct UserGroup {
+    pub user_id: String,
+    pub group_id: String,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub expire: NaiveDateTime,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl UserGroup{
+  pub async fn find_by_user(user_id: &str, include_expired: bool) -> Result<Vec<Self>, DBError> {
+    let mut w = POOL.new_wrapper().eq("user_id", user_id);
+    if !include_expired {
+      w = w.and().gt("expire", now());
+    }
+    Self::find_list(w).await
+  }
+  pub async fn find_by_group(group_id: &str, include_expired: bool) -> Result<Vec<Self>, DBError> {
+    let mut w = POOL.new_wrapper().eq("group_id", group_id);
+    if !include_expired {
+      w = w.and().gt("expire", now());
+    }
+    Self::find_list(w).await
+  }
+  pub async fn find_expired(at: NaiveDateTime) -> Result<Vec<Self>, DBError> {
+    let w = POOL.new_wrapper().le("expire", at);
+    Self::find_list(w).await
+  }
+  pub async fn delete_expired(at: NaiveDateTime) -> Result<u64, DBError> {
+    let w = POOL.new_wrapper().le("expire", at);
+    Self::delete_one(w).await
+  }
+}
@@ -0,0 +1,38 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::serde_format::naive_datetime,
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+#[crud_table(table_name: "user_identities")]
+#[derive(Debug, Clone, Dao)]
+pub struct UserIdentity {
+    pub id: String,
+    pub user_id: String,
+    pub provider_id: String,
+    pub subject: String,
+    pub email: Option<String>,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub last_logined_at: NaiveDateTime,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl UserIdentity {
+    pub async fn find_by_subject(provider_id: &str, subject: &str) -> Result<Self, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .eq("provider_id", provider_id)
+            .and()
+            .eq("subject", subject);
+        Self::find_one(w).await
+    }
+    pub async fn find_by_user(user_id: &str) -> Result<Vec<Self>, DBError> {
+        let w = POOL.new_wrapper().eq("user_id", user_id);
+        Self::find_list(w).await
+    }
+}
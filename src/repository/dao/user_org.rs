@@ -1,4 +1,4 @@
-use crate::{repository::{DBError, POOL, Dao}, util::serde_format::naive_datetime};
+use crate::{repository::{DBError, POOL, Dao}, util::{now, serde_format::naive_datetime}};
 use app_macro::Dao;
 use async_trait::async_trait;
 use serde::Serialize;
@@ -18,15 +18,36 @@ pub struct UserOrg {
 
 impl UserOrg{
   pub async fn find_by_id(user_id: &str, org_id: &str) -> Result<Self, DBError> {
-    let w = POOL.new_wrapper().eq("user_id", user_id).and().eq("org_id", org_id);
+    let w = POOL.new_wrapper().eq("user_id", user_id).and().eq("org_id", org_id).and().gt("expire", now());
     Self::find_one(w).await
   }
-  pub async fn find_by_user(user_id: &str) -> Result<Vec<Self>, DBError> {
-    let w = POOL.new_wrapper().eq("user_id", user_id);
+  pub async fn find_by_user(user_id: &str, include_expired: bool) -> Result<Vec<Self>, DBError> {
+    let mut w = POOL.new_wrapper().eq("user_id", user_id);
+    if !include_expired {
+      w = w.and().gt("expire", now());
+    }
     Self::find_list(w).await
   }
-  pub async fn find_by_org(org_id: &str) -> Result<Vec<Self>, DBError> {
-    let w = POOL.new_wrapper().eq("org_id", org_id);
+  pub async fn find_by_org(org_id: &str, include_expired: bool) -> Result<Vec<Self>, DBError> {
+    let mut w = POOL.new_wrapper().eq("org_id", org_id);
+    if !include_expired {
+      w = w.and().gt("expire", now());
+    }
     Self::find_list(w).await
   }
+  pub async fn find_by_orgs(org_ids: &[String], include_expired: bool) -> Result<Vec<Self>, DBError> {
+    let mut w = POOL.new_wrapper().r#in("org_id", org_ids);
+    if !include_expired {
+      w = w.and().gt("expire", now());
+    }
+    Self::find_list(w).await
+  }
+  pub async fn find_expired(at: NaiveDateTime) -> Result<Vec<Self>, DBError> {
+    let w = POOL.new_wrapper().le("expire", at);
+    Self::find_list(w).await
+  }
+  pub async fn delete_expired(at: NaiveDateTime) -> Result<u64, DBError> {
+    let w = POOL.new_wrapper().le("expire", at);
+    Self::delete_one(w).await
+  }
 }
\ No newline at end of file
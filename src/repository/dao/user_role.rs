@@ -1,6 +1,6 @@
 use crate::{
     repository::{DBError, Dao, POOL},
-    util::serde_format::naive_datetime,
+    util::{now, serde_format::naive_datetime},
 };
 use app_macro::Dao;
 use async_trait::async_trait;
@@ -26,17 +26,34 @@ impl UserRole {
             .new_wrapper()
             .eq("user_id", user_id)
             .and()
-            .eq("role_id", role_id);
+            .eq("role_id", role_id)
+            .and()
+            .gt("expire", now());
         Self::find_one(w).await
     }
     pub async fn find_by_user(
         user_id: &str,
+        include_expired: bool,
     ) -> Result<Vec<Self>, DBError> {
-        let w = POOL.new_wrapper().eq("user_id", user_id);
+        let mut w = POOL.new_wrapper().eq("user_id", user_id);
+        if !include_expired {
+            w = w.and().gt("expire", now());
+        }
+        Self::find_list(w).await
+    }
+    pub async fn find_by_role(role_id: &str, include_expired: bool) -> Result<Vec<Self>, DBError> {
+        let mut w = POOL.new_wrapper().eq("role_id", role_id);
+        if !include_expired {
+            w = w.and().gt("expire", now());
+        }
         Self::find_list(w).await
     }
-    pub async fn find_by_role(role_id: &str) -> Result<Vec<Self>, DBError> {
-        let w = POOL.new_wrapper().eq("role_id", role_id);
+    pub async fn find_expired(at: NaiveDateTime) -> Result<Vec<Self>, DBError> {
+        let w = POOL.new_wrapper().le("expire", at);
         Self::find_list(w).await
     }
+    pub async fn delete_expired(at: NaiveDateTime) -> Result<u64, DBError> {
+        let w = POOL.new_wrapper().le("expire", at);
+        Self::delete_one(w).await
+    }
 }
@@ -0,0 +1,39 @@
+use crate::{
+    repository::{DBError, Dao, POOL},
+    util::serde_format::{i32_bool, naive_datetime},
+};
+use app_macro::Dao;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rbatis::{crud::CRUD, wrapper::Wrapper};
+use serde::Serialize;
+
+#[crud_table(table_name: "user_totps")]
+#[derive(Debug, Clone, Dao)]
+pub struct UserTotp {
+    pub id: String,
+    pub user_id: String,
+    // base32 shared secret
+    pub secret: String,
+    // time step of the last accepted code, codes are single use
+    pub last_used_step: i64,
+    #[serde(serialize_with = "i32_bool::serialize")]
+    pub is_enabled: i32,
+    #[serde(serialize_with = "naive_datetime::serialize")]
+    pub created_at: NaiveDateTime,
+}
+
+impl UserTotp {
+    pub async fn find_by_user(user_id: &str) -> Result<Self, DBError> {
+        let w = POOL.new_wrapper().eq("user_id", user_id);
+        Self::find_one(w).await
+    }
+    pub async fn is_enabled_for(user_id: &str) -> Result<bool, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .eq("user_id", user_id)
+            .and()
+            .eq("is_enabled", 1);
+        Ok(!Self::find_list(w).await?.is_empty())
+    }
+}
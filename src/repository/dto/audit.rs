@@ -0,0 +1,132 @@
+use crate::{
+    repository::{dao::AuditLog, vo, DBError, POOL},
+    util::{audit::Actor, now, serde_format::naive_datetime, uuid_v4},
+};
+use chrono::NaiveDateTime;
+use rbatis::{
+    crud::CRUD,
+    plugin::page::{Page, PageRequest},
+};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// an audit entry under construction, saved by the mutation in its own transaction
+#[derive(Debug, Clone)]
+pub struct NewAuditLog {
+    action: String,
+    target_id: String,
+    domain_id: Option<String>,
+    before: Option<String>,
+    after: Option<String>,
+}
+
+impl NewAuditLog {
+    /// `action` is `<target_type>.<verb>`
+    pub fn new(action: &str, target_id: &str, domain_id: Option<&str>) -> Self {
+        Self {
+            action: action.to_string(),
+            target_id: target_id.to_string(),
+            domain_id: domain_id.map(String::from),
+            before: None,
+            after: None,
+        }
+    }
+    pub fn before<T: Serialize>(mut self, v: &T) -> Self {
+        self.before = serde_json::to_string(v).ok();
+        self
+    }
+    pub fn after<T: Serialize>(mut self, v: &T) -> Self {
+        self.after = serde_json::to_string(v).ok();
+        self
+    }
+    pub fn by(self, actor: &Actor) -> AuditLog {
+        let target_type = self.action.split('.').next().unwrap_or_default().to_string();
+        AuditLog {
+            id: uuid_v4(),
+            actor_id: actor.id.clone(),
+            impersonator_id: actor.impersonator_id.clone(),
+            action: self.action,
+            target_type,
+            target_id: self.target_id,
+            domain_id: self.domain_id,
+            before_json: self.before,
+            after_json: self.after,
+            request_id: actor.request_id.clone(),
+            ip: actor.ip.clone(),
+            created_at: now(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct QueryAudit {
+    pub domain_id: Option<String>,
+    pub actor_id: Option<String>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    #[serde(default, deserialize_with = "option_datetime::deserialize")]
+    pub from: Option<NaiveDateTime>,
+    #[serde(default, deserialize_with = "option_datetime::deserialize")]
+    pub to: Option<NaiveDateTime>,
+    #[validate(range(min = 1))]
+    page: Option<u64>,
+    #[validate(range(min = 1))]
+    limit: Option<u64>,
+}
+
+mod option_datetime {
+    use super::naive_datetime;
+    use chrono::NaiveDateTime;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<NaiveDateTime>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "naive_datetime")] NaiveDateTime);
+        let v: Option<Wrapper> = Option::deserialize(deserializer)?;
+        Ok(v.map(|Wrapper(v)| v))
+    }
+}
+
+impl QueryAudit {
+    pub async fn find_all(self) -> Result<Page<vo::AuditLog>, DBError> {
+        let page = self.page.unwrap_or(1);
+        let limit = self.limit.unwrap_or(10);
+        let req = PageRequest::new(page, limit);
+        let mut w = POOL.new_wrapper();
+        if let Some(domain_id) = self.domain_id {
+            w = w.and().eq("domain_id", domain_id);
+        }
+        if let Some(actor_id) = self.actor_id {
+            w = w.and().eq("actor_id", actor_id);
+        }
+        if let Some(action) = self.action {
+            w = w.and().eq("action", action);
+        }
+        if let Some(target_type) = self.target_type {
+            w = w.and().eq("target_type", target_type);
+        }
+        if let Some(target_id) = self.target_id {
+            w = w.and().eq("target_id", target_id);
+        }
+        if let Some(from) = self.from {
+            w = w.and().ge("created_at", from);
+        }
+        if let Some(to) = self.to {
+            w = w.and().lt("created_at", to);
+        }
+        w = w.order_by(false, &["created_at"]);
+        let ret = POOL.fetch_page_by_wrapper::<AuditLog>(w, &req).await?;
+        Ok(Page::<vo::AuditLog> {
+            records: ret.records.into_iter().map(Into::into).collect(),
+            total: ret.total,
+            pages: ret.pages,
+            page_no: ret.page_no,
+            page_size: ret.page_size,
+            search_count: ret.search_count,
+        })
+    }
+}
@@ -3,12 +3,24 @@ use crate::{
         dao::{Domain, Role, UserRole},
         DBError, Dao, POOL,
     },
-    util::{default_expire, now, uuid_v4},
+    util::{audit::Actor, default_expire, now, uuid_v4},
 };
+use super::NewAuditLog;
 use rbatis::crud::CRUDMut;
 use serde::{Deserialize, Serialize};
 use std::env;
-use validator::Validate;
+use validator::{Validate, ValidationError};
+
+pub const CONFLICT_STRATEGIES: [&str; 2] = ["deny_overrides", "allow_overrides"];
+
+fn validate_conflict_strategy(v: &str) -> Result<(), ValidationError> {
+    if !CONFLICT_STRATEGIES.contains(&v) {
+        let mut e = ValidationError::new("conflict_strategy");
+        e.message = Some("冲突策略不合法".into());
+        return Err(e);
+    }
+    Ok(())
+}
 
 #[derive(Debug, Deserialize, Serialize, Validate)]
 pub struct NewDomain {
@@ -16,10 +28,16 @@ pub struct NewDomain {
     pub name: String,
     pub description: Option<String>,
     pub admin_id: String,
+    pub allow_jit: Option<bool>,
+    #[validate(range(min = 0, max = 2))]
+    pub email_verification: Option<i32>,
+    #[validate(custom = "validate_conflict_strategy")]
+    pub conflict_strategy: Option<String>,
 }
 
 impl NewDomain {
-    pub async fn create(self, user_id: &str) -> Result<Domain, DBError> {
+    pub async fn create(self, actor: &Actor) -> Result<Domain, DBError> {
+        let user_id = actor.id.as_str();
         let domain_id = uuid_v4();
         let admin_role_name =
             env::var("ADMIN_ROLE_NAME").expect("environment variable ADMIN_ROLE_NAME must be set");
@@ -33,6 +51,7 @@ impl NewDomain {
             description: None,
             value: admin_role_name.to_uppercase().clone(),
             level: 1,
+            max_holders: None,
             is_deleted: 0,
             domain_id: domain_id.clone(),
             created_at: now(),
@@ -49,6 +68,9 @@ impl NewDomain {
             created_at: now(),
         };
         tx.save(&user_role, &[]).await?;
+        let log = NewAuditLog::new("user.grant_role", &user_role.user_id, Some(&domain_id))
+            .after(&user_role);
+        tx.save(&log.by(actor), &[]).await?;
         let common_role_id = uuid_v4();
         let common_role = Role {
             id: common_role_id.clone(),
@@ -56,6 +78,7 @@ impl NewDomain {
             description: None,
             value: common_role_name.to_uppercase().clone(),
             level: 999,
+            max_holders: None,
             is_deleted: 0,
             domain_id: domain_id.clone(),
             created_at: now(),
@@ -69,12 +92,19 @@ impl NewDomain {
             name: self.name,
             description: self.description,
             default_role_id: common_role_id,
-            admin_role_id: admin_role_id,
+            admin_role_id,
+            allow_jit: self.allow_jit.unwrap_or(false) as i32,
+            email_verification: self.email_verification.unwrap_or(0),
+            conflict_strategy: self
+                .conflict_strategy
+                .unwrap_or_else(|| CONFLICT_STRATEGIES[0].to_string()),
             is_deleted: 0,
             created_at: now(),
             updated_at: now(),
         };
         tx.save(&dao, &[]).await?;
+        let log = NewAuditLog::new("domain.create", &dao.id, Some(&dao.id)).after(&dao);
+        tx.save(&log.by(actor), &[]).await?;
         // DomainDao::create_one(&dao).await?;
         tx.commit().await.unwrap();
         Ok(dao)
@@ -86,17 +116,35 @@ pub struct UpdateDomain {
     #[validate(length(min = 1, max = 100))]
     pub name: Option<String>,
     pub description: Option<String>,
+    pub allow_jit: Option<bool>,
+    #[validate(range(min = 0, max = 2))]
+    pub email_verification: Option<i32>,
+    #[validate(custom = "validate_conflict_strategy")]
+    pub conflict_strategy: Option<String>,
 }
 
 impl UpdateDomain {
-    pub async fn save(self, id: &str) -> Result<Domain, DBError> {
+    pub async fn save(self, id: &str, actor: &Actor) -> Result<Domain, DBError> {
         let w = POOL.new_wrapper().eq("id", id);
         let mut dao = Domain::find_one(w.clone()).await?;
+        let log = NewAuditLog::new("domain.update", id, Some(id)).before(&dao);
         if let Some(name) = self.name {
             dao.name = name;
         }
         dao.description = self.description;
-        Domain::update_one(&dao, w).await?;
+        if let Some(allow_jit) = self.allow_jit {
+            dao.allow_jit = allow_jit as i32;
+        }
+        if let Some(email_verification) = self.email_verification {
+            dao.email_verification = email_verification;
+        }
+        if let Some(conflict_strategy) = self.conflict_strategy {
+            dao.conflict_strategy = conflict_strategy;
+        }
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.update_by_wrapper(&dao, w, &[]).await?;
+        tx.save(&log.after(&dao).by(actor), &[]).await?;
+        tx.commit().await.unwrap();
         Ok(dao)
     }
 }
@@ -0,0 +1,210 @@
+use crate::{
+    repository::{
+        dao::{
+            role::{Holdings, Scope},
+            Domain, IdentityProvider, Role, User, UserIdentity, UserRole,
+        },
+        ConstraintError, DBError, Dao, POOL,
+    },
+    util::{
+        audit::Actor,
+        default_expire,
+        federation::{is_public_ip, ExternalClaims},
+        now,
+        password::hash_password,
+        random_token, uuid_v4,
+    },
+};
+use super::{user_role::check_constraints, NewAuditLog};
+use rbatis::crud::CRUDMut;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use url::{Host, Url};
+use validator::{Validate, ValidationError};
+
+/// only public `https://` urls, the JWK set is fetched by the server
+fn validate_jwks_uri(v: &str) -> Result<(), ValidationError> {
+    let is_public = match Url::parse(v) {
+        Ok(url) if url.scheme() == "https" => match url.host() {
+            Some(Host::Domain(domain)) => {
+                let domain = domain.trim_end_matches('.').to_lowercase();
+                domain != "localhost" && !domain.ends_with(".localhost")
+            }
+            Some(Host::Ipv4(ip)) => is_public_ip(IpAddr::V4(ip)),
+            Some(Host::Ipv6(ip)) => is_public_ip(IpAddr::V6(ip)),
+            None => false,
+        },
+        _ => false,
+    };
+    if !is_public {
+        let mut e = ValidationError::new("jwks_uri");
+        e.message = Some("jwks_uri 需为公网 https 地址".into());
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct NewIdentityProvider {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    pub domain_id: String,
+    #[validate(length(min = 1, max = 200))]
+    pub issuer: String,
+    #[validate(length(min = 1, max = 200))]
+    pub audience: String,
+    #[validate(length(max = 500), custom = "validate_jwks_uri")]
+    pub jwks_uri: Option<String>,
+    /// JWK set file on the server, only sys admins may set it
+    #[validate(length(max = 500))]
+    pub jwks_path: Option<String>,
+    pub public_key: Option<String>,
+    #[serde(skip_deserializing)]
+    pub created_by: Option<String>,
+}
+
+impl NewIdentityProvider {
+    pub async fn create(self) -> Result<IdentityProvider, DBError> {
+        let dao = IdentityProvider {
+            id: uuid_v4(),
+            name: self.name,
+            domain_id: self.domain_id,
+            issuer: self.issuer,
+            audience: self.audience,
+            jwks_uri: self.jwks_uri,
+            jwks_path: self.jwks_path,
+            public_key: self.public_key,
+            is_deleted: 0,
+            created_at: now(),
+            updated_at: now(),
+            created_by: self.created_by.clone(),
+            updated_by: self.created_by,
+        };
+        IdentityProvider::create_one(&dao).await?;
+        Ok(dao)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct FederatedLogin {
+    pub provider_id: String,
+    #[validate(length(min = 1, max = 8192))]
+    pub token: String,
+}
+
+impl FederatedLogin {
+    pub async fn login(
+        &self,
+        identity: &UserIdentity,
+        claims: &ExternalClaims,
+    ) -> Result<UserIdentity, DBError> {
+        let mut dao = identity.to_owned();
+        dao.last_logined_at = now();
+        dao.email = claims.email.clone().or(dao.email);
+        let w = POOL.new_wrapper().eq("id", &dao.id);
+        UserIdentity::update_one(&dao, w).await?;
+        Ok(dao)
+    }
+    pub async fn link(
+        &self,
+        user_id: &str,
+        claims: &ExternalClaims,
+    ) -> Result<UserIdentity, DBError> {
+        let dao = UserIdentity {
+            id: uuid_v4(),
+            user_id: user_id.to_string(),
+            provider_id: self.provider_id.clone(),
+            subject: claims.sub.clone(),
+            email: claims.email.clone(),
+            last_logined_at: now(),
+            created_at: now(),
+        };
+        UserIdentity::create_one(&dao).await?;
+        Ok(dao)
+    }
+    /// creates the user with the default role of the domain, granted under the domain's role
+    /// constraints, and links the identity; `actor` is the new user and gives its id
+    pub async fn provision(
+        &self,
+        domain: &Domain,
+        claims: &ExternalClaims,
+        actor: &Actor,
+    ) -> Result<User, ConstraintError> {
+        let base: String = claims
+            .preferred_username
+            .clone()
+            .or_else(|| {
+                claims
+                    .email
+                    .as_ref()
+                    .and_then(|v| v.split('@').next().map(String::from))
+            })
+            .unwrap_or_else(|| claims.sub.clone())
+            .chars()
+            .take(40)
+            .collect();
+        let username = if User::find_by_username(&base).await.is_ok() {
+            format!("{}_{}", base, &uuid_v4()[..8])
+        } else {
+            base
+        };
+        let user = User {
+            id: actor.id.clone(),
+            username,
+            // never handed out, the user signs in through the provider
+            password: hash_password(&random_token()).await,
+            email: claims.email.clone(),
+            email_verified_at: match claims.email_verified {
+                Some(true) => Some(now()),
+                _ => None,
+            },
+            avatar: claims.picture.clone(),
+            memo: None,
+            sys_role: Some("member".to_string()),
+            is_actived: 1,
+            is_deleted: 0,
+            last_logined_at: now(),
+            created_at: now(),
+        };
+        let identity = UserIdentity {
+            id: uuid_v4(),
+            user_id: user.id.clone(),
+            provider_id: self.provider_id.clone(),
+            subject: claims.sub.clone(),
+            email: claims.email.clone(),
+            last_logined_at: now(),
+            created_at: now(),
+        };
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        // the default role is granted like any other, under the domain's constraints
+        let scope = Scope {
+            user_ids: vec![user.id.clone()],
+            role_ids: vec![domain.default_role_id.clone()],
+            ..Default::default()
+        };
+        let before = Holdings::load(&mut tx, &domain.id, &scope).await?;
+        let role = Role::lock(&mut tx, &domain.default_role_id).await?;
+        let user_role = UserRole {
+            user_id: user.id.clone(),
+            role_id: role.id.clone(),
+            role_level: role.level,
+            expire: default_expire(),
+            created_at: now(),
+        };
+        let mut after = before.clone();
+        after.user_roles.push(user_role.clone());
+        if let Err(e) = check_constraints(&mut tx, &role, &before, &after).await {
+            tx.rollback().await.unwrap();
+            return Err(e);
+        }
+        tx.save(&user, &[]).await?;
+        tx.save(&user_role, &[]).await?;
+        tx.save(&identity, &[]).await?;
+        let log = NewAuditLog::new("user.grant_role", &user.id, Some(&role.domain_id))
+            .after(&user_role)
+            .by(actor);
+        tx.save(&log, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(user)
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{
+    repository::{
+        dao::{group::IntoVecOfVo, Group, GroupRole, ResourceGrant, UserGroup},
+        vo, DBError, POOL,
+    },
+    util::{audit::Actor, now, uuid_v4},
+};
+use super::NewAuditLog;
+use rbatis::{
+    crud::{CRUD, CRUDMut},
+    plugin::page::{Page, PageRequest},
+};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct NewGroup {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    pub description: Option<String>,
+    pub domain_id: String,
+}
+
+impl NewGroup {
+    pub async fn create(self, actor: &Actor) -> Result<Group, DBError> {
+        let dao = Group {
+            id: uuid_v4(),
+            name: self.name,
+            description: self.description,
+            domain_id: self.domain_id,
+            is_deleted: 0,
+            created_by: Some(actor.id.clone()),
+            updated_by: Some(actor.id.clone()),
+            created_at: now(),
+            updated_at: now(),
+        };
+        let log = NewAuditLog::new("group.create", &dao.id, Some(&dao.domain_id)).after(&dao);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.save(&dao, &[]).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(dao)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct UpdateGroup {
+    #[validate(length(min = 1, max = 100))]
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+impl UpdateGroup {
+    pub async fn save(self, found: &Group, actor: &Actor) -> Result<Group, DBError> {
+        let log = NewAuditLog::new("group.update", &found.id, Some(&found.domain_id)).before(found);
+        let mut dao = found.to_owned();
+        if let Some(name) = self.name {
+            dao.name = name;
+        }
+        dao.description = self.description;
+        dao.updated_by = Some(actor.id.clone());
+        dao.updated_at = now();
+        let w = POOL.new_wrapper().eq("id", &dao.id);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.update_by_wrapper(&dao, w, &[]).await?;
+        tx.save(&log.after(&dao).by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(dao)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteGroup {
+    pub id: String,
+}
+
+impl DeleteGroup {
+    /// drops the members, role grants and resource grants along with the group
+    pub async fn save(&self, found: &Group, actor: &Actor) -> Result<u64, DBError> {
+        let log = NewAuditLog::new("group.delete", &self.id, Some(&found.domain_id)).before(found);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let w = POOL.new_wrapper().eq("group_id", &self.id);
+        tx.remove_by_wrapper::<UserGroup>(w.clone()).await?;
+        tx.remove_by_wrapper::<GroupRole>(w).await?;
+        let w = POOL
+            .new_wrapper()
+            .eq("subject_type", "group")
+            .and()
+            .eq("subject_id", &self.id);
+        tx.remove_by_wrapper::<ResourceGrant>(w).await?;
+        let w = POOL.new_wrapper().eq("id", &self.id);
+        let removed = tx.remove_by_wrapper::<Group>(w).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(removed)
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct QueryGroup {
+    key: Option<String>,
+    domain_id: Option<String>,
+    #[validate(range(min = 1))]
+    page: Option<u64>,
+    #[validate(range(min = 1))]
+    limit: Option<u64>,
+}
+
+impl QueryGroup {
+    pub async fn find_all(self) -> Result<Page<vo::Group>, DBError> {
+        let page = self.page.unwrap_or(1);
+        let limit = self.limit.unwrap_or(10);
+        let req = PageRequest::new(page, limit);
+        let mut w = POOL.new_wrapper();
+        if let Some(domain_id) = self.domain_id {
+            let domain_ids: Vec<&str> = domain_id.split(",").collect();
+            w = w.r#in("domain_id", &domain_ids);
+        }
+        if let Some(key) = self.key {
+            if !key.is_empty() {
+                w = w.and().like("name", key);
+            }
+        }
+        w = w.order_by(false, &["created_at"]);
+        let ret = POOL.fetch_page_by_wrapper::<Group>(w, &req).await?;
+        let records = ret.records.into_vo().await?;
+        Ok(Page::<vo::Group> {
+            records,
+            total: ret.total,
+            pages: ret.pages,
+            page_no: ret.page_no,
+            page_size: ret.page_size,
+            search_count: ret.search_count,
+        })
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{
+    repository::{
+        dao::{role::{Holdings, Scope}, GroupRole, Role},
+        ConstraintError, DBError, Dao, POOL,
+    },
+    util::{audit::Actor, default_expire, now},
+};
+use super::{user_role::check_constraints, NewAuditLog};
+use chrono::NaiveDateTime;
+use rbatis::crud::CRUDMut;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct GroupGrantRole {
+    #[validate(length(min = 1))]
+    pub group_ids: Vec<String>,
+    pub role_id: String,
+    pub expire: Option<NaiveDateTime>,
+}
+
+impl GroupGrantRole {
+    /// `Err` describes the holder limit or prerequisite the grant would break for a member,
+    /// nothing is written then
+    pub async fn save(
+        self,
+        role: &Role,
+        actor: &Actor,
+    ) -> Result<Vec<GroupRole>, ConstraintError> {
+        let group_roles: Vec<GroupRole> = self
+            .group_ids
+            .iter()
+            .map(|group_id| GroupRole {
+                group_id: group_id.clone(),
+                role_id: self.role_id.clone(),
+                expire: self.expire.unwrap_or_else(default_expire),
+                created_at: now(),
+            })
+            .collect();
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let scope = Scope {
+            role_ids: vec![role.id.clone()],
+            group_ids: self.group_ids.clone(),
+            ..Default::default()
+        };
+        let before = Holdings::load(&mut tx, &role.domain_id, &scope).await?;
+        let role = Role::lock(&mut tx, &role.id).await?;
+        let mut after = before.clone();
+        after
+            .group_roles
+            .retain(|v| !(v.role_id == role.id && self.group_ids.contains(&v.group_id)));
+        after.group_roles.extend(group_roles.iter().cloned());
+        if let Err(e) = check_constraints(&mut tx, &role, &before, &after).await {
+            tx.rollback().await.unwrap();
+            return Err(e);
+        }
+        let w = POOL
+            .new_wrapper()
+            .r#in("group_id", &self.group_ids)
+            .and()
+            .eq("role_id", &self.role_id);
+        tx.remove_by_wrapper::<GroupRole>(w).await?;
+        tx.save_batch(&group_roles, &[]).await?;
+        let logs: Vec<_> = group_roles
+            .iter()
+            .map(|v| {
+                NewAuditLog::new("group.grant_role", &v.group_id, Some(&role.domain_id))
+                    .after(v)
+                    .by(actor)
+            })
+            .collect();
+        tx.save_batch(&logs, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(group_roles)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct GroupRevokeRole {
+    #[validate(length(min = 1))]
+    pub group_ids: Vec<String>,
+    pub role_id: String,
+}
+
+impl GroupRevokeRole {
+    pub async fn save(self, role: &Role, actor: &Actor) -> Result<u64, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .r#in("group_id", &self.group_ids)
+            .and()
+            .eq("role_id", &self.role_id);
+        let logs: Vec<_> = GroupRole::find_list(w.clone())
+            .await?
+            .iter()
+            .map(|v| {
+                NewAuditLog::new("group.revoke_role", &v.group_id, Some(&role.domain_id))
+                    .before(v)
+                    .by(actor)
+            })
+            .collect();
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let revoked = tx.remove_by_wrapper::<GroupRole>(w).await?;
+        tx.save_batch(&logs, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(revoked)
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{
+    repository::{dao::LoginLockout, DBError, Dao, POOL},
+    util::{lockout, now, uuid_v4},
+};
+use chrono::NaiveDateTime;
+use rbatis::{
+    crud::CRUD,
+    plugin::page::{Page, PageRequest},
+};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NewLockout {
+    pub lock_key: String,
+    pub user_id: Option<String>,
+    pub username: Option<String>,
+    pub ip: Option<String>,
+    pub failures: u32,
+    pub locked_until: NaiveDateTime,
+}
+
+impl NewLockout {
+    pub async fn create(self) -> Result<LoginLockout, DBError> {
+        let dao = LoginLockout {
+            id: uuid_v4(),
+            lock_key: self.lock_key,
+            user_id: self.user_id,
+            username: self.username,
+            ip: self.ip,
+            failures: self.failures as i32,
+            locked_until: self.locked_until,
+            is_unlocked: 0,
+            unlocked_by: None,
+            created_at: now(),
+        };
+        LoginLockout::create_one(&dao).await?;
+        tracing::warn!(
+            lock_key = %dao.lock_key, ip = ?dao.ip, failures = dao.failures,
+            locked_until = %dao.locked_until, "login locked out"
+        );
+        Ok(dao)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UnlockUser {
+    pub user_id: String,
+    pub unlocked_by: String,
+}
+
+impl UnlockUser {
+    /// lifts the account lock and resets its failure count
+    pub async fn save(&self) -> Result<u64, DBError> {
+        let key = lockout::account_key(&self.user_id);
+        let locks = LoginLockout::find_active_by_key(&key, now()).await?;
+        let mut unlocked = 0;
+        for mut lock in locks.into_iter() {
+            lock.is_unlocked = 1;
+            lock.unlocked_by = Some(self.unlocked_by.clone());
+            let w = POOL.new_wrapper().eq("id", &lock.id);
+            unlocked += lock.update_one(w).await?;
+        }
+        lockout::unlock(&key);
+        Ok(unlocked)
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct QueryLockout {
+    pub user_id: Option<String>,
+    pub ip: Option<String>,
+    // only locks still in force
+    pub active: Option<bool>,
+    #[validate(range(min = 1))]
+    page: Option<u64>,
+    #[validate(range(min = 1))]
+    limit: Option<u64>,
+}
+
+impl QueryLockout {
+    pub async fn find_all(self) -> Result<Page<LoginLockout>, DBError> {
+        let page = self.page.unwrap_or(1);
+        let limit = self.limit.unwrap_or(10);
+        let req = PageRequest::new(page, limit);
+        let mut w = POOL.new_wrapper();
+        if let Some(user_id) = self.user_id {
+            w = w.and().eq("user_id", user_id);
+        }
+        if let Some(ip) = self.ip {
+            w = w.and().eq("ip", ip);
+        }
+        if self.active == Some(true) {
+            w = w.and().gt("locked_until", now()).and().eq("is_unlocked", 0);
+        }
+        w = w.order_by(false, &["created_at"]);
+        POOL.fetch_page_by_wrapper::<LoginLockout>(w, &req).await
+    }
+}
@@ -0,0 +1,152 @@
+use crate::{
+    repository::{
+        dao::{RecoveryCode, User, UserTotp},
+        DBError, Dao, POOL,
+    },
+    util::{hash_token, now, totp, uuid_v4},
+};
+use rbatis::crud::CRUDMut;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+
+fn recovery_codes(user_id: &str) -> (Vec<RecoveryCode>, Vec<String>) {
+    let codes = totp::generate_recovery_codes(RECOVERY_CODE_COUNT);
+    let daos = codes
+        .iter()
+        .map(|code| RecoveryCode {
+            id: uuid_v4(),
+            user_id: user_id.to_string(),
+            code_hash: hash_token(code),
+            is_used: 0,
+            created_at: now(),
+        })
+        .collect();
+    (daos, codes)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EnrollTotp {
+    pub user_id: String,
+}
+
+impl EnrollTotp {
+    /// replaces any pending enrollment with a fresh secret, returns the secret and its provisioning uri
+    pub async fn create(self, user: &User) -> Result<(UserTotp, String), DBError> {
+        let dao = UserTotp {
+            id: uuid_v4(),
+            user_id: self.user_id,
+            secret: totp::generate_secret(),
+            last_used_step: 0,
+            is_enabled: 0,
+            created_at: now(),
+        };
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let w = POOL.new_wrapper().eq("user_id", &dao.user_id);
+        tx.remove_by_wrapper::<UserTotp>(w).await?;
+        tx.save(&dao, &[]).await?;
+        tx.commit().await.unwrap();
+        let uri = totp::provisioning_uri(&user.username, &dao.secret);
+        Ok((dao, uri))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct VerifyTotp {
+    #[validate(length(min = 6, max = 10))]
+    pub code: String,
+}
+
+impl VerifyTotp {
+    /// checks the code and consumes its time step
+    pub async fn verify(&self, dao: &UserTotp) -> Result<bool, DBError> {
+        let step = match totp::verify(&dao.secret, &self.code, dao.last_used_step) {
+            Some(step) => step,
+            None => return Ok(false),
+        };
+        let mut dao = dao.to_owned();
+        dao.last_used_step = step;
+        let w = POOL
+            .new_wrapper()
+            .eq("id", &dao.id)
+            .and()
+            .lt("last_used_step", step);
+        Ok(UserTotp::update_one(&dao, w).await? == 1)
+    }
+    /// enables the second factor and issues new recovery codes, the plain codes are never stored
+    pub async fn activate(&self, dao: &UserTotp) -> Result<Vec<String>, DBError> {
+        // reload, `verify` has consumed a time step since `dao` was read
+        let mut dao = UserTotp::find_by_id(&dao.id).await?;
+        dao.is_enabled = 1;
+        let (recovery_codes, codes) = recovery_codes(&dao.user_id);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let w = POOL.new_wrapper().eq("id", &dao.id);
+        tx.update_by_wrapper(&dao, w, &[]).await?;
+        let w = POOL.new_wrapper().eq("user_id", &dao.user_id);
+        tx.remove_by_wrapper::<RecoveryCode>(w).await?;
+        tx.save_batch(&recovery_codes, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(codes)
+    }
+    pub async fn regenerate_recovery_codes(&self, user_id: &str) -> Result<Vec<String>, DBError> {
+        let (recovery_codes, codes) = recovery_codes(user_id);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let w = POOL.new_wrapper().eq("user_id", user_id);
+        tx.remove_by_wrapper::<RecoveryCode>(w).await?;
+        tx.save_batch(&recovery_codes, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(codes)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct LoginMfa {
+    #[validate(length(min = 1, max = 2000))]
+    pub challenge_token: String,
+    #[validate(length(min = 6, max = 10))]
+    pub code: Option<String>,
+    #[validate(length(min = 1, max = 50))]
+    pub recovery_code: Option<String>,
+}
+
+impl LoginMfa {
+    pub async fn login(&self, dao: &User) -> Result<User, DBError> {
+        let mut dao = dao.to_owned();
+        dao.last_logined_at = now();
+        let w = POOL.new_wrapper().eq("id", &dao.id);
+        User::update_one(&dao, w).await?;
+        Ok(dao)
+    }
+    /// marks the recovery code as used, returns `false` when it is unknown or already used
+    pub async fn use_recovery_code(&self, user_id: &str, code: &str) -> Result<bool, DBError> {
+        let mut dao = match RecoveryCode::find_unused(user_id, code).await {
+            Ok(val) => val,
+            Err(_) => return Ok(false),
+        };
+        dao.is_used = 1;
+        let w = POOL
+            .new_wrapper()
+            .eq("id", &dao.id)
+            .and()
+            .eq("is_used", 0);
+        Ok(RecoveryCode::update_one(&dao, w).await? == 1)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ResetMfa {
+    pub user_id: String,
+}
+
+impl ResetMfa {
+    /// removes the second factor and all recovery codes of the user
+    pub async fn save(&self) -> Result<u64, DBError> {
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let w = POOL.new_wrapper().eq("user_id", &self.user_id);
+        let removed = tx.remove_by_wrapper::<UserTotp>(w.clone()).await?;
+        tx.remove_by_wrapper::<RecoveryCode>(w).await?;
+        tx.commit().await.unwrap();
+        Ok(removed)
+    }
+}
@@ -5,15 +5,59 @@ mod user;
 mod domain;
 mod user_role;
 mod role_perm;
+mod role_parent;
+mod role_prerequisite;
+mod role_exclusion;
 mod user_org;
+mod org_role;
+mod group;
+mod user_group;
+mod group_role;
+mod resource_grant;
+mod relation;
 mod rbac;
+mod token;
+mod oauth;
+mod federation;
+mod mfa;
+mod lockout;
+mod verification;
+mod audit;
 
-pub use org::{NewOrg, UpdateOrg, QueryOrg};
-pub use perm::{NewPerm, UpdatePerm, QueryPerm, BatchInsertPerm};
-pub use role::{NewRole, UpdateRole, QueryRole};
-pub use user::{LoginUser, NewUser, UpdateUser, ConnectUser, ChangePassword, ResetPassword, QueryUser};
+pub use org::{NewOrg, UpdateOrg, MoveOrg, QueryOrg};
+pub use perm::{NewPerm, UpdatePerm, DeletePerm, QueryPerm, BatchInsertPerm};
+pub use role::{NewRole, UpdateRole, DeleteRole, QueryRole};
+pub use user::{
+    ChangePassword, ChangeSysRole, CreateUser, DeleteUser, ForgotPassword, LoginUser, NewUser,
+    QueryUser, ResetPassword, UpdateUser, UpdateUserStatus,
+};
 pub use domain::{NewDomain, UpdateDomain};
 pub use user_role::{UserGrantRole, UserRevokeRole, UpdateUserRole, UserChangeRole};
-pub use role_perm::{RoleGrantPerm, RoleRevokePerm, RoleChangePerm};
+pub use role_perm::{RoleGrantPerm, RoleRevokePerm, RoleChangePerm, UpdateRolePerm};
+pub use role_parent::RoleChangeParent;
+pub use role_prerequisite::RoleChangePrerequisite;
+pub use role_exclusion::{
+    find_exclusion_violations, find_inactive_role_ids,
+    find_session_violation, ActivateRoles, DeleteRoleExclusion, NewRoleExclusion,
+    QueryRoleExclusion,
+};
 pub use user_org::{UserJoinOrg, UserLeaveOrg};
-pub use rbac::{Access};
\ No newline at end of file
+pub use org_role::{OrgGrantRole, OrgRevokeRole};
+pub use group::{DeleteGroup, NewGroup, QueryGroup, UpdateGroup};
+pub use user_group::{UserJoinGroup, UserLeaveGroup};
+pub use group_role::{GroupGrantRole, GroupRevokeRole};
+pub use resource_grant::{QueryResourceGrant, ResourceGrantPerm, ResourceRevokePerm};
+pub use relation::{
+    relation_snapshot, CheckRelation, ExpandRelation, ListObjects, ListSubjects, QueryRelation,
+    RelationBody, UpdateRelationSchema, WriteRelations,
+};
+pub use rbac::{decide_rule, Access, Decide, QueryMember};
+pub use token::{Impersonate, Logout, NewRefreshToken, RevokeSessions, RotateRefreshToken};
+pub use oauth::{Authorize, NewClient, RemoveClient, TokenRequest, UpdateClient};
+pub use federation::{FederatedLogin, NewIdentityProvider};
+pub use mfa::{EnrollTotp, LoginMfa, ResetMfa, VerifyTotp};
+pub use lockout::{NewLockout, QueryLockout, UnlockUser};
+pub use verification::{
+    is_verification_enabled, verify_email_ttl, NewEmailVerification, ResendVerification, VerifyEmail,
+};
+pub use audit::{NewAuditLog, QueryAudit};
\ No newline at end of file
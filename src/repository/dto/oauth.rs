@@ -0,0 +1,228 @@
+use crate::{
+    repository::{
+        dao::{OAuthClient, OAuthCode},
+        vo, DBError, Dao, POOL,
+    },
+    util::{hash_token, now, random_token, uuid_v4},
+};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+use validator::{Validate, ValidationError};
+
+const GRANT_TYPES: [&str; 2] = ["authorization_code", "client_credentials"];
+const SCOPES: [&str; 3] = ["openid", "profile", "email"];
+
+fn invalid(code: &'static str, message: &'static str) -> ValidationError {
+    let mut e = ValidationError::new(code);
+    e.message = Some(message.into());
+    e
+}
+
+fn validate_redirect_uris(v: &[String]) -> Result<(), ValidationError> {
+    if v
+        .iter()
+        .any(|v| v.contains(' ') || Url::parse(v).is_err())
+    {
+        return Err(invalid("redirect_uris", "回调地址不合法"));
+    }
+    Ok(())
+}
+
+fn validate_grant_types(v: &[String]) -> Result<(), ValidationError> {
+    if v.is_empty() || v.iter().any(|v| !GRANT_TYPES.contains(&v.as_str())) {
+        return Err(invalid("grant_types", "授权类型不合法"));
+    }
+    Ok(())
+}
+
+fn validate_scopes(v: &[String]) -> Result<(), ValidationError> {
+    if v.iter().any(|v| v.is_empty() || v.contains(' ')) {
+        return Err(invalid("scopes", "授权范围不合法"));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct NewClient {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    pub domain_id: String,
+    #[validate(custom = "validate_redirect_uris")]
+    pub redirect_uris: Vec<String>,
+    #[validate(custom = "validate_grant_types")]
+    pub grant_types: Vec<String>,
+    #[validate(custom = "validate_scopes")]
+    pub scopes: Option<Vec<String>>,
+    pub is_public: Option<bool>,
+    #[serde(skip_deserializing)]
+    pub created_by: Option<String>,
+}
+
+impl NewClient {
+    pub async fn create(self) -> Result<vo::OAuthClient, DBError> {
+        let secret = if self.is_public.unwrap_or(false) {
+            None
+        } else {
+            Some(random_token())
+        };
+        let scopes = self
+            .scopes
+            .unwrap_or_else(|| SCOPES.iter().map(|v| v.to_string()).collect());
+        let dao = OAuthClient {
+            id: uuid_v4(),
+            name: self.name,
+            secret_hash: secret.as_deref().map(hash_token),
+            redirect_uris: self.redirect_uris.join(" "),
+            grant_types: self.grant_types.join(" "),
+            scopes: scopes.join(" "),
+            domain_id: self.domain_id,
+            is_deleted: 0,
+            created_at: now(),
+            updated_at: now(),
+            created_by: self.created_by.clone(),
+            updated_by: self.created_by,
+        };
+        OAuthClient::create_one(&dao).await?;
+        let mut client = vo::OAuthClient::from(dao);
+        client.secret = secret;
+        Ok(client)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct UpdateClient {
+    #[validate(length(min = 1, max = 100))]
+    pub name: Option<String>,
+    #[validate(custom = "validate_redirect_uris")]
+    pub redirect_uris: Option<Vec<String>>,
+    #[validate(custom = "validate_grant_types")]
+    pub grant_types: Option<Vec<String>>,
+    #[validate(custom = "validate_scopes")]
+    pub scopes: Option<Vec<String>>,
+    #[serde(skip_deserializing)]
+    pub updated_by: Option<String>,
+}
+
+impl UpdateClient {
+    pub async fn save(self, id: &str) -> Result<OAuthClient, DBError> {
+        let w = POOL.new_wrapper().eq("id", id);
+        let mut dao = OAuthClient::find_one(w.clone()).await?;
+        if let Some(name) = self.name {
+            dao.name = name;
+        }
+        if let Some(redirect_uris) = self.redirect_uris {
+            dao.redirect_uris = redirect_uris.join(" ");
+        }
+        if let Some(grant_types) = self.grant_types {
+            dao.grant_types = grant_types.join(" ");
+        }
+        if let Some(scopes) = self.scopes {
+            dao.scopes = scopes.join(" ");
+        }
+        dao.updated_by = self.updated_by;
+        dao.updated_at = now();
+        OAuthClient::update_one(&dao, w).await?;
+        Ok(dao)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RemoveClient {
+    pub removed_by: String,
+}
+
+impl RemoveClient {
+    /// only marks the client removed, its codes and tokens stop being accepted
+    pub async fn save(self, found: OAuthClient) -> Result<OAuthClient, DBError> {
+        let mut dao = found;
+        dao.is_deleted = 1;
+        dao.updated_by = Some(self.removed_by);
+        dao.updated_at = now();
+        let w = POOL.new_wrapper().eq("id", &dao.id);
+        OAuthClient::update_one(&dao, w).await?;
+        Ok(dao)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct Authorize {
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scope: Option<String>,
+    #[validate(length(max = 200))]
+    pub state: Option<String>,
+    #[validate(length(max = 200))]
+    pub nonce: Option<String>,
+    #[validate(length(min = 43, max = 128))]
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+}
+
+impl Authorize {
+    /// stores the authorization code, only its hash is kept
+    pub async fn create(&self, user_id: &str, scope: String) -> Result<String, DBError> {
+        let code = random_token();
+        let code_challenge_method = self.code_challenge.as_ref().map(|_| {
+            self.code_challenge_method
+                .clone()
+                .unwrap_or_else(|| "S256".to_string())
+        });
+        let dao = OAuthCode {
+            id: uuid_v4(),
+            code_hash: hash_token(&code),
+            client_id: self.client_id.clone(),
+            user_id: user_id.to_string(),
+            redirect_uri: self.redirect_uri.clone(),
+            scope,
+            nonce: self.nonce.clone(),
+            code_challenge: self.code_challenge.clone(),
+            code_challenge_method,
+            is_used: 0,
+            expire: now() + Duration::minutes(10),
+            created_at: now(),
+        };
+        OAuthCode::create_one(&dao).await?;
+        Ok(code)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub scope: Option<String>,
+}
+
+impl TokenRequest {
+    pub fn is_verifier_matched(&self, code: &OAuthCode) -> bool {
+        let (challenge, verifier) = match (&code.code_challenge, &self.code_verifier) {
+            (None, _) => return true,
+            (Some(challenge), Some(verifier)) => (challenge, verifier),
+            _ => return false,
+        };
+        // codes issued with a plain challenge before only S256 was accepted don't redeem
+        if code.code_challenge_method.as_deref() != Some("S256") {
+            return false;
+        }
+        let hashed = Sha256::digest(verifier.as_bytes());
+        base64::encode_config(hashed, base64::URL_SAFE_NO_PAD) == *challenge
+    }
+    /// marks the code as used, `false` if it has been redeemed already
+    pub async fn redeem(&self, code: &OAuthCode) -> Result<bool, DBError> {
+        let mut dao = code.to_owned();
+        dao.is_used = 1;
+        let w = POOL
+            .new_wrapper()
+            .eq("id", &dao.id)
+            .and()
+            .eq("is_used", 0);
+        Ok(dao.update_one(w).await? > 0)
+    }
+}
@@ -1,15 +1,21 @@
 use crate::{
     repository::{
-        dao::{org::IntoVecOfVo, Org},
-        vo, DBError, Dao, POOL,
+        dao::{
+            org::IntoVecOfVo,
+            role::{Holdings, Scope},
+            Org,
+        },
+        vo, ConstraintError, DBError, Dao, POOL,
     },
-    util::now,
+    util::{audit::Actor, now},
 };
+use super::{user_role::check_roles_constraints, NewAuditLog};
 use rbatis::{
-    crud::CRUD,
+    crud::{CRUD, CRUDMut},
     plugin::page::{Page, PageRequest},
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use uuid::Uuid;
 use validator::Validate;
 
@@ -19,25 +25,31 @@ pub struct NewOrg {
     pub name: String,
     pub description: Option<String>,
     pub domain_id: String,
+    pub parent_id: Option<String>,
     #[serde(skip_deserializing)]
     pub created_by: Option<String>,
 }
 
 impl NewOrg {
-    pub async fn create(self) -> Result<Org, DBError> {
+    pub async fn create(self, actor: &Actor) -> Result<Org, DBError> {
         let id = Uuid::new_v4().to_string();
         let dao = Org {
             id: id.clone(),
             name: self.name,
             description: self.description,
             domain_id: self.domain_id,
+            parent_id: self.parent_id,
             is_deleted: 0,
             created_by: self.created_by.clone(),
             updated_by: self.created_by,
             created_at: now(),
             updated_at: now(),
         };
-        Org::create_one(&dao).await?;
+        let log = NewAuditLog::new("org.create", &dao.id, Some(&dao.domain_id)).after(&dao);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.save(&dao, &[]).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
         Ok(dao)
     }
 }
@@ -52,14 +64,76 @@ pub struct UpdateOrg {
 }
 
 impl UpdateOrg {
-    pub async fn save(self, id: &str) -> Result<Org, DBError> {
+    pub async fn save(self, id: &str, actor: &Actor) -> Result<Org, DBError> {
         let w = POOL.new_wrapper().eq("id", id);
         let mut dao = Org::find_one(w.clone()).await?;
+        let log = NewAuditLog::new("org.update", id, Some(&dao.domain_id)).before(&dao);
         if let Some(name) = self.name {
             dao.name = name;
         }
         dao.description = self.description;
-        Org::update_one(&dao, w).await?;
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.update_by_wrapper(&dao, w, &[]).await?;
+        tx.save(&log.after(&dao).by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(dao)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MoveOrg {
+    /// none makes the org a root
+    pub parent_id: Option<String>,
+}
+
+impl MoveOrg {
+    /// rejects moves below the org itself and moves that make a member of the subtree break a
+    /// holder limit, static exclusion or prerequisite through the roles of the new parents,
+    /// nothing is written then
+    pub async fn save(self, org: &Org, actor: &Actor) -> Result<Org, ConstraintError> {
+        let log = NewAuditLog::new("org.move", &org.id, Some(&org.domain_id))
+            .before(&json!({"parent_id": org.parent_id}))
+            .after(&json!({"parent_id": self.parent_id}));
+        let mut dao = org.to_owned();
+        dao.parent_id = self.parent_id;
+        dao.updated_by = Some(actor.id.clone());
+        dao.updated_at = now();
+        let w = POOL.new_wrapper().eq("id", &dao.id);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let mut before = Holdings::lock(&mut tx, &org.domain_id).await?;
+        // checked once the domain is locked, so concurrent moves can't make a cycle together
+        if let Some(parent_id) = &dao.parent_id {
+            if before.subtree_ids(&[org.id.clone()]).contains(parent_id) {
+                tx.rollback().await.unwrap();
+                return Err(ConstraintError::Violated("不能移动到自身或下级组织".to_string()));
+            }
+        }
+        let moved = |holdings: &Holdings| {
+            let mut after = holdings.clone();
+            for v in after.orgs.iter_mut().filter(|v| v.id == dao.id) {
+                v.parent_id = dao.parent_id.clone();
+            }
+            after
+        };
+        // the subtree's members gain the roles of the new parents and lose those of the old
+        let mut role_ids = before.org_role_ids(&org.id);
+        role_ids.extend(moved(&before).org_role_ids(&org.id));
+        role_ids.sort();
+        role_ids.dedup();
+        let scope = Scope {
+            role_ids: role_ids.clone(),
+            org_ids: vec![org.id.clone()],
+            ..Default::default()
+        };
+        before.load_members(&mut tx, &scope).await?;
+        let after = moved(&before);
+        if let Err(e) = check_roles_constraints(&mut tx, &role_ids, &before, &after).await {
+            tx.rollback().await.unwrap();
+            return Err(e);
+        }
+        tx.update_by_wrapper(&dao, w, &[]).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
         Ok(dao)
     }
 }
@@ -68,6 +142,7 @@ impl UpdateOrg {
 pub struct QueryOrg {
     key: Option<String>,
     domain_id: Option<String>,
+    parent_id: Option<String>,
     #[validate(range(min = 1))]
     page: Option<u64>,
     #[validate(range(min = 1))]
@@ -85,11 +160,14 @@ impl QueryOrg {
         let sort_order = self.sort_order.unwrap_or("DESC".to_string());
         let mut w = POOL.new_wrapper();
         if let Some(domain_id) = self.domain_id {
-            let domain_ids: Vec<&str> = domain_id.split(",").into_iter().collect();
+            let domain_ids: Vec<&str> = domain_id.split(",").collect();
             w = w.r#in("domain_id", &domain_ids);
         }
+        if let Some(parent_id) = self.parent_id {
+            w = w.and().eq("parent_id", parent_id);
+        }
         if let Some(key) = self.key {
-            if key != "" {
+            if !key.is_empty() {
                 w = w.and().like("name", key);
             }
         }
@@ -0,0 +1,107 @@
+use crate::{
+    repository::{
+        dao::{role::{Holdings, Scope}, OrgRole, Role},
+        ConstraintError, DBError, Dao, POOL,
+    },
+    util::{audit::Actor, default_expire, now},
+};
+use super::{user_role::check_constraints, NewAuditLog};
+use chrono::NaiveDateTime;
+use rbatis::crud::CRUDMut;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct OrgGrantRole {
+    #[validate(length(min = 1))]
+    pub org_ids: Vec<String>,
+    pub role_id: String,
+    pub expire: Option<NaiveDateTime>,
+}
+
+impl OrgGrantRole {
+    /// `Err` describes the holder limit or prerequisite the grant would break for a member,
+    /// nothing is written then
+    pub async fn save(
+        self,
+        role: &Role,
+        actor: &Actor,
+    ) -> Result<Vec<OrgRole>, ConstraintError> {
+        let org_roles: Vec<OrgRole> = self
+            .org_ids
+            .iter()
+            .map(|org_id| OrgRole {
+                org_id: org_id.clone(),
+                role_id: self.role_id.clone(),
+                expire: self.expire.unwrap_or_else(default_expire),
+                created_at: now(),
+            })
+            .collect();
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let scope = Scope {
+            role_ids: vec![role.id.clone()],
+            org_ids: self.org_ids.clone(),
+            ..Default::default()
+        };
+        let before = Holdings::load(&mut tx, &role.domain_id, &scope).await?;
+        let role = Role::lock(&mut tx, &role.id).await?;
+        let mut after = before.clone();
+        after
+            .org_roles
+            .retain(|v| !(v.role_id == role.id && self.org_ids.contains(&v.org_id)));
+        after.org_roles.extend(org_roles.iter().cloned());
+        if let Err(e) = check_constraints(&mut tx, &role, &before, &after).await {
+            tx.rollback().await.unwrap();
+            return Err(e);
+        }
+        let w = POOL
+            .new_wrapper()
+            .r#in("org_id", &self.org_ids)
+            .and()
+            .eq("role_id", &self.role_id);
+        tx.remove_by_wrapper::<OrgRole>(w).await?;
+        tx.save_batch(&org_roles, &[]).await?;
+        let logs: Vec<_> = org_roles
+            .iter()
+            .map(|v| {
+                NewAuditLog::new("org.grant_role", &v.org_id, Some(&role.domain_id))
+                    .after(v)
+                    .by(actor)
+            })
+            .collect();
+        tx.save_batch(&logs, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(org_roles)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct OrgRevokeRole {
+    #[validate(length(min = 1))]
+    pub org_ids: Vec<String>,
+    pub role_id: String,
+}
+
+impl OrgRevokeRole {
+    pub async fn save(self, role: &Role, actor: &Actor) -> Result<u64, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .r#in("org_id", &self.org_ids)
+            .and()
+            .eq("role_id", &self.role_id);
+        let logs: Vec<_> = OrgRole::find_list(w.clone())
+            .await?
+            .iter()
+            .map(|v| {
+                NewAuditLog::new("org.revoke_role", &v.org_id, Some(&role.domain_id))
+                    .before(v)
+                    .by(actor)
+            })
+            .collect();
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let revoked = tx.remove_by_wrapper::<OrgRole>(w).await?;
+        tx.save_batch(&logs, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(revoked)
+    }
+}
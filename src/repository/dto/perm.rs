@@ -1,12 +1,13 @@
 use crate::{
     repository::{
-        dao::{perm::IntoVecOfVo, Perm},
+        dao::{perm::IntoVecOfVo, Perm, ResourceGrant},
         vo, DBError, Dao, POOL,
     },
-    util::{now, uuid_v4},
+    util::{audit::Actor, now, perm_value::validate_perm_value, uuid_v4},
 };
+use super::NewAuditLog;
 use rbatis::{
-    crud::CRUD,
+    crud::{CRUD, CRUDMut},
     plugin::page::{Page, PageRequest},
 };
 use serde::{Deserialize, Serialize};
@@ -17,7 +18,7 @@ pub struct NewPerm {
     #[validate(length(min = 1, max = 100))]
     pub name: String,
     pub description: Option<String>,
-    #[validate(length(min = 1, max = 200))]
+    #[validate(length(min = 1, max = 200), custom = "validate_perm_value")]
     pub value: String,
     pub domain_id: Option<String>,
     #[serde(skip_deserializing)]
@@ -25,7 +26,7 @@ pub struct NewPerm {
 }
 
 impl NewPerm {
-    pub async fn create(self) -> Result<Perm, DBError> {
+    pub async fn create(self, actor: &Actor) -> Result<Perm, DBError> {
         let id = uuid_v4();
         let dao = Perm {
             id,
@@ -39,7 +40,11 @@ impl NewPerm {
             created_at: now(),
             updated_at: now(),
         };
-        Perm::create_one(&dao).await?;
+        let log = NewAuditLog::new("perm.create", &dao.id, Some(&dao.domain_id)).after(&dao);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.save(&dao, &[]).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
         Ok(dao)
     }
 }
@@ -49,25 +54,48 @@ pub struct UpdatePerm {
     #[validate(length(min = 1, max = 100))]
     pub name: String,
     pub description: Option<String>,
-    #[validate(length(min = 1, max = 200))]
+    #[validate(length(min = 1, max = 200), custom = "validate_perm_value")]
     pub value: String,
     #[serde(skip_deserializing)]
     pub updated_by: Option<String>,
 }
 
 impl UpdatePerm {
-    pub async fn save(self, id: &str) -> Result<Perm, DBError> {
+    pub async fn save(self, id: &str, actor: &Actor) -> Result<Perm, DBError> {
         let w = POOL.new_wrapper().eq("id", id);
         let mut dao = Perm::find_one(w.clone()).await?;
+        let log = NewAuditLog::new("perm.update", id, Some(&dao.domain_id)).before(&dao);
         dao.name = self.name;
         dao.description = self.description;
         dao.value = self.value;
         dao.updated_by = self.updated_by;
-        Perm::update_one(&dao, w).await?;
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.update_by_wrapper(&dao, w, &[]).await?;
+        tx.save(&log.after(&dao).by(actor), &[]).await?;
+        tx.commit().await.unwrap();
         Ok(dao)
     }
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeletePerm {
+    pub id: String,
+}
+
+impl DeletePerm {
+    pub async fn save(&self, found: &Perm, actor: &Actor) -> Result<u64, DBError> {
+        let log = NewAuditLog::new("perm.delete", &self.id, Some(&found.domain_id)).before(found);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let w = POOL.new_wrapper().eq("perm_id", &self.id);
+        tx.remove_by_wrapper::<ResourceGrant>(w).await?;
+        let w = POOL.new_wrapper().eq("id", &self.id);
+        let removed = tx.remove_by_wrapper::<Perm>(w).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(removed)
+    }
+}
+
 #[derive(Debug, Deserialize, Validate)]
 pub struct QueryPerm {
     key: Option<String>,
@@ -89,11 +117,11 @@ impl QueryPerm {
         let sort_order = self.sort_order.unwrap_or("DESC".to_string());
         let mut w = POOL.new_wrapper();
         if let Some(domain_id) = self.domain_id {
-            let domain_ids: Vec<&str> = domain_id.split(",").into_iter().collect();
+            let domain_ids: Vec<&str> = domain_id.split(",").collect();
             w = w.r#in("domain_id", &domain_ids);
         }
         if let Some(key) = self.key {
-            if key != "" {
+            if !key.is_empty() {
                 w = w.and().like("name", key);
             }
         }
@@ -112,8 +140,43 @@ impl QueryPerm {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct BatchInsertPerm{
   pub domain_id: String,
+  #[validate]
   pub perms: Vec<NewPerm>
 }
+
+impl BatchInsertPerm {
+    pub async fn create(self, actor: &Actor) -> Result<Vec<Perm>, DBError> {
+        let perms: Vec<Perm> = self
+            .perms
+            .iter()
+            .map(|v| Perm {
+                id: uuid_v4(),
+                name: v.name.clone(),
+                description: Some(v.name.clone()),
+                value: v.value.clone(),
+                domain_id: self.domain_id.clone(),
+                is_deleted: 0,
+                created_at: now(),
+                updated_at: now(),
+                created_by: Some(actor.id.clone()),
+                updated_by: Some(actor.id.clone()),
+            })
+            .collect();
+        let logs: Vec<_> = perms
+            .iter()
+            .map(|v| {
+                NewAuditLog::new("perm.create", &v.id, Some(&self.domain_id))
+                    .after(v)
+                    .by(actor)
+            })
+            .collect();
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.save_batch(&perms, &[]).await?;
+        tx.save_batch(&logs, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(perms)
+    }
+}
@@ -1,7 +1,287 @@
+use crate::{
+    repository::{
+        dao::{Perm, ResourceGrant, Role, RoleParent, RolePerm},
+        vo, DBError,
+    },
+    util::perm_value,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use validator::Validate;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Access {
     pub perm_id: Vec<String>,
     pub role_id: String,
+    /// attributes conditional grants are checked against
+    pub context: Option<Value>,
+    /// answer each perm with the rule that settled it instead of a bare bool
+    pub explain: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueryMember {
+    pub include_expired: Option<bool>,
+    /// also members of the orgs below, for org members
+    pub descendants: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct Decide {
+    pub user_id: Option<String>,
+    pub domain_id: String,
+    #[validate(length(min = 1, max = 200))]
+    pub perms: Vec<String>,
+    /// attributes conditional grants are checked against
+    pub context: Option<Value>,
+    /// a resource id like `project:42`, also checked against the grants on matching resources
+    #[validate(length(min = 1, max = 255))]
+    pub resource: Option<String>,
+    /// `jti` of the user's session, dynamically excluded roles count only while active in it
+    pub session: Option<String>,
+    /// held roles left out, not active in the session
+    #[serde(skip)]
+    pub inactive_role_ids: Vec<String>,
+}
+
+/// a grant matching a requested perm, with the values of the held roles it reaches the user through
+struct Match {
+    rule: vo::Rule,
+    roles: Vec<String>,
+}
+
+/// settles the rules matching one perm by the domain's conflict strategy, `deny_overrides` lets
+/// any deny win, `allow_overrides` any allow; the first rule of the winning effect is reported
+pub fn decide_rule<'a>(
+    strategy: &str,
+    rules: impl IntoIterator<Item = &'a vo::Rule>,
+) -> Option<&'a vo::Rule> {
+    let (mut allow, mut deny) = (None, None);
+    for rule in rules {
+        if rule.effect == "deny" {
+            deny = deny.or(Some(rule));
+        } else {
+            allow = allow.or(Some(rule));
+        }
+    }
+    match strategy {
+        "allow_overrides" => allow.or(deny),
+        _ => deny.or(allow),
+    }
+}
+
+impl Decide {
+    pub async fn decide(
+        &self,
+        user_id: &str,
+        ctx: &Value,
+        strategy: &str,
+    ) -> Result<Vec<vo::Decision>, DBError> {
+        let mut matches: Vec<Vec<Match>> = self.perms.iter().map(|_| vec![]).collect();
+        self.match_roles(user_id, ctx, &mut matches).await?;
+        if let Some(resource) = &self.resource {
+            self.match_resource(user_id, resource, &mut matches).await?;
+        }
+        let decisions = self
+            .perms
+            .iter()
+            .zip(matches.iter())
+            .map(|(perm, found)| {
+                let mut decision = vo::Decision::new(perm);
+                let rule = match decide_rule(strategy, found.iter().map(|v| &v.rule)) {
+                    Some(val) => val,
+                    None => return decision,
+                };
+                decision.allowed = rule.effect != "deny";
+                decision.rule = Some(rule.clone());
+                if !decision.allowed {
+                    return decision;
+                }
+                for v in found.iter().filter(|v| v.rule.effect != "deny") {
+                    for role in v.roles.iter() {
+                        if !decision.roles.contains(role) {
+                            decision.roles.push(role.clone());
+                        }
+                    }
+                    if let Some(resource) = &v.rule.resource {
+                        if !decision.resources.contains(resource) {
+                            decision.resources.push(resource.clone());
+                        }
+                    }
+                }
+                decision
+            })
+            .collect();
+        Ok(decisions)
+    }
+    /// grants of the roles the user holds, on every resource; roles not activated in the
+    /// session only lose their allows, their denies still apply
+    async fn match_roles(
+        &self,
+        user_id: &str,
+        ctx: &Value,
+        matches: &mut [Vec<Match>],
+    ) -> Result<(), DBError> {
+        let role_ids = Role::find_held_ids(user_id).await?;
+        if role_ids.is_empty() {
+            return Ok(());
+        }
+        let held: Vec<String> = Role::find_by_ids(role_ids)
+            .await?
+            .into_iter()
+            .filter(|v| v.domain_id == self.domain_id)
+            .map(|v| v.id)
+            .collect();
+        if held.is_empty() {
+            return Ok(());
+        }
+        // each effective role maps to the held roles granting it
+        let mut granted_by: HashMap<String, Vec<String>> =
+            RoleParent::find_inherited(&self.domain_id, &held).await?;
+        for role_id in held.iter() {
+            granted_by.entry(role_id.clone()).or_default().push(role_id.clone());
+        }
+        let role_ids: Vec<String> = granted_by.keys().cloned().collect();
+        let roles: HashMap<String, Role> = Role::find_by_ids(role_ids.clone())
+            .await?
+            .into_iter()
+            .map(|v| (v.id.clone(), v))
+            .collect();
+        let role_perms: Vec<RolePerm> = RolePerm::find_by_roles(&role_ids)
+            .await?
+            .into_iter()
+            .filter(|v| v.applies(ctx))
+            .collect();
+        let perm_ids: Vec<String> = role_perms.iter().map(|v| v.perm_id.clone()).collect();
+        if perm_ids.is_empty() {
+            return Ok(());
+        }
+        let perms: HashMap<String, Perm> = Perm::find_by_ids(perm_ids, Some(self.domain_id.clone()))
+            .await?
+            .into_iter()
+            .map(|v| (v.id.clone(), v))
+            .collect();
+        for role_perm in role_perms.iter() {
+            let (perm, via) = match (perms.get(&role_perm.perm_id), granted_by.get(&role_perm.role_id)) {
+                (Some(perm), Some(via)) => (perm, via),
+                _ => continue,
+            };
+            let rule = vo::Rule {
+                effect: role_perm.effect.clone(),
+                perm: perm.value.clone(),
+                role: roles.get(&role_perm.role_id).map(|v| v.value.clone()),
+                resource: None,
+                condition: role_perm.condition_expr.clone(),
+            };
+            let via: Vec<&String> = match role_perm.effect.as_str() {
+                "deny" => via.iter().collect(),
+                _ => via.iter().filter(|v| !self.inactive_role_ids.contains(v)).collect(),
+            };
+            if via.is_empty() {
+                continue;
+            }
+            let via: Vec<String> = via
+                .into_iter()
+                .filter_map(|v| roles.get(v))
+                .map(|v| v.value.clone())
+                .collect();
+            for (i, _) in self
+                .perms
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| perm_value::covers(&perm.value, v))
+            {
+                matches[i].push(Match { rule: rule.clone(), roles: via.clone() });
+            }
+        }
+        Ok(())
+    }
+    /// grants on the resource to the user or to the roles, orgs and groups reaching them
+    async fn match_resource(
+        &self,
+        user_id: &str,
+        resource: &str,
+        matches: &mut [Vec<Match>],
+    ) -> Result<(), DBError> {
+        let grants: Vec<ResourceGrant> = ResourceGrant::find_by_user(user_id, &self.domain_id)
+            .await?
+            .into_iter()
+            .filter(|v| v.covers(resource))
+            // resource grants only allow, so grants to inactive roles can be dropped whole
+            .filter(|v| v.subject_type != "role" || !self.inactive_role_ids.contains(&v.subject_id))
+            .collect();
+        if grants.is_empty() {
+            return Ok(());
+        }
+        let perm_ids: Vec<String> = grants.iter().map(|v| v.perm_id.clone()).collect();
+        let perms: HashMap<String, Perm> = Perm::find_by_ids(perm_ids, Some(self.domain_id.clone()))
+            .await?
+            .into_iter()
+            .map(|v| (v.id.clone(), v))
+            .collect();
+        for grant in grants.iter() {
+            let perm = match perms.get(&grant.perm_id) {
+                Some(val) => val,
+                None => continue,
+            };
+            let rule = vo::Rule {
+                effect: "allow".to_string(),
+                perm: perm.value.clone(),
+                role: None,
+                resource: Some(grant.resource.clone()),
+                condition: None,
+            };
+            for (i, _) in self
+                .perms
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| perm_value::covers(&perm.value, v))
+            {
+                matches[i].push(Match { rule: rule.clone(), roles: vec![] });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(effect: &str, perm: &str) -> vo::Rule {
+        vo::Rule {
+            effect: effect.to_string(),
+            perm: perm.to_string(),
+            role: None,
+            resource: None,
+            condition: None,
+        }
+    }
+
+    #[test]
+    fn deny_overrides_lets_any_deny_win() {
+        let rules = vec![rule("allow", "order:read"), rule("deny", "order:*"), rule("deny", "*:read")];
+        let found = decide_rule("deny_overrides", rules.iter()).unwrap();
+        assert_eq!((found.effect.as_str(), found.perm.as_str()), ("deny", "order:*"));
+        let allows = vec![rule("allow", "order:read")];
+        assert_eq!(decide_rule("deny_overrides", allows.iter()).unwrap().effect, "allow");
+    }
+
+    #[test]
+    fn allow_overrides_lets_any_allow_win() {
+        let rules = vec![rule("deny", "order:*"), rule("allow", "order:read"), rule("allow", "*:*")];
+        let found = decide_rule("allow_overrides", rules.iter()).unwrap();
+        assert_eq!((found.effect.as_str(), found.perm.as_str()), ("allow", "order:read"));
+        let denies = vec![rule("deny", "order:*")];
+        assert_eq!(decide_rule("allow_overrides", denies.iter()).unwrap().effect, "deny");
+    }
+
+    #[test]
+    fn unknown_strategies_deny_and_no_rules_decide_nothing() {
+        let rules = vec![rule("allow", "order:read"), rule("deny", "order:read")];
+        assert_eq!(decide_rule("", rules.iter()).unwrap().effect, "deny");
+        let none: Vec<vo::Rule> = vec![];
+        assert!(decide_rule("deny_overrides", none.iter()).is_none());
+    }
 }
@@ -0,0 +1,218 @@
+use crate::{
+    repository::{
+        dao::{RelationSchema, RelationTuple},
+        DBError, POOL,
+    },
+    util::{
+        audit::Actor,
+        now,
+        relation::{self, Object, Schema, Snapshot, Subject, Tuple},
+    },
+};
+use super::NewAuditLog;
+use rbatis::crud::CRUDMut;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use validator::Validate;
+
+/// the domain's tuples at its latest revision, or a cached snapshot at least at `min_revision`
+pub async fn relation_snapshot(
+    domain_id: &str,
+    min_revision: Option<i64>,
+) -> Result<Arc<Snapshot>, DBError> {
+    if let Some(cached) = relation::cached(domain_id, min_revision) {
+        return Ok(cached);
+    }
+    // the revision is read before the tuples, so they are at least as new
+    let found = RelationSchema::find_by_domain(domain_id).await?;
+    let (revision, schema) = match found {
+        Some(found) => {
+            let definition: Value = serde_json::from_str(&found.definition).unwrap_or_default();
+            let schema = Schema::parse(&definition).unwrap_or_else(|e| {
+                tracing::warn!(domain_id, "invalid relation schema: {}", e);
+                Schema::default()
+            });
+            (found.revision, schema)
+        }
+        None => (0, Schema::default()),
+    };
+    let tuples: Vec<Tuple> = RelationTuple::find_by_domain(domain_id)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    Ok(relation::cache(Snapshot::new(domain_id, revision, schema, tuples)))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateRelationSchema {
+    pub definition: Value,
+}
+
+impl UpdateRelationSchema {
+    /// `None` when another write bumped the revision meanwhile
+    pub async fn save(
+        self,
+        domain_id: &str,
+        found: Option<&RelationSchema>,
+        actor: &Actor,
+    ) -> Result<Option<RelationSchema>, DBError> {
+        let dao = RelationSchema {
+            domain_id: domain_id.to_string(),
+            definition: self.definition.to_string(),
+            revision: found.map_or(0, |v| v.revision) + 1,
+            updated_at: now(),
+            updated_by: Some(actor.id.clone()),
+        };
+        let mut log = NewAuditLog::new("relation.update_schema", domain_id, Some(domain_id));
+        if let Some(found) = found {
+            log = log.before(found);
+        }
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        match found {
+            Some(found) => {
+                let w = POOL
+                    .new_wrapper()
+                    .eq("domain_id", domain_id)
+                    .and()
+                    .eq("revision", found.revision);
+                if tx.update_by_wrapper(&dao, w, &[]).await? == 0 {
+                    tx.rollback().await.unwrap();
+                    return Ok(None);
+                }
+            }
+            None => {
+                tx.save(&dao, &[]).await?;
+            }
+        }
+        tx.save(&log.after(&dao).by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        relation::evict(domain_id);
+        Ok(Some(dao))
+    }
+}
+
+/// `object` like `document:d1`, `subject` like `user:u1` or `group:eng#member`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RelationBody {
+    pub object: String,
+    pub relation: String,
+    pub subject: String,
+}
+
+impl RelationBody {
+    pub fn tuple(&self) -> Result<Tuple, String> {
+        Ok(Tuple {
+            object: Object::parse(&self.object)?,
+            relation: self.relation.clone(),
+            subject: Subject::parse(&self.subject)?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct WriteRelations {
+    pub domain_id: String,
+    #[serde(default)]
+    #[validate(length(max = 100))]
+    pub writes: Vec<RelationBody>,
+    #[serde(default)]
+    #[validate(length(max = 100))]
+    pub deletes: Vec<RelationBody>,
+}
+
+impl WriteRelations {
+    /// the new revision, `None` when another write bumped the revision meanwhile
+    pub async fn save(
+        &self,
+        found: &RelationSchema,
+        writes: &[Tuple],
+        deletes: &[Tuple],
+        actor: &Actor,
+    ) -> Result<Option<i64>, DBError> {
+        let mut dao = found.to_owned();
+        dao.revision += 1;
+        dao.updated_at = now();
+        dao.updated_by = Some(actor.id.clone());
+        let rows: Vec<RelationTuple> = writes
+            .iter()
+            .map(|v| RelationTuple::new(&found.domain_id, v, &actor.id))
+            .collect();
+        let log = NewAuditLog::new("relation.write", &found.domain_id, Some(&found.domain_id))
+            .after(&json!({
+                "writes": writes.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+                "deletes": deletes.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+                "revision": dao.revision
+            }));
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let w = POOL
+            .new_wrapper()
+            .eq("domain_id", &found.domain_id)
+            .and()
+            .eq("revision", found.revision);
+        if tx.update_by_wrapper(&dao, w, &[]).await? == 0 {
+            tx.rollback().await.unwrap();
+            return Ok(None);
+        }
+        // writing a tuple that exists keeps a single row
+        for tuple in deletes.iter().chain(writes.iter()) {
+            let w = RelationTuple::wrapper_of(&found.domain_id, tuple);
+            tx.remove_by_wrapper::<RelationTuple>(w).await?;
+        }
+        if !rows.is_empty() {
+            tx.save_batch(&rows, &[]).await?;
+        }
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        relation::evict(&found.domain_id);
+        Ok(Some(dao.revision))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryRelation {
+    pub domain_id: String,
+    pub object: Option<String>,
+    pub relation: Option<String>,
+    pub subject: Option<String>,
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CheckRelation {
+    pub domain_id: String,
+    pub object: String,
+    pub relation: String,
+    pub subject: String,
+    /// answer at least as fresh as the write that returned it
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExpandRelation {
+    pub domain_id: String,
+    pub object: String,
+    pub relation: String,
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ListObjects {
+    pub domain_id: String,
+    #[validate(length(min = 1, max = 64))]
+    pub object_type: String,
+    pub relation: String,
+    pub subject: String,
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListSubjects {
+    pub domain_id: String,
+    pub object: String,
+    pub relation: String,
+    /// only subjects of this type, usersets are left out then
+    pub subject_type: Option<String>,
+    pub token: Option<String>,
+}
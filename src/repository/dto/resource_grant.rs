@@ -0,0 +1,174 @@
+use crate::{
+    repository::{
+        dao::{resource_grant::SUBJECT_TYPES, Perm, ResourceGrant},
+        DBError, Dao, POOL,
+    },
+    util::{audit::Actor, default_expire, now, uuid_v4},
+};
+use super::NewAuditLog;
+use chrono::NaiveDateTime;
+use rbatis::{
+    crud::{CRUD, CRUDMut},
+    plugin::page::{Page, PageRequest},
+};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+fn validate_subject_type(v: &str) -> Result<(), ValidationError> {
+    if !SUBJECT_TYPES.contains(&v) {
+        let mut e = ValidationError::new("subject_type");
+        e.message = Some("授权对象类型不合法".into());
+        return Err(e);
+    }
+    Ok(())
+}
+
+fn validate_resources(v: &[String]) -> Result<(), ValidationError> {
+    if v.iter().any(|r| r.is_empty() || r.len() > 255) {
+        let mut e = ValidationError::new("resources");
+        e.message = Some("资源长度需在 1 到 255 之间".into());
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct ResourceGrantPerm {
+    pub perm_id: String,
+    #[validate(length(min = 1, max = 100), custom = "validate_resources")]
+    pub resources: Vec<String>,
+    #[validate(custom = "validate_subject_type")]
+    pub subject_type: String,
+    pub subject_id: String,
+    pub expire: Option<NaiveDateTime>,
+}
+
+impl ResourceGrantPerm {
+    /// re-granting a resource to the same subject replaces the grant
+    pub async fn save(self, perm: &Perm, actor: &Actor) -> Result<Vec<ResourceGrant>, DBError> {
+        let grants: Vec<ResourceGrant> = self
+            .resources
+            .iter()
+            .map(|resource| ResourceGrant {
+                id: uuid_v4(),
+                perm_id: self.perm_id.clone(),
+                resource: resource.clone(),
+                subject_type: self.subject_type.clone(),
+                subject_id: self.subject_id.clone(),
+                domain_id: perm.domain_id.clone(),
+                expire: self.expire.unwrap_or_else(default_expire),
+                created_at: now(),
+                created_by: Some(actor.id.clone()),
+            })
+            .collect();
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let w = POOL
+            .new_wrapper()
+            .eq("perm_id", &self.perm_id)
+            .and()
+            .eq("subject_type", &self.subject_type)
+            .and()
+            .eq("subject_id", &self.subject_id)
+            .and()
+            .r#in("resource", &self.resources);
+        tx.remove_by_wrapper::<ResourceGrant>(w).await?;
+        tx.save_batch(&grants, &[]).await?;
+        let logs: Vec<_> = grants
+            .iter()
+            .map(|v| {
+                NewAuditLog::new("perm.grant_resource", &v.perm_id, Some(&v.domain_id))
+                    .after(v)
+                    .by(actor)
+            })
+            .collect();
+        tx.save_batch(&logs, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(grants)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct ResourceRevokePerm {
+    #[validate(length(min = 1))]
+    pub ids: Vec<String>,
+}
+
+impl ResourceRevokePerm {
+    pub async fn save(self, found: &[ResourceGrant], actor: &Actor) -> Result<u64, DBError> {
+        let logs: Vec<_> = found
+            .iter()
+            .map(|v| {
+                NewAuditLog::new("perm.revoke_resource", &v.perm_id, Some(&v.domain_id))
+                    .before(v)
+                    .by(actor)
+            })
+            .collect();
+        let w = POOL.new_wrapper().r#in("id", &self.ids);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let revoked = tx.remove_by_wrapper::<ResourceGrant>(w).await?;
+        tx.save_batch(&logs, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(revoked)
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct QueryResourceGrant {
+    pub domain_id: String,
+    perm_id: Option<String>,
+    subject_type: Option<String>,
+    subject_id: Option<String>,
+    /// grants whose pattern covers this resource
+    resource: Option<String>,
+    pub include_expired: Option<bool>,
+    #[validate(range(min = 1))]
+    page: Option<u64>,
+    #[validate(range(min = 1))]
+    limit: Option<u64>,
+}
+
+impl QueryResourceGrant {
+    pub async fn find_all(self) -> Result<Page<ResourceGrant>, DBError> {
+        let page = self.page.unwrap_or(1);
+        let limit = self.limit.unwrap_or(10);
+        let req = PageRequest::new(page, limit);
+        let mut w = POOL.new_wrapper().eq("domain_id", &self.domain_id);
+        if let Some(perm_id) = &self.perm_id {
+            w = w.and().eq("perm_id", perm_id);
+        }
+        if let Some(subject_type) = &self.subject_type {
+            w = w.and().eq("subject_type", subject_type);
+        }
+        if let Some(subject_id) = &self.subject_id {
+            w = w.and().eq("subject_id", subject_id);
+        }
+        if !self.include_expired.unwrap_or(false) {
+            w = w.and().gt("expire", now());
+        }
+        w = w.order_by(false, &["created_at"]);
+        let resource = match self.resource {
+            Some(val) => val,
+            None => return POOL.fetch_page_by_wrapper::<ResourceGrant>(w, &req).await,
+        };
+        // patterns can't be matched in SQL, page over the covering grants instead
+        let covering: Vec<ResourceGrant> = ResourceGrant::find_list(w)
+            .await?
+            .into_iter()
+            .filter(|v| v.covers(&resource))
+            .collect();
+        let total = covering.len() as u64;
+        let records = covering
+            .into_iter()
+            .skip(((page - 1) * limit) as usize)
+            .take(limit as usize)
+            .collect();
+        Ok(Page::<ResourceGrant> {
+            records,
+            total,
+            pages: (total + limit - 1) / limit,
+            page_no: page,
+            page_size: limit,
+            search_count: true,
+        })
+    }
+}
@@ -1,12 +1,16 @@
 use crate::{
     repository::{
-        dao::{role::IntoVecOfVo, Role},
+        dao::{
+            role::IntoVecOfVo, ResourceGrant, Role, RoleExclusionMember, RoleParent,
+            RolePrerequisite, SessionRole,
+        },
         vo, DBError, Dao, POOL,
     },
-    util::{now, uuid_v4},
+    util::{audit::Actor, now, uuid_v4},
 };
+use super::NewAuditLog;
 use rbatis::{
-    crud::CRUD,
+    crud::{CRUD, CRUDMut},
     plugin::page::{Page, PageRequest},
 };
 use serde::{Deserialize, Serialize};
@@ -21,13 +25,16 @@ pub struct NewRole {
     pub value: String,
     #[validate(range(min = 2, max = 99))]
     pub level: i32,
+    /// most users holding the role directly or through orgs and groups
+    #[validate(range(min = 1))]
+    pub max_holders: Option<i32>,
     pub domain_id: String,
     #[serde(skip_deserializing)]
     pub created_by: Option<String>,
 }
 
 impl NewRole {
-    pub async fn create(self) -> Result<Role, DBError> {
+    pub async fn create(self, actor: &Actor) -> Result<Role, DBError> {
         let id = uuid_v4();
         let dao = Role {
             id,
@@ -35,6 +42,7 @@ impl NewRole {
             description: self.description,
             value: self.value,
             level: self.level,
+            max_holders: self.max_holders,
             domain_id: self.domain_id,
             is_deleted: 0,
             created_by: self.created_by.clone(),
@@ -42,7 +50,11 @@ impl NewRole {
             created_at: now(),
             updated_at: now(),
         };
-        Role::create_one(&dao).await?;
+        let log = NewAuditLog::new("role.create", &dao.id, Some(&dao.domain_id)).after(&dao);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.save(&dao, &[]).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
         Ok(dao)
     }
 }
@@ -56,14 +68,18 @@ pub struct UpdateRole {
     pub value: Option<String>,
     #[validate(range(min = 1, max = 999))]
     pub level: Option<i32>,
+    /// `0` lifts the limit
+    #[validate(range(min = 0))]
+    pub max_holders: Option<i32>,
     #[serde(skip_deserializing)]
     pub updated_by: Option<String>,
 }
 
 impl UpdateRole {
-    pub async fn save(self, id: &str) -> Result<Role, DBError> {
+    pub async fn save(self, id: &str, actor: &Actor) -> Result<Role, DBError> {
         let w = POOL.new_wrapper().eq("id", id);
         let mut dao = Role::find_one(w.clone()).await?;
+        let log = NewAuditLog::new("role.update", id, Some(&dao.domain_id)).before(&dao);
         if let Some(name) = self.name {
             dao.name = name;
         }
@@ -73,13 +89,57 @@ impl UpdateRole {
         if let Some(level) = self.level {
             dao.level = level;
         }
+        if let Some(max_holders) = self.max_holders {
+            dao.max_holders = Some(max_holders).filter(|v| *v > 0);
+        }
         dao.description = self.description;
         dao.updated_by = self.updated_by;
-        Role::update_one(&dao, w).await?;
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.update_by_wrapper(&dao, w, &[]).await?;
+        tx.save(&log.after(&dao).by(actor), &[]).await?;
+        tx.commit().await.unwrap();
         Ok(dao)
     }
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteRole {
+    pub id: String,
+}
+
+impl DeleteRole {
+    pub async fn save(&self, found: &Role, actor: &Actor) -> Result<u64, DBError> {
+        let log = NewAuditLog::new("role.delete", &self.id, Some(&found.domain_id)).before(found);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let w = POOL
+            .new_wrapper()
+            .eq("role_id", &self.id)
+            .or()
+            .eq("parent_id", &self.id);
+        tx.remove_by_wrapper::<RoleParent>(w).await?;
+        let w = POOL
+            .new_wrapper()
+            .eq("role_id", &self.id)
+            .or()
+            .eq("prerequisite_id", &self.id);
+        tx.remove_by_wrapper::<RolePrerequisite>(w).await?;
+        let w = POOL
+            .new_wrapper()
+            .eq("subject_type", "role")
+            .and()
+            .eq("subject_id", &self.id);
+        tx.remove_by_wrapper::<ResourceGrant>(w).await?;
+        let w = POOL.new_wrapper().eq("role_id", &self.id);
+        tx.remove_by_wrapper::<RoleExclusionMember>(w.clone()).await?;
+        tx.remove_by_wrapper::<SessionRole>(w).await?;
+        let w = POOL.new_wrapper().eq("id", &self.id);
+        let removed = tx.remove_by_wrapper::<Role>(w).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(removed)
+    }
+}
+
 #[derive(Debug, Deserialize, Validate)]
 pub struct QueryRole {
     domain_id: Option<String>,
@@ -101,11 +161,11 @@ impl QueryRole {
         let sort_order = self.sort_order.unwrap_or("DESC".to_string());
         let mut w = POOL.new_wrapper();
         if let Some(domain_id) = self.domain_id {
-            let domain_ids:Vec<&str> = domain_id.split(",").into_iter().collect();
+            let domain_ids:Vec<&str> = domain_id.split(",").collect();
             w = w.r#in("domain_id", &domain_ids);
         }
         if let Some(key) = self.key {
-            if key != "" {
+            if !key.is_empty() {
                 w = w.and().like("name", key);
             }
         }
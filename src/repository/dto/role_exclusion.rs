@@ -0,0 +1,417 @@
+use crate::{
+    repository::{
+        dao::{
+            role::Holdings,
+            role_exclusion::{conflicting, with_inherited, KINDS},
+            GroupRole, Org, OrgRole, Role, RoleExclusion, RoleExclusionMember, RoleParent,
+            SessionRole, UserGroup, UserOrg, UserRole,
+        },
+        vo, ConstraintError, DBError, Dao, POOL,
+    },
+    util::{audit::Actor, jwt::Payload, now, uuid_v4},
+};
+use super::NewAuditLog;
+use chrono::Local;
+use rbatis::{crud::CRUDMut, executor::RBatisTxExecutor};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use validator::{Validate, ValidationError};
+
+fn validate_kind(v: &str) -> Result<(), ValidationError> {
+    if !KINDS.contains(&v) {
+        let mut e = ValidationError::new("kind");
+        e.message = Some("互斥类型应为 static 或 dynamic".into());
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct NewRoleExclusion {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    /// `static` by default, or `dynamic`
+    #[validate(custom = "validate_kind")]
+    pub kind: Option<String>,
+    pub domain_id: String,
+    #[validate(length(min = 2, max = 50))]
+    pub role_ids: Vec<String>,
+}
+
+impl NewRoleExclusion {
+    pub async fn create(self, actor: &Actor) -> Result<vo::RoleExclusion, DBError> {
+        let dao = RoleExclusion {
+            id: uuid_v4(),
+            name: self.name,
+            kind: self.kind.unwrap_or_else(|| KINDS[0].to_string()),
+            domain_id: self.domain_id,
+            created_at: now(),
+            created_by: Some(actor.id.clone()),
+        };
+        let members: Vec<RoleExclusionMember> = self
+            .role_ids
+            .iter()
+            .map(|role_id| RoleExclusionMember {
+                exclusion_id: dao.id.clone(),
+                role_id: role_id.clone(),
+            })
+            .collect();
+        let created: vo::RoleExclusion = (dao.clone(), self.role_ids).into();
+        let log = NewAuditLog::new("role_exclusion.create", &dao.id, Some(&dao.domain_id))
+            .after(&created);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.save(&dao, &[]).await?;
+        tx.save_batch(&members, &[]).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(created)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteRoleExclusion {
+    pub id: String,
+}
+
+impl DeleteRoleExclusion {
+    pub async fn save(&self, found: &vo::RoleExclusion, actor: &Actor) -> Result<u64, DBError> {
+        let log = NewAuditLog::new("role_exclusion.delete", &self.id, Some(&found.domain_id))
+            .before(found);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let w = POOL.new_wrapper().eq("exclusion_id", &self.id);
+        tx.remove_by_wrapper::<RoleExclusionMember>(w).await?;
+        let w = POOL.new_wrapper().eq("id", &self.id);
+        let removed = tx.remove_by_wrapper::<RoleExclusion>(w).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(removed)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryRoleExclusion {
+    pub domain_id: String,
+    pub kind: Option<String>,
+}
+
+impl QueryRoleExclusion {
+    pub async fn find_all(&self) -> Result<Vec<vo::RoleExclusion>, DBError> {
+        let found = RoleExclusion::find_by_domain(&self.domain_id, self.kind.as_deref()).await?;
+        Ok(found.into_iter().map(Into::into).collect())
+    }
+}
+
+/// the first static exclusion of the domain a user breaks in `after`, `before` with a change
+/// applied; an exclusion the user already breaks only counts when the change adds to it.
+/// The holdings are read in `tx`, the transaction writing the change
+pub async fn find_exclusion_violation(
+    tx: &mut RBatisTxExecutor<'_>,
+    domain_id: &str,
+    before: &Holdings,
+    after: &Holdings,
+) -> Result<Option<vo::ExclusionViolation>, DBError> {
+    let w = POOL
+        .new_wrapper()
+        .eq("domain_id", domain_id)
+        .and()
+        .eq("kind", "static")
+        .order_by(true, &["created_at"]);
+    let exclusions: Vec<RoleExclusion> = tx.fetch_list_by_wrapper(w).await?;
+    if exclusions.is_empty() {
+        return Ok(None);
+    }
+    let ids: Vec<String> = exclusions.iter().map(|v| v.id.clone()).collect();
+    let w = POOL.new_wrapper().r#in("exclusion_id", &ids);
+    let members: Vec<RoleExclusionMember> = tx.fetch_list_by_wrapper(w).await?;
+    Ok(first_violation(&exclusions, &members, before, after))
+}
+
+/// `find_exclusion_violation` over exclusions already read, in their order
+fn first_violation(
+    exclusions: &[RoleExclusion],
+    members: &[RoleExclusionMember],
+    before: &Holdings,
+    after: &Holdings,
+) -> Option<vo::ExclusionViolation> {
+    let prev_by_user = before.roles_by_user();
+    let mut held_by_user: Vec<_> = after.roles_by_user().into_iter().collect();
+    held_by_user.sort();
+    for (user_id, held_ids) in held_by_user {
+        let prev_ids = prev_by_user.get(&user_id).cloned().unwrap_or_default();
+        let held = with_inherited(&after.edges, &held_ids);
+        let prev = with_inherited(&before.edges, &prev_ids);
+        for exclusion in exclusions.iter() {
+            let role_ids: Vec<String> = members
+                .iter()
+                .filter(|v| v.exclusion_id == exclusion.id)
+                .map(|v| v.role_id.clone())
+                .collect();
+            let conflict = conflicting(&role_ids, &held);
+            if conflict.len() > 1 && conflict.len() > conflicting(&role_ids, &prev).len() {
+                return Some(vo::ExclusionViolation {
+                    exclusion_id: exclusion.id.clone(),
+                    name: exclusion.name.clone(),
+                    user_id,
+                    role_ids: conflict,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// `find_exclusion_violation` described for a rejected request
+pub async fn check_exclusions(
+    tx: &mut RBatisTxExecutor<'_>,
+    domain_id: &str,
+    before: &Holdings,
+    after: &Holdings,
+) -> Result<(), ConstraintError> {
+    match find_exclusion_violation(tx, domain_id, before, after).await? {
+        Some(v) => Err(ConstraintError::Violated(format!(
+            "用户 {} 将同时持有互斥角色 {}, 违反约束 {}",
+            v.user_id,
+            v.role_ids.join(", "),
+            v.name
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// users holding more than one role of a static exclusion of the domain, directly, through
+/// orgs and groups or by inheritance
+pub async fn find_exclusion_violations(
+    domain_id: &str,
+) -> Result<Vec<vo::ExclusionViolation>, DBError> {
+    let exclusions = RoleExclusion::find_by_domain(domain_id, Some("static")).await?;
+    if exclusions.is_empty() {
+        return Ok(vec![]);
+    }
+    let role_ids: Vec<String> = Role::find_all(vec![domain_id.to_string()])
+        .await?
+        .into_iter()
+        .map(|v| v.id)
+        .collect();
+    if role_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut held: HashMap<String, Vec<String>> = HashMap::new();
+    let w = POOL
+        .new_wrapper()
+        .r#in("role_id", &role_ids)
+        .and()
+        .gt("expire", now());
+    for v in UserRole::find_list(w.clone()).await? {
+        held.entry(v.user_id).or_default().push(v.role_id);
+    }
+    // org grants reach the members of the org and of the orgs below
+    let org_roles = OrgRole::find_list(w.clone()).await?;
+    if !org_roles.is_empty() {
+        let orgs = Org::find_all(vec![domain_id.to_string()]).await?;
+        let org_ids: Vec<String> = orgs.iter().map(|v| v.id.clone()).collect();
+        let mut members: HashMap<String, Vec<String>> = HashMap::new();
+        for v in UserOrg::find_by_orgs(&org_ids, false).await? {
+            members.entry(v.org_id).or_default().push(v.user_id);
+        }
+        for org_role in org_roles.iter() {
+            let org = match orgs.iter().find(|v| v.id == org_role.org_id) {
+                Some(val) => val,
+                None => continue,
+            };
+            for sub in org.subtree_of(&orgs) {
+                for user_id in members.get(&sub.id).into_iter().flatten() {
+                    held.entry(user_id.clone()).or_default().push(org_role.role_id.clone());
+                }
+            }
+        }
+    }
+    let group_roles = GroupRole::find_list(w).await?;
+    if !group_roles.is_empty() {
+        let group_ids: Vec<String> = group_roles.iter().map(|v| v.group_id.clone()).collect();
+        let w = POOL
+            .new_wrapper()
+            .r#in("group_id", &group_ids)
+            .and()
+            .gt("expire", now());
+        for member in UserGroup::find_list(w).await? {
+            for v in group_roles.iter().filter(|v| v.group_id == member.group_id) {
+                held.entry(member.user_id.clone()).or_default().push(v.role_id.clone());
+            }
+        }
+    }
+    let edges = RoleParent::find_by_domain(domain_id).await?;
+    let mut user_ids: Vec<&String> = held.keys().collect();
+    user_ids.sort();
+    let mut violations = vec![];
+    for user_id in user_ids {
+        let effective = with_inherited(&edges, &held[user_id]);
+        for (exclusion, role_ids) in exclusions.iter() {
+            let conflict = conflicting(role_ids, &effective);
+            if conflict.len() > 1 {
+                violations.push(vo::ExclusionViolation {
+                    exclusion_id: exclusion.id.clone(),
+                    name: exclusion.name.clone(),
+                    user_id: user_id.clone(),
+                    role_ids: conflict,
+                });
+            }
+        }
+    }
+    Ok(violations)
+}
+
+/// the user's roles in the domain that count only while active in the session: the roles in
+/// or inheriting a role of a dynamic exclusion, not activated for `jti`
+pub async fn find_inactive_role_ids(
+    user_id: &str,
+    domain_id: &str,
+    jti: Option<&str>,
+) -> Result<Vec<String>, DBError> {
+    let exclusions = RoleExclusion::find_by_domain(domain_id, Some("dynamic")).await?;
+    if exclusions.is_empty() {
+        return Ok(vec![]);
+    }
+    let constrained: Vec<String> = exclusions.into_iter().flat_map(|(_, v)| v).collect();
+    let edges = RoleParent::find_by_domain(domain_id).await?;
+    let active: Vec<String> = match jti {
+        Some(jti) => SessionRole::find_by_jti(jti)
+            .await?
+            .into_iter()
+            .filter(|v| v.user_id == user_id)
+            .map(|v| v.role_id)
+            .collect(),
+        None => vec![],
+    };
+    Ok(Role::find_by_user(user_id, domain_id)
+        .await?
+        .into_iter()
+        .map(|v| v.id)
+        .filter(|v| !active.contains(v))
+        .filter(|v| {
+            with_inherited(&edges, &[v.clone()])
+                .iter()
+                .any(|r| constrained.contains(r))
+        })
+        .collect())
+}
+
+/// the dynamic exclusion activating `role_ids` together would break
+pub async fn find_session_violation(
+    domain_id: &str,
+    role_ids: &[String],
+) -> Result<Option<(String, Vec<String>)>, DBError> {
+    let exclusions = RoleExclusion::find_by_domain(domain_id, Some("dynamic")).await?;
+    if exclusions.is_empty() {
+        return Ok(None);
+    }
+    let edges = RoleParent::find_by_domain(domain_id).await?;
+    let effective = with_inherited(&edges, role_ids);
+    Ok(exclusions.into_iter().find_map(|(exclusion, set)| {
+        let conflict = conflicting(&set, &effective);
+        if conflict.len() > 1 {
+            Some((exclusion.name, conflict))
+        } else {
+            None
+        }
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct ActivateRoles {
+    #[validate(length(max = 50))]
+    pub role_ids: Vec<String>,
+}
+
+impl ActivateRoles {
+    /// replaces the roles active in the session of the token
+    pub async fn save(self, payload: &Payload, actor: &Actor) -> Result<Vec<SessionRole>, DBError> {
+        let rows: Vec<SessionRole> = self
+            .role_ids
+            .iter()
+            .map(|role_id| SessionRole {
+                jti: payload.jti.clone(),
+                user_id: payload.auth.id.clone(),
+                role_id: role_id.clone(),
+                expire: payload.exp.with_timezone(&Local).naive_local(),
+                created_at: now(),
+            })
+            .collect();
+        let log = NewAuditLog::new("session.activate_roles", &payload.auth.id, None)
+            .after(&json!({ "jti": &payload.jti, "role_ids": &self.role_ids }));
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let w = POOL.new_wrapper().eq("jti", &payload.jti);
+        tx.remove_by_wrapper::<SessionRole>(w).await?;
+        if !rows.is_empty() {
+            tx.save_batch(&rows, &[]).await?;
+        }
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exclusion(id: &str, role_ids: &[&str]) -> (RoleExclusion, Vec<RoleExclusionMember>) {
+        let exclusion = RoleExclusion {
+            id: id.to_string(),
+            name: id.to_string(),
+            kind: "static".to_string(),
+            domain_id: "d1".to_string(),
+            created_at: now(),
+            created_by: None,
+        };
+        let members = role_ids
+            .iter()
+            .map(|v| RoleExclusionMember {
+                exclusion_id: id.to_string(),
+                role_id: v.to_string(),
+            })
+            .collect();
+        (exclusion, members)
+    }
+
+    fn violation(
+        role_ids: &[&str],
+        before: &Holdings,
+        after: &Holdings,
+    ) -> Option<vo::ExclusionViolation> {
+        let (exclusion, members) = exclusion("e1", role_ids);
+        first_violation(&[exclusion], &members, before, after)
+    }
+
+    #[test]
+    fn counts_inherited_roles() {
+        let before = Holdings::default().inherit("lead", "approver").grant("u1", "payer");
+        let after = before.clone().grant("u1", "lead");
+        let found = violation(&["payer", "approver"], &before, &after).unwrap();
+        assert_eq!(found.user_id, "u1");
+        assert_eq!(found.role_ids, vec!["payer", "approver"]);
+    }
+
+    #[test]
+    fn counts_grants_to_orgs_above() {
+        let before = Holdings::default()
+            .org("root", None)
+            .org("team", Some("root"))
+            .grant_org("root", "approver")
+            .grant("u1", "payer");
+        let after = before.clone().join_org("u1", "team");
+        assert!(violation(&["payer", "approver"], &before, &after).is_some());
+        let other = before.clone().join_org("u2", "team");
+        assert!(violation(&["payer", "approver"], &before, &other).is_none());
+    }
+
+    #[test]
+    fn counts_old_violations_only_when_they_grow() {
+        let before = Holdings::default().grant("u1", "payer").grant("u1", "approver");
+        let unrelated = before.clone().grant("u1", "viewer");
+        assert!(violation(&["payer", "approver", "auditor"], &before, &unrelated).is_none());
+        let grown = before.clone().grant_group("g1", "auditor").join_group("u1", "g1");
+        let found = violation(&["payer", "approver", "auditor"], &before, &grown).unwrap();
+        assert_eq!(found.role_ids.len(), 3);
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{
+    repository::{
+        dao::{
+            role::{Holdings, Scope},
+            role_parent, Role, RoleParent,
+        },
+        ConstraintError, POOL,
+    },
+    util::{audit::Actor, now},
+};
+use super::{role_exclusion::check_exclusions, NewAuditLog};
+use rbatis::crud::CRUDMut;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct RoleChangeParent {
+    #[validate(length(max = 50))]
+    pub parent_ids: Vec<String>,
+}
+
+impl RoleChangeParent {
+    /// rejects parents that would make the role inherit from itself and parents that make a
+    /// holder break a static exclusion by inheriting them, nothing is written then
+    pub async fn save(
+        self,
+        role: &Role,
+        actor: &Actor,
+    ) -> Result<Vec<RoleParent>, ConstraintError> {
+        let prev = RoleParent::find_by_role(&role.id).await?;
+        let rows: Vec<RoleParent> = self
+            .parent_ids
+            .iter()
+            .map(|parent_id| RoleParent {
+                role_id: role.id.clone(),
+                parent_id: parent_id.clone(),
+                domain_id: role.domain_id.clone(),
+                created_at: now(),
+            })
+            .collect();
+        let log = NewAuditLog::new("role.change_parent", &role.id, Some(&role.domain_id))
+            .before(&prev)
+            .after(&rows);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let mut before = Holdings::lock(&mut tx, &role.domain_id).await?;
+        // the role may not end up among the ancestors of its own parents, checked once the
+        // domain is locked so that concurrent changes can't make a cycle together
+        let edges: Vec<RoleParent> = before
+            .edges
+            .iter()
+            .filter(|v| v.role_id != role.id)
+            .cloned()
+            .collect();
+        for parent_id in self.parent_ids.iter() {
+            let ancestors = role_parent::ancestors(&edges, parent_id);
+            if *parent_id == role.id || ancestors.contains(&role.id) {
+                tx.rollback().await.unwrap();
+                let e = format!("角色 {} 会形成循环继承", parent_id);
+                return Err(ConstraintError::Violated(e));
+            }
+        }
+        // holders of the role and of the roles below it inherit the new parents
+        let mut role_ids = vec![role.id.clone()];
+        let mut i = 0;
+        while i < role_ids.len() {
+            let children: Vec<String> = before
+                .edges
+                .iter()
+                .filter(|v| v.parent_id == role_ids[i])
+                .map(|v| v.role_id.clone())
+                .collect();
+            for child in children {
+                if !role_ids.contains(&child) {
+                    role_ids.push(child);
+                }
+            }
+            i += 1;
+        }
+        let scope = Scope {
+            role_ids,
+            ..Default::default()
+        };
+        before.load_members(&mut tx, &scope).await?;
+        let mut after = before.clone();
+        after.edges.retain(|v| v.role_id != role.id);
+        after.edges.extend(rows.iter().cloned());
+        if let Err(e) = check_exclusions(&mut tx, &role.domain_id, &before, &after).await {
+            tx.rollback().await.unwrap();
+            return Err(e);
+        }
+        let w = POOL.new_wrapper().eq("role_id", &role.id);
+        tx.remove_by_wrapper::<RoleParent>(w).await?;
+        if !rows.is_empty() {
+            tx.save_batch(&rows, &[]).await?;
+        }
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(rows)
+    }
+}
@@ -1,29 +1,56 @@
 use crate::{
-    repository::{dao::RolePerm, DBError, Dao, POOL},
-    util::now,
+    repository::{
+        dao::{role_perm::EFFECTS, Role, RolePerm},
+        DBError, Dao, POOL,
+    },
+    util::{audit::Actor, condition::validate_condition, now},
 };
-use rbatis::crud::{CRUD, CRUDMut};
+use super::NewAuditLog;
+use rbatis::crud::CRUDMut;
 use serde::{Deserialize, Serialize};
-use validator::Validate;
+use std::collections::HashMap;
+use validator::{Validate, ValidationError};
 
 #[derive(Debug, Deserialize, Serialize, Validate)]
 pub struct RoleGrantPerm {
     pub role_id: String,
     pub perm_ids: Vec<String>,
+    /// applies to every granted perm
+    #[validate(custom = "validate_condition")]
+    pub condition: Option<String>,
+    /// `allow` by default, or `deny`
+    #[validate(custom = "validate_effect")]
+    pub effect: Option<String>,
+}
+
+fn validate_effect(v: &str) -> Result<(), ValidationError> {
+    if !EFFECTS.contains(&v) {
+        let mut e = ValidationError::new("effect");
+        e.message = Some("授权效果应为 allow 或 deny".into());
+        return Err(e);
+    }
+    Ok(())
 }
 
 impl RoleGrantPerm {
-    pub async fn save(self) -> Result<Vec<RolePerm>, DBError> {
+    pub async fn save(self, role: &Role, actor: &Actor) -> Result<Vec<RolePerm>, DBError> {
         let role_perms: Vec<RolePerm> = self
             .perm_ids
             .iter()
             .map(|perm_id| RolePerm {
                 role_id: self.role_id.clone(),
                 perm_id: perm_id.clone(),
+                condition_expr: self.condition.clone(),
+                effect: self.effect.clone().unwrap_or_else(|| EFFECTS[0].to_string()),
                 created_at: now(),
             })
             .collect();
-        POOL.save_batch(&role_perms, &[]).await?;
+        let log = NewAuditLog::new("role.grant_perm", &role.id, Some(&role.domain_id))
+            .after(&role_perms);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.save_batch(&role_perms, &[]).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
         Ok(role_perms)
     }
 }
@@ -35,24 +62,73 @@ pub struct RoleRevokePerm {
 }
 
 impl RoleRevokePerm {
-    pub async fn save(self) -> Result<u64, DBError> {
+    pub async fn save(self, role: &Role, actor: &Actor) -> Result<u64, DBError> {
         let w = POOL
             .new_wrapper()
             .r#in("perm_id", &self.perm_ids)
             .and()
             .eq("role_id", self.role_id);
-        RolePerm::delete_one(w).await
+        let removed = RolePerm::find_list(w.clone()).await?;
+        let log = NewAuditLog::new("role.revoke_perm", &role.id, Some(&role.domain_id))
+            .before(&removed);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let revoked = tx.remove_by_wrapper::<RolePerm>(w).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(revoked)
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Validate)]
 pub struct RoleChangePerm {
     pub perm_ids: Vec<String>,
     pub role_id: String,
+    /// conditions by perm id, perms without one are granted unconditionally
+    #[serde(default)]
+    #[validate(custom = "validate_conditions")]
+    pub conditions: HashMap<String, String>,
+    /// effects by perm id, perms without one are allowed
+    #[serde(default)]
+    #[validate(custom = "validate_effects")]
+    pub effects: HashMap<String, String>,
+}
+
+fn validate_conditions(v: &HashMap<String, String>) -> Result<(), ValidationError> {
+    v.values().try_for_each(|v| validate_condition(v))
+}
+
+fn validate_effects(v: &HashMap<String, String>) -> Result<(), ValidationError> {
+    v.values().try_for_each(|v| validate_effect(v))
+}
+
+/// sets or clears the condition of an existing grant
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct UpdateRolePerm {
+    #[validate(custom = "validate_condition")]
+    pub condition: Option<String>,
+}
+
+impl UpdateRolePerm {
+    pub async fn save(self, found: &RolePerm, role: &Role, actor: &Actor) -> Result<RolePerm, DBError> {
+        let log = NewAuditLog::new("role.update_perm", &role.id, Some(&role.domain_id)).before(found);
+        let mut dao = found.to_owned();
+        dao.condition_expr = self.condition;
+        let w = POOL
+            .new_wrapper()
+            .eq("role_id", &dao.role_id)
+            .and()
+            .eq("perm_id", &dao.perm_id);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.update_by_wrapper(&dao, w, &[]).await?;
+        tx.save(&log.after(&dao).by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(dao)
+    }
 }
 
 impl RoleChangePerm {
-    pub async fn save(self) -> Result<Vec<RolePerm>, DBError> {
+    pub async fn save(self, role: &Role, actor: &Actor) -> Result<Vec<RolePerm>, DBError> {
+        let prev = RolePerm::find_by_role(&self.role_id).await?;
         let mut tx = POOL.acquire_begin().await.unwrap();
         let w = POOL.new_wrapper().eq("role_id", &self.role_id);
         tx.remove_by_wrapper::<RolePerm>(w).await?;
@@ -61,10 +137,20 @@ impl RoleChangePerm {
             .map(|perm_id| RolePerm {
                 role_id: self.role_id.clone(),
                 perm_id: perm_id.clone(),
+                condition_expr: self.conditions.get(perm_id).cloned(),
+                effect: self
+                    .effects
+                    .get(perm_id)
+                    .cloned()
+                    .unwrap_or_else(|| EFFECTS[0].to_string()),
                 created_at: now(),
             })
             .collect();
         tx.save_batch(&rows, &[]).await?;
+        let log = NewAuditLog::new("role.change_perm", &role.id, Some(&role.domain_id))
+            .before(&prev)
+            .after(&rows);
+        tx.save(&log.by(actor), &[]).await?;
         tx.commit().await.unwrap();
         Ok(rows)
     }
@@ -0,0 +1,45 @@
+use crate::{
+    repository::{
+        dao::{Role, RolePrerequisite},
+        DBError, POOL,
+    },
+    util::{audit::Actor, now},
+};
+use super::NewAuditLog;
+use rbatis::crud::CRUDMut;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct RoleChangePrerequisite {
+    #[validate(length(max = 50))]
+    pub prerequisite_ids: Vec<String>,
+}
+
+impl RoleChangePrerequisite {
+    pub async fn save(self, role: &Role, actor: &Actor) -> Result<Vec<RolePrerequisite>, DBError> {
+        let prev = RolePrerequisite::find_by_role(&role.id).await?;
+        let rows: Vec<RolePrerequisite> = self
+            .prerequisite_ids
+            .iter()
+            .map(|prerequisite_id| RolePrerequisite {
+                role_id: role.id.clone(),
+                prerequisite_id: prerequisite_id.clone(),
+                domain_id: role.domain_id.clone(),
+                created_at: now(),
+            })
+            .collect();
+        let log = NewAuditLog::new("role.change_prerequisite", &role.id, Some(&role.domain_id))
+            .before(&prev)
+            .after(&rows);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let w = POOL.new_wrapper().eq("role_id", &role.id);
+        tx.remove_by_wrapper::<RolePrerequisite>(w).await?;
+        if !rows.is_empty() {
+            tx.save_batch(&rows, &[]).await?;
+        }
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(rows)
+    }
+}
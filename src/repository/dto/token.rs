@@ -0,0 +1,153 @@
+use crate::{
+    repository::{
+        dao::{Impersonation, RefreshToken, TokenRevocation},
+        DBError, Dao, POOL,
+    },
+    util::{
+        hash_token,
+        jwt::{max_token_ttl, refresh_token_ttl, Payload},
+        now, random_token, revocation, uuid_v4,
+    },
+};
+use chrono::{Local, Utc};
+use rbatis::crud::CRUDMut;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+fn next_token(user_id: &str, family_id: &str) -> (RefreshToken, String) {
+    let token = random_token();
+    let dao = RefreshToken {
+        id: uuid_v4(),
+        user_id: user_id.to_string(),
+        family_id: family_id.to_string(),
+        token_hash: hash_token(&token),
+        replaced_by: None,
+        is_revoked: 0,
+        expire: now() + refresh_token_ttl(),
+        created_at: now(),
+    };
+    (dao, token)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NewRefreshToken {
+    pub user_id: String,
+}
+
+impl NewRefreshToken {
+    /// starts a new token family, the plain token is returned but never stored
+    pub async fn create(self) -> Result<(RefreshToken, String), DBError> {
+        let (dao, token) = next_token(&self.user_id, &uuid_v4());
+        RefreshToken::create_one(&dao).await?;
+        Ok((dao, token))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct RotateRefreshToken {
+    #[validate(length(min = 1, max = 200))]
+    pub refresh_token: String,
+}
+
+impl RotateRefreshToken {
+    /// replaces `prev` with a new token of the same family,
+    /// returns `None` when `prev` has already been replaced
+    pub async fn save(&self, prev: RefreshToken) -> Result<Option<(RefreshToken, String)>, DBError> {
+        let (dao, token) = next_token(&prev.user_id, &prev.family_id);
+        let mut prev = prev;
+        prev.replaced_by = Some(dao.id.clone());
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let w = POOL
+            .new_wrapper()
+            .eq("id", &prev.id)
+            .and()
+            .is_null("replaced_by");
+        if tx.update_by_wrapper(&prev, w, &[]).await? == 0 {
+            tx.rollback().await.unwrap();
+            return Ok(None);
+        }
+        tx.save(&dao, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(Some((dao, token)))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct Logout {
+    #[validate(length(min = 1, max = 200))]
+    pub refresh_token: Option<String>,
+}
+
+impl Logout {
+    pub async fn save(&self, payload: &Payload) -> Result<TokenRevocation, DBError> {
+        let dao = TokenRevocation {
+            id: uuid_v4(),
+            user_id: payload.auth.id.clone(),
+            jti: Some(payload.jti.clone()),
+            issued_until: None,
+            expire: payload.exp.with_timezone(&Local).naive_local(),
+            created_at: now(),
+        };
+        TokenRevocation::create_one(&dao).await?;
+        revocation::revoke_token(&payload.jti, dao.expire);
+        if let Some(token) = &self.refresh_token {
+            if let Ok(refresh_token) = RefreshToken::find_by_token(token).await {
+                if refresh_token.user_id == payload.auth.id {
+                    RefreshToken::revoke_family(&refresh_token.family_id).await?;
+                }
+            }
+        }
+        Ok(dao)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RevokeSessions {
+    pub user_id: String,
+}
+
+impl RevokeSessions {
+    /// revokes every access token issued so far and all refresh tokens of the user
+    pub async fn save(&self) -> Result<TokenRevocation, DBError> {
+        let issued_until = Utc::now().timestamp_millis();
+        let dao = TokenRevocation {
+            id: uuid_v4(),
+            user_id: self.user_id.clone(),
+            jti: None,
+            issued_until: Some(issued_until),
+            expire: now() + max_token_ttl(),
+            created_at: now(),
+        };
+        TokenRevocation::create_one(&dao).await?;
+        revocation::revoke_user(&self.user_id, issued_until);
+        RefreshToken::revoke_by_user(&self.user_id).await?;
+        Ok(dao)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct Impersonate {
+    #[validate(length(min = 1, max = 500))]
+    pub reason: String,
+}
+
+impl Impersonate {
+    /// records who acts as whom and why, the token itself is not stored
+    pub async fn create(&self, actor_id: &str, payload: &Payload) -> Result<Impersonation, DBError> {
+        let dao = Impersonation {
+            id: uuid_v4(),
+            actor_id: actor_id.to_string(),
+            user_id: payload.auth.id.clone(),
+            reason: self.reason.clone(),
+            jti: payload.jti.clone(),
+            expire: payload.exp.with_timezone(&Local).naive_local(),
+            created_at: now(),
+        };
+        Impersonation::create_one(&dao).await?;
+        tracing::warn!(
+            actor_id = %dao.actor_id, user_id = %dao.user_id, jti = %dao.jti,
+            "impersonation token issued"
+        );
+        Ok(dao)
+    }
+}
@@ -1,19 +1,36 @@
 use crate::{
-    repository::{dao::User, DBError, Dao, POOL},
-    util::now,
+    repository::{
+        dao::{
+            PasswordHistory, PasswordResetToken, ResourceGrant, SessionRole, User, UserGroup,
+            UserOrg, UserRole,
+        },
+        vo, DBError, Dao, POOL,
+    },
+    util::{
+        audit::Actor,
+        hash_token,
+        mail::Mail,
+        now,
+        password::{
+            hash_password, reset_link, reset_token_ttl, spend_verify, validate_password,
+            verify_password,
+        },
+        random_token, uuid_v4,
+    },
 };
-use bcrypt::{hash, verify};
+use super::NewAuditLog;
 use chrono::NaiveDateTime;
-use rbatis::{plugin::page::{Page, PageRequest}, crud::CRUD};
+use rbatis::{plugin::page::{Page, PageRequest}, crud::{CRUD, CRUDMut}};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use uuid::Uuid;
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
 #[derive(Debug, Deserialize, Serialize, Validate)]
 pub struct NewUser {
     #[validate(length(min = 1, max = 50))]
     pub username: String,
-    #[validate(length(min = 1, max = 100))]
+    #[validate(length(max = 100), custom = "validate_password")]
     pub password: String,
     #[validate(must_match(other = "password", message = "密码不匹配"))]
     pub repeat_password: String,
@@ -29,20 +46,23 @@ pub struct NewUser {
 impl NewUser {
     pub async fn create(self) -> Result<User, DBError> {
         let id = Uuid::new_v4().to_string();
-        let hashed_password = hash(&self.password, 4).unwrap();
+        let hashed_password = hash_password(&self.password).await;
         let dao = User {
             id: id.clone(),
             username: self.username,
             password: hashed_password,
             email: self.email,
+            email_verified_at: None,
             avatar: self.avatar,
             memo: self.memo,
             sys_role: Some("member".to_string()),
             is_actived: 1,
+            is_deleted: 0,
             last_logined_at: now(),
             created_at: now(),
         };
         User::create_one(&dao).await?;
+        PasswordHistory::push(&dao.id, &dao.password).await?;
         Ok(dao)
     }
 }
@@ -59,6 +79,9 @@ impl UpdateUser {
     pub async fn save(self, id: &str) -> Result<User, DBError> {
         let w = POOL.new_wrapper().eq("id", id);
         let mut dao = User::find_one(w.clone()).await?;
+        if dao.email != self.email {
+            dao.email_verified_at = None;
+        }
         dao.email = self.email;
         dao.avatar = self.avatar;
         dao.memo = self.memo;
@@ -76,8 +99,20 @@ pub struct LoginUser {
 }
 
 impl LoginUser {
-    pub fn is_password_matched(&self, target: &str) -> bool {
-        verify(self.password.clone(), target).unwrap()
+    pub async fn is_password_matched(&self, target: &str) -> bool {
+        verify_password(&self.password, target).await
+    }
+    /// verifies against a throwaway hash, so unknown users take as long as wrong passwords
+    pub async fn spend_verify(&self) {
+        spend_verify(&self.password).await;
+    }
+    /// rehashes the password just verified with the configured scheme and cost
+    pub async fn rehash(&self, dao: &User) -> Result<User, DBError> {
+        let mut dao = dao.to_owned();
+        dao.password = hash_password(&self.password).await;
+        let w = POOL.new_wrapper().eq("id", &dao.id);
+        User::update_one(&dao, w).await?;
+        Ok(dao)
     }
     pub async fn login(&self, dao: &User) -> Result<User, DBError> {
         let mut dao = dao.to_owned();
@@ -88,85 +123,228 @@ impl LoginUser {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ChangePassword {
+    #[validate(length(min = 3, max = 100))]
+    pub old_password: String,
+    #[validate(length(max = 100), custom = "validate_password")]
+    pub new_password: String,
+    #[validate(must_match(other = "new_password", message = "密码不匹配"))]
+    pub repeat_password: String,
+}
+
+impl ChangePassword {
+    pub async fn is_password_matched(&self, target: &str) -> bool {
+        verify_password(&self.old_password, target).await
+    }
+    pub async fn change_password(&self, dao: &User) -> Result<User, DBError> {
+        let mut dao = dao.to_owned();
+        let hashed_password = hash_password(&self.new_password).await;
+        dao.password = hashed_password;
+        let w = POOL.new_wrapper().eq("id", &dao.id);
+        User::update_one(&dao, w).await?;
+        PasswordHistory::push(&dao.id, &dao.password).await?;
+        Ok(dao)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ResetPassword {
+    #[validate(length(max = 100), custom = "validate_password")]
+    pub new_password: String,
+    #[validate(must_match = "new_password")]
+    pub repeat_password: String,
+    // required by the self-service reset, ignored when an admin resets
+    #[validate(length(min = 1, max = 200))]
+    pub token: Option<String>,
+}
+
+impl ResetPassword {
+    pub async fn reset_password(&self, dao: &User) -> Result<User, DBError> {
+        let hashed_password = hash_password(&self.new_password).await;
+        let mut dao = dao.to_owned();
+        let w = POOL.new_wrapper().eq("id", &dao.id);
+        dao.password = hashed_password;
+        User::update_one(&dao, w).await?;
+        PasswordHistory::push(&dao.id, &dao.password).await?;
+        Ok(dao)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ForgotPassword {
+    #[validate(length(min = 1, max = 200))]
+    pub username_or_email: String,
+}
+
+impl ForgotPassword {
+    /// issues a single-use reset token, the plain token only goes into the returned mail
+    pub async fn create(&self, dao: &User, email: &str) -> Result<(PasswordResetToken, Mail), DBError> {
+        let token = random_token();
+        let ttl = reset_token_ttl();
+        let reset_token = PasswordResetToken {
+            id: uuid_v4(),
+            user_id: dao.id.clone(),
+            token_hash: hash_token(&token),
+            is_used: 0,
+            expire: now() + ttl,
+            created_at: now(),
+        };
+        PasswordResetToken::create_one(&reset_token).await?;
+        let mail = Mail {
+            to: email.to_string(),
+            subject: "重置密码".to_string(),
+            body: format!(
+                "{}，您好：\n\n请在 {} 分钟内通过以下链接重置密码，如非本人操作请忽略本邮件。\n\n{}\n",
+                dao.username,
+                ttl.num_minutes(),
+                reset_link(&token)
+            ),
+        };
+        Ok((reset_token, mail))
+    }
+}
+
+const SYS_ROLES: [&str; 2] = ["admin", "member"];
+
+fn validate_sys_role(v: &str) -> Result<(), ValidationError> {
+    if !SYS_ROLES.contains(&v) {
+        let mut e = ValidationError::new("sys_role");
+        e.message = Some("系统角色不合法".into());
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// an account created by an admin on someone's behalf
 #[derive(Debug, Deserialize, Serialize, Validate)]
-pub struct ConnectUser {
+pub struct CreateUser {
     #[validate(length(min = 1, max = 50))]
     pub username: String,
+    #[validate(length(max = 100), custom = "validate_password")]
+    pub password: String,
     #[validate(email)]
     pub email: Option<String>,
+    // skip email verification, the admin vouches for the address
+    pub email_verified: Option<bool>,
     pub avatar: Option<String>,
     pub memo: Option<String>,
+    #[validate(custom = "validate_sys_role")]
     pub sys_role: Option<String>,
-    #[serde(default = "now")]
-    pub last_logined_at: NaiveDateTime,
+    pub is_actived: Option<bool>,
 }
 
-const CONNECT_PASSWORD: &'static str = "123456";
-
-impl ConnectUser {
+impl CreateUser {
     pub async fn create(self) -> Result<User, DBError> {
-        let id = Uuid::new_v4().to_string();
-        let hashed_password = hash(CONNECT_PASSWORD, 4).unwrap();
+        let email_verified_at = match (&self.email, self.email_verified) {
+            (Some(_), Some(true)) => Some(now()),
+            _ => None,
+        };
         let dao = User {
-            id: id.clone(),
+            id: uuid_v4(),
             username: self.username,
-            password: hashed_password,
+            password: hash_password(&self.password).await,
             email: self.email,
+            email_verified_at,
             avatar: self.avatar,
             memo: self.memo,
-            sys_role: Some("member".to_string()),
-            is_actived: 1,
+            sys_role: Some(self.sys_role.unwrap_or_else(|| "member".to_string())),
+            is_actived: self.is_actived.unwrap_or(true) as i32,
+            is_deleted: 0,
             last_logined_at: now(),
             created_at: now(),
         };
         User::create_one(&dao).await?;
+        PasswordHistory::push(&dao.id, &dao.password).await?;
         Ok(dao)
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Validate)]
-pub struct ChangePassword {
-    #[validate(length(min = 3, max = 200))]
-    pub old_password: String,
-    #[validate(length(min = 3, max = 200))]
-    pub new_password: String,
-    #[validate(must_match(other = "new_password", message = "密码不匹配"))]
-    pub repeat_password: String,
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UpdateUserStatus {
+    pub is_actived: bool,
 }
 
-impl ChangePassword {
-    pub fn is_password_matched(&self, target: &str) -> bool {
-        verify(&self.old_password, target).unwrap()
-    }
-    pub async fn change_password(&self, dao: &User) -> Result<User, DBError> {
+impl UpdateUserStatus {
+    pub async fn save(&self, dao: &User, actor: &Actor) -> Result<User, DBError> {
+        let action = if self.is_actived { "user.enable" } else { "user.disable" };
+        let log = NewAuditLog::new(action, &dao.id, None)
+            .before(&json!({"is_actived": dao.is_actived == 1}))
+            .after(&json!({"is_actived": self.is_actived}));
         let mut dao = dao.to_owned();
-        let hashed_password = hash(&self.new_password, 4).unwrap();
-        dao.password = hashed_password;
+        dao.is_actived = self.is_actived as i32;
         let w = POOL.new_wrapper().eq("id", &dao.id);
-        User::update_one(&dao, w).await?;
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.update_by_wrapper(&dao, w, &[]).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
         Ok(dao)
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Validate)]
-pub struct ResetPassword {
-    #[validate(length(min = 3, max = 200))]
-    pub new_password: String,
-    #[validate(must_match = "new_password")]
-    pub repeat_password: String,
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct ChangeSysRole {
+    #[validate(custom = "validate_sys_role")]
+    pub sys_role: String,
 }
 
-impl ResetPassword {
-    pub async fn reset_password(&self, dao: &User) -> Result<User, DBError> {
-        let hashed_password = hash(&self.new_password, 4).unwrap();
+impl ChangeSysRole {
+    pub async fn save(&self, dao: &User, actor: &Actor) -> Result<User, DBError> {
+        let log = NewAuditLog::new("user.change_sys_role", &dao.id, None)
+            .before(&json!({"sys_role": dao.sys_role}))
+            .after(&json!({"sys_role": self.sys_role}));
         let mut dao = dao.to_owned();
+        dao.sys_role = Some(self.sys_role.clone());
         let w = POOL.new_wrapper().eq("id", &dao.id);
-        dao.password = hashed_password;
-        User::update_one(&dao, w).await?;
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        tx.update_by_wrapper(&dao, w, &[]).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
         Ok(dao)
     }
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteUser {
+    pub user_id: String,
+}
+
+impl DeleteUser {
+    /// soft-deletes and disables the user and drops all role, org and group memberships and
+    /// resource grants in one transaction
+    pub async fn save(&self, dao: &User, actor: &Actor) -> Result<u64, DBError> {
+        let user_roles = UserRole::find_by_user(&self.user_id, true).await?;
+        let user_orgs = UserOrg::find_by_user(&self.user_id, true).await?;
+        let user_groups = UserGroup::find_by_user(&self.user_id, true).await?;
+        let log = NewAuditLog::new("user.delete", &self.user_id, None).before(&json!({
+            "user": vo::User::from(dao.clone()), "roles": user_roles, "orgs": user_orgs,
+            "groups": user_groups
+        }));
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        // a deleted user keeps the row, disabling it too keeps every login path closed
+        let mut disabled = dao.clone();
+        disabled.is_actived = 0;
+        let w = POOL.new_wrapper().eq("id", &self.user_id);
+        tx.update_by_wrapper(&disabled, w, &[]).await?;
+        let w = POOL.new_wrapper().eq("user_id", &self.user_id);
+        tx.remove_by_wrapper::<UserRole>(w.clone()).await?;
+        tx.remove_by_wrapper::<UserOrg>(w.clone()).await?;
+        tx.remove_by_wrapper::<UserGroup>(w.clone()).await?;
+        tx.remove_by_wrapper::<SessionRole>(w).await?;
+        let w = POOL
+            .new_wrapper()
+            .eq("subject_type", "user")
+            .and()
+            .eq("subject_id", &self.user_id);
+        tx.remove_by_wrapper::<ResourceGrant>(w).await?;
+        let w = POOL.new_wrapper().eq("id", &self.user_id);
+        let removed = tx.remove_by_wrapper::<User>(w).await?;
+        tx.save(&log.by(actor), &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(removed)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct QueryUser {
     pub key: Option<String>,
@@ -185,9 +363,9 @@ impl QueryUser {
       let req = PageRequest::new(page, limit);
       let sort_by = self.sort_by.unwrap_or("created_at".to_string());
       let sort_order = self.sort_order.unwrap_or("DESC".to_string());
-      let mut w = POOL.new_wrapper();
+      let mut w = POOL.new_wrapper().eq("is_deleted", 0);
       if let Some(key) = self.key {
-          if key != "" {
+          if !key.is_empty() {
               w = w.and().like("username", key);
           }
       }
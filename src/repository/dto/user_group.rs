@@ -0,0 +1,108 @@
+use crate::{
+    repository::{
+        dao::{role::{Holdings, Scope}, Group, UserGroup},
+        ConstraintError, DBError, Dao, POOL,
+    },
+    util::{audit::Actor, default_expire, now},
+};
+use super::{user_role::check_roles_constraints, NewAuditLog};
+use chrono::NaiveDateTime;
+use rbatis::crud::CRUDMut;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct UserJoinGroup {
+    #[validate(length(min = 1, max = 500))]
+    pub user_ids: Vec<String>,
+    pub group_id: String,
+    pub expire: Option<NaiveDateTime>,
+}
+
+impl UserJoinGroup {
+    /// rejects joins that make a member break a holder limit, static exclusion or
+    /// prerequisite through the group's roles, nothing is written then
+    pub async fn save(
+        self,
+        group: &Group,
+        actor: &Actor,
+    ) -> Result<Vec<UserGroup>, ConstraintError> {
+        let user_groups: Vec<UserGroup> = self
+            .user_ids
+            .iter()
+            .map(|user_id| UserGroup {
+                group_id: self.group_id.clone(),
+                user_id: user_id.clone(),
+                created_at: now(),
+                expire: self.expire.unwrap_or_else(default_expire),
+            })
+            .collect();
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let mut before = Holdings::lock(&mut tx, &group.domain_id).await?;
+        let role_ids = before.group_role_ids(&group.id);
+        let scope = Scope {
+            user_ids: self.user_ids.clone(),
+            role_ids: role_ids.clone(),
+            ..Default::default()
+        };
+        before.load_members(&mut tx, &scope).await?;
+        let mut after = before.clone();
+        after
+            .user_groups
+            .retain(|v| !(v.group_id == self.group_id && self.user_ids.contains(&v.user_id)));
+        after.user_groups.extend(user_groups.iter().cloned());
+        if let Err(e) = check_roles_constraints(&mut tx, &role_ids, &before, &after).await {
+            tx.rollback().await.unwrap();
+            return Err(e);
+        }
+        let w = POOL
+            .new_wrapper()
+            .r#in("user_id", &self.user_ids)
+            .and()
+            .eq("group_id", &self.group_id);
+        tx.remove_by_wrapper::<UserGroup>(w).await?;
+        tx.save_batch(&user_groups, &[]).await?;
+        let logs: Vec<_> = user_groups
+            .iter()
+            .map(|v| {
+                NewAuditLog::new("user.join_group", &v.user_id, Some(&group.domain_id))
+                    .after(v)
+                    .by(actor)
+            })
+            .collect();
+        tx.save_batch(&logs, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(user_groups)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct UserLeaveGroup {
+    #[validate(length(min = 1, max = 500))]
+    pub user_ids: Vec<String>,
+    pub group_id: String,
+}
+
+impl UserLeaveGroup {
+    pub async fn save(self, group: &Group, actor: &Actor) -> Result<u64, DBError> {
+        let w = POOL
+            .new_wrapper()
+            .r#in("user_id", &self.user_ids)
+            .and()
+            .eq("group_id", self.group_id);
+        let logs: Vec<_> = UserGroup::find_list(w.clone())
+            .await?
+            .iter()
+            .map(|v| {
+                NewAuditLog::new("user.leave_group", &v.user_id, Some(&group.domain_id))
+                    .before(v)
+                    .by(actor)
+            })
+            .collect();
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let left = tx.remove_by_wrapper::<UserGroup>(w).await?;
+        tx.save_batch(&logs, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(left)
+    }
+}
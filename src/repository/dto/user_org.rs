@@ -1,9 +1,13 @@
 use crate::{
-    repository::{dao::UserOrg, DBError, Dao, POOL},
-    util::{default_expire, now},
+    repository::{
+        dao::{role::{Holdings, Scope}, Org, UserOrg},
+        ConstraintError, DBError, Dao, POOL,
+    },
+    util::{audit::Actor, default_expire, now},
 };
+use super::{user_role::check_roles_constraints, NewAuditLog};
 use chrono::NaiveDateTime;
-use rbatis::crud::CRUD;
+use rbatis::crud::CRUDMut;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
@@ -15,7 +19,13 @@ pub struct UserJoinOrg {
 }
 
 impl UserJoinOrg {
-    pub async fn save(self) -> Result<Vec<UserOrg>, DBError> {
+    /// rejects joins that make a member break a holder limit, static exclusion or
+    /// prerequisite through the roles of the org and the orgs above it, nothing is written then
+    pub async fn save(
+        self,
+        org: &Org,
+        actor: &Actor,
+    ) -> Result<Vec<UserOrg>, ConstraintError> {
         let user_orgs: Vec<UserOrg> = self
             .user_ids
             .iter()
@@ -23,10 +33,44 @@ impl UserJoinOrg {
                 org_id: self.org_id.clone(),
                 user_id: user_id.clone(),
                 created_at: now(),
-                expire: default_expire(),
+                expire: self.expire.unwrap_or_else(default_expire),
             })
             .collect();
-        POOL.save_batch(&user_orgs, &[]).await?;
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let mut before = Holdings::lock(&mut tx, &org.domain_id).await?;
+        let role_ids = before.org_role_ids(&org.id);
+        let scope = Scope {
+            user_ids: self.user_ids.clone(),
+            role_ids: role_ids.clone(),
+            ..Default::default()
+        };
+        before.load_members(&mut tx, &scope).await?;
+        let mut after = before.clone();
+        after
+            .user_orgs
+            .retain(|v| !(v.org_id == self.org_id && self.user_ids.contains(&v.user_id)));
+        after.user_orgs.extend(user_orgs.iter().cloned());
+        if let Err(e) = check_roles_constraints(&mut tx, &role_ids, &before, &after).await {
+            tx.rollback().await.unwrap();
+            return Err(e);
+        }
+        let w = POOL
+            .new_wrapper()
+            .r#in("user_id", &self.user_ids)
+            .and()
+            .eq("org_id", &self.org_id);
+        tx.remove_by_wrapper::<UserOrg>(w).await?;
+        tx.save_batch(&user_orgs, &[]).await?;
+        let logs: Vec<_> = user_orgs
+            .iter()
+            .map(|v| {
+                NewAuditLog::new("user.join_org", &v.user_id, Some(&org.domain_id))
+                    .after(v)
+                    .by(actor)
+            })
+            .collect();
+        tx.save_batch(&logs, &[]).await?;
+        tx.commit().await.unwrap();
         Ok(user_orgs)
     }
 }
@@ -38,12 +82,25 @@ pub struct UserLeaveOrg {
 }
 
 impl UserLeaveOrg {
-    pub async fn save(self) -> Result<u64, DBError> {
+    pub async fn save(self, org: &Org, actor: &Actor) -> Result<u64, DBError> {
         let w = POOL
             .new_wrapper()
             .r#in("user_id", &self.user_ids)
             .and()
             .eq("org_id", self.org_id);
-        UserOrg::delete_one(w).await
+        let logs: Vec<_> = UserOrg::find_list(w.clone())
+            .await?
+            .iter()
+            .map(|v| {
+                NewAuditLog::new("user.leave_org", &v.user_id, Some(&org.domain_id))
+                    .before(v)
+                    .by(actor)
+            })
+            .collect();
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let left = tx.remove_by_wrapper::<UserOrg>(w).await?;
+        tx.save_batch(&logs, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(left)
     }
 }
@@ -1,15 +1,108 @@
 use crate::{
     repository::{
-        dao::{Role, User, UserRole},
-        DBError, Dao, POOL,
+        dao::{
+            role::{Holdings, Scope},
+            role_exclusion::with_inherited, Role, RolePrerequisite, User,
+            UserRole,
+        },
+        ConstraintError, DBError, Dao, POOL,
     },
-    util::{default_expire, now, serde_format::naive_datetime},
+    util::{audit::Actor, default_expire, now, serde_format::naive_datetime},
 };
+use super::{role_exclusion::check_exclusions, NewAuditLog};
 use chrono::NaiveDateTime;
-use rbatis::crud::{CRUDMut, CRUD};
+use rbatis::{crud::CRUDMut, executor::RBatisTxExecutor};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
+/// checks the holder limit of `role`, the static exclusions of its domain and its
+/// prerequisites for the users the change makes hold it, `after` being `before` with the change applied; the holdings must be loaded
+/// in `tx` and the role locked after that. The violated constraint is described on `Err`
+pub async fn check_constraints(
+    tx: &mut RBatisTxExecutor<'_>,
+    role: &Role,
+    before: &Holdings,
+    after: &Holdings,
+) -> Result<(), ConstraintError> {
+    let prev = before.holders_of(&role.id);
+    let granted_ids: Vec<String> = after
+        .holders_of(&role.id)
+        .into_iter()
+        .filter(|v| !prev.contains(v))
+        .collect();
+    if granted_ids.is_empty() {
+        return Ok(());
+    }
+    check_holder_limit(role, after)?;
+    check_exclusions(tx, &role.domain_id, before, after).await?;
+    let w = POOL.new_wrapper().eq("role_id", &role.id);
+    let prerequisite_ids: Vec<String> = tx
+        .fetch_list_by_wrapper::<RolePrerequisite>(w)
+        .await?
+        .into_iter()
+        .map(|v| v.prerequisite_id)
+        .collect();
+    if prerequisite_ids.is_empty() {
+        return Ok(());
+    }
+    let w = POOL.new_wrapper().r#in("id", &prerequisite_ids);
+    let prerequisites: Vec<Role> = tx.fetch_list_by_wrapper(w).await?;
+    check_prerequisites(role, &prerequisites, &granted_ids, after)
+}
+
+/// users holding `role` in `after` through any grant, org or group, against its limit
+fn check_holder_limit(role: &Role, after: &Holdings) -> Result<(), ConstraintError> {
+    let holders = after.holders_of(&role.id);
+    match role.max_holders {
+        Some(max_holders) if holders.len() > max_holders as usize => {
+            Err(ConstraintError::Violated(format!(
+                "角色 {} 最多 {} 人持有, 授予后将有 {} 人",
+                role.value,
+                max_holders,
+                holders.len()
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// every user in `granted_ids` has to hold all of `prerequisites` in `after`, inherited ones
+/// included
+fn check_prerequisites(
+    role: &Role,
+    prerequisites: &[Role],
+    granted_ids: &[String],
+    after: &Holdings,
+) -> Result<(), ConstraintError> {
+    let roles_by_user = after.roles_by_user();
+    for user_id in granted_ids {
+        let role_ids = roles_by_user.get(user_id).cloned().unwrap_or_default();
+        let held = with_inherited(&after.edges, &role_ids);
+        if let Some(missing) = prerequisites.iter().find(|v| !held.contains(&v.id)) {
+            return Err(ConstraintError::Violated(format!(
+                "用户 {} 未持有前置角色 {}, 不能授予角色 {}",
+                user_id, missing.value, role.value
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// `check_constraints` for every role in `role_ids`, for changes of memberships and orgs that
+/// give or take roles to many users at once; the roles are locked here
+pub async fn check_roles_constraints(
+    tx: &mut RBatisTxExecutor<'_>,
+    role_ids: &[String],
+    before: &Holdings,
+    after: &Holdings,
+) -> Result<(), ConstraintError> {
+    for role_id in role_ids {
+        let role = Role::lock(tx, role_id).await?;
+        check_constraints(tx, &role, before, after).await?;
+    }
+    Ok(())
+}
+
 #[derive(Debug, Deserialize, Serialize, Validate)]
 pub struct UserGrantRole {
     pub user_ids: Vec<String>,
@@ -19,7 +112,13 @@ pub struct UserGrantRole {
 }
 
 impl UserGrantRole {
-    pub async fn save(self) -> Result<Vec<UserRole>, DBError> {
+    /// `Err` describes the holder limit or prerequisite the grant would break, nothing is
+    /// written then
+    pub async fn save(
+        self,
+        role: &Role,
+        actor: &Actor,
+    ) -> Result<Vec<UserRole>, ConstraintError> {
         let user_roles: Vec<UserRole> = self
             .user_ids
             .iter()
@@ -31,7 +130,40 @@ impl UserGrantRole {
                 expire: default_expire(),
             })
             .collect();
-        POOL.save_batch(&user_roles, &[]).await?;
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let scope = Scope {
+            user_ids: self.user_ids.clone(),
+            role_ids: vec![role.id.clone()],
+            ..Default::default()
+        };
+        let before = Holdings::load(&mut tx, &role.domain_id, &scope).await?;
+        let role = Role::lock(&mut tx, &role.id).await?;
+        let mut after = before.clone();
+        after
+            .user_roles
+            .retain(|v| !(v.role_id == role.id && self.user_ids.contains(&v.user_id)));
+        after.user_roles.extend(user_roles.iter().cloned());
+        if let Err(e) = check_constraints(&mut tx, &role, &before, &after).await {
+            tx.rollback().await.unwrap();
+            return Err(e);
+        }
+        let w = POOL
+            .new_wrapper()
+            .r#in("user_id", &self.user_ids)
+            .and()
+            .eq("role_id", &self.role_id);
+        tx.remove_by_wrapper::<UserRole>(w).await?;
+        tx.save_batch(&user_roles, &[]).await?;
+        let logs: Vec<_> = user_roles
+            .iter()
+            .map(|v| {
+                NewAuditLog::new("user.grant_role", &v.user_id, Some(&role.domain_id))
+                    .after(v)
+                    .by(actor)
+            })
+            .collect();
+        tx.save_batch(&logs, &[]).await?;
+        tx.commit().await.unwrap();
         Ok(user_roles)
     }
 }
@@ -45,15 +177,43 @@ pub struct UpdateUserRole {
 }
 
 impl UpdateUserRole {
-    pub async fn save(self) -> Result<UserRole, DBError> {
+    /// an expiry that brings an expired grant back into force is checked like a new grant,
+    /// `Err` describes the constraint it would break and nothing is written then
+    pub async fn save(self, role: &Role, actor: &Actor) -> Result<UserRole, ConstraintError> {
         let w = POOL
             .new_wrapper()
-            .eq("user_id", self.user_id)
+            .eq("user_id", &self.user_id)
             .and()
-            .eq("role_id", self.role_id);
-        let mut dao = UserRole::find_one(w.clone()).await?;
+            .eq("role_id", &self.role_id);
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let mut holdings = None;
+        if self.expire > now() {
+            let scope = Scope {
+                user_ids: vec![self.user_id.clone()],
+                role_ids: vec![role.id.clone()],
+                ..Default::default()
+            };
+            let before = Holdings::load(&mut tx, &role.domain_id, &scope).await?;
+            let role = Role::lock(&mut tx, &role.id).await?;
+            holdings = Some((before, role));
+        }
+        // the grant is read once the role is locked
+        let mut dao: UserRole = tx.fetch_by_wrapper(w.clone()).await?;
+        let log = NewAuditLog::new("user.expire_role", &self.user_id, Some(&role.domain_id))
+            .before(&dao);
+        let expired = dao.expire <= now();
         dao.expire = self.expire;
-        UserRole::update_one(&dao, w).await?;
+        if let Some((before, role)) = holdings.as_ref().filter(|_| expired) {
+            let mut after = before.clone();
+            after.user_roles.push(dao.clone());
+            if let Err(e) = check_constraints(&mut tx, role, before, &after).await {
+                tx.rollback().await.unwrap();
+                return Err(e);
+            }
+        }
+        tx.update_by_wrapper(&dao, w, &[]).await?;
+        tx.save(&log.after(&dao).by(actor), &[]).await?;
+        tx.commit().await.unwrap();
         Ok(dao)
     }
 }
@@ -65,13 +225,26 @@ pub struct UserRevokeRole {
 }
 
 impl UserRevokeRole {
-    pub async fn save(self) -> Result<u64, DBError> {
+    pub async fn save(self, role: &Role, actor: &Actor) -> Result<u64, DBError> {
         let w = POOL
             .new_wrapper()
             .r#in("user_id", &self.user_ids)
             .and()
             .eq("role_id", self.role_id);
-        UserRole::delete_one(w).await
+        let logs: Vec<_> = UserRole::find_list(w.clone())
+            .await?
+            .iter()
+            .map(|v| {
+                NewAuditLog::new("user.revoke_role", &v.user_id, Some(&role.domain_id))
+                    .before(v)
+                    .by(actor)
+            })
+            .collect();
+        let mut tx = POOL.acquire_begin().await.unwrap();
+        let revoked = tx.remove_by_wrapper::<UserRole>(w).await?;
+        tx.save_batch(&logs, &[]).await?;
+        tx.commit().await.unwrap();
+        Ok(revoked)
     }
 }
 
@@ -82,10 +255,25 @@ pub struct UserChangeRole {
 }
 
 impl UserChangeRole {
-    pub async fn save(self, role: Role, users: Vec<User>) -> Result<Vec<UserRole>, DBError> {
+    /// `Err` describes the holder limit or prerequisite the change would break, nothing is
+    /// written then
+    pub async fn save(
+        self,
+        role: Role,
+        users: Vec<User>,
+        actor: &Actor,
+    ) -> Result<Vec<UserRole>, ConstraintError> {
         let mut tx = POOL.acquire_begin().await.unwrap();
+        let scope = Scope {
+            user_ids: users.iter().map(|v| v.id.clone()).collect(),
+            role_ids: vec![role.id.clone()],
+            ..Default::default()
+        };
+        let before = Holdings::load(&mut tx, &role.domain_id, &scope).await?;
+        let role = Role::lock(&mut tx, &role.id).await?;
+        // the holders are read once the role is locked
         let w = POOL.new_wrapper().eq("role_id", &self.role_id);
-        tx.remove_by_wrapper::<UserRole>(w).await?;
+        let prev: Vec<UserRole> = tx.fetch_list_by_wrapper(w).await?;
         let rows: Vec<UserRole> = users
             .into_iter()
             .map(|user| UserRole {
@@ -96,8 +284,108 @@ impl UserChangeRole {
                 created_at: now(),
             })
             .collect();
+        let mut after = before.clone();
+        after.user_roles.retain(|v| v.role_id != role.id);
+        after.user_roles.extend(rows.iter().cloned());
+        if let Err(e) = check_constraints(&mut tx, &role, &before, &after).await {
+            tx.rollback().await.unwrap();
+            return Err(e);
+        }
+        let w = POOL.new_wrapper().eq("role_id", &self.role_id);
+        tx.remove_by_wrapper::<UserRole>(w).await?;
         tx.save_batch(&rows, &[]).await?;
+        let mut logs = vec![];
+        for v in prev.iter().filter(|v| !self.user_ids.contains(&v.user_id)) {
+            let log = NewAuditLog::new("user.change_role", &v.user_id, Some(&role.domain_id));
+            logs.push(log.before(v).by(actor));
+        }
+        for v in rows.iter() {
+            let mut log = NewAuditLog::new("user.change_role", &v.user_id, Some(&role.domain_id));
+            if let Some(before) = prev.iter().find(|p| p.user_id == v.user_id) {
+                log = log.before(before);
+            }
+            logs.push(log.after(v).by(actor));
+        }
+        tx.save_batch(&logs, &[]).await?;
         tx.commit().await.unwrap();
         Ok(rows)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn role(id: &str, max_holders: Option<i32>) -> Role {
+        Role {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: None,
+            value: id.to_string(),
+            level: 0,
+            max_holders,
+            domain_id: "d1".to_string(),
+            is_deleted: 0,
+            created_at: now(),
+            updated_at: now(),
+            created_by: None,
+            updated_by: None,
+        }
+    }
+
+    #[test]
+    fn counts_holders_through_orgs_and_groups() {
+        let holdings = Holdings::default()
+            .org("root", None)
+            .org("team", Some("root"))
+            .grant_org("root", "r1")
+            .join_org("u1", "team")
+            .join_group("u2", "g1")
+            .grant_group("g1", "r1");
+        assert!(check_holder_limit(&role("r1", Some(2)), &holdings).is_ok());
+        let after = holdings.grant("u3", "r1");
+        assert!(matches!(
+            check_holder_limit(&role("r1", Some(2)), &after),
+            Err(ConstraintError::Violated(_))
+        ));
+        assert!(check_holder_limit(&role("r1", None), &after).is_ok());
+    }
+
+    #[test]
+    fn counts_a_user_holding_the_role_twice_once() {
+        let after = Holdings::default()
+            .org("team", None)
+            .grant_org("team", "r1")
+            .join_org("u1", "team")
+            .grant("u1", "r1");
+        assert!(check_holder_limit(&role("r1", Some(1)), &after).is_ok());
+    }
+
+    #[test]
+    fn meets_prerequisites_through_inheritance() {
+        let r1 = role("r1", None);
+        let prerequisites = vec![role("base", None)];
+        let granted_ids = vec!["u1".to_string()];
+        let after = Holdings::default()
+            .grant("u1", "r1")
+            .grant("u1", "senior")
+            .inherit("senior", "base");
+        assert!(check_prerequisites(&r1, &prerequisites, &granted_ids, &after).is_ok());
+        let after = Holdings::default()
+            .org("team", None)
+            .grant_org("team", "base")
+            .join_org("u1", "team")
+            .grant("u1", "r1");
+        assert!(check_prerequisites(&r1, &prerequisites, &granted_ids, &after).is_ok());
+        let after = Holdings::default()
+            .grant("u1", "r1")
+            .grant("u1", "base")
+            .inherit("base", "senior")
+            .grant("u2", "base");
+        let granted_ids = vec!["u1".to_string(), "u3".to_string()];
+        assert!(matches!(
+            check_prerequisites(&r1, &prerequisites, &granted_ids, &after),
+            Err(ConstraintError::Violated(e)) if e.contains("u3")
+        ));
+    }
+}
@@ -0,0 +1,82 @@
+use crate::{
+    repository::{
+        dao::{EmailVerificationToken, User},
+        DBError, Dao, POOL,
+    },
+    util::{hash_token, mail, now, random_token, uuid_v4},
+};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use std::env;
+use validator::Validate;
+
+pub fn verify_email_ttl() -> Duration {
+    let secs = env::var("EMAIL_VERIFY_TTL")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(24 * 3600);
+    Duration::seconds(secs)
+}
+
+/// whether registering or changing an email mails a verification link right away
+pub fn is_verification_enabled() -> bool {
+    env::var("EMAIL_VERIFICATION").map(|v| v == "true").unwrap_or(false)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NewEmailVerification {
+    pub user_id: String,
+    pub email: String,
+}
+
+impl NewEmailVerification {
+    /// issues a single-use token for the current email, earlier tokens stop working
+    pub async fn create(&self, username: &str) -> Result<(EmailVerificationToken, mail::Mail), DBError> {
+        EmailVerificationToken::invalidate_by_user(&self.user_id).await?;
+        let token = random_token();
+        let ttl = verify_email_ttl();
+        let dao = EmailVerificationToken {
+            id: uuid_v4(),
+            user_id: self.user_id.clone(),
+            email: self.email.clone(),
+            token_hash: hash_token(&token),
+            is_used: 0,
+            expire: now() + ttl,
+            created_at: now(),
+        };
+        EmailVerificationToken::create_one(&dao).await?;
+        let mail = mail::Mail {
+            to: self.email.clone(),
+            subject: "验证邮箱".to_string(),
+            body: format!(
+                "{}，您好：\n\n请在 {} 小时内通过以下链接验证邮箱。\n\n{}\n",
+                username,
+                ttl.num_hours(),
+                mail::link("EMAIL_VERIFY_URL", &token)
+            ),
+        };
+        Ok((dao, mail))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct VerifyEmail {
+    #[validate(length(min = 1, max = 200))]
+    pub token: String,
+}
+
+impl VerifyEmail {
+    pub async fn save(&self, dao: &User) -> Result<User, DBError> {
+        let mut dao = dao.to_owned();
+        dao.email_verified_at = Some(now());
+        let w = POOL.new_wrapper().eq("id", &dao.id);
+        User::update_one(&dao, w).await?;
+        Ok(dao)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct ResendVerification {
+    #[validate(length(min = 1, max = 200))]
+    pub username_or_email: String,
+}
@@ -11,6 +11,16 @@ pub use traits::Dao;
 pub type DBPool = Rbatis;
 pub type DBError = Error;
 
+/// a write refused by a constraint checked in its transaction, described for the caller, or a
+/// failed query
+#[derive(Debug, thiserror::Error)]
+pub enum ConstraintError {
+    #[error("{0}")]
+    Violated(String),
+    #[error(transparent)]
+    DB(#[from] DBError),
+}
+
 async fn init_db() -> DBPool {
     let database_url =
         env::var("DATABASE_URL").expect("environment variable DATABASE_URL must be set");
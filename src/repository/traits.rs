@@ -17,7 +17,7 @@ pub trait Dao: Sized {
     where
         T: Serialize + Send + Sync;
     async fn create_one(&self) -> Result<i64, Error>;
-    async fn create_all(all: &Vec<Self>) -> Result<i64, Error>;
+    async fn create_all(all: &[Self]) -> Result<i64, Error>;
     async fn update_one(&self, w: Wrapper) -> Result<u64, Error>;
     async fn delete_one(w: Wrapper) -> Result<u64, Error>;
     async fn delete_by_id<T>(id: T) -> Result<u64, Error>
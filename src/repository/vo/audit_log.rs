@@ -0,0 +1,44 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::{util::serde_format::naive_datetime, repository::dao};
+use chrono::NaiveDateTime;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditLog{
+  pub id: String,
+  pub actor_id: String,
+  pub impersonator_id: Option<String>,
+  pub action: String,
+  pub target_type: String,
+  pub target_id: String,
+  pub domain_id: Option<String>,
+  pub before: Option<Value>,
+  pub after: Option<Value>,
+  pub request_id: String,
+  pub ip: String,
+  #[serde(serialize_with = "naive_datetime::serialize")]
+  pub created_at: NaiveDateTime,
+}
+
+fn parse(v: Option<String>) -> Option<Value> {
+  v.and_then(|v| serde_json::from_str(&v).ok())
+}
+
+impl From<dao::AuditLog> for AuditLog{
+  fn from(d: dao::AuditLog) -> Self {
+      Self{
+        id: d.id,
+        actor_id: d.actor_id,
+        impersonator_id: d.impersonator_id,
+        action: d.action,
+        target_type: d.target_type,
+        target_id: d.target_id,
+        domain_id: d.domain_id,
+        before: parse(d.before_json),
+        after: parse(d.after_json),
+        request_id: d.request_id,
+        ip: d.ip,
+        created_at: d.created_at
+      }
+  }
+}
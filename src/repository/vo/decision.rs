@@ -0,0 +1,42 @@
+use serde::{Serialize, Deserialize};
+
+/// the grant that settled a decision
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule{
+  /// `allow` or `deny`
+  pub effect: String,
+  /// value of the granted perm, a pattern like `order:*` may cover the asked one
+  pub perm: String,
+  /// value of the role holding the grant, for role grants
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub role: Option<String>,
+  /// pattern of the resource grant
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub resource: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub condition: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Decision{
+  pub perm: String,
+  pub allowed: bool,
+  pub roles: Vec<String>,
+  /// resource patterns granting the perm, when asked for a resource
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub resources: Vec<String>,
+  /// `null` when no grant applies and the perm is denied by default
+  pub rule: Option<Rule>,
+}
+
+impl Decision{
+  pub fn new(perm: &str) -> Self {
+      Self{
+        perm: perm.to_string(),
+        allowed: false,
+        roles: vec![],
+        resources: vec![],
+        rule: None,
+      }
+  }
+}
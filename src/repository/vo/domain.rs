@@ -7,6 +7,9 @@ use super::User;
 pub struct Domain{
   id: String,
   name: String,
+  allow_jit: bool,
+  email_verification: i32,
+  conflict_strategy: String,
   pub admin: Vec<User>,
   created_at: NaiveDateTime,
   updated_at: NaiveDateTime,
@@ -17,6 +20,9 @@ impl From<dao::Domain> for Domain{
       Self{
         id: d.id,
         name: d.name,
+        allow_jit: d.allow_jit == 1,
+        email_verification: d.email_verification,
+        conflict_strategy: d.conflict_strategy,
         admin: vec![],
         created_at: d.created_at,
         updated_at: d.updated_at
@@ -1,11 +1,21 @@
 mod role;
 mod perm;
 mod org;
+mod group;
 mod domain;
 mod user;
+mod decision;
+mod oauth_client;
+mod audit_log;
+mod role_exclusion;
 
-pub use role::Role;
+pub use role::{Role, RoleSource};
 pub use perm::Perm;
-pub use org::Org;
+pub use org::{Org, OrgNode};
+pub use group::Group;
 pub use domain::Domain;
-pub use user::User;
\ No newline at end of file
+pub use user::User;
+pub use decision::{Decision, Rule};
+pub use oauth_client::OAuthClient;
+pub use audit_log::AuditLog;
+pub use role_exclusion::{ExclusionViolation, RoleExclusion};
\ No newline at end of file
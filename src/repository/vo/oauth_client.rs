@@ -0,0 +1,42 @@
+use serde::{Serialize, Deserialize};
+use crate::{util::serde_format::naive_datetime, repository::dao};
+use chrono::NaiveDateTime;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthClient{
+  pub id: String,
+  pub name: String,
+  pub is_public: bool,
+  pub redirect_uris: Vec<String>,
+  pub grant_types: Vec<String>,
+  pub scopes: Vec<String>,
+  pub domain_id: String,
+  // only returned once, when the client is created
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub secret: Option<String>,
+  #[serde(serialize_with = "naive_datetime::serialize")]
+  pub created_at: NaiveDateTime,
+  #[serde(serialize_with = "naive_datetime::serialize")]
+  pub updated_at: NaiveDateTime,
+}
+
+fn split(v: &str) -> Vec<String> {
+  v.split(' ').filter(|v| !v.is_empty()).map(String::from).collect()
+}
+
+impl From<dao::OAuthClient> for OAuthClient{
+  fn from(d: dao::OAuthClient) -> Self {
+      Self{
+        is_public: d.is_public(),
+        id: d.id,
+        name: d.name,
+        redirect_uris: split(&d.redirect_uris),
+        grant_types: split(&d.grant_types),
+        scopes: split(&d.scopes),
+        domain_id: d.domain_id,
+        secret: None,
+        created_at: d.created_at,
+        updated_at: d.updated_at
+      }
+  }
+}
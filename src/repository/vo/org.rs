@@ -10,6 +10,7 @@ pub struct Org{
   pub description: Option<String>,
   pub domain_id: String,
   pub domain: Option<Domain>,
+  pub parent_id: Option<String>,
   #[serde(serialize_with = "i32_bool::serialize")]
   pub is_deleted: i32,
   #[serde(serialize_with = "naive_datetime::serialize")]
@@ -26,9 +27,33 @@ impl From<dao::Org> for Org{
         description: d.description,
         domain_id: d.domain_id,
         domain: None,
+        parent_id: d.parent_id,
         is_deleted: d.is_deleted,
         created_at: d.created_at,
         updated_at: d.updated_at
       }
   }
-}
\ No newline at end of file
+}
+
+/// an org with its descendants nested below
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrgNode{
+  #[serde(flatten)]
+  pub org: Org,
+  pub children: Vec<OrgNode>,
+}
+
+impl OrgNode{
+  /// builds the tree below `root` from the orgs of its domain
+  pub fn build(root: dao::Org, all: &[dao::Org]) -> Self {
+      let children = all
+        .iter()
+        .filter(|v| v.parent_id.as_deref() == Some(root.id.as_str()))
+        .map(|v| Self::build(v.clone(), all))
+        .collect();
+      Self{
+        org: root.into(),
+        children
+      }
+  }
+}
@@ -17,6 +17,12 @@ pub struct Perm{
   pub created_at: NaiveDateTime,
   #[serde(serialize_with = "naive_datetime::serialize")]
   pub updated_at: NaiveDateTime,
+  /// condition of the grant, when listed as perm of a role
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub condition: Option<String>,
+  /// effect of the grant, when listed as perm of a role
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub effect: Option<String>,
 }
 
 impl From<dao::Perm> for Perm{
@@ -30,7 +36,9 @@ impl From<dao::Perm> for Perm{
         value: d.value,
         is_deleted: d.is_deleted,
         created_at: d.created_at,
-        updated_at: d.updated_at
+        updated_at: d.updated_at,
+        condition: None,
+        effect: None
       }
   }
 }
\ No newline at end of file
@@ -12,12 +12,24 @@ pub struct Role{
   pub domain: Option<Domain>,
   pub value: String,
   pub level: i32,
+  pub max_holders: Option<i32>,
   #[serde(serialize_with = "i32_bool::serialize")]
   pub is_deleted: i32,
   #[serde(serialize_with = "naive_datetime::serialize")]
   pub created_at: NaiveDateTime,
   #[serde(serialize_with = "naive_datetime::serialize")]
   pub updated_at: NaiveDateTime,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub sources: Vec<RoleSource>,
+}
+
+/// how a user holds a role, both ids are empty for a direct grant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleSource{
+  pub org_id: Option<String>,
+  pub group_id: Option<String>,
+  #[serde(serialize_with = "naive_datetime::serialize")]
+  pub expire: NaiveDateTime,
 }
 
 impl From<dao::Role> for Role{
@@ -30,9 +42,11 @@ impl From<dao::Role> for Role{
         domain: None,
         value: d.value,
         level: d.level,
+        max_holders: d.max_holders,
         is_deleted: d.is_deleted,
         created_at: d.created_at,
-        updated_at: d.updated_at
+        updated_at: d.updated_at,
+        sources: vec![]
       }
   }
 }
\ No newline at end of file
@@ -0,0 +1,39 @@
+use serde::{Serialize, Deserialize};
+use crate::{util::serde_format::naive_datetime, repository::dao};
+use chrono::NaiveDateTime;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleExclusion{
+  pub id: String,
+  pub name: String,
+  pub kind: String,
+  pub domain_id: String,
+  pub role_ids: Vec<String>,
+  #[serde(serialize_with = "naive_datetime::serialize")]
+  pub created_at: NaiveDateTime,
+  pub created_by: Option<String>,
+}
+
+impl From<(dao::RoleExclusion, Vec<String>)> for RoleExclusion{
+  fn from((d, role_ids): (dao::RoleExclusion, Vec<String>)) -> Self {
+      Self{
+        id: d.id,
+        name: d.name,
+        kind: d.kind,
+        domain_id: d.domain_id,
+        role_ids,
+        created_at: d.created_at,
+        created_by: d.created_by
+      }
+  }
+}
+
+/// a user holding more than one role of a static exclusion
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExclusionViolation{
+  pub exclusion_id: String,
+  pub name: String,
+  pub user_id: String,
+  /// the roles of the set the user holds, directly or by inheritance
+  pub role_ids: Vec<String>,
+}
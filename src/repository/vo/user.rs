@@ -7,6 +7,7 @@ pub struct User{
   id: String,
   username: String,
   email: Option<String>,
+  email_verified: bool,
   created_at: NaiveDateTime,
   last_logined_at: NaiveDateTime,
 }
@@ -16,6 +17,7 @@ impl From<dao::User> for User{
       Self{
         id: d.id,
         username: d.username,
+        email_verified: d.is_email_verified(),
         email: d.email,
         created_at: d.created_at,
         last_logined_at: d.last_logined_at
@@ -0,0 +1,97 @@
+use super::prune_revocations;
+use crate::{
+    repository::{
+        dao::{GroupRole, OrgRole, ResourceGrant, UserGroup, UserOrg, UserRole},
+        DBError,
+    },
+    util::{lockout, now},
+};
+use std::{env, time::Duration};
+use tokio::time;
+
+pub async fn sweep_expired(purge: bool) -> Result<(), DBError> {
+    let at = now();
+    let user_roles = UserRole::find_expired(at).await?;
+    for v in user_roles.iter() {
+        tracing::info!(user_id = %v.user_id, role_id = %v.role_id, expire = %v.expire, "role grant expired");
+    }
+    let user_orgs = UserOrg::find_expired(at).await?;
+    for v in user_orgs.iter() {
+        tracing::info!(user_id = %v.user_id, org_id = %v.org_id, expire = %v.expire, "org membership expired");
+    }
+    let org_roles = OrgRole::find_expired(at).await?;
+    for v in org_roles.iter() {
+        tracing::info!(org_id = %v.org_id, role_id = %v.role_id, expire = %v.expire, "org role grant expired");
+    }
+    let user_groups = UserGroup::find_expired(at).await?;
+    for v in user_groups.iter() {
+        tracing::info!(user_id = %v.user_id, group_id = %v.group_id, expire = %v.expire, "group membership expired");
+    }
+    let group_roles = GroupRole::find_expired(at).await?;
+    for v in group_roles.iter() {
+        tracing::info!(group_id = %v.group_id, role_id = %v.role_id, expire = %v.expire, "group role grant expired");
+    }
+    let resource_grants = ResourceGrant::find_expired(at).await?;
+    for v in resource_grants.iter() {
+        tracing::info!(perm_id = %v.perm_id, resource = %v.resource, subject_id = %v.subject_id, expire = %v.expire, "resource grant expired");
+    }
+    if purge {
+        let roles = if !user_roles.is_empty() {
+            UserRole::delete_expired(at).await?
+        } else {
+            0
+        };
+        let orgs = if !user_orgs.is_empty() {
+            UserOrg::delete_expired(at).await?
+        } else {
+            0
+        };
+        let org_roles = if !org_roles.is_empty() {
+            OrgRole::delete_expired(at).await?
+        } else {
+            0
+        };
+        let groups = if !user_groups.is_empty() {
+            UserGroup::delete_expired(at).await?
+        } else {
+            0
+        };
+        let group_roles = if !group_roles.is_empty() {
+            GroupRole::delete_expired(at).await?
+        } else {
+            0
+        };
+        let resources = if !resource_grants.is_empty() {
+            ResourceGrant::delete_expired(at).await?
+        } else {
+            0
+        };
+        tracing::info!(roles, orgs, org_roles, groups, group_roles, resources, "expired grants purged");
+    }
+    Ok(())
+}
+
+pub fn spawn_expire_sweeper() {
+    let interval = env::var("EXPIRE_SWEEP_INTERVAL")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(3600)
+        // a zero period would panic the ticker
+        .max(1);
+    let purge = env::var("EXPIRE_SWEEP_PURGE")
+        .map(|v| v == "true")
+        .unwrap_or(false);
+    tokio::spawn(async move {
+        let mut ticker = time::interval(Duration::from_secs(interval));
+        loop {
+            ticker.tick().await;
+            if let Err(e) = sweep_expired(purge).await {
+                tracing::error!("sweep expired grants failed: {}", e);
+            }
+            if let Err(e) = prune_revocations().await {
+                tracing::error!("prune token revocations failed: {}", e);
+            }
+            lockout::prune(now());
+        }
+    });
+}
@@ -0,0 +1,40 @@
+use crate::{
+    repository::{dao::LoginLockout, DBError},
+    util::{lockout, now},
+};
+use std::{env, time::Duration};
+use tokio::time;
+
+/// merges the active locks into the cache and drops the lifted ones, locks made and lifted
+/// by other instances included
+pub async fn load_lockouts() -> Result<usize, DBError> {
+    let at = now();
+    for v in LoginLockout::find_lifted(at).await?.iter() {
+        lockout::release(&v.lock_key);
+    }
+    // a key locked again after a lift has an active row too
+    let rows = LoginLockout::find_active(at).await?;
+    for v in rows.iter() {
+        lockout::lock(&v.lock_key, v.locked_until);
+    }
+    Ok(rows.len())
+}
+
+/// reloads the locks every `LOCKOUT_SYNC_INTERVAL` seconds, 10 by default and 1 at least
+pub fn spawn_lockout_sync() {
+    let interval = env::var("LOCKOUT_SYNC_INTERVAL")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(10)
+        // a zero period would panic the ticker
+        .max(1);
+    tokio::spawn(async move {
+        let mut ticker = time::interval(Duration::from_secs(interval));
+        loop {
+            ticker.tick().await;
+            if let Err(e) = load_lockouts().await {
+                tracing::error!("sync login lockouts failed: {}", e);
+            }
+        }
+    });
+}
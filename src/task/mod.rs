@@ -0,0 +1,7 @@
+mod expire;
+mod revocation;
+mod lockout;
+
+pub use expire::{spawn_expire_sweeper, sweep_expired};
+pub use revocation::{load_revocations, prune_revocations, spawn_revocation_sync};
+pub use lockout::{load_lockouts, spawn_lockout_sync};
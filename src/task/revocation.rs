@@ -0,0 +1,52 @@
+use crate::{
+    repository::{
+        dao::{SessionRole, TokenRevocation},
+        DBError,
+    },
+    util::{jwt::max_token_ttl, now, revocation},
+};
+use chrono::Utc;
+use std::{env, time::Duration};
+use tokio::time;
+
+/// merges the active revocations into the cache, revocations made by other instances
+/// included
+pub async fn load_revocations() -> Result<usize, DBError> {
+    let rows = TokenRevocation::find_active(now()).await?;
+    for v in rows.iter() {
+        match (&v.jti, v.issued_until) {
+            (Some(jti), _) => revocation::revoke_token(jti, v.expire),
+            (None, Some(issued_until)) => revocation::revoke_user(&v.user_id, issued_until),
+            (None, None) => (),
+        }
+    }
+    Ok(rows.len())
+}
+
+/// reloads the revocations every `REVOCATION_SYNC_INTERVAL` seconds, 10 by default and 1 at least
+pub fn spawn_revocation_sync() {
+    let interval = env::var("REVOCATION_SYNC_INTERVAL")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(10)
+        // a zero period would panic the ticker
+        .max(1);
+    tokio::spawn(async move {
+        let mut ticker = time::interval(Duration::from_secs(interval));
+        loop {
+            ticker.tick().await;
+            if let Err(e) = load_revocations().await {
+                tracing::error!("sync token revocations failed: {}", e);
+            }
+        }
+    });
+}
+
+pub async fn prune_revocations() -> Result<u64, DBError> {
+    let at = now();
+    let issued_until = (Utc::now() - max_token_ttl()).timestamp_millis();
+    revocation::prune(at, issued_until);
+    // roles activated for tokens that are gone
+    SessionRole::delete_expired(at).await?;
+    TokenRevocation::delete_expired(at).await
+}
@@ -1,4 +1,4 @@
-use crate::repository::DBError;
+use crate::repository::{ConstraintError, DBError};
 use axum::{
     body::Body,
     http::{response::Response, StatusCode},
@@ -20,6 +20,17 @@ pub enum APIError {
     ParseInt(#[from] ParseIntError),
     Validate(#[from] ValidationErrors),
     DBError(#[from] DBError),
+    // error code and description of RFC 6749 section 5.2
+    OAuth(String, String),
+}
+
+impl From<ConstraintError> for APIError {
+    fn from(e: ConstraintError) -> Self {
+        match e {
+            ConstraintError::Violated(msg) => Self::Custom(msg),
+            ConstraintError::DB(e) => Self::DBError(e),
+        }
+    }
 }
 
 impl Serialize for APIError {
@@ -49,7 +60,7 @@ fn format_validator_errors(e: &ValidationErrors) -> HashMap<String, String> {
                 .iter()
                 .map(|e| match &e.message {
                     Some(msg) => msg.to_string(),
-                    None => format!("{} is invalid", e.code.to_string()),
+                    None => format!("{} is invalid", e.code),
                 })
                 .collect::<String>();
             (k.to_string(), errors)
@@ -62,9 +73,14 @@ impl IntoResponse for APIError {
     type Body = Body;
     type BodyError = <Self::Body as axum::body::HttpBody>::Error;
     fn into_response(self) -> Response<Body> {
-        let (code, message) = match self {
-            _ => (-2, json!(self)),
-        };
+        if let Self::OAuth(error, description) = &self {
+            let body = Body::from(json!({"error": error, "error_description": description}).to_string());
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(body)
+                .unwrap();
+        }
+        let (code, message) = (-2, json!(self));
         let body = Body::from(json!({"code": code, "message": message}).to_string());
         Response::builder()
             .status(StatusCode::OK)
@@ -0,0 +1,60 @@
+use super::{client_ip, jwt::Payload, uuid_v4, APIError};
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRequest, RequestParts},
+    http::HeaderMap,
+};
+use std::net::SocketAddr;
+
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// who performs a mutation, extracted on routes behind `Restrict`
+#[derive(Debug, Clone)]
+pub struct Actor {
+    pub id: String,
+    // admin acting through an impersonation token
+    pub impersonator_id: Option<String>,
+    pub request_id: String,
+    pub ip: String,
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Actor {
+    type Rejection = APIError;
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let payload = req
+            .extensions()
+            .and_then(|v| v.get::<Payload>())
+            .cloned()
+            .ok_or_else(|| APIError::Custom("未登录".to_string()))?;
+        let addr = req
+            .extensions()
+            .and_then(|v| v.get::<ConnectInfo<SocketAddr>>())
+            .map(|v| v.0)
+            .unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 0)));
+        let empty = HeaderMap::new();
+        let headers = req.headers().unwrap_or(&empty);
+        Ok(Self {
+            impersonator_id: payload.act,
+            ..Self::new(&payload.auth.id, headers, addr)
+        })
+    }
+}
+
+impl Actor {
+    /// the user `id` acting from `addr`, for routes where nobody is signed in yet
+    pub fn new(id: &str, headers: &HeaderMap, addr: SocketAddr) -> Self {
+        let request_id = headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty() && v.len() <= 100)
+            .map(String::from)
+            .unwrap_or_else(uuid_v4);
+        Self {
+            id: id.to_string(),
+            impersonator_id: None,
+            request_id,
+            ip: client_ip(headers, addr),
+        }
+    }
+}
@@ -0,0 +1,608 @@
+//! conditions on permission grants, small boolean expressions over a JSON context
+//!
+//! ```text
+//! resource.owner_id == subject.id && time_between(env.time, "09:00", "18:00")
+//! ip_in(env.ip, "10.0.0.0/8") || subject.level >= 3
+//! resource.status in ["draft", "review"] && !resource.locked
+//! ```
+//!
+//! Paths are looked up in the context, missing ones are `null`. Comparisons of
+//! different types are false, errors while evaluating deny the grant.
+use super::now;
+use serde_json::{json, Map, Value};
+use std::net::IpAddr;
+use validator::ValidationError;
+
+pub const MAX_LENGTH: usize = 1000;
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Op(&'static str),
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    const OPS: [&str; 16] = [
+        "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")", "[", "]", ",", ".", "-",
+    ];
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_digit()
+                    || (chars[i] == '.' && chars.get(i + 1).map_or(false, |v| v.is_ascii_digit())))
+            {
+                i += 1;
+            }
+            let s: String = chars[start..i].iter().collect();
+            let n = s.parse::<f64>().map_err(|_| format!("无效的数字 {}", s))?;
+            tokens.push(Token::Num(n));
+        } else if c == '"' || c == '\'' {
+            let mut s = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("字符串未结束".to_string()),
+                    Some('\\') => {
+                        s.extend(chars.get(i + 1));
+                        i += 2;
+                    }
+                    Some(v) if *v == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(v) => {
+                        s.push(*v);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(Token::Str(s));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let op = OPS
+                .iter()
+                .copied()
+                .find(|v| rest.starts_with(v))
+                .ok_or_else(|| format!("无法识别的字符 {}", c))?;
+            tokens.push(Token::Op(op));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Lit(Value),
+    Path(Vec<String>),
+    List(Vec<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Cmp(&'static str, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+    fn eat(&mut self, op: &str) -> bool {
+        if self.is_op(op) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+    fn is_op(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(Token::Op(v)) if *v == op)
+    }
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.is_op(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("缺少 {}", op))
+        }
+    }
+    fn enter(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("表达式嵌套过深".to_string());
+        }
+        Ok(())
+    }
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.is_op("||") {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.cmp()?;
+        while self.is_op("&&") {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.cmp()?));
+        }
+        Ok(left)
+    }
+    fn cmp(&mut self) -> Result<Expr, String> {
+        let left = self.unary()?;
+        for op in ["==", "!=", "<=", ">=", "<", ">"] {
+            if self.is_op(op) {
+                self.pos += 1;
+                return Ok(Expr::Cmp(op, Box::new(left), Box::new(self.unary()?)));
+            }
+        }
+        if self.peek() == Some(&Token::Ident("in".to_string())) {
+            self.pos += 1;
+            return Ok(Expr::Cmp("in", Box::new(left), Box::new(self.unary()?)));
+        }
+        Ok(left)
+    }
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.enter()?;
+        let expr = if self.is_op("!") {
+            self.pos += 1;
+            Expr::Not(Box::new(self.unary()?))
+        } else if self.is_op("-") {
+            self.pos += 1;
+            Expr::Neg(Box::new(self.unary()?))
+        } else {
+            self.primary()?
+        };
+        self.depth -= 1;
+        Ok(expr)
+    }
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(Expr::Lit(json!(n))),
+            Some(Token::Str(s)) => Ok(Expr::Lit(Value::String(s))),
+            Some(Token::Op("(")) => {
+                let expr = self.or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Op("[")) => {
+                let mut items = vec![];
+                if !self.is_op("]") {
+                    loop {
+                        items.push(self.or()?);
+                        if !self.is_op(",") {
+                            break;
+                        }
+                        self.pos += 1;
+                    }
+                }
+                self.expect("]")?;
+                Ok(Expr::List(items))
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Lit(Value::Bool(true))),
+                "false" => Ok(Expr::Lit(Value::Bool(false))),
+                "null" => Ok(Expr::Lit(Value::Null)),
+                _ if self.is_op("(") => {
+                    self.pos += 1;
+                    let mut args = vec![];
+                    if !self.is_op(")") {
+                        loop {
+                            args.push(self.or()?);
+                            if !self.is_op(",") {
+                                break;
+                            }
+                            self.pos += 1;
+                        }
+                    }
+                    self.expect(")")?;
+                    check_call(&name, args.len())?;
+                    Ok(Expr::Call(name, args))
+                }
+                _ => {
+                    let mut path = vec![name];
+                    while self.eat(".") {
+                        match self.next() {
+                            Some(Token::Ident(v)) => path.push(v),
+                            Some(Token::Num(n)) if n.fract() == 0.0 => path.push(n.to_string()),
+                            _ => return Err("属性名无效".to_string()),
+                        }
+                    }
+                    Ok(Expr::Path(path))
+                }
+            },
+            Some(token) => Err(format!("意外的 {:?}", token)),
+            None => Err("表达式不完整".to_string()),
+        }
+    }
+}
+
+fn check_call(name: &str, argc: usize) -> Result<(), String> {
+    let expected = match name {
+        "ip_in" => 2,
+        "time_between" => 3,
+        "starts_with" | "ends_with" | "contains" => 2,
+        "len" | "lower" => 1,
+        _ => return Err(format!("未知函数 {}", name)),
+    };
+    if argc != expected {
+        return Err(format!("函数 {} 需要 {} 个参数", name, expected));
+    }
+    Ok(())
+}
+
+fn parse(src: &str) -> Result<Expr, String> {
+    if src.len() > MAX_LENGTH {
+        return Err(format!("条件不能超过 {} 个字符", MAX_LENGTH));
+    }
+    let mut parser = Parser {
+        tokens: tokenize(src)?,
+        pos: 0,
+        depth: 0,
+    };
+    let expr = parser.or()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(format!("意外的 {:?}", parser.tokens[parser.pos]));
+    }
+    Ok(expr)
+}
+
+/// checks the syntax of a condition, for validating grants
+pub fn check(src: &str) -> Result<(), String> {
+    parse(src).map(|_| ())
+}
+
+fn truthy(v: &Value) -> bool {
+    match v {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().map(|v| v != 0.0).unwrap_or(false),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+    }
+}
+
+fn equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64() == y.as_f64(),
+        _ => a == b,
+    }
+}
+
+fn ip_in(ip: &str, cidr: &str) -> bool {
+    let (net, bits) = match cidr.split_once('/') {
+        Some((net, bits)) => (net, bits.parse::<u32>().ok()),
+        None => (cidr, None),
+    };
+    match (ip.parse::<IpAddr>(), net.parse::<IpAddr>()) {
+        (Ok(IpAddr::V4(ip)), Ok(IpAddr::V4(net))) => {
+            let bits = bits.unwrap_or(32).min(32);
+            let mask = u32::MAX.checked_shl(32 - bits).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(net) & mask
+        }
+        (Ok(IpAddr::V6(ip)), Ok(IpAddr::V6(net))) => {
+            let bits = bits.unwrap_or(128).min(128);
+            let mask = u128::MAX.checked_shl(128 - bits).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
+/// `HH:MM[:SS]` within `from..to`, windows past midnight wrap around
+fn time_between(time: &str, from: &str, to: &str) -> bool {
+    let pad = |v: &str| if v.len() == 5 { format!("{}:00", v) } else { v.to_string() };
+    let (time, from, to) = (pad(time), pad(from), pad(to));
+    if from <= to {
+        from <= time && time < to
+    } else {
+        time >= from || time < to
+    }
+}
+
+fn call(name: &str, args: &[Value]) -> Result<Value, String> {
+    let text = |i: usize| -> Result<&str, String> {
+        args[i]
+            .as_str()
+            .ok_or_else(|| format!("函数 {} 的参数应为字符串", name))
+    };
+    let value = match name {
+        "ip_in" => {
+            let ip = text(0)?;
+            match &args[1] {
+                Value::Array(v) => v.iter().filter_map(|v| v.as_str()).any(|v| ip_in(ip, v)),
+                _ => ip_in(ip, text(1)?),
+            }
+            .into()
+        }
+        "time_between" => time_between(text(0)?, text(1)?, text(2)?).into(),
+        "starts_with" => text(0)?.starts_with(text(1)?).into(),
+        "ends_with" => text(0)?.ends_with(text(1)?).into(),
+        "contains" => match &args[0] {
+            Value::Array(v) => v.iter().any(|v| equals(v, &args[1])).into(),
+            _ => text(0)?.contains(text(1)?).into(),
+        },
+        "len" => match &args[0] {
+            Value::Array(v) => json!(v.len()),
+            Value::Object(v) => json!(v.len()),
+            _ => json!(text(0)?.chars().count()),
+        },
+        "lower" => Value::String(text(0)?.to_lowercase()),
+        _ => return Err(format!("未知函数 {}", name)),
+    };
+    Ok(value)
+}
+
+fn eval(expr: &Expr, ctx: &Value) -> Result<Value, String> {
+    let value = match expr {
+        Expr::Lit(v) => v.clone(),
+        Expr::Path(path) => {
+            let mut v = ctx;
+            for key in path {
+                v = match v {
+                    Value::Object(o) => o.get(key).unwrap_or(&Value::Null),
+                    Value::Array(a) => key.parse::<usize>().ok().and_then(|i| a.get(i)).unwrap_or(&Value::Null),
+                    _ => &Value::Null,
+                };
+            }
+            v.clone()
+        }
+        Expr::List(items) => Value::Array(
+            items
+                .iter()
+                .map(|v| eval(v, ctx))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        Expr::Not(v) => Value::Bool(!truthy(&eval(v, ctx)?)),
+        Expr::Neg(v) => match eval(v, ctx)?.as_f64() {
+            Some(n) => json!(-n),
+            None => return Err("只能对数字取负".to_string()),
+        },
+        Expr::And(a, b) => Value::Bool(truthy(&eval(a, ctx)?) && truthy(&eval(b, ctx)?)),
+        Expr::Or(a, b) => Value::Bool(truthy(&eval(a, ctx)?) || truthy(&eval(b, ctx)?)),
+        Expr::Cmp(op, a, b) => {
+            let (a, b) = (eval(a, ctx)?, eval(b, ctx)?);
+            let ordering = match (&a, &b) {
+                (Value::Number(x), Value::Number(y)) => x.as_f64().partial_cmp(&y.as_f64()),
+                (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+                _ => None,
+            };
+            let ordered = || ordering.ok_or_else(|| format!("{} 只能比较同类型的数字或字符串", op));
+            let result = match *op {
+                "==" => equals(&a, &b),
+                "!=" => !equals(&a, &b),
+                "<" => ordered()?.is_lt(),
+                "<=" => ordered()?.is_le(),
+                ">" => ordered()?.is_gt(),
+                ">=" => ordered()?.is_ge(),
+                "in" => match (&a, &b) {
+                    (_, Value::Array(items)) => items.iter().any(|v| equals(&a, v)),
+                    (Value::String(x), Value::String(y)) => y.contains(x.as_str()),
+                    (Value::String(x), Value::Object(o)) => o.contains_key(x),
+                    _ => false,
+                },
+                _ => return Err(format!("未知运算符 {}", op)),
+            };
+            Value::Bool(result)
+        }
+        Expr::Call(name, args) => {
+            let args = args
+                .iter()
+                .map(|v| eval(v, ctx))
+                .collect::<Result<Vec<_>, _>>()?;
+            call(name, &args)?
+        }
+    };
+    Ok(value)
+}
+
+/// whether the condition holds in `ctx`, `Err` when it is invalid or fails to evaluate, like
+/// ordering values of different types; the caller decides what a failure means
+pub fn evaluate(src: &str, ctx: &Value) -> Result<bool, String> {
+    parse(src)
+        .and_then(|expr| eval(&expr, ctx))
+        .map(|v| truthy(&v))
+}
+
+/// the context conditions see: the caller's attributes, with `subject` and the server's `env`
+/// values filled in; the caller may add to `env` but not replace what the server sets
+pub fn context(attrs: Option<Value>, subject: Value, ip: &str) -> Value {
+    let mut ctx = match attrs {
+        Some(Value::Object(v)) => v,
+        _ => Map::new(),
+    };
+    let at = now();
+    let server = json!({
+        "ip": ip,
+        "time": at.format("%H:%M:%S").to_string(),
+        "date": at.format("%Y-%m-%d").to_string(),
+        "weekday": at.format("%u").to_string().parse::<u8>().unwrap_or_default(),
+    });
+    let mut env = match ctx.remove("env") {
+        Some(Value::Object(given)) => given,
+        _ => Map::new(),
+    };
+    if let Value::Object(server) = server {
+        env.extend(server);
+    }
+    ctx.insert("env".to_string(), Value::Object(env));
+    ctx.insert("subject".to_string(), subject);
+    Value::Object(ctx)
+}
+
+pub fn validate_condition(v: &str) -> Result<(), ValidationError> {
+    check(v).map_err(|msg| {
+        let mut e = ValidationError::new("condition");
+        e.message = Some(format!("条件无效: {}", msg).into());
+        e
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx() -> Value {
+        json!({
+            "subject": {"id": "u1", "level": 3, "tags": ["ops", "eng"]},
+            "resource": {"owner_id": "u1", "status": "draft", "locked": false, "size": 2.5},
+            "env": {"ip": "10.1.2.3", "time": "12:30:00"}
+        })
+    }
+
+    fn holds(src: &str) -> bool {
+        evaluate(src, &ctx()) == Ok(true)
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        assert!(check("resource.owner_id == subject.id").is_ok());
+        assert!(check("").is_err());
+        assert!(check("a ==").is_err());
+        assert!(check("(a == 1").is_err());
+        assert!(check("a == 1)").is_err());
+        assert!(check("'unterminated").is_err());
+        assert!(check("a # b").is_err());
+        assert!(check("a.").is_err());
+        assert!(check("unknown(a)").is_err());
+        assert!(check("ip_in(env.ip)").is_err());
+        assert!(check("[1, 2").is_err());
+    }
+
+    #[test]
+    fn limits_length_and_depth() {
+        let long = format!("a == '{}'", "x".repeat(MAX_LENGTH));
+        assert!(check(&long).is_err());
+        let nested = format!("{}true{}", "(".repeat(MAX_DEPTH + 1), ")".repeat(MAX_DEPTH + 1));
+        assert!(check(&nested).is_err());
+        let nots = format!("{}true", "!".repeat(MAX_DEPTH + 1));
+        assert!(check(&nots).is_err());
+        let shallow = format!("{}true{}", "(".repeat(8), ")".repeat(8));
+        assert!(check(&shallow).is_ok());
+    }
+
+    #[test]
+    fn compares_values() {
+        assert!(holds("resource.owner_id == subject.id"));
+        assert!(holds("subject.level >= 3 && subject.level < 4"));
+        assert!(holds("resource.size > 2 && resource.size <= 2.5"));
+        assert!(holds("subject.level == 3.0"));
+        assert!(holds("resource.status != 'review'"));
+        assert!(holds("'abc' < 'abd'"));
+        assert!(holds("-subject.level < 0"));
+    }
+
+    #[test]
+    fn different_types_never_compare() {
+        assert!(!holds("subject.level == '3'"));
+        assert!(holds("subject.level != '3'"));
+        assert!(evaluate("subject.level < '4'", &ctx()).is_err());
+        assert!(evaluate("resource.missing > 2", &ctx()).is_err());
+    }
+
+    #[test]
+    fn missing_paths_are_null() {
+        assert!(holds("resource.missing == null"));
+        assert!(!holds("resource.missing"));
+        assert!(!holds("resource.missing.deeper == 1"));
+        assert!(holds("subject.tags.1 == 'eng'"));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert!(holds("true || false && false"));
+        assert!(!holds("(true || false) && false"));
+        assert!(holds("!resource.locked && !false"));
+    }
+
+    #[test]
+    fn in_checks_lists_strings_and_objects() {
+        assert!(holds("resource.status in ['draft', 'review']"));
+        assert!(!holds("resource.status in ['published']"));
+        assert!(holds("'ops' in subject.tags"));
+        assert!(holds("'raf' in resource.status"));
+        assert!(holds("'owner_id' in resource"));
+        assert!(!holds("3 in resource.status"));
+    }
+
+    #[test]
+    fn calls_functions() {
+        assert!(holds("ip_in(env.ip, '10.0.0.0/8')"));
+        assert!(!holds("ip_in(env.ip, '192.168.0.0/16')"));
+        assert!(holds("ip_in(env.ip, ['192.168.0.0/16', '10.1.2.3'])"));
+        assert!(holds("time_between(env.time, '09:00', '18:00')"));
+        assert!(!holds("time_between(env.time, '13:00', '18:00')"));
+        assert!(holds("starts_with(resource.status, 'dr') && ends_with(resource.status, 'ft')"));
+        assert!(holds("contains(subject.tags, 'eng') && contains(resource.status, 'raf')"));
+        assert!(holds("len(subject.tags) == 2 && len('文档') == 2"));
+        assert!(holds("lower('DRAFT') == resource.status"));
+    }
+
+    #[test]
+    fn reports_errors_while_evaluating() {
+        assert!(evaluate("lower(subject.level) == '3'", &ctx()).is_err());
+        assert!(evaluate("-resource.status == 1", &ctx()).is_err());
+        assert!(evaluate("not a condition (", &ctx()).is_err());
+        assert_eq!(evaluate("resource.missing == null", &ctx()), Ok(true));
+    }
+
+    #[test]
+    fn ip_in_handles_v4_and_v6() {
+        assert!(ip_in("10.1.2.3", "10.1.2.3"));
+        assert!(ip_in("10.1.2.3", "0.0.0.0/0"));
+        assert!(!ip_in("10.1.2.3", "10.1.2.4/32"));
+        assert!(ip_in("2001:db8::1", "2001:db8::/32"));
+        assert!(!ip_in("2001:db9::1", "2001:db8::/32"));
+        assert!(!ip_in("10.1.2.3", "2001:db8::/32"));
+        assert!(!ip_in("not an ip", "10.0.0.0/8"));
+    }
+
+    #[test]
+    fn time_windows_wrap_past_midnight() {
+        assert!(time_between("23:30", "22:00", "06:00"));
+        assert!(time_between("05:59:59", "22:00", "06:00"));
+        assert!(!time_between("06:00", "22:00", "06:00"));
+        assert!(time_between("09:00", "09:00", "18:00"));
+        assert!(!time_between("18:00", "09:00", "18:00"));
+    }
+
+    #[test]
+    fn context_keeps_server_env() {
+        let given = json!({"env": {"ip": "10.0.0.1", "time": "25:00:00", "region": "eu"}});
+        let ctx = context(Some(given), json!({"id": "u1"}), "1.2.3.4");
+        assert_eq!(ctx["env"]["ip"], "1.2.3.4");
+        assert_ne!(ctx["env"]["time"], "25:00:00");
+        assert!(ctx["env"]["date"].is_string());
+        assert_eq!(ctx["env"]["region"], "eu");
+        assert_eq!(ctx["subject"]["id"], "u1");
+    }
+}
@@ -0,0 +1,218 @@
+use hyper::{
+    body::HttpBody,
+    client::{connect::dns::Name, HttpConnector},
+    Body, Client, Uri,
+};
+use hyper_rustls::HttpsConnector;
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use serde::Deserialize;
+use rustls::ClientConfig;
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+use tokio::{fs, net::lookup_host, time::timeout};
+use url::{Host, Url};
+
+const JWKS_CACHE_TTL: Duration = Duration::from_secs(600);
+const JWKS_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const JWKS_MAX_SIZE: usize = 1024 * 1024;
+
+/// claims azman reads from an upstream identity token
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExternalClaims {
+    pub iss: String,
+    pub sub: String,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub preferred_username: Option<String>,
+    pub picture: Option<String>,
+}
+
+pub enum KeySource<'a> {
+    // https url of a JWK set
+    Jwks(&'a str),
+    // JWK set file on the server, configured by sys admins
+    JwksFile(&'a str),
+    // PEM public key of a trusted service
+    PublicKey(&'a str),
+}
+
+lazy_static! {
+    static ref JWKS_CACHE: RwLock<HashMap<String, (Instant, JwkSet)>> = RwLock::new(HashMap::new());
+}
+
+/// whether the address is reachable from the internet, private, loopback and link local
+/// ranges are not
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                // 100.64.0.0/10, shared address space
+                || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_public_ip(IpAddr::V4(v4)),
+            // unique local fc00::/7 and link local fe80::/10
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.segments()[0] & 0xfe00 == 0xfc00
+                    || ip.segments()[0] & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// the addresses the url's host resolves to now, rejected when any is not public; a name
+/// checked when the provider was registered may point elsewhere since
+async fn check_resolved(location: &str) -> Result<Vec<SocketAddr>, String> {
+    let invalid = || format!("JWKS 地址 {} 不合法", location);
+    let url = Url::parse(location).map_err(|_| invalid())?;
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs: Vec<SocketAddr> = match url.host() {
+        Some(Host::Domain(domain)) => lookup_host((domain, port))
+            .await
+            .map_err(|e| e.to_string())?
+            .collect(),
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(IpAddr::V4(ip), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(IpAddr::V6(ip), port)],
+        None => vec![],
+    };
+    if addrs.is_empty() || !addrs.iter().all(|v| is_public_ip(v.ip())) {
+        return Err(invalid());
+    }
+    Ok(addrs)
+}
+
+/// GETs the url over https from one of `addrs` only, the host is not resolved again so it
+/// can't be pointed elsewhere after `check_resolved`; bodies over `JWKS_MAX_SIZE` are refused
+async fn fetch_pinned(uri: Uri, addrs: Vec<SocketAddr>) -> Result<Vec<u8>, String> {
+    let resolver = tower::service_fn(move |_: Name| {
+        let addrs = addrs.clone();
+        async move { Ok::<_, io::Error>(addrs.into_iter()) }
+    });
+    let mut http = HttpConnector::new_with_resolver(resolver);
+    http.enforce_http(false);
+    let mut tls = ClientConfig::new();
+    tls.root_store.add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    let client = Client::builder().build::<_, Body>(HttpsConnector::from((http, tls)));
+    let res = client.get(uri).await.map_err(|e| e.to_string())?;
+    if !res.status().is_success() {
+        return Err(format!("获取 JWKS 失败: {}", res.status()));
+    }
+    let mut body = res.into_body();
+    let mut bytes = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| e.to_string())?;
+        if bytes.len() + chunk.len() > JWKS_MAX_SIZE {
+            return Err("JWKS 过大".to_string());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+async fn fetch_jwks(location: &str, is_file: bool, refresh: bool) -> Result<JwkSet, String> {
+    if !refresh {
+        let cached = JWKS_CACHE.read().unwrap().get(location).cloned();
+        if let Some((fetched_at, jwks)) = cached {
+            if fetched_at.elapsed() < JWKS_CACHE_TTL {
+                return Ok(jwks);
+            }
+        }
+    }
+    let bytes = if is_file {
+        fs::read(location).await.map_err(|e| e.to_string())?
+    } else {
+        // providers registered before urls were checked may still hold a path or plain http
+        if !location.starts_with("https://") {
+            return Err(format!("JWKS 地址 {} 不合法", location));
+        }
+        let uri: Uri = location.parse().map_err(|_| format!("JWKS 地址 {} 不合法", location))?;
+        let fetch = async {
+            let addrs = check_resolved(location).await?;
+            fetch_pinned(uri, addrs).await
+        };
+        timeout(JWKS_FETCH_TIMEOUT, fetch)
+            .await
+            .map_err(|_| "获取 JWKS 超时".to_string())??
+    };
+    let jwks: JwkSet = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+    JWKS_CACHE
+        .write()
+        .unwrap()
+        .insert(location.to_string(), (Instant::now(), jwks.clone()));
+    Ok(jwks)
+}
+
+fn find_jwk(jwks: &JwkSet, kid: Option<&str>) -> Option<Jwk> {
+    match kid {
+        Some(kid) => jwks.find(kid).cloned(),
+        None if jwks.keys.len() == 1 => jwks.keys.first().cloned(),
+        None => None,
+    }
+}
+
+fn key_from_pem(alg: Algorithm, pem: &str) -> Result<DecodingKey, String> {
+    let key = match alg {
+        Algorithm::RS256
+        | Algorithm::RS384
+        | Algorithm::RS512
+        | Algorithm::PS256
+        | Algorithm::PS384
+        | Algorithm::PS512 => DecodingKey::from_rsa_pem(pem.as_bytes()),
+        Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(pem.as_bytes()),
+        Algorithm::EdDSA => DecodingKey::from_ed_pem(pem.as_bytes()),
+        _ => return Err("不支持的签名算法".to_string()),
+    };
+    key.map_err(|e| e.to_string())
+}
+
+/// verifies signature, `iss`, `aud` and `exp` of an upstream identity token
+pub async fn verify_token(
+    token: &str,
+    issuer: &str,
+    audience: &str,
+    keys: KeySource<'_>,
+) -> Result<ExternalClaims, String> {
+    let header = decode_header(token).map_err(|e| e.to_string())?;
+    if matches!(
+        header.alg,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        return Err("不支持对称签名的令牌".to_string());
+    }
+    let is_file = matches!(keys, KeySource::JwksFile(_));
+    let key = match keys {
+        KeySource::Jwks(location) | KeySource::JwksFile(location) => {
+            let kid = header.kid.as_deref();
+            let jwk = match find_jwk(&fetch_jwks(location, is_file, false).await?, kid) {
+                Some(jwk) => jwk,
+                // the issuer may have rotated its keys since the last fetch
+                None => find_jwk(&fetch_jwks(location, is_file, true).await?, kid)
+                    .ok_or_else(|| "未找到签名公钥".to_string())?,
+            };
+            DecodingKey::from_jwk(&jwk).map_err(|e| e.to_string())?
+        }
+        KeySource::PublicKey(pem) => key_from_pem(header.alg, pem)?,
+    };
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[issuer]);
+    validation.set_audience(&[audience]);
+    decode::<ExternalClaims>(token, &key, &validation)
+        .map(|v| v.claims)
+        .map_err(|e| format!("{:?}", e.kind()))
+}
@@ -1,10 +1,30 @@
-use crate::util::serde_format::utc_datetime;
+use crate::util::{
+    serde_format::{utc_datetime, utc_datetime_millis},
+    uuid_v4,
+};
 use chrono::{DateTime, Duration, Timelike, Utc};
 use jsonwebtoken::{
-    decode, encode, errors::Result, DecodingKey, EncodingKey, Header, TokenData, Validation,
+    decode, decode_header, encode,
+    errors::{ErrorKind, Result},
+    Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
 };
-use serde::{Deserialize, Serialize};
-use std::env;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use simple_asn1::{from_der, oid, ASN1Block};
+use std::{collections::HashMap, env, fs};
+
+/// kind of a token, checked on every verification so that one kind never passes for another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    Access,
+    MfaChallenge,
+    Id,
+    Client,
+    // issued to an OAuth client on behalf of a user, never accepted by the management API
+    #[serde(rename = "oauth")]
+    OAuth,
+}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Auth {
@@ -13,47 +33,351 @@ pub struct Auth {
     pub is_admin: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Payload {
-    #[serde(with = "utc_datetime")]
+    pub iss: String,
+    pub aud: String,
+    pub typ: TokenType,
+    // to the millisecond, so that revoking a user's tokens spares a login right after
+    #[serde(with = "utc_datetime_millis")]
     pub iat: DateTime<Utc>,
     #[serde(with = "utc_datetime")]
     pub exp: DateTime<Utc>,
+    pub jti: String,
     pub auth: Auth,
+    // id of the admin acting as `auth`, RFC 8693 actor claim
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<String>,
 }
 
 impl Payload {
     pub fn new(auth: Auth, iat: DateTime<Utc>, exp: DateTime<Utc>) -> Self {
         let iat = iat
             .date()
-            .and_hms_milli(iat.hour(), iat.minute(), iat.second(), 0);
+            .and_hms_milli(iat.hour(), iat.minute(), iat.second(), iat.timestamp_subsec_millis());
         let exp = exp
             .date()
             .and_hms_milli(exp.hour(), exp.minute(), exp.second(), 0);
-        Self { auth, iat, exp }
+        Self {
+            iss: issuer(),
+            aud: audience(),
+            typ: TokenType::Access,
+            auth,
+            iat,
+            exp,
+            jti: uuid_v4(),
+            act: None,
+        }
     }
 }
 
+/// proves the password step of a login with a second factor pending
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Challenge {
+    pub iss: String,
+    pub aud: String,
+    pub typ: TokenType,
+    #[serde(with = "utc_datetime")]
+    pub iat: DateTime<Utc>,
+    #[serde(with = "utc_datetime")]
+    pub exp: DateTime<Utc>,
+    pub jti: String,
+    pub mfa_user_id: String,
+}
+
+struct VerifyingKey {
+    alg: Algorithm,
+    key: DecodingKey,
+    jwk: Option<Value>,
+}
+
+struct KeyStore {
+    header: Header,
+    signing_key: EncodingKey,
+    verifying_keys: HashMap<String, VerifyingKey>,
+    // verifies tokens without `kid`, only set for HS256
+    secret: Option<DecodingKey>,
+}
+
+lazy_static! {
+    static ref KEYS: KeyStore = KeyStore::from_env();
+}
+
+fn read_pem(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|_| panic!("read key file {} failed", path))
+}
+
+fn b64(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn unsigned_bytes(v: &ASN1Block) -> Option<Vec<u8>> {
+    match v {
+        ASN1Block::Integer(_, n) => Some(n.to_biguint()?.to_bytes_be()),
+        _ => None,
+    }
+}
+
+/// builds the public JWK of a `PUBLIC KEY` pem, the algorithm is taken from the key type
+fn parse_public_key(kid: &str, pem_bytes: &[u8]) -> Option<VerifyingKey> {
+    let der = pem::parse(pem_bytes).ok()?.contents;
+    let (key_type, curve, bits) = match from_der(&der).ok()?.first()? {
+        ASN1Block::Sequence(_, v) => match (v.first()?, v.get(1)?) {
+            (ASN1Block::Sequence(_, alg), ASN1Block::BitString(_, _, bits)) => {
+                (alg.first()?.clone(), alg.get(1).cloned(), bits.clone())
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let key_type = match key_type {
+        ASN1Block::ObjectIdentifier(_, oid) => oid,
+        _ => return None,
+    };
+    if key_type == oid!(1, 2, 840, 113549, 1, 1, 1) {
+        let (n, e) = match from_der(&bits).ok()?.first()? {
+            ASN1Block::Sequence(_, v) => (unsigned_bytes(v.first()?)?, unsigned_bytes(v.get(1)?)?),
+            _ => return None,
+        };
+        return Some(VerifyingKey {
+            alg: Algorithm::RS256,
+            key: DecodingKey::from_rsa_raw_components(&n, &e),
+            jwk: Some(json!({
+              "kty": "RSA", "use": "sig", "alg": "RS256", "kid": kid, "n": b64(&n), "e": b64(&e)
+            })),
+        });
+    }
+    if key_type == oid!(1, 2, 840, 10045, 2, 1) {
+        match curve {
+            Some(ASN1Block::ObjectIdentifier(_, curve)) if curve == oid!(1, 2, 840, 10045, 3, 1, 7) => (),
+            _ => return None,
+        };
+        // uncompressed point: 0x04 || x || y
+        if bits.len() != 65 || bits[0] != 4 {
+            return None;
+        }
+        return Some(VerifyingKey {
+            alg: Algorithm::ES256,
+            key: DecodingKey::from_ec_der(&bits),
+            jwk: Some(json!({
+              "kty": "EC", "use": "sig", "alg": "ES256", "kid": kid, "crv": "P-256",
+              "x": b64(&bits[1..33]), "y": b64(&bits[33..])
+            })),
+        });
+    }
+    if key_type == oid!(1, 3, 101, 112) {
+        return Some(VerifyingKey {
+            alg: Algorithm::EdDSA,
+            key: DecodingKey::from_ed_der(&bits),
+            jwk: Some(json!({
+              "kty": "OKP", "use": "sig", "alg": "EdDSA", "kid": kid, "crv": "Ed25519", "x": b64(&bits)
+            })),
+        });
+    }
+    None
+}
+
+impl KeyStore {
+    fn from_env() -> Self {
+        let alg = env::var("JWT_ALG").unwrap_or_else(|_| "HS256".to_string());
+        let kid = env::var("JWT_KID").ok().filter(|v| !v.is_empty());
+        let mut verifying_keys = HashMap::new();
+        if let Ok(public_keys) = env::var("JWT_PUBLIC_KEYS") {
+            for item in public_keys.split(',').filter(|v| !v.trim().is_empty()) {
+                let (kid, path) = item
+                    .trim()
+                    .split_once('=')
+                    .expect("environment variable JWT_PUBLIC_KEYS must be kid=path list");
+                let key = parse_public_key(kid, &read_pem(path))
+                    .unwrap_or_else(|| panic!("unsupported public key {}", path));
+                verifying_keys.insert(kid.to_string(), key);
+            }
+        }
+        if alg == "HS256" {
+            let jwt_key = env::var("JWT_KEY").expect("environment variable JWT_KEY must be set");
+            let mut header = Header::new(Algorithm::HS256);
+            header.kid = kid.clone();
+            if let Some(kid) = kid {
+                verifying_keys.insert(
+                    kid,
+                    VerifyingKey {
+                        alg: Algorithm::HS256,
+                        key: DecodingKey::from_secret(jwt_key.as_ref()),
+                        jwk: None,
+                    },
+                );
+            }
+            return Self {
+                header,
+                signing_key: EncodingKey::from_secret(jwt_key.as_ref()),
+                verifying_keys,
+                secret: Some(DecodingKey::from_secret(jwt_key.as_ref())),
+            };
+        }
+        let kid = kid.expect("environment variable JWT_KID must be set");
+        let private_key = read_pem(
+            &env::var("JWT_PRIVATE_KEY").expect("environment variable JWT_PRIVATE_KEY must be set"),
+        );
+        let (alg, signing_key) = match alg.as_str() {
+            "RS256" => (Algorithm::RS256, EncodingKey::from_rsa_pem(&private_key)),
+            "ES256" => (Algorithm::ES256, EncodingKey::from_ec_pem(&private_key)),
+            "EdDSA" => (Algorithm::EdDSA, EncodingKey::from_ed_pem(&private_key)),
+            _ => panic!("environment variable JWT_ALG must be HS256, RS256, ES256 or EdDSA"),
+        };
+        match verifying_keys.get(&kid) {
+            Some(v) if v.alg == alg => (),
+            _ => panic!("public key of JWT_KID {} must be listed in JWT_PUBLIC_KEYS", kid),
+        };
+        let mut header = Header::new(alg);
+        header.kid = Some(kid);
+        Self {
+            header,
+            signing_key: signing_key.expect("invalid JWT_PRIVATE_KEY"),
+            verifying_keys,
+            secret: None,
+        }
+    }
+}
+
+/// loads the signing and verification keys, panics on misconfiguration
+pub fn load_keys() {
+    lazy_static::initialize(&KEYS);
+}
+
+fn ttl_from_env(key: &str, default: i64) -> Duration {
+    let secs = env::var(key)
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(default);
+    Duration::seconds(secs)
+}
+
+pub fn access_token_ttl() -> Duration {
+    ttl_from_env("ACCESS_TOKEN_TTL", 15 * 60)
+}
+
+pub fn refresh_token_ttl() -> Duration {
+    ttl_from_env("REFRESH_TOKEN_TTL", 30 * 24 * 3600)
+}
+
+pub fn challenge_ttl() -> Duration {
+    ttl_from_env("MFA_CHALLENGE_TTL", 5 * 60)
+}
+
+pub fn impersonation_ttl() -> Duration {
+    ttl_from_env("IMPERSONATION_TTL", 15 * 60)
+}
+
+/// the longest an access token lives, impersonation tokens included
+pub fn max_token_ttl() -> Duration {
+    access_token_ttl().max(impersonation_ttl())
+}
+
+pub fn issuer() -> String {
+    env::var("JWT_ISSUER").unwrap_or_else(|_| {
+        let port = env::var("APP_PORT").unwrap_or_else(|_| "2020".to_string());
+        format!("http://localhost:{}", port)
+    })
+}
+
+/// audience of the tokens accepted by the management API
+pub fn audience() -> String {
+    env::var("JWT_AUDIENCE").unwrap_or_else(|_| issuer())
+}
+
+pub fn algorithm() -> String {
+    format!("{:?}", KEYS.header.alg)
+}
+
+fn encode_claims<T: Serialize>(claims: &T) -> String {
+    encode(&KEYS.header, claims, &KEYS.signing_key).unwrap()
+}
+
+/// signs arbitrary claims with the current signing key, stamped with issuer, audience and kind
+pub fn sign(typ: TokenType, aud: &str, mut claims: Value) -> String {
+    claims["iss"] = json!(issuer());
+    claims["aud"] = json!(aud);
+    claims["typ"] = json!(typ);
+    encode_claims(&claims)
+}
+
 pub fn generate_token(
     auth: Auth
 ) -> String {
     let iat = Utc::now();
-    let exp = iat + Duration::days(30);
+    let exp = iat + access_token_ttl();
     let payload = Payload::new(auth, iat, exp);
-    let jwt_key = env::var("JWT_KEY").expect("environment variable JWT_KEY must be set");
-    encode(
-        &Header::default(),
-        &payload,
-        &EncodingKey::from_secret(jwt_key.as_ref()),
-    )
-    .unwrap()
+    encode_claims(&payload)
+}
+
+/// short-lived access token of `auth` carrying `actor_id` as actor, never refreshable
+pub fn generate_impersonation(auth: Auth, actor_id: &str) -> (String, Payload) {
+    let iat = Utc::now();
+    let mut payload = Payload::new(auth, iat, iat + impersonation_ttl());
+    payload.act = Some(actor_id.to_string());
+    (encode_claims(&payload), payload)
+}
+
+pub fn generate_challenge(user_id: &str) -> String {
+    let iat = Utc::now().with_nanosecond(0).unwrap();
+    encode_claims(&Challenge {
+        iss: issuer(),
+        aud: audience(),
+        typ: TokenType::MfaChallenge,
+        iat,
+        exp: iat + challenge_ttl(),
+        jti: uuid_v4(),
+        mfa_user_id: user_id.to_string(),
+    })
+}
+
+pub fn decode_challenge(token: &str) -> Result<TokenData<Challenge>> {
+    verify(token, TokenType::MfaChallenge, Some(&audience()))
 }
 
 pub fn decode_token(token: &str) -> Result<TokenData<Payload>> {
-    let jwt_key = env::var("JWT_KEY").expect("environment variable JWT_KEY must be set");
-    decode::<Payload>(
-        token,
-        &DecodingKey::from_secret(jwt_key.as_ref()),
-        &Validation::default(),
-    )
+    verify(token, TokenType::Access, Some(&audience()))
+}
+
+/// checks signature, expiry, issuer and kind, and the audience unless `aud` is `None`
+pub fn verify<T: DeserializeOwned>(
+    token: &str,
+    typ: TokenType,
+    aud: Option<&str>,
+) -> Result<TokenData<T>> {
+    let header = decode_header(token)?;
+    let (alg, key) = match header.kid {
+        Some(kid) => match KEYS.verifying_keys.get(&kid) {
+            Some(v) => (v.alg, &v.key),
+            None => return Err(ErrorKind::InvalidKeyFormat.into()),
+        },
+        None => match &KEYS.secret {
+            Some(secret) => (Algorithm::HS256, secret),
+            None => return Err(ErrorKind::InvalidKeyFormat.into()),
+        },
+    };
+    let mut validation = Validation::new(alg);
+    validation.set_issuer(&[issuer()]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+    if let Some(aud) = aud {
+        validation.set_audience(&[aud]);
+    }
+    let data = decode::<Value>(token, key, &validation)?;
+    if data.claims.get("typ") != Some(&json!(typ)) {
+        return Err(ErrorKind::InvalidToken.into());
+    }
+    Ok(TokenData {
+        header: data.header,
+        claims: serde_json::from_value(data.claims)?,
+    })
+}
+
+/// public keys of every configured verification key, for `/.well-known/jwks.json`
+pub fn jwks() -> Value {
+    let keys: Vec<&Value> = KEYS
+        .verifying_keys
+        .values()
+        .filter_map(|v| v.jwk.as_ref())
+        .collect();
+    json!({ "keys": keys })
 }
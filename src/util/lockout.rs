@@ -0,0 +1,211 @@
+use crate::util::now;
+use chrono::{Duration, NaiveDateTime};
+use std::{collections::HashMap, env, sync::RwLock};
+
+#[derive(Debug, Clone)]
+struct Failures {
+    count: u32,
+    first_failed_at: NaiveDateTime,
+    last_failed_at: NaiveDateTime,
+}
+
+#[derive(Debug, Default)]
+struct Attempts {
+    // `user:..` or `ip:..` -> failures within the window
+    failures: HashMap<String, Failures>,
+    // `user:..` or `ip:..` -> locked until
+    locks: HashMap<String, NaiveDateTime>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Policy {
+    pub window: Duration,
+    // failures before every further attempt is delayed
+    pub delay_after: u32,
+    pub max_delay: Duration,
+    pub account_threshold: u32,
+    pub ip_threshold: u32,
+    pub lock_duration: Duration,
+}
+
+fn env_or(key: &str, default: i64) -> i64 {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(default)
+}
+
+impl Policy {
+    fn from_env() -> Self {
+        Self {
+            window: Duration::seconds(env_or("LOGIN_FAILURE_WINDOW", 15 * 60)),
+            delay_after: env_or("LOGIN_DELAY_AFTER", 3) as u32,
+            max_delay: Duration::seconds(env_or("LOGIN_MAX_DELAY", 60)),
+            account_threshold: env_or("LOGIN_LOCKOUT_THRESHOLD", 10) as u32,
+            ip_threshold: env_or("LOGIN_IP_LOCKOUT_THRESHOLD", 50) as u32,
+            lock_duration: Duration::seconds(env_or("LOGIN_LOCKOUT_DURATION", 15 * 60)),
+        }
+    }
+}
+
+lazy_static! {
+    static ref ATTEMPTS: RwLock<Attempts> = RwLock::new(Attempts::default());
+    pub static ref POLICY: Policy = Policy::from_env();
+}
+
+pub fn account_key(id: &str) -> String {
+    format!("user:{}", id.to_lowercase())
+}
+
+pub fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+/// returns the moment the next attempt is allowed, when `key` is locked or delayed
+pub fn blocked_until(key: &str) -> Option<NaiveDateTime> {
+    let at = now();
+    let attempts = ATTEMPTS.read().unwrap();
+    if let Some(until) = attempts.locks.get(key) {
+        if *until > at {
+            return Some(*until);
+        }
+    }
+    let failures = attempts.failures.get(key)?;
+    if failures.count < POLICY.delay_after || failures.first_failed_at + POLICY.window <= at {
+        return None;
+    }
+    // 1s, 2s, 4s .. up to `max_delay`
+    let exp = (failures.count - POLICY.delay_after).min(16);
+    let delay = Duration::seconds(1 << exp).min(POLICY.max_delay);
+    Some(failures.last_failed_at + delay).filter(|v| *v > at)
+}
+
+/// counts a failure, returns the failure count and lock expiry when `key` gets locked
+pub fn record_failure(key: &str, threshold: u32) -> Option<(u32, NaiveDateTime)> {
+    let at = now();
+    let mut attempts = ATTEMPTS.write().unwrap();
+    let failures = attempts
+        .failures
+        .entry(key.to_string())
+        .or_insert(Failures {
+            count: 0,
+            first_failed_at: at,
+            last_failed_at: at,
+        });
+    if failures.first_failed_at + POLICY.window <= at {
+        failures.count = 0;
+        failures.first_failed_at = at;
+    }
+    failures.count += 1;
+    failures.last_failed_at = at;
+    let count = failures.count;
+    if count < threshold {
+        return None;
+    }
+    let until = at + POLICY.lock_duration;
+    attempts.failures.remove(key);
+    attempts.locks.insert(key.to_string(), until);
+    Some((count, until))
+}
+
+pub fn clear(key: &str) {
+    let mut attempts = ATTEMPTS.write().unwrap();
+    attempts.failures.remove(key);
+}
+
+pub fn lock(key: &str, until: NaiveDateTime) {
+    let mut attempts = ATTEMPTS.write().unwrap();
+    let entry = attempts.locks.entry(key.to_string()).or_insert(until);
+    if *entry < until {
+        *entry = until;
+    }
+}
+
+pub fn unlock(key: &str) {
+    let mut attempts = ATTEMPTS.write().unwrap();
+    attempts.failures.remove(key);
+    attempts.locks.remove(key);
+}
+
+/// drops the lock of `key` but keeps its failures, for locks lifted on another instance
+pub fn release(key: &str) {
+    let mut attempts = ATTEMPTS.write().unwrap();
+    attempts.locks.remove(key);
+}
+
+/// drops expired locks and failures outside the window
+pub fn prune(at: NaiveDateTime) {
+    let mut attempts = ATTEMPTS.write().unwrap();
+    attempts.locks.retain(|_, v| *v > at);
+    attempts
+        .failures
+        .retain(|_, v| v.first_failed_at + POLICY.window > at);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_keys() {
+        assert_eq!(account_key("Alice"), "user:alice");
+        assert_eq!(ip_key("10.0.0.1"), "ip:10.0.0.1");
+    }
+
+    #[test]
+    fn locks_on_threshold() {
+        let key = account_key("locks_on_threshold");
+        assert_eq!(record_failure(&key, 3), None);
+        assert_eq!(record_failure(&key, 3), None);
+        let (failures, until) = record_failure(&key, 3).unwrap();
+        assert_eq!(failures, 3);
+        assert!(until > now());
+        assert_eq!(blocked_until(&key), Some(until));
+        unlock(&key);
+        assert_eq!(blocked_until(&key), None);
+    }
+
+    #[test]
+    fn delays_after_repeated_failures() {
+        let key = account_key("delays_after_repeated_failures");
+        for _ in 0..POLICY.delay_after {
+            assert_eq!(record_failure(&key, u32::MAX), None);
+        }
+        assert!(blocked_until(&key).is_some());
+        clear(&key);
+        assert_eq!(blocked_until(&key), None);
+    }
+
+    #[test]
+    fn few_failures_are_not_delayed() {
+        let key = account_key("few_failures_are_not_delayed");
+        for _ in 1..POLICY.delay_after {
+            record_failure(&key, u32::MAX);
+        }
+        assert_eq!(blocked_until(&key), None);
+    }
+
+    #[test]
+    fn release_keeps_failures() {
+        let key = account_key("release_keeps_failures");
+        lock(&key, now() + Duration::minutes(10));
+        for _ in 0..POLICY.delay_after {
+            record_failure(&key, u32::MAX);
+        }
+        release(&key);
+        let attempts = ATTEMPTS.read().unwrap();
+        assert!(!attempts.locks.contains_key(&key));
+        assert_eq!(attempts.failures.get(&key).map(|v| v.count), Some(POLICY.delay_after));
+    }
+
+    #[test]
+    fn keeps_the_later_lock() {
+        let key = ip_key("keeps_the_later_lock");
+        let later = now() + Duration::minutes(10);
+        lock(&key, later);
+        lock(&key, now() + Duration::minutes(1));
+        assert_eq!(blocked_until(&key), Some(later));
+        prune(later + Duration::seconds(1));
+        assert_eq!(blocked_until(&key), None);
+    }
+}
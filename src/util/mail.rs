@@ -0,0 +1,109 @@
+use async_trait::async_trait;
+use lettre::{
+    transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport, Message,
+    Tokio1Executor,
+};
+use std::{env, fs::OpenOptions, io::Write};
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: &Mail) -> Result<(), String>;
+}
+
+pub struct SmtpMailer {
+    from: String,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    fn from_env() -> Self {
+        let host = env::var("SMTP_HOST").expect("environment variable SMTP_HOST must be set");
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::relay(&host)
+            .expect("invalid SMTP_HOST");
+        if let Ok(port) = env::var("SMTP_PORT") {
+            builder = builder.port(port.parse::<u16>().expect("SMTP_PORT must be u16"));
+        }
+        if let (Ok(username), Ok(password)) = (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        Self {
+            from: env::var("MAIL_FROM").expect("environment variable MAIL_FROM must be set"),
+            transport: builder.build(),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: &Mail) -> Result<(), String> {
+        let message = Message::builder()
+            .from(self.from.parse().map_err(|_| "MAIL_FROM 不合法".to_string())?)
+            .to(mail.to.parse().map_err(|_| format!("邮箱 {} 不合法", mail.to))?)
+            .subject(mail.subject.clone())
+            .body(mail.body.clone())
+            .map_err(|e| e.to_string())?;
+        self.transport
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// appends mails to `MAIL_FILE` or logs them, for local testing
+pub struct LogMailer {
+    path: Option<String>,
+}
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: &Mail) -> Result<(), String> {
+        let text = format!("To: {}\nSubject: {}\n\n{}\n\n", mail.to, mail.subject, mail.body);
+        match &self.path {
+            Some(path) => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut f| f.write_all(text.as_bytes()))
+                .map_err(|e| e.to_string()),
+            None => {
+                tracing::info!("mail\n{}", text);
+                Ok(())
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref MAILER: Box<dyn Mailer> = match env::var("MAIL_TRANSPORT").as_deref() {
+        Ok("smtp") => Box::new(SmtpMailer::from_env()),
+        _ => Box::new(LogMailer {
+            path: env::var("MAIL_FILE").ok(),
+        }),
+    };
+}
+
+/// url from `key` with `{token}` replaced by the token, or the token appended
+pub fn link(key: &str, token: &str) -> String {
+    match env::var(key) {
+        Ok(url) if url.contains("{token}") => url.replace("{token}", token),
+        Ok(url) => format!("{}{}", url, token),
+        Err(_) => token.to_string(),
+    }
+}
+
+/// sends in the background, so responses don't reveal whether a mail went out
+pub fn deliver(mail: Mail) {
+    tokio::spawn(async move {
+        if let Err(e) = MAILER.send(&mail).await {
+            tracing::error!("send mail to {} failed: {}", mail.to, e);
+        }
+    });
+}
@@ -2,6 +2,18 @@ pub mod serde_format;
 mod api_error;
 pub mod jwt;
 pub mod restrict;
+pub mod federation;
+pub mod revocation;
+pub mod lockout;
+pub mod totp;
+pub mod password;
+pub mod mail;
+pub mod audit;
+pub mod condition;
+pub mod pattern;
+pub mod perm_value;
+pub mod relation;
+#[allow(clippy::module_inception)]
 mod util;
 mod cors;
 mod handle_error;
@@ -0,0 +1,201 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use super::mail;
+use chrono::Duration;
+use std::{env, fs};
+use tokio::task;
+use validator::ValidationError;
+
+// checked case-insensitively, extended by `PASSWORD_DENYLIST_FILE`
+const COMMON_PASSWORDS: [&str; 24] = [
+    "123456", "12345678", "123456789", "1234567890", "password", "password1", "password123",
+    "qwerty", "qwerty123", "abc123", "111111", "000000", "123123", "iloveyou", "admin",
+    "admin123", "welcome", "letmein", "monkey", "dragon", "football", "1q2w3e4r", "aa123456",
+    "woaini1314",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scheme {
+    Bcrypt,
+    Argon2id,
+}
+
+#[derive(Debug, Clone)]
+pub struct Policy {
+    pub min_length: usize,
+    // how many of lowercase, uppercase, digits and symbols are required
+    pub char_classes: usize,
+    // how many previous passwords may not be reused
+    pub history: usize,
+    pub scheme: Scheme,
+    pub bcrypt_cost: u32,
+    // memory in KiB, iterations, lanes
+    pub argon2_params: (u32, u32, u32),
+    denylist: Vec<String>,
+}
+
+fn env_or(key: &str, default: u32) -> u32 {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(default)
+}
+
+impl Policy {
+    fn from_env() -> Self {
+        let mut denylist: Vec<String> = COMMON_PASSWORDS.iter().map(|v| v.to_string()).collect();
+        if let Ok(path) = env::var("PASSWORD_DENYLIST_FILE") {
+            let content = fs::read_to_string(&path).expect("read password denylist failed");
+            denylist.extend(
+                content
+                    .lines()
+                    .map(|v| v.trim().to_lowercase())
+                    .filter(|v| !v.is_empty()),
+            );
+        }
+        let scheme = match env::var("PASSWORD_HASH").as_deref() {
+            Ok("argon2id") => Scheme::Argon2id,
+            _ => Scheme::Bcrypt,
+        };
+        Self {
+            min_length: env_or("PASSWORD_MIN_LENGTH", 8) as usize,
+            char_classes: env_or("PASSWORD_CHAR_CLASSES", 3).min(4) as usize,
+            history: env_or("PASSWORD_HISTORY", 5) as usize,
+            scheme,
+            bcrypt_cost: env_or("BCRYPT_COST", 12).clamp(4, 31),
+            argon2_params: (
+                env_or("ARGON2_MEMORY", 19 * 1024),
+                env_or("ARGON2_ITERATIONS", 2),
+                env_or("ARGON2_PARALLELISM", 1),
+            ),
+            denylist,
+        }
+    }
+    fn argon2(&self) -> Argon2<'static> {
+        let (m, t, p) = self.argon2_params;
+        let params = Params::new(m, t, p, None).expect("invalid argon2 parameters");
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+    }
+}
+
+lazy_static! {
+    pub static ref POLICY: Policy = Policy::from_env();
+    static ref DUMMY_HASH: String = hash(&super::uuid_v4());
+}
+
+fn invalid(message: String) -> ValidationError {
+    let mut e = ValidationError::new("password");
+    e.message = Some(message.into());
+    e
+}
+
+/// checks length, character classes and the denylist, used as `#[validate(custom)]`
+pub fn validate_password(password: &str) -> Result<(), ValidationError> {
+    if password.chars().count() < POLICY.min_length {
+        return Err(invalid(format!("密码至少 {} 位", POLICY.min_length)));
+    }
+    let classes = [
+        password.chars().any(|c| c.is_lowercase()),
+        password.chars().any(|c| c.is_uppercase()),
+        password.chars().any(|c| c.is_ascii_digit()),
+        password.chars().any(|c| !c.is_alphanumeric()),
+    ];
+    if classes.iter().filter(|v| **v).count() < POLICY.char_classes {
+        return Err(invalid(format!(
+            "密码需包含大写字母、小写字母、数字、符号中的至少 {} 种",
+            POLICY.char_classes
+        )));
+    }
+    if POLICY.denylist.contains(&password.to_lowercase()) {
+        return Err(invalid("密码过于常见".to_string()));
+    }
+    Ok(())
+}
+
+fn hash(password: &str) -> String {
+    match POLICY.scheme {
+        Scheme::Bcrypt => bcrypt::hash(password, POLICY.bcrypt_cost).unwrap(),
+        Scheme::Argon2id => {
+            let salt = SaltString::generate(&mut OsRng);
+            POLICY
+                .argon2()
+                .hash_password(password.as_bytes(), &salt)
+                .unwrap()
+                .to_string()
+        }
+    }
+}
+
+// against a bcrypt or argon2id hash, whatever the configured scheme
+fn verify(password: &str, hashed: &str) -> bool {
+    if hashed.starts_with("$argon2") {
+        return match PasswordHash::new(hashed) {
+            Ok(parsed) => Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok(),
+            Err(_) => false,
+        };
+    }
+    bcrypt::verify(password, hashed).unwrap_or(false)
+}
+
+/// hashes with the configured scheme on the blocking pool, it takes long on purpose
+pub async fn hash_password(password: &str) -> String {
+    let password = password.to_string();
+    task::spawn_blocking(move || hash(&password))
+        .await
+        .expect("password hashing panicked")
+}
+
+pub async fn verify_password(password: &str, hashed: &str) -> bool {
+    verify_any(password, vec![hashed.to_string()]).await
+}
+
+/// whether `password` matches one of `hashes`, all verified in one blocking task
+pub async fn verify_any(password: &str, hashes: Vec<String>) -> bool {
+    let password = password.to_string();
+    task::spawn_blocking(move || hashes.iter().any(|v| verify(&password, v)))
+        .await
+        .expect("password verification panicked")
+}
+
+/// verifies against a throwaway hash, so unknown users take as long as wrong passwords
+pub async fn spend_verify(password: &str) {
+    let password = password.to_string();
+    task::spawn_blocking(move || verify(&password, &DUMMY_HASH))
+        .await
+        .expect("password verification panicked");
+}
+
+/// whether `hashed` uses another scheme or weaker parameters than configured
+pub fn needs_rehash(hashed: &str) -> bool {
+    match POLICY.scheme {
+        Scheme::Bcrypt => {
+            // `$2b$12$...`
+            let cost = hashed.get(4..6).and_then(|v| v.parse::<u32>().ok());
+            !hashed.starts_with("$2") || cost.map_or(true, |v| v < POLICY.bcrypt_cost)
+        }
+        Scheme::Argon2id => {
+            let parsed = match PasswordHash::new(hashed) {
+                Ok(val) => val,
+                Err(_) => return true,
+            };
+            let (m, t, p) = POLICY.argon2_params;
+            let param = |name: &str| parsed.params.get_decimal(name).unwrap_or(0);
+            parsed.algorithm.as_str() != "argon2id"
+                || param("m") < m
+                || param("t") < t
+                || param("p") < p
+        }
+    }
+}
+
+pub fn reset_token_ttl() -> Duration {
+    Duration::seconds(env_or("PASSWORD_RESET_TTL", 30 * 60) as i64)
+}
+
+pub fn reset_link(token: &str) -> String {
+    mail::link("PASSWORD_RESET_URL", token)
+}
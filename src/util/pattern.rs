@@ -0,0 +1,66 @@
+/// whether `value` matches `pattern`, `*` stands for any run of characters including none,
+/// so `bucket:logs/*` matches everything below `bucket:logs/` and `*` matches anything
+pub fn matches(pattern: &str, value: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let v: Vec<char> = value.chars().collect();
+    let (mut i, mut j) = (0, 0);
+    // position of the last `*` and of the value it was tried against
+    let mut star: Option<(usize, usize)> = None;
+    while j < v.len() {
+        if i < p.len() && p[i] == '*' {
+            star = Some((i, j));
+            i += 1;
+        } else if i < p.len() && p[i] == v[j] {
+            i += 1;
+            j += 1;
+        } else if let Some((si, sj)) = star {
+            // let the `*` swallow one more character
+            star = Some((si, sj + 1));
+            i = si + 1;
+            j = sj + 1;
+        } else {
+            return false;
+        }
+    }
+    p[i..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_literally_without_stars() {
+        assert!(matches("bucket:logs", "bucket:logs"));
+        assert!(!matches("bucket:logs", "bucket:log"));
+        assert!(!matches("bucket:logs", "bucket:logs2"));
+        assert!(matches("", ""));
+        assert!(!matches("", "a"));
+    }
+
+    #[test]
+    fn star_matches_any_run_including_none() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything:at/all"));
+        assert!(matches("bucket:logs/*", "bucket:logs/"));
+        assert!(matches("bucket:logs/*", "bucket:logs/2021/01"));
+        assert!(!matches("bucket:logs/*", "bucket:logs"));
+        assert!(!matches("bucket:logs/*", "bucket:data/logs/1"));
+    }
+
+    #[test]
+    fn stars_in_the_middle_backtrack() {
+        assert!(matches("a*b*c", "abc"));
+        assert!(matches("a*b*c", "aXXbYYbZc"));
+        assert!(!matches("a*b*c", "aXXbYY"));
+        assert!(matches("*.log", "a.log.log"));
+        assert!(!matches("*.log", "a.log.txt"));
+        assert!(matches("**", "x"));
+    }
+
+    #[test]
+    fn handles_multibyte_characters() {
+        assert!(matches("文档:*", "文档:报告"));
+        assert!(!matches("文档:?", "文档:报告"));
+    }
+}
@@ -0,0 +1,108 @@
+//! permission values `resource:action`, like `order:read` or `order.item:write`
+//!
+//! Either part can be `*`, so a grant of `order:*` covers every action on orders
+//! and `*:admin` the `admin` action on every resource. Parts are made of ASCII
+//! letters, digits, `_`, `-` and `.`.
+use validator::ValidationError;
+
+pub const WILDCARD: &str = "*";
+
+fn is_part(v: &str) -> bool {
+    v == WILDCARD
+        || (!v.is_empty()
+            && v.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.'))
+}
+
+/// the resource and action of a value
+pub fn parse(v: &str) -> Result<(&str, &str), String> {
+    let (resource, action) = match v.split_once(':') {
+        Some(val) => val,
+        None => return Err(format!("{} 应为 resource:action", v)),
+    };
+    if !is_part(resource) {
+        return Err(format!("{} 的资源部分不合法", v));
+    }
+    if !is_part(action) {
+        return Err(format!("{} 的操作部分不合法", v));
+    }
+    Ok((resource, action))
+}
+
+pub fn validate_perm_value(v: &str) -> Result<(), ValidationError> {
+    parse(v).map(|_| ()).map_err(|msg| {
+        let mut e = ValidationError::new("value");
+        e.message = Some(format!("权限值无效: {}", msg).into());
+        e
+    })
+}
+
+/// whether a perm granted as `granted` covers the `requested` one, a `*` part of the grant
+/// matches any part while a requested `*` is only covered by a `*`; values from before the
+/// grammar match exactly
+pub fn covers(granted: &str, requested: &str) -> bool {
+    if granted == requested {
+        return true;
+    }
+    match (parse(granted), parse(requested)) {
+        (Ok((gr, ga)), Ok((rr, ra))) => {
+            (gr == WILDCARD || gr == rr) && (ga == WILDCARD || ga == ra)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_resource_and_action() {
+        assert_eq!(parse("order:read"), Ok(("order", "read")));
+        assert_eq!(parse("order.item:write"), Ok(("order.item", "write")));
+        assert_eq!(parse("*:admin"), Ok(("*", "admin")));
+        assert_eq!(parse("order:*"), Ok(("order", "*")));
+    }
+
+    #[test]
+    fn rejects_malformed_values() {
+        assert!(parse("order").is_err());
+        assert!(parse(":read").is_err());
+        assert!(parse("order:").is_err());
+        assert!(parse("order:read:all").is_err());
+        assert!(parse("or der:read").is_err());
+        assert!(parse("order*:read").is_err());
+        assert!(validate_perm_value("order").is_err());
+        assert!(validate_perm_value("order:read").is_ok());
+    }
+
+    #[test]
+    fn wildcard_parts_cover_any_part() {
+        assert!(covers("order:*", "order:read"));
+        assert!(covers("*:read", "invoice:read"));
+        assert!(covers("*:*", "invoice:delete"));
+        assert!(!covers("order:*", "invoice:read"));
+        assert!(!covers("*:read", "order:write"));
+    }
+
+    #[test]
+    fn requested_wildcard_needs_granted_wildcard() {
+        assert!(!covers("order:read", "order:*"));
+        assert!(covers("order:*", "order:*"));
+        assert!(!covers("order:read", "*:read"));
+    }
+
+    #[test]
+    fn wildcards_are_whole_parts() {
+        // `ord*` isn't a valid part, so it never acts as a prefix match
+        assert!(!covers("ord*:read", "order:read"));
+        assert!(!covers("order:re*", "order:read"));
+    }
+
+    #[test]
+    fn legacy_values_match_exactly() {
+        assert!(covers("user_manage", "user_manage"));
+        assert!(!covers("user_manage", "user_manage_all"));
+        assert!(!covers("*", "order:read"));
+    }
+}
@@ -0,0 +1,810 @@
+//! relationship based access over stored tuples, like Zanzibar
+//!
+//! A tuple `document:d1#viewer@user:u1` relates a subject to an object, the subject can be
+//! a userset like `group:eng#member` standing for every member of the group. The schema of a
+//! domain tells per object type how each relation is computed:
+//!
+//! ```text
+//! {
+//!   "user": {},
+//!   "group": {"member": "this"},
+//!   "folder": {"viewer": "this"},
+//!   "document": {
+//!     "parent": "this",
+//!     "owner": "this",
+//!     "editor": "this | owner",
+//!     "viewer": "(this | editor | parent->viewer) - banned",
+//!     "banned": "this"
+//!   }
+//! }
+//! ```
+//!
+//! `this` are the tuples stored for the relation, another relation name its subjects on the
+//! same object and `parent->viewer` the viewers of the objects related as `parent`. `|` unions,
+//! `&` intersects and `-` excludes, from left to right, parentheses group.
+use super::now;
+use chrono::{Duration, NaiveDateTime};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{BTreeSet, HashMap},
+    env, fmt,
+    sync::{Arc, RwLock},
+};
+
+const MAX_DEPTH: usize = 32;
+pub const MAX_NAME_LENGTH: usize = 64;
+pub const MAX_ID_LENGTH: usize = 100;
+
+fn is_name(v: &str) -> bool {
+    !v.is_empty()
+        && v.len() <= MAX_NAME_LENGTH
+        && v.starts_with(|c: char| c.is_ascii_lowercase())
+        && v.chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn is_id(v: &str) -> bool {
+    !v.is_empty()
+        && v.len() <= MAX_ID_LENGTH
+        && !v.contains(|c: char| c.is_whitespace() || c == '#')
+}
+
+/// `type:id`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Object {
+    pub object_type: String,
+    pub object_id: String,
+}
+
+impl Object {
+    pub fn new(object_type: &str, object_id: &str) -> Self {
+        Self {
+            object_type: object_type.to_string(),
+            object_id: object_id.to_string(),
+        }
+    }
+    pub fn parse(v: &str) -> Result<Self, String> {
+        let (object_type, object_id) = v
+            .split_once(':')
+            .ok_or_else(|| format!("对象 {} 应为 类型:id", v))?;
+        if !is_name(object_type) || !is_id(object_id) {
+            return Err(format!("对象 {} 不合法", v));
+        }
+        Ok(Self::new(object_type, object_id))
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.object_type, self.object_id)
+    }
+}
+
+/// `type:id`, or the userset `type:id#relation`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Subject {
+    pub object: Object,
+    pub relation: Option<String>,
+}
+
+impl Subject {
+    pub fn parse(v: &str) -> Result<Self, String> {
+        let (object, relation) = match v.split_once('#') {
+            Some((object, relation)) if is_name(relation) => (object, Some(relation.to_string())),
+            Some(_) => return Err(format!("主体 {} 不合法", v)),
+            None => (v, None),
+        };
+        Ok(Self {
+            object: Object::parse(object)?,
+            relation,
+        })
+    }
+}
+
+impl fmt::Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.relation {
+            Some(relation) => write!(f, "{}#{}", self.object, relation),
+            None => write!(f, "{}", self.object),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Tuple {
+    pub object: Object,
+    pub relation: String,
+    pub subject: Subject,
+}
+
+impl fmt::Display for Tuple {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}#{}@{}", self.object, self.relation, self.subject)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Rewrite {
+    This,
+    Computed(String),
+    TupleToUserset(String, String),
+    Union(Box<Rewrite>, Box<Rewrite>),
+    Intersection(Box<Rewrite>, Box<Rewrite>),
+    Exclusion(Box<Rewrite>, Box<Rewrite>),
+}
+
+fn tokenize(src: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else if c == '-' && chars.get(i + 1) == Some(&'>') {
+            tokens.push("->".to_string());
+            i += 2;
+        } else if "|&-()".contains(c) {
+            tokens.push(c.to_string());
+            i += 1;
+        } else {
+            return Err(format!("无法识别的字符 {}", c));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+    fn name(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(v) if is_name(&v) => Ok(v),
+            Some(v) => Err(format!("关系名 {} 不合法", v)),
+            None => Err("表达式不完整".to_string()),
+        }
+    }
+    fn expr(&mut self) -> Result<Rewrite, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("表达式嵌套过深".to_string());
+        }
+        let mut left = self.term()?;
+        while let Some(op) = self.peek() {
+            let op = op.to_string();
+            if !["|", "&", "-"].contains(&op.as_str()) {
+                break;
+            }
+            self.pos += 1;
+            let right = Box::new(self.term()?);
+            left = match op.as_str() {
+                "|" => Rewrite::Union(Box::new(left), right),
+                "&" => Rewrite::Intersection(Box::new(left), right),
+                _ => Rewrite::Exclusion(Box::new(left), right),
+            };
+        }
+        self.depth -= 1;
+        Ok(left)
+    }
+    fn term(&mut self) -> Result<Rewrite, String> {
+        if self.peek() == Some("(") {
+            self.pos += 1;
+            let inner = self.expr()?;
+            if self.next().as_deref() != Some(")") {
+                return Err("缺少 )".to_string());
+            }
+            return Ok(inner);
+        }
+        let name = self.name()?;
+        if self.peek() == Some("->") {
+            self.pos += 1;
+            return Ok(Rewrite::TupleToUserset(name, self.name()?));
+        }
+        if name == "this" {
+            return Ok(Rewrite::This);
+        }
+        Ok(Rewrite::Computed(name))
+    }
+}
+
+fn parse_rewrite(src: &str) -> Result<Rewrite, String> {
+    let mut parser = Parser {
+        tokens: tokenize(src)?,
+        pos: 0,
+        depth: 0,
+    };
+    let rewrite = parser.expr()?;
+    if let Some(v) = parser.peek() {
+        return Err(format!("多余的 {}", v));
+    }
+    Ok(rewrite)
+}
+
+/// object types of a domain with the rewrite of each of their relations
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    namespaces: HashMap<String, HashMap<String, Rewrite>>,
+}
+
+impl Schema {
+    /// parses and checks a definition, every relation used must exist on its type
+    pub fn parse(definition: &Value) -> Result<Self, String> {
+        let namespaces = definition.as_object().ok_or("模式应为对象")?;
+        let mut schema = Self::default();
+        for (object_type, relations) in namespaces {
+            if !is_name(object_type) {
+                return Err(format!("类型名 {} 不合法", object_type));
+            }
+            let relations = relations
+                .as_object()
+                .ok_or_else(|| format!("类型 {} 的关系应为对象", object_type))?;
+            let mut parsed = HashMap::new();
+            for (relation, rewrite) in relations {
+                if !is_name(relation) || relation == "this" {
+                    return Err(format!("关系名 {} 不合法", relation));
+                }
+                let rewrite = rewrite
+                    .as_str()
+                    .ok_or_else(|| format!("关系 {}#{} 应为字符串", object_type, relation))?;
+                let rewrite = parse_rewrite(rewrite)
+                    .map_err(|e| format!("关系 {}#{}: {}", object_type, relation, e))?;
+                parsed.insert(relation.clone(), rewrite);
+            }
+            schema.namespaces.insert(object_type.clone(), parsed);
+        }
+        for (object_type, relations) in schema.namespaces.iter() {
+            for (relation, rewrite) in relations {
+                schema
+                    .check_rewrite(object_type, rewrite)
+                    .map_err(|e| format!("关系 {}#{}: {}", object_type, relation, e))?;
+            }
+        }
+        Ok(schema)
+    }
+    fn check_rewrite(&self, object_type: &str, rewrite: &Rewrite) -> Result<(), String> {
+        match rewrite {
+            Rewrite::This => Ok(()),
+            Rewrite::Computed(relation) | Rewrite::TupleToUserset(relation, _)
+                if !self.has_relation(object_type, relation) =>
+            {
+                Err(format!("关系 {} 未定义", relation))
+            }
+            Rewrite::Computed(_) | Rewrite::TupleToUserset(_, _) => Ok(()),
+            Rewrite::Union(a, b) | Rewrite::Intersection(a, b) | Rewrite::Exclusion(a, b) => {
+                self.check_rewrite(object_type, a)?;
+                self.check_rewrite(object_type, b)
+            }
+        }
+    }
+    pub fn has_type(&self, object_type: &str) -> bool {
+        self.namespaces.contains_key(object_type)
+    }
+    pub fn has_relation(&self, object_type: &str, relation: &str) -> bool {
+        self.namespaces
+            .get(object_type)
+            .map_or(false, |v| v.contains_key(relation))
+    }
+    /// the tuple's object type, relation and subject must be defined
+    pub fn check_tuple(&self, tuple: &Tuple) -> Result<(), String> {
+        if !self.has_relation(&tuple.object.object_type, &tuple.relation) {
+            return Err(format!(
+                "关系 {}#{} 未定义",
+                tuple.object.object_type, tuple.relation
+            ));
+        }
+        let subject = &tuple.subject;
+        match &subject.relation {
+            Some(relation) if !self.has_relation(&subject.object.object_type, relation) => Err(
+                format!("关系 {}#{} 未定义", subject.object.object_type, relation),
+            ),
+            None if !self.has_type(&subject.object.object_type) => {
+                Err(format!("类型 {} 未定义", subject.object.object_type))
+            }
+            _ => Ok(()),
+        }
+    }
+    fn rewrite(&self, object_type: &str, relation: &str) -> Option<&Rewrite> {
+        self.namespaces.get(object_type)?.get(relation)
+    }
+}
+
+/// the tuples and schema of a domain at a revision
+#[derive(Debug)]
+pub struct Snapshot {
+    pub domain_id: String,
+    pub revision: i64,
+    pub loaded_at: NaiveDateTime,
+    schema: Schema,
+    // (object, relation) -> subjects
+    direct: HashMap<(Object, String), Vec<Subject>>,
+}
+
+/// subjects computed for each userset, and the usersets being computed to cut cycles
+struct Eval {
+    done: HashMap<(Object, String), BTreeSet<Subject>>,
+    visiting: Vec<(Object, String)>,
+    // lowest position in `visiting` a cycle was cut at, results above it are incomplete
+    cut: usize,
+}
+
+impl Default for Eval {
+    fn default() -> Self {
+        Self {
+            done: HashMap::new(),
+            visiting: vec![],
+            cut: usize::MAX,
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn new(domain_id: &str, revision: i64, schema: Schema, tuples: Vec<Tuple>) -> Self {
+        let mut direct: HashMap<(Object, String), Vec<Subject>> = HashMap::new();
+        for tuple in tuples {
+            direct
+                .entry((tuple.object, tuple.relation))
+                .or_default()
+                .push(tuple.subject);
+        }
+        Self {
+            domain_id: domain_id.to_string(),
+            revision,
+            loaded_at: now(),
+            schema,
+            direct,
+        }
+    }
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+    pub fn tuples(&self) -> impl Iterator<Item = Tuple> + '_ {
+        self.direct
+            .iter()
+            .flat_map(|((object, relation), subjects)| {
+                subjects.iter().map(move |subject| Tuple {
+                    object: object.clone(),
+                    relation: relation.clone(),
+                    subject: subject.clone(),
+                })
+            })
+    }
+    fn direct(&self, object: &Object, relation: &str) -> &[Subject] {
+        self.direct
+            .get(&(object.clone(), relation.to_string()))
+            .map_or(&[], Vec::as_slice)
+    }
+    fn resolve(&self, object: &Object, relation: &str, eval: &mut Eval) -> BTreeSet<Subject> {
+        let key = (object.clone(), relation.to_string());
+        if let Some(found) = eval.done.get(&key) {
+            return found.clone();
+        }
+        if let Some(pos) = eval.visiting.iter().position(|v| *v == key) {
+            eval.cut = eval.cut.min(pos);
+            return BTreeSet::new();
+        }
+        if eval.visiting.len() >= MAX_DEPTH {
+            eval.cut = 0;
+            return BTreeSet::new();
+        }
+        let pos = eval.visiting.len();
+        eval.visiting.push(key.clone());
+        let found = match self.schema.rewrite(&object.object_type, relation) {
+            Some(rewrite) => self.eval(object, relation, rewrite, eval),
+            None => BTreeSet::new(),
+        };
+        eval.visiting.pop();
+        if eval.cut >= pos {
+            eval.cut = usize::MAX;
+            eval.done.insert(key, found.clone());
+        }
+        found
+    }
+    fn eval(
+        &self,
+        object: &Object,
+        relation: &str,
+        rewrite: &Rewrite,
+        eval: &mut Eval,
+    ) -> BTreeSet<Subject> {
+        match rewrite {
+            Rewrite::This => {
+                let mut found = BTreeSet::new();
+                for subject in self.direct(object, relation) {
+                    if let Some(relation) = &subject.relation {
+                        found.extend(self.resolve(&subject.object, relation, eval));
+                    }
+                    found.insert(subject.clone());
+                }
+                found
+            }
+            Rewrite::Computed(computed) => self.resolve(object, computed, eval),
+            Rewrite::TupleToUserset(tupleset, computed) => {
+                let mut found = BTreeSet::new();
+                for subject in self.direct(object, tupleset) {
+                    found.extend(self.resolve(&subject.object, computed, eval));
+                }
+                found
+            }
+            Rewrite::Union(a, b) => {
+                let mut found = self.eval(object, relation, a, eval);
+                found.extend(self.eval(object, relation, b, eval));
+                found
+            }
+            Rewrite::Intersection(a, b) => {
+                let a = self.eval(object, relation, a, eval);
+                let b = self.eval(object, relation, b, eval);
+                a.intersection(&b).cloned().collect()
+            }
+            Rewrite::Exclusion(a, b) => {
+                let a = self.eval(object, relation, a, eval);
+                let outer = std::mem::replace(&mut eval.cut, usize::MAX);
+                let b = self.eval(object, relation, b, eval);
+                // `b` was cut short above itself, at a cycle or `MAX_DEPTH`, and may miss
+                // subjects to exclude; nobody is let through then
+                let is_cut = eval.cut < eval.visiting.len();
+                eval.cut = eval.cut.min(outer);
+                if is_cut {
+                    return BTreeSet::new();
+                }
+                a.difference(&b).cloned().collect()
+            }
+        }
+    }
+    /// whether the subject, a user or userset, has the relation to the object
+    pub fn check(&self, object: &Object, relation: &str, subject: &Subject) -> bool {
+        self.resolve(object, relation, &mut Eval::default())
+            .contains(subject)
+    }
+    /// every subject having the relation to the object, usersets resolved
+    pub fn subjects(&self, object: &Object, relation: &str) -> BTreeSet<Subject> {
+        self.resolve(object, relation, &mut Eval::default())
+    }
+    /// ids of the objects of a type the subject has the relation to
+    pub fn objects(&self, object_type: &str, relation: &str, subject: &Subject) -> Vec<String> {
+        let mut candidates: BTreeSet<&Object> = BTreeSet::new();
+        for ((object, _), subjects) in self.direct.iter() {
+            candidates.insert(object);
+            candidates.extend(subjects.iter().map(|v| &v.object));
+        }
+        let mut eval = Eval::default();
+        candidates
+            .into_iter()
+            .filter(|v| v.object_type == object_type)
+            .filter(|v| self.resolve(v, relation, &mut eval).contains(subject))
+            .map(|v| v.object_id.clone())
+            .collect()
+    }
+    /// the rewrite tree of the userset, usersets among the stored subjects are left as leaves
+    pub fn expand(&self, object: &Object, relation: &str) -> Value {
+        self.expand_userset(object, relation, &mut vec![])
+    }
+    fn expand_userset(&self, object: &Object, relation: &str, path: &mut Vec<String>) -> Value {
+        let userset = format!("{}#{}", object, relation);
+        if path.contains(&userset) || path.len() >= MAX_DEPTH {
+            return json!({ "userset": userset, "cycle": true });
+        }
+        let rewrite = match self.schema.rewrite(&object.object_type, relation) {
+            Some(val) => val,
+            None => return json!({ "userset": userset, "subjects": [] }),
+        };
+        path.push(userset.clone());
+        let mut node = Map::new();
+        node.insert("userset".to_string(), json!(userset));
+        node.insert(
+            "expand".to_string(),
+            self.expand_rewrite(object, relation, rewrite, path),
+        );
+        path.pop();
+        Value::Object(node)
+    }
+    fn expand_rewrite(
+        &self,
+        object: &Object,
+        relation: &str,
+        rewrite: &Rewrite,
+        path: &mut Vec<String>,
+    ) -> Value {
+        match rewrite {
+            Rewrite::This => {
+                let subjects: Vec<String> = self
+                    .direct(object, relation)
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect();
+                json!({ "subjects": subjects })
+            }
+            Rewrite::Computed(computed) => self.expand_userset(object, computed, path),
+            Rewrite::TupleToUserset(tupleset, computed) => {
+                let children: Vec<Value> = self
+                    .direct(object, tupleset)
+                    .iter()
+                    .map(|v| self.expand_userset(&v.object, computed, path))
+                    .collect();
+                json!({ "tupleset": format!("{}#{}", object, tupleset), "union": children })
+            }
+            Rewrite::Union(a, b) => json!({ "union": [
+                self.expand_rewrite(object, relation, a, path),
+                self.expand_rewrite(object, relation, b, path)
+            ] }),
+            Rewrite::Intersection(a, b) => json!({ "intersection": [
+                self.expand_rewrite(object, relation, a, path),
+                self.expand_rewrite(object, relation, b, path)
+            ] }),
+            Rewrite::Exclusion(a, b) => json!({ "exclusion": [
+                self.expand_rewrite(object, relation, a, path),
+                self.expand_rewrite(object, relation, b, path)
+            ] }),
+        }
+    }
+}
+
+lazy_static! {
+    static ref SNAPSHOTS: RwLock<HashMap<String, Arc<Snapshot>>> = RwLock::new(HashMap::new());
+    // reads without a consistency token may be answered from a snapshot this old
+    static ref CACHE_TTL: Duration = Duration::seconds(
+        env::var("RELATION_CACHE_TTL")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .unwrap_or(5)
+    );
+}
+
+/// the cached snapshot of the domain, if it is at least at `min_revision`, or recent enough
+/// when no revision is asked for
+pub fn cached(domain_id: &str, min_revision: Option<i64>) -> Option<Arc<Snapshot>> {
+    let snapshots = SNAPSHOTS.read().unwrap();
+    let snapshot = snapshots.get(domain_id)?;
+    let fresh = match min_revision {
+        Some(revision) => snapshot.revision >= revision,
+        None => snapshot.loaded_at + *CACHE_TTL > now(),
+    };
+    Some(snapshot.clone()).filter(|_| fresh)
+}
+
+pub fn cache(snapshot: Snapshot) -> Arc<Snapshot> {
+    let snapshot = Arc::new(snapshot);
+    let mut snapshots = SNAPSHOTS.write().unwrap();
+    match snapshots.get(&snapshot.domain_id) {
+        Some(found) if found.revision > snapshot.revision => (),
+        _ => {
+            snapshots.insert(snapshot.domain_id.clone(), snapshot.clone());
+        }
+    }
+    snapshot
+}
+
+/// drops the cached snapshot after a write through this instance
+pub fn evict(domain_id: &str) {
+    SNAPSHOTS.write().unwrap().remove(domain_id);
+}
+
+/// opaque consistency token of a domain revision
+pub fn encode_token(domain_id: &str, revision: i64) -> String {
+    base64::encode_config(
+        format!("{}@{}", domain_id, revision),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
+/// the revision a token stands for, `None` for tokens of other domains or garbage
+pub fn decode_token(domain_id: &str, token: &str) -> Option<i64> {
+    let decoded = base64::decode_config(token, base64::URL_SAFE_NO_PAD).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (found, revision) = decoded.rsplit_once('@')?;
+    if found != domain_id {
+        return None;
+    }
+    revision.parse::<i64>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Schema {
+        Schema::parse(&json!({
+            "user": {},
+            "group": {"member": "this"},
+            "folder": {"parent": "this", "viewer": "this | parent->viewer"},
+            "document": {
+                "parent": "this",
+                "owner": "this",
+                "editor": "this | owner",
+                "viewer": "(this | editor | parent->viewer) - banned",
+                "auditor": "viewer & reviewer",
+                "reviewer": "this",
+                "banned": "this"
+            }
+        }))
+        .unwrap()
+    }
+
+    fn tuple(v: &str) -> Tuple {
+        let (object, rest) = v.split_once('#').unwrap();
+        let (relation, subject) = rest.split_once('@').unwrap();
+        Tuple {
+            object: Object::parse(object).unwrap(),
+            relation: relation.to_string(),
+            subject: Subject::parse(subject).unwrap(),
+        }
+    }
+
+    fn snapshot(tuples: &[&str]) -> Snapshot {
+        Snapshot::new("d1", 1, schema(), tuples.iter().map(|v| tuple(v)).collect())
+    }
+
+    fn check(snapshot: &Snapshot, object: &str, relation: &str, subject: &str) -> bool {
+        snapshot.check(
+            &Object::parse(object).unwrap(),
+            relation,
+            &Subject::parse(subject).unwrap(),
+        )
+    }
+
+    #[test]
+    fn parses_objects_and_subjects() {
+        assert!(Object::parse("document:d1").is_ok());
+        assert!(Object::parse("document").is_err());
+        assert!(Object::parse("Document:d1").is_err());
+        assert!(Object::parse("document:").is_err());
+        assert!(Object::parse("document:d 1").is_err());
+        let subject = Subject::parse("group:eng#member").unwrap();
+        assert_eq!(subject.relation.as_deref(), Some("member"));
+        assert_eq!(subject.to_string(), "group:eng#member");
+        assert!(Subject::parse("group:eng#").is_err());
+        assert_eq!(tuple("document:d1#viewer@user:u1").to_string(), "document:d1#viewer@user:u1");
+    }
+
+    #[test]
+    fn rejects_invalid_schemas() {
+        assert!(Schema::parse(&json!([])).is_err());
+        assert!(Schema::parse(&json!({"Doc": {}})).is_err());
+        assert!(Schema::parse(&json!({"doc": {"this": "this"}})).is_err());
+        assert!(Schema::parse(&json!({"doc": {"viewer": 1}})).is_err());
+        assert!(Schema::parse(&json!({"doc": {"viewer": "editor"}})).is_err());
+        assert!(Schema::parse(&json!({"doc": {"viewer": "missing->viewer"}})).is_err());
+        assert!(Schema::parse(&json!({"doc": {"viewer": "this |"}})).is_err());
+        assert!(Schema::parse(&json!({"doc": {"viewer": "(this"}})).is_err());
+        assert!(Schema::parse(&json!({"doc": {"viewer": "this this"}})).is_err());
+        assert!(Schema::parse(&json!({"doc": {"viewer": "this + this"}})).is_err());
+    }
+
+    #[test]
+    fn limits_rewrite_depth() {
+        let deep = format!("{}this{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH));
+        assert!(parse_rewrite(&deep).is_err());
+        let shallow = format!("{}this{}", "(".repeat(8), ")".repeat(8));
+        assert!(parse_rewrite(&shallow).is_ok());
+    }
+
+    #[test]
+    fn checks_tuples_against_the_schema() {
+        let schema = schema();
+        assert!(schema.check_tuple(&tuple("document:d1#viewer@user:u1")).is_ok());
+        assert!(schema.check_tuple(&tuple("document:d1#viewer@group:eng#member")).is_ok());
+        assert!(schema.check_tuple(&tuple("document:d1#missing@user:u1")).is_err());
+        assert!(schema.check_tuple(&tuple("document:d1#viewer@robot:r1")).is_err());
+        assert!(schema.check_tuple(&tuple("document:d1#viewer@group:eng#owner")).is_err());
+    }
+
+    #[test]
+    fn resolves_computed_usersets_and_parents() {
+        let s = snapshot(&[
+            "document:d1#owner@user:alice",
+            "document:d1#parent@folder:f1",
+            "folder:f1#parent@folder:root",
+            "folder:root#viewer@group:eng#member",
+            "group:eng#member@user:bob",
+        ]);
+        assert!(check(&s, "document:d1", "editor", "user:alice"));
+        assert!(check(&s, "document:d1", "viewer", "user:alice"));
+        assert!(check(&s, "document:d1", "viewer", "user:bob"));
+        assert!(check(&s, "document:d1", "viewer", "group:eng#member"));
+        assert!(!check(&s, "document:d1", "editor", "user:bob"));
+        assert!(!check(&s, "document:d2", "viewer", "user:alice"));
+    }
+
+    #[test]
+    fn applies_exclusion_and_intersection() {
+        let s = snapshot(&[
+            "document:d1#viewer@user:alice",
+            "document:d1#viewer@user:bob",
+            "document:d1#banned@user:bob",
+            "document:d1#reviewer@user:alice",
+            "document:d1#reviewer@user:carol",
+        ]);
+        assert!(check(&s, "document:d1", "viewer", "user:alice"));
+        assert!(!check(&s, "document:d1", "viewer", "user:bob"));
+        assert!(check(&s, "document:d1", "auditor", "user:alice"));
+        assert!(!check(&s, "document:d1", "auditor", "user:carol"));
+    }
+
+    #[test]
+    fn cuts_cycles() {
+        let s = snapshot(&[
+            "group:a#member@group:b#member",
+            "group:b#member@group:a#member",
+            "group:b#member@user:u1",
+            "folder:f1#parent@folder:f2",
+            "folder:f2#parent@folder:f1",
+            "folder:f2#viewer@user:u2",
+        ]);
+        assert!(check(&s, "group:a", "member", "user:u1"));
+        assert!(check(&s, "group:b", "member", "user:u1"));
+        assert!(check(&s, "folder:f1", "viewer", "user:u2"));
+        assert!(check(&s, "folder:f2", "viewer", "user:u2"));
+        assert!(!check(&s, "folder:f1", "viewer", "user:u1"));
+        let expanded = s.expand(&Object::parse("folder:f1").unwrap(), "viewer").to_string();
+        assert!(expanded.contains("\"cycle\":true"));
+    }
+
+    #[test]
+    fn stops_at_max_depth() {
+        let mut tuples: Vec<String> = (0..MAX_DEPTH + 8)
+            .map(|i| format!("folder:f{}#parent@folder:f{}", i, i + 1))
+            .collect();
+        tuples.push(format!("folder:f{}#viewer@user:far", MAX_DEPTH + 8));
+        tuples.push("folder:f2#viewer@user:near".to_string());
+        let tuples: Vec<&str> = tuples.iter().map(String::as_str).collect();
+        let s = snapshot(&tuples);
+        assert!(check(&s, "folder:f0", "viewer", "user:near"));
+        assert!(!check(&s, "folder:f0", "viewer", "user:far"));
+        assert!(check(&s, "folder:f20", "viewer", "user:far"));
+    }
+
+    #[test]
+    fn denies_when_the_exclusion_is_cut() {
+        let mut tuples: Vec<String> = (0..MAX_DEPTH + 8)
+            .map(|i| format!("group:g{}#member@group:g{}#member", i, i + 1))
+            .collect();
+        tuples.push(format!("group:g{}#member@user:mallory", MAX_DEPTH + 8));
+        tuples.push("document:d1#banned@group:g0#member".to_string());
+        tuples.push("document:d1#viewer@user:mallory".to_string());
+        tuples.push("document:d2#banned@group:g30#member".to_string());
+        tuples.push("document:d2#viewer@user:alice".to_string());
+        let tuples: Vec<&str> = tuples.iter().map(String::as_str).collect();
+        let s = snapshot(&tuples);
+        assert!(!check(&s, "document:d1", "viewer", "user:mallory"));
+        assert!(check(&s, "document:d2", "viewer", "user:alice"));
+    }
+
+    #[test]
+    fn lists_objects_and_subjects() {
+        let s = snapshot(&[
+            "document:d1#owner@user:alice",
+            "document:d2#viewer@user:alice",
+            "document:d3#viewer@user:bob",
+        ]);
+        let alice = Subject::parse("user:alice").unwrap();
+        assert_eq!(s.objects("document", "viewer", &alice), vec!["d1", "d2"]);
+        assert_eq!(s.objects("document", "editor", &alice), vec!["d1"]);
+        let viewers = s.subjects(&Object::parse("document:d1").unwrap(), "viewer");
+        assert_eq!(viewers.into_iter().map(|v| v.to_string()).collect::<Vec<_>>(), vec!["user:alice"]);
+    }
+
+    #[test]
+    fn consistency_tokens_are_bound_to_the_domain() {
+        let token = encode_token("d1", 42);
+        assert_eq!(decode_token("d1", &token), Some(42));
+        assert_eq!(decode_token("d2", &token), None);
+        assert_eq!(decode_token("d1", "garbage!"), None);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::util::jwt::{decode_token, Auth};
+use crate::util::{
+    jwt::{decode_token, Payload},
+    revocation,
+};
 use axum::{
     body::{box_body, Body, BoxBody},
     http::{Request, Response, StatusCode},
@@ -6,13 +9,19 @@ use axum::{
 use serde_json::json;
 use tower_http::auth::AuthorizeRequest;
 
-const AUTH_HEADER: &'static str = "Authorization";
+const AUTH_HEADER: &str = "Authorization";
 
 #[derive(Debug, Clone)]
 pub struct Restrict {
     reject_reason: Option<String>,
 }
 
+impl Default for Restrict {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Restrict {
     pub fn new() -> Self {
         Self {
@@ -22,7 +31,7 @@ impl Restrict {
 }
 
 impl AuthorizeRequest for Restrict {
-    type Output = Auth;
+    type Output = Payload;
     type ResponseBody = BoxBody;
     fn authorize<B>(&mut self, req: &Request<B>) -> Option<Self::Output> {
         let mut output: Option<Self::Output> = None;
@@ -32,7 +41,15 @@ impl AuthorizeRequest for Restrict {
                 let auth_str = auth_str.replace("Bearer ", "");
                 let decoded = decode_token(&auth_str);
                 match decoded {
-                    Ok(token_data) => output = Some(token_data.claims.auth),
+                    Ok(token_data) => {
+                        let claims = token_data.claims;
+                        let issued_at = claims.iat.timestamp_millis();
+                        if revocation::is_revoked(&claims.jti, &claims.auth.id, issued_at) {
+                            self.reject_reason = Some("令牌已失效".to_string());
+                        } else {
+                            output = Some(claims);
+                        }
+                    }
                     Err(e) => {
                         self.reject_reason = Some(format!("请求头 Authorization 解析错误: {:?}", e))
                     }
@@ -46,6 +63,7 @@ impl AuthorizeRequest for Restrict {
         output
     }
     fn on_authorized<B>(&mut self, req: &mut Request<B>, output: Self::Output) {
+        req.extensions_mut().insert(output.auth.clone());
         req.extensions_mut().insert(output);
     }
     fn unauthorized_response<B>(&mut self, _req: &Request<B>) -> Response<Self::ResponseBody> {
@@ -0,0 +1,49 @@
+use chrono::NaiveDateTime;
+use std::{collections::HashMap, sync::RwLock};
+
+#[derive(Debug, Default)]
+struct Revocations {
+    // jti -> token expire
+    tokens: HashMap<String, NaiveDateTime>,
+    // user_id -> tokens issued up to this moment are revoked, in milliseconds since the epoch
+    users: HashMap<String, i64>,
+}
+
+lazy_static! {
+    static ref REVOCATIONS: RwLock<Revocations> = RwLock::new(Revocations::default());
+}
+
+pub fn revoke_token(jti: &str, expire: NaiveDateTime) {
+    let mut revocations = REVOCATIONS.write().unwrap();
+    revocations.tokens.insert(jti.to_string(), expire);
+}
+
+pub fn revoke_user(user_id: &str, issued_until: i64) {
+    let mut revocations = REVOCATIONS.write().unwrap();
+    let entry = revocations
+        .users
+        .entry(user_id.to_string())
+        .or_insert(issued_until);
+    if *entry < issued_until {
+        *entry = issued_until;
+    }
+}
+
+/// `issued_at` in milliseconds since the epoch
+pub fn is_revoked(jti: &str, user_id: &str, issued_at: i64) -> bool {
+    let revocations = REVOCATIONS.read().unwrap();
+    if revocations.tokens.contains_key(jti) {
+        return true;
+    }
+    match revocations.users.get(user_id) {
+        Some(issued_until) => issued_at <= *issued_until,
+        None => false,
+    }
+}
+
+/// drops entries that can no longer match an unexpired token
+pub fn prune(expired_before: NaiveDateTime, issued_until: i64) {
+    let mut revocations = REVOCATIONS.write().unwrap();
+    revocations.tokens.retain(|_, v| *v > expired_before);
+    revocations.users.retain(|_, v| *v > issued_until);
+}
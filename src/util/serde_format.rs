@@ -2,7 +2,7 @@ pub mod naive_datetime {
     use chrono::NaiveDateTime;
     use serde::{self, Deserialize, Deserializer, Serializer};
 
-    const FORMAT: &'static str = "%Y-%m-%d %H:%M:%S";
+    const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 
     pub fn serialize<S>(date: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -41,13 +41,36 @@ pub mod utc_datetime {
     }
 }
 
+/// seconds with a millisecond fraction, a NumericDate as RFC 7519 allows
+pub mod utc_datetime_millis {
+    use chrono::{DateTime, TimeZone, Utc};
+    use serde::{self, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(datetime: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let t = datetime.timestamp_millis() as f64 / 1000.0;
+        serializer.serialize_f64(t)
+    }
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let t = f64::deserialize(deserializer)?;
+        Utc.timestamp_millis_opt((t * 1000.0).round() as i64)
+            .single()
+            .ok_or_else(|| serde::de::Error::custom("invalid Unix timestamp value"))
+    }
+}
+
 pub mod i32_bool {
     use serde::{self, Deserialize, Deserializer, Serializer};
     pub fn serialize<S>(val: &i32, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let s = if *val == 1 { true } else { false };
+        let s = *val == 1;
         serializer.serialize_bool(s)
     }
     pub fn deserialize<'de, D>(deserializer: D) -> Result<i32, D::Error>
@@ -58,3 +81,19 @@ pub mod i32_bool {
         Ok(d)
     }
 }
+
+pub mod option_naive_datetime {
+    use super::naive_datetime;
+    use chrono::NaiveDateTime;
+    use serde::{self, Serializer};
+
+    pub fn serialize<S>(date: &Option<NaiveDateTime>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match date {
+            Some(date) => naive_datetime::serialize(date, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+}
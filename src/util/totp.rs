@@ -0,0 +1,141 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac, NewMac};
+use sha1::Sha1;
+use std::env;
+use url::Url;
+
+const STEP: i64 = 30;
+const DIGITS: u32 = 6;
+// accepted clock drift, in steps
+const SKEW: i64 = 1;
+
+fn alphabet() -> base32::Alphabet {
+    base32::Alphabet::RFC4648 { padding: false }
+}
+
+pub fn issuer() -> String {
+    env::var("MFA_ISSUER").unwrap_or_else(|_| "azman".to_string())
+}
+
+/// 160 bit base32 secret, as recommended by RFC 4226
+pub fn generate_secret() -> String {
+    base32::encode(alphabet(), &rand::random::<[u8; 20]>())
+}
+
+pub fn current_step() -> i64 {
+    Utc::now().timestamp() / STEP
+}
+
+fn hotp(key: &[u8], counter: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_varkey(key).unwrap();
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!("{:0width$}", binary % 10u32.pow(DIGITS), width = DIGITS as usize)
+}
+
+/// returns the step `code` was generated for, steps up to `last_step` are rejected against replay
+pub fn verify(secret: &str, code: &str, last_step: i64) -> Option<i64> {
+    verify_at(secret, code, last_step, current_step())
+}
+
+fn verify_at(secret: &str, code: &str, last_step: i64, step: i64) -> Option<i64> {
+    let key = base32::decode(alphabet(), secret)?;
+    (step - SKEW..=step + SKEW)
+        .filter(|v| *v > last_step)
+        .find(|v| hotp(&key, *v) == code.trim())
+}
+
+/// `otpauth://` uri of the secret, for QR codes of authenticator apps
+pub fn provisioning_uri(account: &str, secret: &str) -> String {
+    let issuer = issuer();
+    let mut uri = Url::parse("otpauth://totp").unwrap();
+    uri.set_path(&format!("/{}:{}", issuer, account));
+    uri.query_pairs_mut()
+        .append_pair("secret", secret)
+        .append_pair("issuer", &issuer)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &STEP.to_string());
+    uri.to_string()
+}
+
+/// one-time recovery codes like `k3f9x-2mq7d`
+pub fn generate_recovery_codes(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|_| {
+            let code = base32::encode(alphabet(), &rand::random::<[u8; 7]>()).to_lowercase();
+            format!("{}-{}", &code[..5], &code[5..10])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 appendix B, SHA1 with the ASCII key "12345678901234567890", last 6 of 8 digits
+    const VECTORS: [(i64, &str); 6] = [
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1111111111, "050471"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+        (20000000000, "353130"),
+    ];
+
+    fn secret() -> String {
+        base32::encode(alphabet(), b"12345678901234567890")
+    }
+
+    #[test]
+    fn matches_rfc_6238_vectors() {
+        let key = b"12345678901234567890";
+        for (time, code) in VECTORS.iter() {
+            assert_eq!(hotp(key, time / STEP), *code, "at {}", time);
+        }
+    }
+
+    #[test]
+    fn accepts_codes_within_the_skew_window() {
+        let step = 1111111109 / STEP;
+        for drift in -SKEW..=SKEW {
+            assert_eq!(verify_at(&secret(), "081804", 0, step + drift), Some(step));
+        }
+        assert_eq!(verify_at(&secret(), "081804", 0, step + SKEW + 1), None);
+        assert_eq!(verify_at(&secret(), "081804", 0, step - SKEW - 1), None);
+        assert_eq!(verify_at(&secret(), " 081804 ", 0, step), Some(step));
+        assert_eq!(verify_at(&secret(), "000000", 0, step), None);
+    }
+
+    #[test]
+    fn rejects_replayed_steps() {
+        let step = 1234567890 / STEP;
+        assert_eq!(verify_at(&secret(), "005924", step - 1, step), Some(step));
+        assert_eq!(verify_at(&secret(), "005924", step, step), None);
+        assert_eq!(verify_at(&secret(), "005924", step + 1, step + 1), None);
+    }
+
+    #[test]
+    fn rejects_invalid_secrets() {
+        assert_eq!(verify_at("not base32!", "287082", 0, 1), None);
+    }
+
+    #[test]
+    fn builds_secrets_and_recovery_codes() {
+        let secret = generate_secret();
+        assert_eq!(base32::decode(alphabet(), &secret).map(|v| v.len()), Some(20));
+        let codes = generate_recovery_codes(10);
+        assert_eq!(codes.len(), 10);
+        assert!(codes.iter().all(|v| v.len() == 11 && v.chars().nth(5) == Some('-')));
+        let uri = provisioning_uri("alice", &secret);
+        assert!(uri.starts_with("otpauth://totp/"));
+        assert!(uri.contains(&format!("secret={}", secret)));
+    }
+}
@@ -1,4 +1,7 @@
+use axum::http::HeaderMap;
 use chrono::{Local, NaiveDateTime, Duration};
+use sha2::{Digest, Sha256};
+use std::{env, net::SocketAddr};
 use uuid::Uuid;
 
 pub fn now() -> NaiveDateTime {
@@ -11,4 +14,37 @@ pub fn default_expire() -> NaiveDateTime {
 
 pub fn uuid_v4() -> String {
   Uuid::new_v4().to_string()
-}
\ No newline at end of file
+}
+
+pub fn random_token() -> String {
+  base64::encode_config(rand::random::<[u8; 32]>(), base64::URL_SAFE_NO_PAD)
+}
+
+pub fn hash_token(token: &str) -> String {
+  hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// compares in time independent of where the strings differ
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+  a.len() == b.len()
+    && a
+      .bytes()
+      .zip(b.bytes())
+      .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+      == 0
+}
+
+/// address of the client, `X-Forwarded-For` is only honoured behind a trusted proxy
+pub fn client_ip(headers: &HeaderMap, addr: SocketAddr) -> String {
+  let trust_proxy = env::var("TRUST_PROXY").map(|v| v == "true").unwrap_or(false);
+  let forwarded = headers
+    .get("X-Forwarded-For")
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.split(',').next())
+    .map(|v| v.trim().to_string())
+    .filter(|v| !v.is_empty());
+  match forwarded {
+    Some(ip) if trust_proxy => ip,
+    _ => addr.ip().to_string(),
+  }
+}